@@ -0,0 +1,113 @@
+# Die Meldungen von restore auf Deutsch. Was hier fehlt, kommt aus en.ftl.
+
+## Vorbereitung
+
+error-source-not-found = FEHLER: Quelle nicht gefunden: { $path }
+error-ask-no-terminal = FEHLER: --on-conflict ask braucht jemanden am Terminal, der antwortet
+error-invalid-regex = FEHLER: Ungültiger regulärer Ausdruck: { $error }
+error-background = FEHLER: Die Wiederherstellung kann nicht im Hintergrund starten: { $error }
+error-signing-key = FEHLER: Der Signaturschlüssel { $path } ist nicht lesbar: { $error }
+error-categories = FEHLER: Die Kategorien { $path } sind nicht lesbar: { $error }
+error-analysis = Fehler bei der Analyse: { $error }
+error-extraction = Fehler beim Entpacken: { $error }
+error-stats-json = Fehler beim Schreiben von { $path }: { $error }
+error-signing = Fehler beim Signieren von { $path }: { $error }
+error-no-archives = FEHLER: Keine ZIP-Dateien gefunden!
+error-no-sets = FEHLER: Keine Sicherungssätze gefunden!
+
+background-started = Wiederherstellung läuft im Hintergrund (Prozess { $pid }).
+background-log = {"  "}Protokoll:    { $path }
+background-progress = {"  "}Fortschritt: restore status -d { $dest } --follow
+
+## Die Analyse
+
+analysis-title = Windows-Sicherungsanalyse
+analysis-source = Quellordner:       { $path }
+analysis-zips = ZIP-Dateien:       { $count }
+analysis-other = Andere Archive:    { $count } { $kind }
+analysis-size = Gesamtgröße:       { $size } GB
+analysis-archives = Archive, nummeriert wie eine Wiederherstellung und extract --zip sie zählen:
+analysis-sample = Stichprobe aus: { $archive }
+analysis-sample-files = { $count ->
+    [one] { $count } Datei
+   *[other] { $count } Dateien
+}
+
+## Der Plan und seine Bestätigung
+
+plan-source = {"  "}Quelle:   { $path }
+plan-dest = {"  "}Ziel:     { $path }
+plan-mirror = {"  "}Spiegel:  { $path }
+plan-user = {"  "}Benutzer: { $old } -> { $new }
+plan-space = {"  "}Platz:    { $size } in { $path } ({ $available })
+plan-free = { $size } frei
+plan-free-unknown = freier Platz unbekannt
+warning-no-space = WARNUNG: Nicht genug freier Platz in { $path }
+confirm-prompt = Fortfahren? (j/n):{" "}
+confirm-no-terminal = Ohne --yes wird nichts wiederhergestellt: kein Terminal, also kann niemand bestätigen.
+tuning-threads = Geschwindigkeit der Archive und des Ziels wird gemessen, um die Zahl der Threads zu wählen...
+tuned-threads = {"  "}Threads: { $readers } lesen mit { $read }/s, { $writers } schreiben mit { $write }/s
+cancelled = Abgebrochen.
+signed = {"  "}Signiert: { $path }
+
+## Die Wiederherstellung
+
+latest-sets-only = Nur der neueste Sicherungssatz jedes Rechners wird wiederhergestellt; { $count } ZIP-Dateien älterer Sätze werden übersprungen (mit --all-runs auch diese)
+skipping-archives = Die ersten { $count } ZIP-Dateien werden übersprungen; Beginn bei { $archive }
+snapshot = === Snapshot { $number }/{ $count }: { $path } ===
+counting = Zähle die Dateien in { $count } ZIP-Dateien...
+starting = Entpacke { $files } Dateien ({ $size })...
+destination = Ziel: { $path }
+
+## Konflikte, mit --on-conflict ask
+
+conflict-exists = Vorhanden: { $path }
+conflict-existing = {"  "}Am Ziel:           { $size }  { $modified }
+conflict-backed-up = {"  "}In der Sicherung:  { $size }  { $modified }  ({ $archive })
+conflict-files-of-type = .{ $extension }-Dateien
+conflict-files-without-extension = Dateien ohne Endung
+conflict-prompt = [b]ehalten, [e]rsetzen oder [u]mbenennen? B, E oder U für alle { $files }:{" "}
+
+## Die Zusammenfassung
+
+summary-stopped = Entpacken beim ersten Fehler angehalten!
+summary-cancelled = Entpacken abgebrochen!
+summary-completed = Entpacken abgeschlossen!
+summary-extracted = Entpackte Dateien:    { $count }
+summary-filtered = Ausgefiltert:         { $count }
+summary-skipped = Schon vorhanden:      { $count } übersprungen
+summary-overwritten = Überschrieben:        { $count }
+summary-renamed = Umbenannt:            { $count }
+summary-placeholders = Nur in der Cloud:     { $count } Platzhalter nicht wiederhergestellt, ihre Daten sind NICHT in der Sicherung
+summary-system-isolated = Systemdateien:        { $count } wiederhergestellt nach { $path }
+summary-system-skipped = Systemdateien:        { $count } übersprungen (mit --system-files extract oder isolate wiederherstellen)
+summary-apple-double = Mac-Metadaten:        { $count } AppleDouble-Dateien übersprungen (mit --keep-apple-double behalten)
+summary-normalized = Namen bereinigt:      { $count } Einträge ohne .-Segmente, doppelte Trenner oder Punkte und Leerzeichen am Ende
+summary-absolute = Absolute Namen:       { $count } Einträge unterhalb des Ziels wiederhergestellt (mit --absolute-entries reject auslassen)
+summary-rejected = Unsichere Namen:      { $count } Einträge nicht wiederhergestellt, siehe unten
+summary-quarantined = In Quarantäne:        { $count } Dateien mit falscher CRC-Prüfsumme, abgelegt in { $path }
+summary-efs = EFS-verschlüsselt:    { $count } Dateien noch verschlüsselt wiederhergestellt, siehe unten
+summary-errors = Fehler:               { $count }
+summary-destination = Ziel:                 { $path }
+summary-linked = Verlinkt:             { $count } unverändert (Hardlinks auf den vorigen Snapshot)
+summary-tree = Baum:                 { $path }
+summary-deduplicated = Dedupliziert:         { $count } Dateien hatten den Inhalt einer anderen
+summary-mirrored = Gespiegelt nach:      { $path }
+summary-relabeled = SELinux-Labels:       neu gesetzt für { $path }
+summary-relabel-failed = SELinux-Labels:       FEHLER für { $path }: { $error }
+summary-elapsed = Dauer:                { $seconds } s
+summary-order = Reihenfolge:          { $order } ({ $switches } Ordnerwechsel, Dateien im Mittel nach { $seconds } s fertig)
+summary-pipeline = Pipeline:             { $readers } Leser, { $decoders } Decoder, { $writers } Schreiber
+summary-decode-queue = Decodier-Queue:       max. { $max }/{ $capacity }, Mittel { $mean }
+summary-write-queue = Schreib-Queue:        max. { $max }/{ $capacity }, Mittel { $mean }
+
+summary-placeholder-list = Platzhalter nur in der Cloud (nicht in der Sicherung):
+summary-rejected-list = Wegen ihrer Namen nicht wiederhergestellte Einträge, die aus dem Ziel hinausführen, mit --absolute-entries reject absolut oder leer sind:
+summary-damaged-list = Beschädigte Dateien (so weit wie möglich wiederhergestellt nach { $path }):
+summary-efs-list =
+    EFS-verschlüsselte Dateien (nicht lesbar ohne Zertifikat und Schlüssel des alten
+    Windows-Kontos; diese aus einer .pfx-Sicherung importieren und die Dateien als dieser Benutzer öffnen):
+summary-slowest = Langsamste Archive:
+summary-slow-archive = { $seconds } s  { $size }  { $rate }  { $errors } Fehler  { $name }
+summary-error-kind = Fehler: { $kind } ({ $count })
+summary-more-errors = {"  "}... und { $count } weitere
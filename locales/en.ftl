@@ -0,0 +1,115 @@
+# Messages of restore in English, the catalog the others fall back to.
+# Labels of the summary are padded so the values line up; translations
+# keep the values of their own catalog in line.
+
+## Setup
+
+error-source-not-found = ERROR: Source not found: { $path }
+error-ask-no-terminal = ERROR: --on-conflict ask needs someone at a terminal to answer
+error-invalid-regex = ERROR: Invalid regular expression: { $error }
+error-background = ERROR: Cannot start the restore in the background: { $error }
+error-signing-key = ERROR: Cannot read the signing key { $path }: { $error }
+error-categories = ERROR: Cannot read the categories { $path }: { $error }
+error-analysis = Error during analysis: { $error }
+error-extraction = Error during extraction: { $error }
+error-stats-json = Error writing { $path }: { $error }
+error-signing = Error signing { $path }: { $error }
+error-no-archives = ERROR: No ZIP files found!
+error-no-sets = ERROR: No backup sets found!
+
+background-started = Restoring in the background (process { $pid }).
+background-log = {"  "}Log:      { $path }
+background-progress = {"  "}Progress: restore status -d { $dest } --follow
+
+## The analysis
+
+analysis-title = Windows Backup Analyzer
+analysis-source = Source directory:  { $path }
+analysis-zips = ZIP files:         { $count }
+analysis-other = Other archives:    { $count } { $kind }
+analysis-size = Total size:        { $size } GB
+analysis-archives = Archives, numbered as a restore and extract --zip count them:
+analysis-sample = Sample from: { $archive }
+analysis-sample-files = { $count ->
+    [one] { $count } file
+   *[other] { $count } files
+}
+
+## The plan and its confirmation
+
+plan-source = {"  "}Source: { $path }
+plan-dest = {"  "}Dest:   { $path }
+plan-mirror = {"  "}Mirror: { $path }
+plan-user = {"  "}User:   { $old } -> { $new }
+plan-space = {"  "}Space:  { $size } in { $path } ({ $available })
+plan-free = { $size } free
+plan-free-unknown = free space unknown
+warning-no-space = WARNING: Not enough free space in { $path }
+confirm-prompt = Proceed? (y/n):{" "}
+confirm-no-terminal = Not restoring without --yes: not run at a terminal, so no one can confirm.
+tuning-threads = Measuring the speed of the archives and the destination to choose the number of threads...
+tuned-threads = {"  "}Threads: { $readers } reading at { $read }/s, { $writers } writing at { $write }/s
+cancelled = Cancelled.
+signed = {"  "}Signed: { $path }
+
+## The restore
+
+latest-sets-only = Restoring the latest backup set of each machine; skipping { $count } ZIP files of older sets (use --all-runs to restore them)
+skipping-archives = Skipping the first { $count } ZIP files; starting at { $archive }
+snapshot = === Snapshot { $number }/{ $count }: { $path } ===
+counting = Counting files in { $count } ZIP files...
+starting = Starting extraction of { $files } files ({ $size })...
+destination = Destination: { $path }
+
+## Conflicts, with --on-conflict ask
+
+conflict-exists = Exists: { $path }
+conflict-existing = {"  "}At the destination: { $size }  { $modified }
+conflict-backed-up = {"  "}In the backup:      { $size }  { $modified }  ({ $archive })
+conflict-files-of-type = .{ $extension } files
+conflict-files-without-extension = files without extension
+conflict-prompt = [k]eep, [r]eplace or re[n]ame? K, R or N for all { $files }:{" "}
+
+## The summary
+
+summary-stopped = Extraction stopped at the first error!
+summary-cancelled = Extraction cancelled!
+summary-completed = Extraction completed!
+summary-extracted = Files extracted:   { $count }
+summary-filtered = Filtered out:      { $count }
+summary-skipped = Skipped existing:  { $count }
+summary-overwritten = Overwritten:       { $count }
+summary-renamed = Renamed:           { $count }
+summary-placeholders = Cloud-only:        { $count } placeholders not restored, their data is NOT in the backup
+summary-system-isolated = System files:      { $count } restored to { $path }
+summary-system-skipped = System files:      { $count } skipped (use --system-files extract or isolate)
+summary-apple-double = Mac metadata:      { $count } AppleDouble files skipped (use --keep-apple-double)
+summary-normalized = Names cleaned:     { $count } entries lost . segments, repeated separators or trailing dots and spaces
+summary-absolute = Absolute names:    { $count } entries restored below the destination (use --absolute-entries reject)
+summary-rejected = Unsafe names:      { $count } entries not restored, see below
+summary-quarantined = Quarantined:       { $count } files failed the CRC check, kept in { $path }
+summary-efs = EFS-encrypted:     { $count } files restored still encrypted, see below
+summary-errors = Errors:            { $count }
+summary-destination = Destination:       { $path }
+summary-linked = Linked unchanged:  { $count } (hard links to the previous snapshot)
+summary-tree = Tree:              { $path }
+summary-deduplicated = Deduplicated:      { $count } files had the content of another
+summary-mirrored = Mirrored to:       { $path }
+summary-relabeled = SELinux relabeled: { $path }
+summary-relabel-failed = SELinux relabel:   FAILED for { $path }: { $error }
+summary-elapsed = Elapsed:           { $seconds } s
+summary-order = Order:             { $order } ({ $switches } directory switches, files done after { $seconds } s on average)
+summary-pipeline = Pipeline:          { $readers } readers, { $decoders } decoders, { $writers } writers
+summary-decode-queue = Decode queue:      max { $max }/{ $capacity }, mean { $mean }
+summary-write-queue = Write queue:       max { $max }/{ $capacity }, mean { $mean }
+
+summary-placeholder-list = Cloud-only placeholders (not in the backup):
+summary-rejected-list = Entries not restored for their names, which lead out of the destination, are absolute with --absolute-entries reject, or are empty:
+summary-damaged-list = Damaged files (restored as far as possible to { $path }):
+summary-efs-list =
+    EFS-encrypted files (unreadable without the certificate and key of the old
+    Windows account; import them from a .pfx backup, then open the files as that user):
+summary-slowest = Slowest archives:
+summary-slow-archive = { $seconds } s  { $size }  { $rate }  { $errors } errors  { $name }
+summary-error-kind = Errors: { $kind } ({ $count })
+summary-more-errors = {"  "}... and { $count } more
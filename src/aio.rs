@@ -0,0 +1,135 @@
+//! Async variant of the library API, driven by tokio.
+//!
+//! ZIP decoding is inherently blocking, so every archive is handled on
+//! tokio's shared blocking pool. A semaphore bounds how many archives are
+//! in flight at once, letting embedding services keep restores from
+//! crowding out their own blocking work.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::{Analysis, ArchiveError, ArchiveStats, Outcome, Stats};
+
+async fn blocking<T, F>(f: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await.map_err(io::Error::other)?
+}
+
+/// Async [`crate::find_zip_files`].
+pub async fn find_zip_files(source_dir: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let source_dir = source_dir.as_ref().to_path_buf();
+    blocking(move || crate::find_zip_files(&source_dir)).await
+}
+
+/// Async [`crate::find_archives`].
+pub async fn find_archives(source_dir: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let source_dir = source_dir.as_ref().to_path_buf();
+    blocking(move || crate::find_archives(&source_dir)).await
+}
+
+/// Async [`crate::analyze`].
+pub async fn analyze(source_dir: impl AsRef<Path>) -> io::Result<Analysis> {
+    let source_dir = source_dir.as_ref().to_path_buf();
+    blocking(move || crate::analyze(&source_dir)).await
+}
+
+/// Async wrapper around [`crate::Restorer`] extracting up to `concurrency`
+/// archives at the same time.
+#[derive(Debug, Clone)]
+pub struct Restorer {
+    inner: Arc<crate::Restorer>,
+    permits: Arc<Semaphore>,
+}
+
+impl Restorer {
+    /// Wraps `inner`, extracting one archive at a time.
+    pub fn new(inner: crate::Restorer) -> Self {
+        Self::with_concurrency(inner, 1)
+    }
+
+    pub fn with_concurrency(inner: crate::Restorer, concurrency: usize) -> Self {
+        Self::with_semaphore(inner, Arc::new(Semaphore::new(concurrency.max(1))))
+    }
+
+    /// Shares `permits` with other restorers, so several restores running in
+    /// the same service stay within one global limit.
+    pub fn with_semaphore(inner: crate::Restorer, permits: Arc<Semaphore>) -> Self {
+        Restorer { inner: Arc::new(inner), permits }
+    }
+
+    /// Async [`crate::Restorer::archives`].
+    pub async fn archives(&self) -> io::Result<Vec<PathBuf>> {
+        let inner = Arc::clone(&self.inner);
+        blocking(move || inner.archives()).await
+    }
+
+    /// Async [`crate::Restorer::extract_archive`], waiting for a permit first.
+    pub async fn extract_archive(&self, zip_path: impl AsRef<Path>) -> io::Result<Result<ArchiveStats, ArchiveError>> {
+        let _permit = self.permits.acquire().await.map_err(io::Error::other)?;
+        let inner = Arc::clone(&self.inner);
+        let zip_path = zip_path.as_ref().to_path_buf();
+        blocking(move || Ok(inner.extract_archive(&zip_path))).await
+    }
+
+    /// Async [`crate::Restorer::run`]. Archives are extracted concurrently,
+    /// errors are reported in archive order. Archives still waiting for a
+    /// permit when the restorer's token is cancelled are not started.
+    pub async fn run(&self) -> io::Result<Outcome> {
+        let zips = self.archives().await?;
+        let dest = self.inner.dest().to_path_buf();
+        tokio::fs::create_dir_all(&dest).await?;
+        for mirror in self.inner.mirrors() {
+            tokio::fs::create_dir_all(mirror).await?;
+        }
+        // Started anew here; each archive adds to them.
+        if let Some(manifest) = self.inner.manifest() {
+            tokio::fs::File::create(manifest).await?;
+        }
+        if let Some(tree) = self.inner.tree_file() {
+            tokio::fs::File::create(tree).await?;
+        }
+
+        let mut tasks = JoinSet::new();
+        for (i, zip_path) in zips.into_iter().enumerate() {
+            let this = self.clone();
+            tasks.spawn(async move {
+                let _permit = this.permits.acquire().await.map_err(io::Error::other)?;
+                if this.inner.cancellation_token().is_cancelled() {
+                    return Ok(None);
+                }
+                let inner = Arc::clone(&this.inner);
+                let path = zip_path.clone();
+                let result = blocking(move || Ok(inner.extract_archive(&path))).await?;
+                Ok::<_, io::Error>(Some((i, zip_path, result)))
+            });
+        }
+
+        let mut results = Vec::with_capacity(tasks.len());
+        while let Some(joined) = tasks.join_next().await {
+            if let Some(result) = joined.map_err(io::Error::other)?? {
+                results.push(result);
+            }
+        }
+        results.sort_by_key(|(i, _, _)| *i);
+
+        let mut stats = Stats::default();
+        let mut cancelled = self.inner.cancellation_token().is_cancelled();
+        for (_, path, result) in results {
+            match result {
+                Ok(archive) => {
+                    cancelled |= archive.cancelled;
+                    stats.add_archive(&path, archive);
+                }
+                Err(e) => stats.add_failed_archive(e),
+            }
+        }
+        Ok(if cancelled { Outcome::Cancelled(stats) } else { Outcome::Completed(stats) })
+    }
+}
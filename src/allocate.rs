@@ -0,0 +1,82 @@
+//! Reserving the space of a restored file before it is written, for
+//! [`crate::RestorerBuilder::preallocate`]. The file system can then lay it
+//! out in one piece instead of growing it a write at a time, which
+//! fragments large files on a disk restored to in parallel, and a full disk
+//! shows before the file is half written. The size of the file stays what
+//! has been written.
+
+use std::fs::File;
+use std::io;
+
+/// Smaller files take a call more than they save.
+pub(crate) const MIN_SIZE: u64 = 1 << 20;
+
+/// Reserves `len` bytes for `file`. Fails only if the disk lacks the
+/// space; file systems that cannot reserve it ahead grow the file as they
+/// would anyway.
+pub(crate) fn reserve(file: &File, len: u64) -> io::Result<()> {
+    match allocate(file, len) {
+        Err(e) if e.kind() == io::ErrorKind::StorageFull => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn allocate(file: &File, len: u64) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: `file` stays open across the call.
+    match unsafe { libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, 0, len as libc::off_t) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn allocate(file: &File, len: u64) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let mut store = libc::fstore_t {
+        fst_flags: libc::F_ALLOCATECONTIG | libc::F_ALLOCATEALL,
+        fst_posmode: libc::F_PEOFPOSMODE,
+        fst_offset: 0,
+        fst_length: len as libc::off_t,
+        fst_bytesalloc: 0,
+    };
+    // SAFETY: `file` stays open across the calls, which only read `store`
+    // and write its `fst_bytesalloc`.
+    unsafe {
+        if libc::fcntl(file.as_raw_fd(), libc::F_PREALLOCATE, &mut store) != -1 {
+            return Ok(());
+        }
+        // Not in one piece, then.
+        store.fst_flags = libc::F_ALLOCATEALL;
+        match libc::fcntl(file.as_raw_fd(), libc::F_PREALLOCATE, &mut store) {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(windows)]
+fn allocate(file: &File, len: u64) -> io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{FILE_ALLOCATION_INFO, FileAllocationInfo, SetFileInformationByHandle};
+
+    let info = FILE_ALLOCATION_INFO { AllocationSize: len as i64 };
+    // SAFETY: `file` stays open across the call, which only reads `info`.
+    let done = unsafe {
+        SetFileInformationByHandle(
+            file.as_raw_handle(),
+            FileAllocationInfo,
+            &info as *const FILE_ALLOCATION_INFO as *const _,
+            size_of::<FILE_ALLOCATION_INFO>() as u32,
+        )
+    };
+    if done == 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn allocate(_: &File, _: u64) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,318 @@
+//! A cache of the entries of each archive, for [`crate::BackupSet::with_cache`],
+//! so that analyzing, listing and restoring the same backup again does not
+//! read the central directory of every unchanged archive again; over USB 2
+//! that takes minutes per thousand archives.
+//!
+//! Each archive has a file of JSON lines in the cache folder, named after a
+//! hash of its path: a header with the path, size and modification time of
+//! the archive, then one line per entry. An archive that changed no longer
+//! matches its header and is read again. Files are written under a
+//! temporary name and renamed once the last entry is in, so an interrupted
+//! listing is not mistaken for a complete one. Folders, such as those of
+//! File History, are not cached: their time does not change with the files
+//! inside.
+//!
+//! This is also the index of `restore index`, which `restore query` reads.
+//! A file per archive, rather than one database, is replaced alone when its
+//! archive changes and needs no SQLite in the binary.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::analyze::read_each;
+use crate::backup_set::{BackupSet, EntryInfo};
+use crate::error::ArchiveError;
+
+/// Version of the format of the cache files; files of another are read
+/// again.
+const VERSION: u32 = 1;
+
+/// Extension of the cache files.
+const EXTENSION: &str = "jsonl";
+
+/// Tells the temporary files of the threads of one process apart.
+static NEXT_TEMP: AtomicU64 = AtomicU64::new(0);
+
+/// The folder holding the cached entries of archives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnalysisCache {
+    dir: PathBuf,
+}
+
+impl AnalysisCache {
+    /// A cache in `dir`, created when the first archive is cached.
+    pub fn new(dir: impl Into<PathBuf>) -> AnalysisCache {
+        AnalysisCache { dir: dir.into() }
+    }
+
+    /// The cache in the state folder of the user: `$XDG_STATE_HOME/restore`
+    /// or `~/.local/state/restore` on Linux, `~/Library/Application
+    /// Support/restore` on macOS and `%LOCALAPPDATA%\restore` on Windows.
+    /// `None` if the home folder is not known.
+    pub fn user() -> Option<AnalysisCache> {
+        let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+        let state = if cfg!(windows) {
+            var("LOCALAPPDATA")
+        } else if cfg!(target_os = "macos") {
+            var("HOME").map(|home| home.join("Library/Application Support"))
+        } else {
+            var("XDG_STATE_HOME").or_else(|| var("HOME").map(|home| home.join(".local/state")))
+        };
+        state.map(|state| AnalysisCache::new(state.join("restore").join("analysis")))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Removes every cached archive, returning how many there were. A
+    /// missing cache folder has none.
+    pub fn clear(&self) -> io::Result<usize> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let mut removed = 0;
+        for entry in entries {
+            let path = entry?.path();
+            let cached = path.extension().is_some_and(|ext| ext == EXTENSION);
+            if cached || path.extension().is_some_and(|ext| ext == "tmp") {
+                fs::remove_file(&path)?;
+                removed += usize::from(cached);
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Reads the entries of every archive of `set` into the cache with
+    /// `threads` workers, so later runs over it need not read them. With
+    /// `update`, archives cached as they are now are left alone: after
+    /// copying more archives into a backup folder, only those are read.
+    pub fn index(&self, set: &BackupSet, threads: usize, update: bool) -> Indexed {
+        let uncached = BackupSet::from_archives(set.source(), Vec::new()).with_mmap(set.mmap());
+        let mut indexed = Indexed::default();
+        let read = |path: &PathBuf| {
+            if update && self.load(path).is_some() {
+                return Ok(None);
+            }
+            let mut writer = self.writer(path);
+            let mut entries = 0;
+            for entry in uncached.archive_entries(path) {
+                let entry = entry?;
+                if let Some(writer) = &mut writer {
+                    writer.push(&entry);
+                }
+                entries += 1;
+            }
+            Ok(Some((entries, writer.is_some_and(CacheWriter::finish))))
+        };
+        read_each(set.archives(), threads, read, |result: Result<_, ArchiveError>| match result {
+            Ok(None) => indexed.unchanged += 1,
+            Ok(Some((entries, cached))) => {
+                indexed.read += 1;
+                indexed.entries += entries;
+                indexed.uncached += usize::from(!cached);
+            }
+            Err(e) => indexed.errors.push(e),
+        });
+        indexed
+    }
+
+    /// The cached entries of `archive`, if they are those of the archive as
+    /// it is now.
+    pub(crate) fn load(&self, archive: &Path) -> Option<CachedEntries> {
+        let header = Header::of(archive)?;
+        let mut lines = BufReader::new(File::open(self.file(&header.path)).ok()?).lines();
+        let cached: Header = serde_json::from_str(&lines.next()?.ok()?).ok()?;
+        (cached == header).then_some(CachedEntries { lines, path: self.file(&header.path) })
+    }
+
+    /// A writer caching the entries of `archive` as they are read; `None`
+    /// if the archive is not cached or the cache folder cannot be written.
+    pub(crate) fn writer(&self, archive: &Path) -> Option<CacheWriter> {
+        let header = Header::of(archive)?;
+        fs::create_dir_all(&self.dir).ok()?;
+        let temp = self.file(&header.path).with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            NEXT_TEMP.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut out = BufWriter::new(File::create(&temp).ok()?);
+        let written = serde_json::to_writer(&mut out, &header).is_ok() && out.write_all(b"\n").is_ok();
+        let writer = CacheWriter { out: Some(out), temp, path: self.file(&header.path) };
+        written.then_some(writer)
+    }
+
+    fn file(&self, archive: &Path) -> PathBuf {
+        let digest = Sha256::digest(archive.as_os_str().as_encoded_bytes());
+        let name: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(name).with_extension(EXTENSION)
+    }
+}
+
+/// What [`AnalysisCache::index`] did.
+#[derive(Debug, Default)]
+pub struct Indexed {
+    /// Archives read, and the entries in them.
+    pub read: usize,
+    pub entries: u64,
+    /// Archives cached already as they are, with `update`.
+    pub unchanged: usize,
+    /// Archives read but not cached: File History folders, and any when the
+    /// cache folder cannot be written.
+    pub uncached: usize,
+    pub errors: Vec<ArchiveError>,
+}
+
+/// The first line of a cache file: which archive it lists, and how that
+/// was when it was read.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    path: PathBuf,
+    size: u64,
+    /// Modification time, in seconds and nanoseconds since the Unix epoch.
+    modified: (u64, u32),
+}
+
+impl Header {
+    /// The header for `archive` as it is now; `None` for folders and
+    /// archives that cannot be read.
+    fn of(archive: &Path) -> Option<Header> {
+        let metadata = fs::metadata(archive).ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        let path = fs::canonicalize(archive).unwrap_or_else(|_| archive.to_path_buf());
+        Some(Header { version: VERSION, path, size: metadata.len(), modified: (modified.as_secs(), modified.subsec_nanos()) })
+    }
+}
+
+/// One entry in a cache file: an [`EntryInfo`] without the archive and
+/// index, which follow from the file and the line.
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    name: String,
+    size: u64,
+    compressed_size: u64,
+    /// DOS date and time.
+    modified: Option<(u16, u16)>,
+    crc32: u32,
+    method: u16,
+    encrypted: bool,
+    is_dir: bool,
+    version_made_by: u16,
+    attributes: u32,
+    /// The extra field, in hex.
+    extra: String,
+    placeholder: bool,
+    efs: bool,
+}
+
+impl Record {
+    fn of(entry: &EntryInfo) -> Record {
+        Record {
+            name: entry.name.clone(),
+            size: entry.size,
+            compressed_size: entry.compressed_size,
+            modified: entry.modified.map(|t| (t.datepart(), t.timepart())),
+            crc32: entry.crc32,
+            method: entry.method,
+            encrypted: entry.encrypted,
+            is_dir: entry.is_dir,
+            version_made_by: entry.version_made_by,
+            attributes: entry.attributes,
+            extra: entry.extra.iter().map(|b| format!("{:02x}", b)).collect(),
+            placeholder: entry.placeholder,
+            efs: entry.efs,
+        }
+    }
+
+    fn into_entry(self, archive: &Arc<Path>, index: usize) -> Option<EntryInfo> {
+        let extra = (0..self.extra.len())
+            .step_by(2)
+            .map(|i| self.extra.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+            .collect::<Option<Vec<u8>>>()?;
+        Some(EntryInfo {
+            archive: Arc::clone(archive),
+            index,
+            name: self.name,
+            size: self.size,
+            compressed_size: self.compressed_size,
+            modified: self.modified.and_then(|(date, time)| zip::DateTime::try_from_msdos(date, time).ok()),
+            crc32: self.crc32,
+            method: self.method,
+            encrypted: self.encrypted,
+            is_dir: self.is_dir,
+            version_made_by: self.version_made_by,
+            attributes: self.attributes,
+            extra,
+            placeholder: self.placeholder,
+            efs: self.efs,
+        })
+    }
+}
+
+/// The entries of an archive read from its cache file.
+pub(crate) struct CachedEntries {
+    lines: io::Lines<BufReader<File>>,
+    /// The cache file, removed if damaged.
+    path: PathBuf,
+}
+
+impl CachedEntries {
+    /// The next entry, as entry `index` of `archive`.
+    pub(crate) fn next(&mut self, archive: &Arc<Path>, index: usize) -> Option<io::Result<EntryInfo>> {
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e)),
+        };
+        let entry = serde_json::from_str::<Record>(&line).ok().and_then(|record| record.into_entry(archive, index));
+        Some(entry.ok_or_else(|| {
+            let _ = fs::remove_file(&self.path);
+            let message = format!("damaged cache file {}, removed: the next run reads the archive again", self.path.display());
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        }))
+    }
+}
+
+/// Writes the entries of an archive to its cache file as they are read.
+/// Dropped before [`finish`](Self::finish), it removes what it wrote.
+pub(crate) struct CacheWriter {
+    /// `None` once writing failed; the archive is then not cached.
+    out: Option<BufWriter<File>>,
+    temp: PathBuf,
+    path: PathBuf,
+}
+
+impl CacheWriter {
+    pub(crate) fn push(&mut self, entry: &EntryInfo) {
+        if let Some(out) = &mut self.out
+            && (serde_json::to_writer(&mut *out, &Record::of(entry)).is_err() || out.write_all(b"\n").is_err())
+        {
+            self.out = None;
+        }
+    }
+
+    /// Puts the cache file in place, once every entry is written; returns
+    /// whether it is.
+    pub(crate) fn finish(mut self) -> bool {
+        let Some(out) = self.out.take() else { return false };
+        out.into_inner().is_ok_and(|file| file.sync_all().is_ok()) && fs::rename(&self.temp, &self.path).is_ok()
+    }
+}
+
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.temp);
+    }
+}
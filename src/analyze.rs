@@ -0,0 +1,549 @@
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+use crate::backup_set::{BackupSet, EntryInfo};
+use crate::catalog::{BackupRun, Catalog};
+use crate::discovery::{cmp_natural, find_archives, run_location};
+use crate::file_history::FileTree;
+use crate::error::ArchiveError;
+use crate::mail;
+use crate::paths::profile_owner;
+use crate::system_files::is_system_file;
+use crate::apple_double::is_apple_double;
+
+/// Overview of a backup folder, as shown in the analyze banner.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub source: PathBuf,
+    /// The archives found, including File History `Data` folders.
+    pub zips: Vec<PathBuf>,
+    pub total_size: u64,
+    pub sample: Option<Sample>,
+    /// The Windows Backup catalog of the folder, when there is one.
+    pub catalog: Option<Catalog>,
+    /// Backup runs, oldest first: those of the catalog if there is one,
+    /// otherwise those found in the folder names.
+    pub runs: Vec<RunSummary>,
+}
+
+/// The archives of one backup run.
+#[derive(Debug, Clone)]
+pub struct RunSummary {
+    pub machine: Option<String>,
+    pub run: BackupRun,
+    pub archives: usize,
+    pub size: u64,
+}
+
+fn summarize_runs(zips: &[PathBuf], sizes: &[u64], catalog: Option<&Catalog>) -> Vec<RunSummary> {
+    let mut runs: Vec<RunSummary> = match catalog {
+        Some(catalog) => catalog
+            .runs
+            .iter()
+            .map(|run| RunSummary { machine: catalog.machine.clone(), run: run.clone(), archives: 0, size: 0 })
+            .collect(),
+        None => Vec::new(),
+    };
+    for (zip, size) in zips.iter().zip(sizes) {
+        let summary = match catalog {
+            Some(catalog) => catalog.run_of(zip).and_then(|run| runs.iter_mut().find(|s| &s.run == run)),
+            None => match run_location(zip) {
+                Some(location) => {
+                    let i = match runs.iter().position(|s| s.machine == location.machine && s.run == location.run) {
+                        Some(i) => i,
+                        None => {
+                            runs.push(RunSummary { machine: location.machine, run: location.run, archives: 0, size: 0 });
+                            runs.len() - 1
+                        }
+                    };
+                    Some(&mut runs[i])
+                }
+                None => None,
+            },
+        };
+        if let Some(summary) = summary {
+            summary.archives += 1;
+            summary.size += size;
+        }
+    }
+    runs.sort_by(|a, b| a.run.time.cmp(&b.run.time).then_with(|| a.machine.cmp(&b.machine)));
+    runs
+}
+
+/// Extension distribution of the first archive, most frequent first.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub archive: PathBuf,
+    pub extensions: Vec<(String, usize)>,
+}
+
+fn sample_archive(path: &Path) -> Option<Sample> {
+    let file = fs::File::open(path).ok()?;
+    let mut archive = zip::ZipArchive::new(file).ok()?;
+    let mut extensions: HashMap<String, usize> = HashMap::new();
+    for i in 0..archive.len() {
+        if let Ok(entry) = archive.by_index(i)
+            && let Some(ext) = Path::new(entry.name()).extension()
+        {
+            *extensions.entry(ext.to_string_lossy().to_lowercase()).or_insert(0) += 1;
+        }
+    }
+    let mut sorted: Vec<_> = extensions.into_iter().collect();
+    sorted.sort_by_key(|e| std::cmp::Reverse(e.1));
+    Some(Sample { archive: path.to_path_buf(), extensions: sorted })
+}
+
+fn archive_size(path: &Path) -> u64 {
+    if path.is_dir() {
+        FileTree::scan(path).map_or(0, |tree| tree.size())
+    } else {
+        fs::metadata(path).map_or(0, |m| m.len())
+    }
+}
+
+/// Discovers the archives below `source_dir` and samples the first one.
+pub fn analyze(source_dir: &Path) -> io::Result<Analysis> {
+    let zips = find_archives(source_dir)?;
+    let sizes: Vec<u64> = zips.iter().map(|z| archive_size(z)).collect();
+    let total_size = sizes.iter().sum();
+    let sample = zips.first().and_then(|first| sample_archive(first));
+    let catalog = Catalog::find(source_dir);
+    let runs = summarize_runs(&zips, &sizes, catalog.as_ref());
+    Ok(Analysis { source: source_dir.to_path_buf(), zips, total_size, sample, catalog, runs })
+}
+
+/// Count and uncompressed size of a group of entries, such as those
+/// sharing one extension.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExtensionStats {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Files of a backup with the same content as another file at a different
+/// path, as found by [`DeepStats::duplicates`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Duplicates {
+    /// Contents held by more than one file.
+    pub groups: u64,
+    /// Files beyond the first of each group.
+    pub files: u64,
+    /// Their size: the space restoring them as hard links or reflinks to
+    /// the first one would save.
+    pub bytes: u64,
+}
+
+/// The files seen with one content.
+#[derive(Debug, Clone, Copy)]
+struct Copies {
+    /// Hash of the path of the first one. The same path again, from a
+    /// later backup run, is overwritten on restore and not a copy.
+    path: u64,
+    count: u64,
+}
+
+/// Bytes of the two headers of a ZIP entry besides its name.
+const ENTRY_HEADERS: u64 = 30 + 46;
+
+/// Files smaller than a filesystem block save next to nothing as links.
+const MIN_DUPLICATE_SIZE: u64 = 4096;
+
+/// An Outlook data file found in the backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MailArchive {
+    /// Restore path, e.g. `Users/bob/Documents/Outlook Files/archive.pst`.
+    pub path: String,
+    pub size: u64,
+    /// The Windows user whose profile holds the file, if it is in one.
+    pub owner: Option<String>,
+}
+
+/// Totals gathered from the central directories of a set of archives.
+///
+/// Computed per archive and merged, so the same type describes one archive
+/// or the whole backup set.
+#[derive(Debug, Clone, Default)]
+pub struct DeepStats {
+    pub archives: usize,
+    pub files: u64,
+    pub dirs: u64,
+    pub uncompressed_bytes: u64,
+    pub compressed_bytes: u64,
+    /// Cloud-only placeholders among the files, see [`EntryInfo::placeholder`].
+    pub placeholders: u64,
+    /// Registry hives, page files and `Windows/System32` content.
+    pub system_files: u64,
+    /// AppleDouble files of macOS, `._name` and `__MACOSX/` content.
+    pub apple_double: u64,
+    /// Files encrypted with EFS, see [`EntryInfo::efs`].
+    pub efs: u64,
+    /// Outlook `.pst` and `.ost` files, sorted by path.
+    pub mail: Vec<MailArchive>,
+    pub extensions: HashMap<String, ExtensionStats>,
+    /// Files by year and month modified.
+    pub months: BTreeMap<(u16, u8), ExtensionStats>,
+    /// Files without a modification time, or with the DOS epoch
+    /// archivers write for none.
+    pub undated: u64,
+    pub errors: Vec<ArchiveError>,
+    /// Files by CRC-32 and size, for [`DeepStats::duplicates`].
+    contents: HashMap<(u32, u64), Copies>,
+}
+
+impl DeepStats {
+    pub fn add_entry(&mut self, entry: &EntryInfo) {
+        if entry.is_dir {
+            self.dirs += 1;
+            return;
+        }
+        self.files += 1;
+        self.uncompressed_bytes += entry.size;
+        self.compressed_bytes += entry.compressed_size;
+        if entry.placeholder {
+            self.placeholders += 1;
+        }
+        if is_system_file(entry.path()) {
+            self.system_files += 1;
+        }
+        if is_apple_double(entry.path()) {
+            self.apple_double += 1;
+        }
+        if entry.efs {
+            self.efs += 1;
+        }
+        if mail::is_mail_archive(entry.path()) {
+            self.mail.push(MailArchive {
+                path: entry.path().to_string(),
+                size: entry.size,
+                owner: profile_owner(entry.path()).map(String::from),
+            });
+        }
+        let ext = Path::new(entry.path())
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let stats = self.extensions.entry(ext).or_default();
+        stats.files += 1;
+        stats.bytes += entry.size;
+        match entry.modified.filter(|t| *t != zip::DateTime::default()) {
+            Some(t) => {
+                let stats = self.months.entry((t.year(), t.month())).or_default();
+                stats.files += 1;
+                stats.bytes += entry.size;
+            }
+            None => self.undated += 1,
+        }
+        // Only ZIP files record a CRC-32; the other formats leave it 0.
+        if entry.size >= MIN_DUPLICATE_SIZE && entry.crc32 != 0 {
+            let mut hasher = DefaultHasher::new();
+            entry.path().hash(&mut hasher);
+            let path = hasher.finish();
+            let copies = self.contents.entry((entry.crc32, entry.size)).or_insert(Copies { path, count: 0 });
+            if copies.count == 0 || copies.path != path {
+                copies.count += 1;
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: DeepStats) {
+        self.archives += other.archives;
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.uncompressed_bytes += other.uncompressed_bytes;
+        self.compressed_bytes += other.compressed_bytes;
+        self.placeholders += other.placeholders;
+        self.system_files += other.system_files;
+        self.apple_double += other.apple_double;
+        self.efs += other.efs;
+        self.mail.extend(other.mail);
+        for (ext, stats) in other.extensions {
+            let total = self.extensions.entry(ext).or_default();
+            total.files += stats.files;
+            total.bytes += stats.bytes;
+        }
+        for (month, stats) in other.months {
+            let total = self.months.entry(month).or_default();
+            total.files += stats.files;
+            total.bytes += stats.bytes;
+        }
+        self.undated += other.undated;
+        self.errors.extend(other.errors);
+        for (content, copies) in other.contents {
+            let total = self.contents.entry(content).or_insert(Copies { path: copies.path, count: 0 });
+            total.count += copies.count - u64::from(total.count > 0 && total.path == copies.path);
+        }
+    }
+
+    /// Estimates the duplicated content of the backup from files of the
+    /// same CRC-32 and size at different paths. Files under 4 KiB and those
+    /// of archives without CRCs, all but ZIP, are left out.
+    pub fn duplicates(&self) -> Duplicates {
+        let mut duplicates = Duplicates::default();
+        for (&(_, size), copies) in &self.contents {
+            if copies.count > 1 {
+                duplicates.groups += 1;
+                duplicates.files += copies.count - 1;
+                duplicates.bytes += (copies.count - 1) * size;
+            }
+        }
+        duplicates
+    }
+
+    /// Extensions ordered by file count, most frequent first.
+    pub fn top_extensions(&self) -> Vec<(&str, ExtensionStats)> {
+        let mut sorted: Vec<_> = self.extensions.iter().map(|(ext, stats)| (ext.as_str(), *stats)).collect();
+        sorted.sort_by(|a, b| b.1.files.cmp(&a.1.files).then_with(|| a.0.cmp(b.0)));
+        sorted
+    }
+
+    /// Files by year modified, oldest first.
+    pub fn years(&self) -> Vec<(u16, ExtensionStats)> {
+        let mut years: Vec<(u16, ExtensionStats)> = Vec::new();
+        for (&(year, _), stats) in &self.months {
+            match years.last_mut() {
+                Some((last, total)) if *last == year => {
+                    total.files += stats.files;
+                    total.bytes += stats.bytes;
+                }
+                _ => years.push((year, *stats)),
+            }
+        }
+        years
+    }
+
+    /// The runs of months, from first to last as `(year, month)`, in
+    /// which no file was modified, between the oldest and newest file.
+    /// Long ones in a backup meant to be complete hint at missing archives.
+    pub fn gaps(&self) -> Vec<((u16, u8), (u16, u8))> {
+        let next = |(year, month): (u16, u8)| if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+        let previous = |(year, month): (u16, u8)| if month == 1 { (year - 1, 12) } else { (year, month - 1) };
+        let mut gaps = Vec::new();
+        let mut months = self.months.keys().copied();
+        let Some(mut last) = months.next() else { return gaps };
+        for month in months {
+            if month != next(last) {
+                gaps.push((next(last), previous(month)));
+            }
+            last = month;
+        }
+        gaps
+    }
+
+    /// Reads the central directory of one archive of `set`.
+    pub fn of_archive(set: &BackupSet, path: &PathBuf) -> DeepStats {
+        DeepStats::of_entries(set, path, None).0
+    }
+
+    /// Reads the first `limit` entries of one archive of `set`, or all of
+    /// them. If there were more, also returns about how many bytes of the
+    /// archive those read take: their data, and in a ZIP file their local
+    /// header and central directory record, each with the name.
+    fn of_entries(set: &BackupSet, path: &PathBuf, limit: Option<usize>) -> (DeepStats, Option<u64>) {
+        let mut stats = DeepStats { archives: 1, ..DeepStats::default() };
+        let mut covered = 0;
+        for (read, entry) in set.archive_entries(path).enumerate() {
+            if limit.is_some_and(|limit| read == limit) {
+                return (stats, Some(covered));
+            }
+            match entry {
+                Ok(entry) => {
+                    covered += entry.compressed_size + ENTRY_HEADERS + 2 * entry.path().len() as u64;
+                    stats.add_entry(&entry);
+                }
+                Err(e) => stats.errors.push(e),
+            }
+        }
+        (stats, None)
+    }
+
+    /// Sorts the mail archives and errors merged from several archives.
+    fn sort(&mut self) {
+        self.mail.sort_by(|a, b| cmp_natural(&a.path, &b.path));
+        self.errors.sort_by(|a, b| cmp_natural(&a.archive.to_string_lossy(), &b.archive.to_string_lossy()));
+    }
+
+    /// These totals times `factor`, for the whole of a backup set of which
+    /// they are a sample. Mail archives and errors stay those found; the
+    /// duplicates are dropped, as copies in the archives not read are not
+    /// known.
+    fn scaled(&self, factor: f64) -> DeepStats {
+        let scale = |n: u64| (n as f64 * factor).round() as u64;
+        let scale_stats = |stats: &ExtensionStats| ExtensionStats { files: scale(stats.files), bytes: scale(stats.bytes) };
+        DeepStats {
+            archives: self.archives,
+            files: scale(self.files),
+            dirs: scale(self.dirs),
+            uncompressed_bytes: scale(self.uncompressed_bytes),
+            compressed_bytes: scale(self.compressed_bytes),
+            placeholders: scale(self.placeholders),
+            system_files: scale(self.system_files),
+            apple_double: scale(self.apple_double),
+            efs: scale(self.efs),
+            mail: self.mail.clone(),
+            extensions: self.extensions.iter().map(|(ext, stats)| (ext.clone(), scale_stats(stats))).collect(),
+            months: self.months.iter().map(|(&month, stats)| (month, scale_stats(stats))).collect(),
+            undated: scale(self.undated),
+            errors: self.errors.clone(),
+            contents: HashMap::new(),
+        }
+    }
+}
+
+/// A total of a backup set estimated from a sample, by [`SampledStats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Estimate {
+    pub value: u64,
+    /// Half the width of its 95% confidence interval; `None` when the
+    /// sample is a single archive, which tells nothing of the others.
+    pub margin: Option<u64>,
+}
+
+/// What [`sample_analyze`] read of one archive.
+#[derive(Debug, Clone, Copy)]
+struct Unit {
+    /// Bytes of the archive the entries read take on disk.
+    covered: u64,
+    files: u64,
+    bytes: u64,
+}
+
+/// Totals of a sample of the archives of a backup set, and the estimates
+/// for the whole set drawn from them.
+///
+/// The estimates scale the sample by the size on disk of the set over
+/// that of the part read, which holds as long as the archives not read
+/// keep files of the same kind. Their margins follow from how much the
+/// archives read differ in files and bytes per byte on disk.
+#[derive(Debug, Clone, Default)]
+pub struct SampledStats {
+    /// The totals of the entries read, as read.
+    pub sample: DeepStats,
+    /// Archives in the set; `sample.archives` of them were read.
+    pub archives: usize,
+    /// Size on disk of the set.
+    pub total_size: u64,
+    /// Size on disk of the entries read.
+    pub covered_size: u64,
+    units: Vec<Unit>,
+}
+
+impl SampledStats {
+    /// Whether the sample is the whole set, so the estimates are exact.
+    pub fn is_complete(&self) -> bool {
+        self.covered_size >= self.total_size
+    }
+
+    /// How much larger the set is than the sample.
+    pub fn factor(&self) -> f64 {
+        if self.covered_size == 0 { 1.0 } else { self.total_size as f64 / self.covered_size as f64 }
+    }
+
+    /// The totals of the sample scaled up to the set, see
+    /// [`DeepStats::scaled`].
+    pub fn estimated(&self) -> DeepStats {
+        DeepStats { archives: self.archives, ..self.sample.scaled(self.factor()) }
+    }
+
+    /// Estimated number of files in the set.
+    pub fn files(&self) -> Estimate {
+        self.estimate(self.sample.files, |unit| unit.files)
+    }
+
+    /// Estimated uncompressed size of the set.
+    pub fn bytes(&self) -> Estimate {
+        self.estimate(self.sample.uncompressed_bytes, |unit| unit.bytes)
+    }
+
+    /// Ratio estimate of a total from its sample total `sampled` and its
+    /// value per archive read, with the margin of the usual approximation
+    /// of its variance.
+    fn estimate(&self, sampled: u64, value: impl Fn(&Unit) -> u64) -> Estimate {
+        let factor = self.factor();
+        let estimate = (sampled as f64 * factor).round() as u64;
+        if self.is_complete() {
+            return Estimate { value: estimate, margin: Some(0) };
+        }
+        let n = self.units.len();
+        if n < 2 || self.covered_size == 0 {
+            return Estimate { value: estimate, margin: None };
+        }
+        let ratio = sampled as f64 / self.covered_size as f64;
+        let residuals: f64 = self.units.iter().map(|unit| (value(unit) as f64 - ratio * unit.covered as f64).powi(2)).sum();
+        let variance = residuals / (n - 1) as f64;
+        let read = self.covered_size as f64 / self.total_size as f64;
+        let error = factor * ((1.0 - read) * variance * n as f64).sqrt();
+        Estimate { value: estimate, margin: Some((1.96 * error).round() as u64) }
+    }
+}
+
+/// Reads the central directories of all archives in `set` with `threads`
+/// workers and merges the results. Workers send per-archive stats over a
+/// channel; the calling thread aggregates them, so memory use does not grow
+/// with the worker count.
+pub fn deep_analyze(set: &BackupSet, threads: usize) -> DeepStats {
+    let mut total = DeepStats::default();
+    read_each(set.archives(), threads, |path| DeepStats::of_archive(set, path), |stats| total.merge(stats));
+    total.sort();
+    total
+}
+
+/// Like [`deep_analyze`], but reads only `archives` of the archives of
+/// `set`, spread evenly over it so every backup run has its share, and
+/// only the first `entries` entries of each; `None` reads all. A sample of
+/// a few dozen archives estimates the totals of a set of thousands in a
+/// fraction of the time; over a slow link, reading every central directory
+/// takes hours.
+pub fn sample_analyze(set: &BackupSet, threads: usize, archives: Option<usize>, entries: Option<usize>) -> SampledStats {
+    let zips = set.archives();
+    let n = archives.map_or(zips.len(), |archives| archives.max(1)).min(zips.len());
+    let chosen: Vec<PathBuf> = (0..n).map(|i| zips[i * zips.len() / n].clone()).collect();
+    let mut sampled = SampledStats {
+        archives: zips.len(),
+        total_size: zips.iter().map(|zip| archive_size(zip)).sum(),
+        ..SampledStats::default()
+    };
+    let read = |path: &PathBuf| {
+        let (stats, covered) = DeepStats::of_entries(set, path, entries);
+        (stats, covered.unwrap_or_else(|| archive_size(path)))
+    };
+    read_each(&chosen, threads, read, |(stats, covered)| {
+        sampled.units.push(Unit { covered, files: stats.files, bytes: stats.uncompressed_bytes });
+        sampled.covered_size += covered;
+        sampled.sample.merge(stats);
+    });
+    sampled.sample.sort();
+    sampled
+}
+
+/// Runs `read` on each of `zips` with `threads` workers and hands the
+/// results to `each` on the calling thread, so memory use does not grow
+/// with the worker count.
+pub(crate) fn read_each<T: Send>(zips: &[PathBuf], threads: usize, read: impl Fn(&PathBuf) -> T + Sync, mut each: impl FnMut(T)) {
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::sync_channel::<T>(threads.max(1));
+    std::thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, zips.len().max(1)) {
+            let tx = tx.clone();
+            let (next, read) = (&next, &read);
+            scope.spawn(move || {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = zips.get(i) else { break };
+                    if tx.send(read(path)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+        for result in rx {
+            each(result);
+        }
+    });
+}
@@ -0,0 +1,112 @@
+//! Answers files, for [`crate::RestorerBuilder::answers`]: decisions on
+//! conflicts and errors by path pattern, so a restore whose conflicts were
+//! answered once, at a terminal with `--on-conflict ask`, can run again
+//! unattended with exactly those choices.
+//!
+//! The file is TOML with `[[conflict]]` and `[[error]]` tables, each with a
+//! `path` glob of [`crate::Filter`] syntax and an `answer`:
+//!
+//! ```toml
+//! [[conflict]]
+//! path = "Users/bob/Documents/thesis.docx"
+//! answer = "keep"      # or "replace", "rename"
+//!
+//! [[conflict]]
+//! path = "*.jpg"
+//! answer = "rename"
+//!
+//! [[error]]
+//! path = "**/*.pst"
+//! answer = "stop"      # or "continue"
+//! ```
+//!
+//! The first rule matching a path decides for it; paths no rule matches are
+//! left to the conflict policy and `fail_fast`.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::conflict::Resolution;
+use crate::filter::{escape, glob_matches_path};
+use crate::paths::strip_drive_letter;
+
+/// What to do after an error of a file, see [`Answers`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnError {
+    /// Go on with the other files, also with `fail_fast`.
+    Continue,
+    /// Stop the restore, as `fail_fast` does.
+    Stop,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Rule<A> {
+    path: String,
+    answer: A,
+}
+
+/// The rules of an answers file, see the [module documentation](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Answers {
+    #[serde(default, rename = "conflict", skip_serializing_if = "Vec::is_empty")]
+    conflicts: Vec<Rule<Resolution>>,
+    #[serde(default, rename = "error", skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<Rule<OnError>>,
+}
+
+impl Answers {
+    /// Reads the answers file at `path`; a missing one has no answers yet.
+    pub fn load(path: &Path) -> io::Result<Answers> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Answers::default()),
+            Err(e) => return Err(e),
+        };
+        toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// Writes the answers to `path`, replacing the file.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = toml::to_string(self).map_err(io::Error::other)?;
+        fs::write(path, format!("# Answers of restore, replayed with --answers.\n\n{}", text))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conflicts.is_empty() && self.errors.is_empty()
+    }
+
+    /// The answer to a conflict of the file at restore path `path`.
+    pub fn conflict(&self, path: &str) -> Option<Resolution> {
+        first_match(&self.conflicts, path)
+    }
+
+    /// What to do after an error of the entry `path`.
+    pub fn on_error(&self, path: &str) -> Option<OnError> {
+        first_match(&self.errors, path)
+    }
+
+    /// Adds `answer` for the conflicts of the files matching `pattern`,
+    /// after the rules there are.
+    pub fn add_conflict(&mut self, pattern: impl Into<String>, answer: Resolution) {
+        self.conflicts.push(Rule { path: pattern.into(), answer });
+    }
+
+    pub fn add_error(&mut self, pattern: impl Into<String>, answer: OnError) {
+        self.errors.push(Rule { path: pattern.into(), answer });
+    }
+
+    /// A pattern matching only the file at restore path `path`; the `/` in
+    /// front keeps it from matching file names in any folder.
+    pub fn exact(path: &str) -> String {
+        format!("/{}", escape(path))
+    }
+}
+
+fn first_match<A: Copy>(rules: &[Rule<A>], path: &str) -> Option<A> {
+    let path = strip_drive_letter(path);
+    rules.iter().find(|rule| glob_matches_path(&rule.path, path)).map(|rule| rule.answer)
+}
@@ -0,0 +1,14 @@
+//! The metadata macOS leaves in ZIP files it creates or unpacks and packs
+//! again: AppleDouble files, `._name` next to the file `name` or below a
+//! `__MACOSX` folder, which hold its resource fork and Finder info. Copied
+//! from a Mac onto the Windows machine that was backed up, they are of no
+//! use there or anywhere else but a Mac, and restored as is only litter the
+//! destination with `._` files.
+
+/// Whether `path`, a restore path such as `Photos/__MACOSX/._IMG_0001.jpg`,
+/// is AppleDouble metadata.
+pub(crate) fn is_apple_double(path: &str) -> bool {
+    let mut components = path.split('/');
+    let name = components.next_back().unwrap_or_default();
+    name.starts_with("._") || components.any(|component| component == "__MACOSX")
+}
@@ -0,0 +1,123 @@
+//! The kinds of archives found in a backup folder, and the interface of the
+//! ones that are not ZIP files.
+//!
+//! ZIP archives are read through the zip crate and the pipeline's own
+//! decoders. Every other source implements [`ArchiveReader`]: a list of
+//! entries and a way to read one, which is all extraction, counting and
+//! listing need.
+
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::catalog::BackupTime;
+use crate::file_history::{FileTree, is_file_history_data};
+use crate::format::{self, FORMATS};
+
+/// What kind of archive a path is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArchiveKind {
+    Zip,
+    /// `.tar`, optionally compressed: `.tar.gz`/`.tgz`, `.tar.bz2`/`.tbz2`,
+    /// `.tar.zst`/`.tzst`.
+    Tar,
+    /// A File History `Data` folder.
+    FileHistory,
+    /// A `.vhd`/`.vhdx` system image (feature `vhd`).
+    DiskImage,
+    /// An NTBackup `.bkf` file (feature `bkf`).
+    Bkf,
+    /// A `.7z` archive (feature `sevenz`).
+    SevenZip,
+    /// A `.rar` archive, or the first volume of a multi-volume one (feature
+    /// `rar`).
+    Rar,
+    /// An `.iso` disc image, ISO 9660 or UDF (feature `iso`).
+    Iso,
+}
+
+impl ArchiveKind {
+    /// The kind of archive at `path`, or `None` if it is nothing this build
+    /// can restore. Files are recognized by their content, see
+    /// [`crate::format`], and by their name if that does not tell.
+    pub fn of(path: &Path) -> Option<ArchiveKind> {
+        if is_file_history_data(path) {
+            return Some(ArchiveKind::FileHistory);
+        }
+        format::detect(path).map(|format| format.kind)
+    }
+
+    /// How the kind is called in summaries, e.g. `ZIP files`.
+    pub fn label(self) -> &'static str {
+        match self {
+            ArchiveKind::Zip => "ZIP files",
+            ArchiveKind::Tar => "tarballs",
+            ArchiveKind::FileHistory => "File History Data folders",
+            ArchiveKind::DiskImage => "VHD/VHDX system images",
+            ArchiveKind::Bkf => "NTBackup BKF files",
+            ArchiveKind::SevenZip => "7-Zip archives",
+            ArchiveKind::Rar => "RAR archives",
+            ArchiveKind::Iso => "ISO/UDF disc images",
+        }
+    }
+}
+
+/// Metadata of one entry of an [`ArchiveReader`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Member<'a> {
+    /// Name with `/` separators, as restored below the destination.
+    pub(crate) name: &'a str,
+    pub(crate) size: u64,
+    pub(crate) modified: Option<BackupTime>,
+    /// Windows file attributes, if the format records them.
+    pub(crate) attributes: Option<u32>,
+}
+
+/// A source of restorable files other than a ZIP archive.
+///
+/// Entries are read as plain file data: whatever decompression or checksum
+/// the format has is handled by the reader, so the pipeline hands them to
+/// the writers without decoding.
+pub(crate) trait ArchiveReader: Send + Sync {
+    fn len(&self) -> usize;
+
+    fn member(&self, j: usize) -> Member<'_>;
+
+    /// Calls `f` with a reader over the data of entry `j`. An error is
+    /// returned, and `f` not called, if the entry cannot be opened.
+    fn read(&self, j: usize, f: &mut dyn FnMut(&mut dyn Read)) -> io::Result<()>;
+
+    /// Whether the entries can only be read in order, like the files of a
+    /// compressed tarball. Such archives are read by one reader, in archive
+    /// order, through [`ArchiveReader::read_in_order`].
+    fn sequential(&self) -> bool {
+        false
+    }
+
+    /// Calls `f` with every entry in archive order, or with the error that
+    /// keeps it from being read, until `f` returns false. Data `f` leaves
+    /// unread is skipped.
+    fn read_in_order(&self, f: &mut dyn FnMut(usize, io::Result<&mut dyn Read>) -> bool) {
+        for j in 0..self.len() {
+            let mut go = true;
+            if let Err(e) = self.read(j, &mut |data| go = f(j, Ok(data))) {
+                go = f(j, Err(e));
+            }
+            if !go {
+                return;
+            }
+        }
+    }
+}
+
+/// Opens the archive at `path`, which is of a kind other than ZIP.
+pub(crate) fn open(kind: ArchiveKind, path: &Path, mmap: bool) -> io::Result<Arc<dyn ArchiveReader>> {
+    match kind {
+        ArchiveKind::Zip => Err(io::Error::new(io::ErrorKind::InvalidInput, "ZIP archives have no ArchiveReader")),
+        ArchiveKind::FileHistory => Ok(Arc::new(FileTree::scan(path)?)),
+        kind => match FORMATS.iter().find(|format| format.kind == kind).and_then(|format| format.open) {
+            Some(open) => open(path, mmap),
+            None => Err(io::Error::new(io::ErrorKind::Unsupported, "restore was built without support for this archive format")),
+        },
+    }
+}
@@ -0,0 +1,189 @@
+//! An append-only audit log of every decision a restore takes, one JSON
+//! object per line. Each line holds the SHA-256 of the line before it in
+//! `prev`, so a line changed, removed or inserted later breaks the chain
+//! from there on; [`verify_audit_log`] finds where.
+//!
+//! Runs add to an existing log and continue its chain. Removing lines from
+//! the end leaves a valid chain; a signature of the log made after the run
+//! covers that too.
+
+use std::borrow::Cow;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// What became of an entry, or of an archive for [`Action::Error`]s of
+/// archives that could not be opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Action {
+    Extracted,
+    /// Extracted over an existing file.
+    Overwritten,
+    /// Extracted under a free name next to an existing file.
+    Renamed,
+    /// Hard-linked to the unchanged file of the previous snapshot.
+    Linked,
+    Skipped,
+    Quarantined,
+    Error,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    time: String,
+    action: Action,
+    archive: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entry: Option<&'a str>,
+    /// Where the entry was written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<&'a Path>,
+    /// Why an entry was skipped, or the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+    prev: &'a str,
+}
+
+/// Hex SHA-256 of the line before the first one.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Appends records to an audit log. The file is locked while a record is
+/// added, so restores writing the same log at once, such as the archives
+/// of an async run, keep one chain.
+pub(crate) struct AuditLog {
+    state: Mutex<Chain>,
+}
+
+struct Chain {
+    file: File,
+    /// The hash of the last line and the length of the file after it, to
+    /// not read it back while no one else writes the log.
+    last: String,
+    len: u64,
+}
+
+impl AuditLog {
+    /// Opens the log at `path` for adding records, creating it if needed.
+    pub(crate) fn append(path: &Path) -> io::Result<AuditLog> {
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        file.lock()?;
+        let last = last_line_hash(&mut file);
+        let len = file.seek(SeekFrom::End(0));
+        file.unlock()?;
+        Ok(AuditLog { state: Mutex::new(Chain { file, last: last?, len: len? }) })
+    }
+
+    pub(crate) fn record(
+        &self,
+        action: Action,
+        archive: &Path,
+        entry: Option<&str>,
+        target: Option<&Path>,
+        reason: Option<&str>,
+    ) -> io::Result<()> {
+        let mut chain = self.state.lock().unwrap();
+        let Chain { file, last, len } = &mut *chain;
+        file.lock()?;
+        let result = (|| {
+            if file.seek(SeekFrom::End(0))? != *len {
+                *last = last_line_hash(file)?;
+            }
+            let time = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+            let record = Record { time, action, archive, entry, target, reason, prev: last };
+            let mut line = serde_json::to_vec(&record)?;
+            let hash = hex(&Sha256::digest(&line));
+            line.push(b'\n');
+            file.write_all(&line)?;
+            *last = hash;
+            *len = file.seek(SeekFrom::End(0))?;
+            Ok(())
+        })();
+        file.unlock()?;
+        result
+    }
+}
+
+/// The outcome of [`verify_audit_log`].
+#[derive(Debug, Default)]
+pub struct AuditCheck {
+    /// Lines of the log.
+    pub records: usize,
+    /// Number of the first line that does not follow from the one before,
+    /// or could not be read as a record.
+    pub broken: Option<usize>,
+}
+
+impl AuditCheck {
+    /// Whether the whole chain holds.
+    pub fn is_intact(&self) -> bool {
+        self.broken.is_none()
+    }
+}
+
+/// Follows the hash chain of the audit log at `path`. Fails only if the log
+/// cannot be read.
+pub fn verify_audit_log(path: &Path) -> io::Result<AuditCheck> {
+    #[derive(Deserialize)]
+    struct Link<'a> {
+        #[serde(borrow)]
+        prev: Cow<'a, str>,
+    }
+
+    let mut check = AuditCheck::default();
+    let mut last = GENESIS.to_string();
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        check.records += 1;
+        let follows = serde_json::from_str::<Link>(&line).is_ok_and(|link| link.prev == last);
+        if !follows && check.broken.is_none() {
+            check.broken = Some(i + 1);
+        }
+        last = hex(&Sha256::digest(line.as_bytes()));
+    }
+    Ok(check)
+}
+
+/// The hash of the last line of `file`, read back from its end.
+fn last_line_hash(file: &mut File) -> io::Result<String> {
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut end = len;
+    if len > 0 {
+        // Without the newline that ends it.
+        let mut byte = [0];
+        file.seek(SeekFrom::Start(len - 1))?;
+        file.read_exact(&mut byte)?;
+        if byte[0] == b'\n' {
+            end -= 1;
+        }
+    }
+    let mut start = end;
+    let mut buf = [0; 4096];
+    while start > 0 {
+        let from = start.saturating_sub(buf.len() as u64);
+        let chunk = &mut buf[..(start - from) as usize];
+        file.seek(SeekFrom::Start(from))?;
+        file.read_exact(chunk)?;
+        if let Some(newline) = chunk.iter().rposition(|&b| b == b'\n') {
+            start = from + newline as u64 + 1;
+            break;
+        }
+        start = from;
+    }
+    if start >= end {
+        return Ok(GENESIS.to_string());
+    }
+    let mut line = vec![0; (end - start) as usize];
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut line)?;
+    Ok(hex(&Sha256::digest(&line)))
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,246 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::analysis_cache::{AnalysisCache, CacheWriter, CachedEntries};
+use crate::archive::{self, ArchiveKind, ArchiveReader, Member};
+use crate::central_dir::{CentralDirectory, RawEntry};
+use crate::discovery::find_archives;
+use crate::error::ArchiveError;
+use crate::paths::strip_drive_letter;
+use crate::efs;
+use crate::placeholder::is_cloud_placeholder;
+use crate::reader::ArchiveFile;
+
+/// Metadata of one archive entry, read from the central directory. Other
+/// archive kinds have none: there the ZIP-specific fields are zero and the
+/// rest comes from the format's own metadata, e.g. the file system and the
+/// version suffix of the file name for File History folders.
+#[derive(Debug, Clone)]
+pub struct EntryInfo {
+    /// The archive containing the entry.
+    pub archive: Arc<Path>,
+    /// Position of the entry in the archive's central directory.
+    pub index: usize,
+    /// Entry name as stored, with `\` replaced by `/`.
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: u64,
+    pub modified: Option<zip::DateTime>,
+    pub crc32: u32,
+    /// ZIP compression method id, e.g. 0 (stored) or 8 (deflate).
+    pub method: u16,
+    pub encrypted: bool,
+    pub is_dir: bool,
+    /// Upper byte: host system that created the entry (0 = DOS/Windows, 3 = Unix).
+    pub version_made_by: u16,
+    /// External file attributes; the low 16 bits hold the DOS/Windows
+    /// attributes, the high 16 bits the Unix mode for Unix-made archives.
+    pub attributes: u32,
+    /// Raw extra field blocks from the central directory.
+    pub extra: Vec<u8>,
+    /// Whether the entry is a cloud-only placeholder, such as a OneDrive
+    /// file that was online only: the backup holds no data for it.
+    pub placeholder: bool,
+    /// Whether the entry was encrypted with EFS on the backed up machine,
+    /// as recorded in its attributes.
+    pub efs: bool,
+}
+
+impl EntryInfo {
+    fn from_raw(archive: &Arc<Path>, index: usize, raw: RawEntry) -> Self {
+        let name = raw.name.replace('\\', "/");
+        let placeholder = raw.is_cloud_placeholder();
+        let efs = efs::is_encrypted(raw.windows_attributes());
+        EntryInfo {
+            archive: Arc::clone(archive),
+            index,
+            is_dir: name.ends_with('/'),
+            name,
+            size: raw.size,
+            compressed_size: raw.compressed_size,
+            modified: zip::DateTime::try_from_msdos(raw.dos_date, raw.dos_time).ok(),
+            crc32: raw.crc32,
+            method: raw.method,
+            encrypted: raw.flags & 1 != 0,
+            version_made_by: raw.version_made_by,
+            attributes: raw.external_attributes,
+            extra: raw.extra,
+            placeholder,
+            efs,
+        }
+    }
+
+    fn from_member(archive: &Arc<Path>, index: usize, member: Member<'_>) -> Self {
+        let modified = member.modified.and_then(|t| {
+            zip::DateTime::from_date_and_time(t.year, t.month, t.day, t.hour, t.minute, t.second).ok()
+        });
+        EntryInfo {
+            archive: Arc::clone(archive),
+            index,
+            name: member.name.to_string(),
+            size: member.size,
+            compressed_size: member.size,
+            modified,
+            crc32: 0,
+            method: 0,
+            encrypted: false,
+            is_dir: false,
+            version_made_by: 0,
+            attributes: 0,
+            extra: Vec::new(),
+            placeholder: is_cloud_placeholder(strip_drive_letter(member.name), member.size, member.attributes),
+            efs: efs::is_encrypted(member.attributes),
+        }
+    }
+
+    /// The restore path relative to the destination, without drive letter.
+    pub fn path(&self) -> &str {
+        strip_drive_letter(&self.name)
+    }
+}
+
+/// The archives of one backup folder, in extraction order.
+#[derive(Debug, Clone)]
+pub struct BackupSet {
+    source: PathBuf,
+    archives: Vec<PathBuf>,
+    mmap: bool,
+    cache: Option<AnalysisCache>,
+}
+
+impl BackupSet {
+    /// Finds all archives below `source`.
+    pub fn discover(source: impl Into<PathBuf>) -> io::Result<Self> {
+        let source = source.into();
+        let archives = find_archives(&source)?;
+        Ok(Self::from_archives(source, archives))
+    }
+
+    pub fn from_archives(source: impl Into<PathBuf>, archives: Vec<PathBuf>) -> Self {
+        BackupSet { source: source.into(), archives, mmap: false, cache: None }
+    }
+
+    /// Reads central directories through a memory map, see
+    /// [`crate::RestorerBuilder::mmap`].
+    pub fn with_mmap(mut self, mmap: bool) -> Self {
+        self.mmap = mmap;
+        self
+    }
+
+    /// Reads the entries of unchanged archives from `cache` instead of
+    /// their central directories, and caches those of the others once
+    /// read to the end. Default: none.
+    pub fn with_cache(mut self, cache: Option<AnalysisCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+
+    pub fn archives(&self) -> &[PathBuf] {
+        &self.archives
+    }
+
+    pub(crate) fn mmap(&self) -> bool {
+        self.mmap
+    }
+
+    /// Iterates over every entry of every archive, streaming one central
+    /// directory at a time. An archive that cannot be read yields one error
+    /// and iteration continues with the next archive.
+    pub fn entries(&self) -> Entries<'_> {
+        Entries { archives: self.archives.iter(), mmap: self.mmap, cache: self.cache.clone(), current: None }
+    }
+
+    /// Like [`entries`](Self::entries), restricted to one archive.
+    pub fn archive_entries<'a>(&self, archive: &'a PathBuf) -> Entries<'a> {
+        Entries { archives: std::slice::from_ref(archive).iter(), mmap: self.mmap, cache: self.cache.clone(), current: None }
+    }
+}
+
+enum Records {
+    Zip(CentralDirectory<ArchiveFile>),
+    Reader(Arc<dyn ArchiveReader>),
+    Cached(CachedEntries),
+}
+
+struct OpenArchive {
+    path: Arc<Path>,
+    records: Records,
+    index: usize,
+    /// Caches the entries as they are read, unless they come from the
+    /// cache.
+    writer: Option<CacheWriter>,
+}
+
+/// Iterator returned by [`BackupSet::entries`].
+pub struct Entries<'a> {
+    archives: std::slice::Iter<'a, PathBuf>,
+    mmap: bool,
+    cache: Option<AnalysisCache>,
+    current: Option<OpenArchive>,
+}
+
+impl Iterator for Entries<'_> {
+    type Item = Result<EntryInfo, ArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(open) = &mut self.current {
+                let record = match &mut open.records {
+                    Records::Zip(records) => records.next().map(|r| r.map(|raw| EntryInfo::from_raw(&open.path, open.index, raw))),
+                    Records::Reader(reader) => (open.index < reader.len())
+                        .then(|| Ok(EntryInfo::from_member(&open.path, open.index, reader.member(open.index)))),
+                    Records::Cached(entries) => entries.next(&open.path, open.index),
+                };
+                match record {
+                    Some(Ok(entry)) => {
+                        open.index += 1;
+                        if let Some(writer) = &mut open.writer {
+                            writer.push(&entry);
+                        }
+                        return Some(Ok(entry));
+                    }
+                    Some(Err(e)) => {
+                        let path = open.path.to_path_buf();
+                        self.current = None;
+                        return Some(Err(ArchiveError::io(&path, e)));
+                    }
+                    None => {
+                        if let Some(writer) = open.writer.take() {
+                            writer.finish();
+                        }
+                        self.current = None;
+                    }
+                }
+            }
+            let path = self.archives.next()?;
+            let cached = self.cache.as_ref().and_then(|cache| cache.load(path));
+            let opened = match cached {
+                Some(entries) => Ok((Records::Cached(entries), None)),
+                None => open_records(path, self.mmap).map(|records| (records, self.cache.as_ref().and_then(|cache| cache.writer(path)))),
+            };
+            match opened {
+                Ok((records, writer)) => self.current = Some(OpenArchive { path: Arc::from(path.as_path()), records, index: 0, writer }),
+                Err(e) => return Some(Err(ArchiveError::io(path, e))),
+            }
+        }
+    }
+}
+
+fn open_records(path: &Path, mmap: bool) -> io::Result<Records> {
+    match ArchiveKind::of(path).unwrap_or(ArchiveKind::Zip) {
+        ArchiveKind::Zip => ArchiveFile::open(path, mmap).and_then(CentralDirectory::open).map(Records::Zip),
+        kind => archive::open(kind, path, mmap).map(Records::Reader),
+    }
+}
+
+/// Reads the entry metadata of one archive without decompressing anything.
+pub fn read_central_directory(path: &Path) -> Result<Vec<EntryInfo>, ArchiveError> {
+    let path = path.to_path_buf();
+    let set = BackupSet::from_archives(path.parent().unwrap_or(&path), Vec::new());
+    set.archive_entries(&path).collect()
+}
@@ -0,0 +1,151 @@
+//! Measuring how fast the archives read and the destination writes, for
+//! `restore estimate`. A few seconds of each, set against the totals of a
+//! run, tell whether it takes minutes or a day before it is started, and
+//! how many threads should read and write it.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::page_cache;
+use crate::restorer::Totals;
+
+/// Bytes read from the archives and written to the destination to measure
+/// their speed.
+const SAMPLE: u64 = 64 << 20;
+
+/// Small files written to measure what creating a file costs.
+const SMALL_FILES: u32 = 200;
+const SMALL_FILE: usize = 4096;
+
+/// Reads slower than this, in bytes per second, come from a disk that
+/// seeks, such as a USB or spinning disk, which parallel reads slow down.
+const SEEKING_READ: f64 = 150e6;
+/// Writes slower than this go to such a disk.
+const SEEKING_WRITE: f64 = 150e6;
+/// Files costing more than this to create beyond their data are on a
+/// network share or the like, whose latency more writers hide.
+const SLOW_CREATE: Duration = Duration::from_millis(2);
+
+/// The speeds [`Throughput::measure`] found.
+#[derive(Debug, Clone, Copy)]
+pub struct Throughput {
+    /// Bytes per second read from the archives.
+    pub read: f64,
+    /// Bytes per second written to the destination, flushed to the disk.
+    pub write: f64,
+    /// What creating, writing and closing a small file costs beyond its
+    /// data, which dominates restores of many small files.
+    pub per_file: Duration,
+}
+
+impl Throughput {
+    /// Reads up to 64 MiB from the start of `archives` and writes as much,
+    /// and 200 small files, into a scratch folder in `dest`, or in the
+    /// nearest folder above it that exists, which is removed again. On
+    /// Linux the archives are dropped from the page cache first, so they
+    /// are read from the disk.
+    pub fn measure(archives: &[PathBuf], dest: &Path) -> io::Result<Throughput> {
+        let read = measure_read(archives)?;
+        let existing = dest.ancestors().find(|dir| dir.is_dir()).unwrap_or(dest);
+        let scratch = existing.join(format!(".restore-estimate-{}", std::process::id()));
+        fs::create_dir(&scratch)?;
+        let written = measure_write(&scratch);
+        let _ = fs::remove_dir_all(&scratch);
+        let (write, per_file) = written?;
+        Ok(Throughput { read, write, per_file })
+    }
+
+    /// Number of threads to read the archives with: one from a disk that
+    /// seeks, otherwise one per CPU, at most 4.
+    pub fn read_threads(&self) -> usize {
+        if self.read < SEEKING_READ { 1 } else { cpus().min(4) }
+    }
+
+    /// Number of threads to write the restored files with: many for a
+    /// destination where creating a file is slow, two for a disk that
+    /// seeks, otherwise one per CPU, at most 8.
+    pub fn write_threads(&self) -> usize {
+        if self.per_file > SLOW_CREATE {
+            16
+        } else if self.write < SEEKING_WRITE {
+            2
+        } else {
+            cpus().min(8)
+        }
+    }
+
+    /// Time reading `archive_bytes` of archives takes.
+    pub fn read_time(&self, archive_bytes: u64) -> Duration {
+        Duration::from_secs_f64(archive_bytes as f64 / self.read)
+    }
+
+    /// Time writing the files of `totals` takes.
+    pub fn write_time(&self, totals: &Totals) -> Duration {
+        Duration::from_secs_f64(totals.bytes as f64 / self.write) + self.per_file * u32::try_from(totals.files).unwrap_or(u32::MAX)
+    }
+
+    /// Time restoring the files of `totals` from `archive_bytes` of
+    /// archives takes: reading and writing overlap, so the slower of the
+    /// two.
+    pub fn duration(&self, archive_bytes: u64, totals: &Totals) -> Duration {
+        self.read_time(archive_bytes).max(self.write_time(totals))
+    }
+}
+
+fn cpus() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+fn measure_read(archives: &[PathBuf]) -> io::Result<f64> {
+    let mut buf = vec![0u8; 1 << 20];
+    let mut read = 0u64;
+    let start = Instant::now();
+    for archive in archives.iter().filter(|archive| archive.is_file()) {
+        page_cache::drop_file(archive);
+        let mut file = page_cache::open_sequential(archive)?.take(SAMPLE - read);
+        loop {
+            match file.read(&mut buf)? {
+                0 => break,
+                n => read += n as u64,
+            }
+        }
+        if read >= SAMPLE {
+            break;
+        }
+    }
+    if read == 0 {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no archive to read"));
+    }
+    Ok(read as f64 / start.elapsed().as_secs_f64())
+}
+
+/// The write speed and cost per file of the folder `dir`.
+fn measure_write(dir: &Path) -> io::Result<(f64, Duration)> {
+    // Data that does not compress, for file systems that compress.
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    let chunk: Vec<u8> = (0..1 << 20)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+    let start = Instant::now();
+    let mut file = page_cache::create_sequential(&dir.join("large"))?;
+    for _ in 0..SAMPLE / chunk.len() as u64 {
+        file.write_all(&chunk)?;
+    }
+    file.sync_all()?;
+    let write = SAMPLE as f64 / start.elapsed().as_secs_f64();
+
+    let start = Instant::now();
+    for i in 0..SMALL_FILES {
+        File::create(dir.join(format!("small-{}", i)))?.write_all(&chunk[..SMALL_FILE])?;
+    }
+    let data = Duration::from_secs_f64(SMALL_FILE as f64 / write);
+    let per_file = (start.elapsed() / SMALL_FILES).saturating_sub(data);
+    Ok((write, per_file))
+}
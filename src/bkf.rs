@@ -0,0 +1,307 @@
+//! NTBackup `.bkf` files (feature `bkf`).
+//!
+//! Windows XP and Server 2003 backups are written in the Microsoft Tape
+//! Format: a sequence of descriptor blocks (`TAPE`, `SSET`, `VOLB`, `DIRB`,
+//! `FILE`, ...) each followed by its data streams. A file is read like one
+//! archive. When several backup sets in it contain the same file, the copy
+//! from the last set is restored.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::archive::{ArchiveReader, Member};
+use crate::catalog::BackupTime;
+use crate::reader::ArchiveFile;
+
+const HEADER_LEN: usize = 52;
+const STREAM_HEADER_LEN: u64 = 22;
+/// Format logical block size assumed until the `TAPE` block says otherwise.
+const DEFAULT_BLOCK_SIZE: u64 = 1024;
+
+/// Stream media attributes that this reader cannot undo.
+const STREAM_VARIABLE: u16 = 0x2;
+const STREAM_ENCRYPTED: u16 = 0x8;
+const STREAM_COMPRESSED: u16 = 0x10;
+
+fn u16_at(b: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([b[at], b[at + 1]])
+}
+
+fn u32_at(b: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(b[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(b: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(b[at..at + 8].try_into().unwrap())
+}
+
+/// Word-wise XOR over `b`, which is zero for a header including its checksum.
+fn xor_words(b: &[u8]) -> u16 {
+    b.chunks_exact(2).fold(0, |sum, w| sum ^ u16::from_le_bytes([w[0], w[1]]))
+}
+
+/// The 5-byte packed `MTF_DATE_TIME`: 14 bits year, then 4 bits month and
+/// 5, 5, 6, 6 bits for day, hour, minute and second.
+fn date_time(b: &[u8]) -> Option<BackupTime> {
+    let v = b.iter().take(5).fold(0u64, |v, &byte| v << 8 | u64::from(byte));
+    let field = |shift: u32, bits: u32| ((v >> shift) & ((1 << bits) - 1)) as u8;
+    let time = BackupTime {
+        year: (v >> 26) as u16,
+        month: field(22, 4),
+        day: field(17, 5),
+        hour: field(12, 5),
+        minute: field(6, 6),
+        second: field(0, 6),
+    };
+    (time.year > 0 && (1..=12).contains(&time.month) && (1..=31).contains(&time.day)).then_some(time)
+}
+
+/// One file of a `.bkf` archive.
+#[derive(Debug, Clone)]
+pub(crate) struct BkfEntry {
+    /// `C/path/name` with `/` separators, like the entry names of Windows
+    /// backup ZIPs; the drive letter is the one of the backed-up volume.
+    pub(crate) name: String,
+    /// Offset of the file's data in the archive.
+    pub(crate) offset: u64,
+    pub(crate) size: u64,
+    pub(crate) modified: Option<BackupTime>,
+    /// Why the data cannot be restored, e.g. software compression.
+    pub(crate) unsupported: Option<&'static str>,
+}
+
+/// The restorable files of a `.bkf` archive in the order of their data.
+pub(crate) struct BkfArchive {
+    file: ArchiveFile,
+    pub(crate) entries: Vec<BkfEntry>,
+}
+
+impl BkfArchive {
+    pub(crate) fn open(path: &Path, mmap: bool) -> io::Result<BkfArchive> {
+        let mut file = ArchiveFile::open(path, mmap)?;
+        let entries = Scanner::new(&mut file)?.scan()?;
+        Ok(BkfArchive { file, entries })
+    }
+}
+
+impl ArchiveReader for BkfArchive {
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn member(&self, j: usize) -> Member<'_> {
+        let entry = &self.entries[j];
+        Member { name: &entry.name, size: entry.size, modified: entry.modified, attributes: None }
+    }
+
+    fn read(&self, j: usize, f: &mut dyn FnMut(&mut dyn Read)) -> io::Result<()> {
+        let entry = &self.entries[j];
+        if let Some(reason) = entry.unsupported {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, reason));
+        }
+        let mut file = self.file.clone();
+        file.seek(SeekFrom::Start(entry.offset))?;
+        f(&mut file.take(entry.size));
+        Ok(())
+    }
+}
+
+/// Walks the descriptor blocks of an archive.
+struct Scanner<'a> {
+    file: &'a mut ArchiveFile,
+    len: u64,
+    block_size: u64,
+    /// Drive of the current `VOLB`, as `C/`.
+    volume: String,
+    /// Directory paths by directory ID, ending in `/` unless empty.
+    dirs: HashMap<u32, String>,
+    current_dir: String,
+}
+
+/// A descriptor block read at some offset.
+struct Block {
+    offset: u64,
+    kind: [u8; 4],
+    data: Vec<u8>,
+}
+
+impl Block {
+    /// The string a `MTF_TAPE_ADDRESS` at `at` points to.
+    fn string(&self, at: usize) -> String {
+        let (size, offset) = (usize::from(u16_at(&self.data, at)), usize::from(u16_at(&self.data, at + 2)));
+        let Some(raw) = self.data.get(offset..offset + size) else { return String::new() };
+        decode_string(raw, self.data[48])
+    }
+}
+
+fn decode_string(raw: &[u8], string_type: u8) -> String {
+    if string_type == 2 {
+        let units: Vec<u16> = raw.chunks_exact(2).map(|u| u16::from_le_bytes([u[0], u[1]])).collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        raw.iter().map(|&b| char::from(b)).collect()
+    }
+}
+
+/// `WINDOWS\0system32\0` as stored in `DIRB` blocks into `WINDOWS/system32/`.
+fn dir_path(raw: &str) -> String {
+    raw.split(['\0', '\\']).filter(|c| !c.is_empty()).map(|c| format!("{}/", c)).collect()
+}
+
+impl<'a> Scanner<'a> {
+    fn new(file: &'a mut ArchiveFile) -> io::Result<Self> {
+        let len = file.seek(SeekFrom::End(0))?;
+        Ok(Scanner {
+            file,
+            len,
+            block_size: DEFAULT_BLOCK_SIZE,
+            volume: String::new(),
+            dirs: HashMap::new(),
+            current_dir: String::new(),
+        })
+    }
+
+    fn read_at(&mut self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// The descriptor block at `offset`, if there is a valid one.
+    fn block_at(&mut self, offset: u64) -> io::Result<Option<Block>> {
+        if offset + HEADER_LEN as u64 > self.len {
+            return Ok(None);
+        }
+        let header = self.read_at(offset, HEADER_LEN)?;
+        let kind: [u8; 4] = header[..4].try_into().unwrap();
+        if !kind.iter().all(u8::is_ascii_uppercase) || xor_words(&header) != 0 {
+            return Ok(None);
+        }
+        // The fixed part plus the strings it points to lie before the first stream.
+        let first_stream = usize::from(u16_at(&header, 8)).max(HEADER_LEN);
+        let data = self.read_at(offset, first_stream.min((self.len - offset) as usize))?;
+        Ok(Some(Block { offset, kind, data }))
+    }
+
+    fn scan(mut self) -> io::Result<Vec<BkfEntry>> {
+        if self.block_at(0)?.is_none_or(|b| &b.kind != b"TAPE") {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not an MTF (NTBackup) file"));
+        }
+        let mut entries: Vec<BkfEntry> = Vec::new();
+        let mut by_name: HashMap<String, usize> = HashMap::new();
+        let mut offset = 0;
+        while offset < self.len {
+            let Some(block) = self.block_at(offset)? else {
+                // Filemarks and padding: resume at the next logical block.
+                offset = (offset / self.block_size + 1) * self.block_size;
+                continue;
+            };
+            let (next, entry) = self.read_block(&block)?;
+            if let Some(entry) = entry {
+                match by_name.get(&entry.name) {
+                    Some(&i) => entries[i] = entry,
+                    None => {
+                        by_name.insert(entry.name.clone(), entries.len());
+                        entries.push(entry);
+                    }
+                }
+            }
+            if &block.kind == b"EOTM" {
+                break;
+            }
+            offset = next.max(offset + 1);
+        }
+        Ok(entries)
+    }
+
+    /// Interprets `block` and walks its streams. Returns the offset after
+    /// them, and for `FILE` blocks the entry.
+    fn read_block(&mut self, block: &Block) -> io::Result<(u64, Option<BkfEntry>)> {
+        let b = &block.data;
+        match &block.kind {
+            b"TAPE" if b.len() >= 86 => {
+                let size = u64::from(u16_at(b, 84));
+                if size >= 512 {
+                    self.block_size = size;
+                }
+            }
+            b"SSET" => self.dirs.clear(),
+            b"VOLB" if b.len() >= 60 => {
+                let device = block.string(56);
+                let drive = device.trim_start_matches(r"\\?\").trim_end_matches('\\');
+                let is_drive = drive.len() == 2 && drive.ends_with(':') && drive.as_bytes()[0].is_ascii_alphabetic();
+                self.volume = if is_drive { format!("{}/", &drive[..1]) } else { String::new() };
+            }
+            _ => {}
+        }
+
+        let mut data = None;
+        let mut name_stream = None;
+        let mut unsupported = None;
+        let mut at = block.offset + u64::from(u16_at(b, 8)).max(HEADER_LEN as u64);
+        while at + STREAM_HEADER_LEN <= self.len {
+            let header = self.read_at(at, STREAM_HEADER_LEN as usize)?;
+            if xor_words(&header) != 0 || !header[..4].iter().all(|c| c.is_ascii_alphanumeric()) {
+                break;
+            }
+            let length = u64_at(&header, 8);
+            let start = at + STREAM_HEADER_LEN;
+            match &header[..4] {
+                b"STAN" => {
+                    let media = u16_at(&header, 6);
+                    if media & STREAM_COMPRESSED != 0 || u16_at(&header, 18) != 0 {
+                        unsupported = Some("compressed MTF data streams are not supported");
+                    } else if media & STREAM_ENCRYPTED != 0 || u16_at(&header, 16) != 0 {
+                        unsupported = Some("encrypted MTF data streams are not supported");
+                    } else if media & STREAM_VARIABLE != 0 {
+                        unsupported = Some("variable-length MTF data streams are not supported");
+                    }
+                    data = Some((start, length));
+                }
+                // Names too long for the descriptor block.
+                b"FNAM" | b"PNAM" if length < 64 * 1024 => name_stream = Some(self.read_at(start, length as usize)?),
+                _ => {}
+            }
+            at = (start + length).next_multiple_of(4);
+            if &header[..4] == b"SPAD" {
+                break;
+            }
+        }
+
+        let entry = match &block.kind {
+            b"DIRB" if b.len() >= 84 => {
+                let mut name = block.string(80);
+                if name.is_empty()
+                    && let Some(raw) = &name_stream
+                {
+                    name = decode_string(raw, b[48]);
+                }
+                let path = format!("{}{}", self.volume, dir_path(&name));
+                self.dirs.insert(u32_at(b, 76), path.clone());
+                self.current_dir = path;
+                None
+            }
+            b"FILE" if b.len() >= 88 => {
+                let mut name = block.string(84);
+                if name.is_empty()
+                    && let Some(raw) = &name_stream
+                {
+                    name = decode_string(raw, b[48]);
+                }
+                let dir = self.dirs.get(&u32_at(b, 76)).unwrap_or(&self.current_dir);
+                let (offset, size) = data.unwrap_or((at, 0));
+                Some(BkfEntry {
+                    name: format!("{}{}", dir, name.trim_end_matches('\0')),
+                    offset,
+                    size,
+                    modified: date_time(&b[56..61]),
+                    unsupported,
+                })
+            }
+            _ => None,
+        };
+        Ok((at, entry))
+    }
+}
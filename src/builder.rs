@@ -0,0 +1,472 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::analysis_cache::AnalysisCache;
+use crate::answers::Answers;
+use crate::cancel::CancellationToken;
+use crate::conflict::ConflictResolver;
+use crate::filter::Filter;
+use crate::options::{AbsoluteEntries, ConflictPolicy, DestLayout, ExtractionOrder, MacQuarantine, ManifestFormat, Options, PlaceholderPolicy, SyncPolicy, SystemFilePolicy};
+use crate::progress::{NoProgress, ProgressSink};
+use crate::restorer::Restorer;
+use crate::snapshot::LinkDest;
+
+/// Marks a required builder setting that has not been provided yet.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Missing;
+
+/// Configures a [`Restorer`]. Obtained from [`Restorer::builder`].
+///
+/// `source` and `dest` are required: [`build`](Self::build) only exists once
+/// both have been set, so a forgotten destination is a compile error rather
+/// than a runtime one. Everything else has the same default as the CLI.
+#[must_use]
+pub struct RestorerBuilder<S = Missing, D = Missing> {
+    source: S,
+    dest: D,
+    options: Options,
+    progress: Arc<dyn ProgressSink>,
+    resolver: Option<Arc<dyn ConflictResolver>>,
+    cancel: CancellationToken,
+}
+
+impl RestorerBuilder {
+    pub(crate) fn new() -> Self {
+        RestorerBuilder {
+            source: Missing,
+            dest: Missing,
+            options: Options::default(),
+            progress: Arc::new(NoProgress),
+            resolver: None,
+            cancel: CancellationToken::new(),
+        }
+    }
+}
+
+impl<D> RestorerBuilder<Missing, D> {
+    /// The backup folder to search for archives.
+    pub fn source(self, source: impl Into<PathBuf>) -> RestorerBuilder<PathBuf, D> {
+        RestorerBuilder {
+            source: source.into(),
+            dest: self.dest,
+            options: self.options,
+            progress: self.progress,
+            resolver: self.resolver,
+            cancel: self.cancel,
+        }
+    }
+}
+
+impl<S> RestorerBuilder<S, Missing> {
+    /// The directory restored files are written to.
+    pub fn dest(self, dest: impl Into<PathBuf>) -> RestorerBuilder<S, PathBuf> {
+        RestorerBuilder {
+            source: self.source,
+            dest: dest.into(),
+            options: self.options,
+            progress: self.progress,
+            resolver: self.resolver,
+            cancel: self.cancel,
+        }
+    }
+}
+
+impl<S, D> RestorerBuilder<S, D> {
+    /// Only restores entries accepted by `filter`. Default: everything.
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.options.filter = filter;
+        self
+    }
+
+    /// Restores the archives of every backup set instead of only the latest
+    /// set of each machine, see [`crate::latest_backup_sets`]. Default: off.
+    pub fn all_runs(mut self, all_runs: bool) -> Self {
+        self.options.all_runs = all_runs;
+        self
+    }
+
+    /// Only restores the archives `selector` names, such as one that failed
+    /// in an earlier run, see [`crate::select_archives`]. Names and globs
+    /// match the archives of all backup sets; a number counts those the run
+    /// would restore, see [`all_runs`](Self::all_runs). Default: all.
+    pub fn archive(mut self, selector: impl Into<String>) -> Self {
+        self.options.archive = Some(selector.into());
+        self
+    }
+
+    /// Leaves out the first `count` of the archives the run would restore,
+    /// to continue one that stopped without a state file where its log
+    /// shows it got to. Default: 0.
+    pub fn skip_archives(mut self, count: usize) -> Self {
+        self.options.skip_archives = count;
+        self
+    }
+
+    /// Default: [`ConflictPolicy::Overwrite`].
+    pub fn conflict_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.options.conflict_policy = policy;
+        self
+    }
+
+    /// Decides the conflicts and errors of the files matching its rules,
+    /// before the conflict policy and [`fail_fast`](Self::fail_fast) do.
+    /// Default: none.
+    pub fn answers(mut self, answers: Answers) -> Self {
+        self.options.answers = Some(answers);
+        self
+    }
+
+    /// Default: [`PlaceholderPolicy::Skip`].
+    pub fn placeholders(mut self, policy: PlaceholderPolicy) -> Self {
+        self.options.placeholders = policy;
+        self
+    }
+
+    /// Default: [`SystemFilePolicy::Skip`], system files are of no use on
+    /// another machine.
+    pub fn system_files(mut self, policy: SystemFilePolicy) -> Self {
+        self.options.system_files = policy;
+        self
+    }
+
+    /// Default: [`AbsoluteEntries::StripRoot`]. Names leading out of the
+    /// destination with `..` are never restored.
+    pub fn absolute_entries(mut self, policy: AbsoluteEntries) -> Self {
+        self.options.absolute_entries = policy;
+        self
+    }
+
+    /// Default: [`ExtractionOrder::Archive`].
+    pub fn order(mut self, order: ExtractionOrder) -> Self {
+        self.options.order = order;
+        self
+    }
+
+    /// Number of threads reading entries out of the archives. They share
+    /// the current archive entry by entry, so this also speeds up a backup
+    /// consisting of a single huge archive. Default: 1.
+    ///
+    /// With more than one thread, the order in which entries write the same
+    /// destination path is not defined.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.threads = threads.max(1);
+        self
+    }
+
+    /// Number of threads decompressing entry data. Default: the number of
+    /// CPUs, at most 8.
+    pub fn decode_threads(mut self, threads: usize) -> Self {
+        self.options.decode_threads = threads.max(1);
+        self
+    }
+
+    /// Number of threads writing restored files. Default: 4.
+    pub fn write_threads(mut self, threads: usize) -> Self {
+        self.options.write_threads = threads.max(1);
+        self
+    }
+
+    /// Writes files larger than `threshold` bytes past the page cache, so a
+    /// restore of large videos or disk images does not push out the cache
+    /// of everything else running. Falls back to ordinary writes where the
+    /// file system does not support it. Default: off.
+    pub fn direct_io(mut self, threshold: u64) -> Self {
+        self.options.direct_io = Some(threshold);
+        self
+    }
+
+    /// Writes the `.part` file each file is written as before it is moved
+    /// to its path in `dir`, and stages the files of a [`DestLayout::Cas`]
+    /// restore there. On another file system than the destination, for FAT
+    /// or network shares whose renames are slow or odd, files are copied
+    /// next to their paths and renamed from there. Default: a hidden
+    /// `.part` file next to each file, and next to the store for
+    /// content-addressed ones; either way the destination never holds a
+    /// half written file.
+    pub fn temp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.temp_dir = Some(dir.into());
+        self
+    }
+
+    /// When restored files are flushed to the disk. Default:
+    /// [`SyncPolicy::None`].
+    pub fn sync(mut self, sync: SyncPolicy) -> Self {
+        self.options.sync = sync;
+        self
+    }
+
+    /// Reserves the space of each restored file of 1 MB and more before
+    /// writing it, so it is not fragmented and a full disk fails it at
+    /// once rather than half-written. A file the backup holds less of than
+    /// its recorded size, such as a damaged one, keeps the space reserved
+    /// past its end. Default: off.
+    pub fn preallocate(mut self, preallocate: bool) -> Self {
+        self.options.preallocate = preallocate;
+        self
+    }
+
+    /// Marks restored files NTFS-compressed, except those of formats that
+    /// are compressed already, such as photos, videos and archives. For
+    /// restoring onto a smaller disk than the backup came from. Windows
+    /// only. Default: off.
+    pub fn ntfs_compress(mut self, ntfs_compress: bool) -> Self {
+        self.options.ntfs_compress = ntfs_compress;
+        self
+    }
+
+    /// Makes each restored file read-only once written, for restores kept
+    /// as reference material that should not change. Folders stay
+    /// writable, so later archives and runs can add to them; files they
+    /// overwrite are made writable first. Default: off.
+    pub fn make_readonly(mut self, make_readonly: bool) -> Self {
+        self.options.make_readonly = make_readonly;
+        self
+    }
+
+    /// Hands restored files, and the folders the restore creates for them,
+    /// to user `uid` and group `gid`; `None` keeps that part. For restoring
+    /// as root into another user's home. Needs the privilege to; Unix only.
+    /// Default: owned by the user restoring.
+    pub fn owner(mut self, uid: Option<u32>, gid: Option<u32>) -> Self {
+        self.options.owner = Some((uid, gid));
+        self
+    }
+
+    /// Gives restored files this mode, such as `0o644`, as is rather than
+    /// filtered by the umask. Unix only. Default: `0o666` less the umask.
+    pub fn file_mode(mut self, mode: u32) -> Self {
+        self.options.file_mode = Some(mode);
+        self
+    }
+
+    /// Gives the folders the restore creates this mode, such as `0o755`,
+    /// as is rather than filtered by the umask. Unix only. Default: `0o777`
+    /// less the umask.
+    pub fn dir_mode(mut self, mode: u32) -> Self {
+        self.options.dir_mode = Some(mode);
+        self
+    }
+
+    /// Gives restored files the modification time the archive records, and
+    /// the access and creation times if an NTFS or extended timestamp extra
+    /// field has them, to the precision it has them in: to 100 ns, where
+    /// the DOS time every entry has is to two seconds. Folders get the
+    /// times of their entry, or of the newest file in them, once all is
+    /// restored. Creation times are set on Windows and macOS, where the file
+    /// system keeps them. Default: on.
+    pub fn preserve_times(mut self, preserve_times: bool) -> Self {
+        self.options.preserve_times = preserve_times;
+        self
+    }
+
+    /// The time zone of the machine backed up. DOS times are in its local
+    /// time, with no zone recorded; restored onto a machine in another
+    /// zone, or one set to UTC, every file is off by the difference
+    /// otherwise. Default: the local time zone of this machine.
+    pub fn source_tz(mut self, zone: chrono_tz::Tz) -> Self {
+        self.options.source_tz = Some(zone);
+        self
+    }
+
+    /// Lets [`crate::Restorer::relabel`] give the restored files the
+    /// SELinux labels the policy has for their paths, as `restorecon`
+    /// does, so a web server or user can read a web root or home restored
+    /// by root or elsewhere first. Needs `restorecon`; nothing happens
+    /// where SELinux is off. Default: off.
+    pub fn selinux_relabel(mut self, selinux_relabel: bool) -> Self {
+        self.options.selinux_relabel = selinux_relabel;
+        self
+    }
+
+    /// Sets or clears the `com.apple.quarantine` attribute of restored
+    /// files, see [`MacQuarantine`], so Gatekeeper checks old downloads
+    /// whatever opens them, or none. macOS only. Default: left as it
+    /// comes, which for files the restore creates is not set.
+    pub fn mac_quarantine(mut self, policy: MacQuarantine) -> Self {
+        self.options.mac_quarantine = Some(policy);
+        self
+    }
+
+    /// Reads archives through a memory map instead of buffered reads.
+    /// Faster on local SSDs; archives must not change during the run.
+    /// Default: off.
+    pub fn mmap(mut self, mmap: bool) -> Self {
+        self.options.mmap = mmap;
+        self
+    }
+
+    /// Counts the files to restore and the space they need from the
+    /// entries `cache` keeps of unchanged archives, see
+    /// [`crate::BackupSet::with_cache`]. Default: none.
+    pub fn analysis_cache(mut self, cache: AnalysisCache) -> Self {
+        self.options.analysis_cache = Some(cache);
+        self
+    }
+
+    /// Opens the next archive and reads its central directory on a
+    /// separate thread while the current one is extracted, hiding the
+    /// per-archive startup latency on slow media. Default: on.
+    pub fn prefetch(mut self, prefetch: bool) -> Self {
+        self.options.prefetch = prefetch;
+        self
+    }
+
+    /// While prefetching, also reads ahead through the next archive to load
+    /// it into the page cache. Default: off.
+    pub fn warm_cache(mut self, warm_cache: bool) -> Self {
+        self.options.warm_cache = warm_cache;
+        self
+    }
+
+    /// Drops large restored files from the page cache as they are written,
+    /// and each archive once it is done, so a long restore does not push
+    /// out the cache of everything else running. Linux only. Default: off.
+    pub fn drop_caches(mut self, drop_caches: bool) -> Self {
+        self.options.drop_caches = drop_caches;
+        self
+    }
+
+    /// Stops the run at the first entry or archive that cannot be restored,
+    /// as if it had been cancelled; the run then ends with
+    /// [`Outcome::Cancelled`](crate::Outcome::Cancelled). Default: off, errors
+    /// are recorded and the run goes on.
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.options.fail_fast = fail_fast;
+        self
+    }
+
+    /// Also writes every restored file to `mirror`, at the same path as in
+    /// the destination, in the same pass over the archives. Can be given
+    /// more than once. Mirrors follow the destination: a file skipped or
+    /// renamed there is skipped or renamed in them too, whatever they hold
+    /// already. A mirror that cannot be written is recorded as an error of
+    /// the entry, which is still restored to the destination. Default: none.
+    pub fn mirror(mut self, mirror: impl Into<PathBuf>) -> Self {
+        self.options.mirrors.push(mirror.into());
+        self
+    }
+
+    /// Writes the SHA-256 of every restored file to `manifest`, in the
+    /// chosen [`ManifestFormat`] with paths relative to the destination, for
+    /// [`crate::verify_manifest`] to check the files against later.
+    /// [`Restorer::extract_all`](crate::Restorer::extract_all) starts a new
+    /// manifest, [`Restorer::extract_archive`](crate::Restorer::extract_archive)
+    /// adds to it. Default: none.
+    pub fn manifest(mut self, manifest: impl Into<PathBuf>) -> Self {
+        self.options.manifest = Some(manifest.into());
+        self
+    }
+
+    /// Layout of the manifest. Default: [`ManifestFormat::Gnu`].
+    pub fn manifest_format(mut self, format: ManifestFormat) -> Self {
+        self.options.manifest_format = format;
+        self
+    }
+
+    /// How the restored files are laid out in the destination. With
+    /// [`DestLayout::Cas`] files of the same content are stored once, the
+    /// conflict policy has no effect as paths only exist in the tree file,
+    /// and mirrors are not written. Default: [`DestLayout::Tree`].
+    pub fn dest_layout(mut self, layout: DestLayout) -> Self {
+        self.options.dest_layout = layout;
+        self
+    }
+
+    /// Restores each archive into a folder of its own below the destination,
+    /// named after the archive without its extension, instead of merging
+    /// them all. Archives in subfolders of the source keep the path to them,
+    /// so `Backup files 1.zip` of two backup sets do not end up together.
+    /// Has no effect with [`DestLayout::Cas`]. Default: off.
+    pub fn subdir_per_archive(mut self, subdir_per_archive: bool) -> Self {
+        self.options.subdir_per_archive = subdir_per_archive;
+        self
+    }
+
+    /// Keeps the drive letter archives put first in entry names as a folder,
+    /// `C/Users/...` and `D/Users/...`, instead of merging every drive into
+    /// one tree where the same path on two drives clobbers one another.
+    /// Default: off, see [`crate::strip_drive_letter`].
+    pub fn keep_drives(mut self, keep_drives: bool) -> Self {
+        self.options.keep_drives = keep_drives;
+        self
+    }
+
+    /// Restores the AppleDouble files macOS leaves in ZIP files, `._name`
+    /// and `__MACOSX/`, as they are. Default: off, they are skipped and
+    /// counted in [`crate::ArchiveStats::apple_double`].
+    pub fn keep_apple_double(mut self, keep_apple_double: bool) -> Self {
+        self.options.keep_apple_double = keep_apple_double;
+        self
+    }
+
+    /// Restores the profile of user `old`, `Users/<old>/...` or `Documents
+    /// and Settings/<old>/...`, as that of `new`. The filter still sees the
+    /// names of the backup. Can be given once per user; see
+    /// [`crate::detect_user_remap`] to find the pair. `new` is a folder
+    /// name, not `.`, `..` or one with a separator. Default: none.
+    pub fn remap_user(mut self, old: impl Into<String>, new: impl Into<String>) -> Self {
+        self.options.user_map.push((old.into(), new.into()));
+        self
+    }
+
+    /// Also renames the profiles of [`remap_user`](Self::remap_user) in the
+    /// absolute paths inside restored `.lnk` shortcuts: their target,
+    /// working folder, arguments and icon. Default: off, shortcuts are
+    /// restored as they are.
+    pub fn remap_shortcuts(mut self, remap_shortcuts: bool) -> Self {
+        self.options.remap_shortcuts = remap_shortcuts;
+        self
+    }
+
+    /// Restores the entries of drive `drive` into `dir` instead of the
+    /// destination. Can be given once per drive. The manifest lists these
+    /// files by absolute path, mirrors get them below a folder named after
+    /// the drive. Has no effect with
+    /// [`DestLayout::Cas`]. Default: none.
+    pub fn drive_map(mut self, drive: char, dir: impl Into<PathBuf>) -> Self {
+        self.options.drive_map.insert(drive.to_ascii_uppercase(), dir.into());
+        self
+    }
+
+    /// Hard-links files that are unchanged since the snapshot `previous`, by
+    /// CRC-32 and size, to its copy instead of extracting them again. Only
+    /// ZIP entries can be linked; a file the previous restore did not write
+    /// completely is extracted. Files overwritten later in the run are
+    /// replaced, never written through the link. Default: none.
+    pub fn link_dest(mut self, previous: LinkDest) -> Self {
+        self.options.link_dest = Some(previous);
+        self
+    }
+
+    /// Records every decision on an entry (extracted, overwritten, renamed,
+    /// skipped, quarantined or failed) in the hash-chained log at `path`,
+    /// for [`crate::verify_audit_log`]. Runs add to an existing log.
+    /// Default: none.
+    pub fn audit_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.options.audit_log = Some(path.into());
+        self
+    }
+
+    pub fn progress(mut self, sink: Arc<dyn ProgressSink>) -> Self {
+        self.progress = sink;
+        self
+    }
+
+    /// Decides the conflicts of [`ConflictPolicy::Ask`]. Default: none,
+    /// existing files are kept.
+    pub fn conflict_resolver(mut self, resolver: Arc<dyn ConflictResolver>) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+}
+
+impl RestorerBuilder<PathBuf, PathBuf> {
+    pub fn build(self) -> Restorer {
+        Restorer::from_parts(self.source, self.dest, self.options, self.progress, self.resolver, self.cancel)
+    }
+}
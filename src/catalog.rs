@@ -0,0 +1,263 @@
+//! Windows Backup catalogs.
+//!
+//! Windows 7 backups keep `MediaID.bin` at the root of the backup drive and
+//! `<machine>/Catalogs/GlobalCatalog.wbcat` next to the `Backup Set ...`
+//! folders of that machine. The catalog format is undocumented; what can be
+//! read reliably are the UTF-16 folder names of the backup runs it records,
+//! which carry the time of each run.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const CATALOG: &str = "GlobalCatalog.wbcat";
+const MEDIA_ID: &str = "MediaID.bin";
+const SET_PREFIX: &str = "Backup Set ";
+const FILES_PREFIX: &str = "Backup Files ";
+/// Length of the `YYYY-MM-DD HHMMSS` suffix of a run folder name.
+const TIME_LEN: usize = 17;
+
+/// Time of a backup run, as encoded in its folder name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BackupTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl BackupTime {
+    /// Parses the `YYYY-MM-DD HHMMSS` format of backup folder names.
+    pub fn parse(s: &str) -> Option<Self> {
+        let b = s.as_bytes();
+        if b.len() != TIME_LEN || b[4] != b'-' || b[7] != b'-' || b[10] != b' ' {
+            return None;
+        }
+        let num = |range: std::ops::Range<usize>| s.get(range)?.parse::<u16>().ok();
+        let time = BackupTime {
+            year: num(0..4)?,
+            month: num(5..7)? as u8,
+            day: num(8..10)? as u8,
+            hour: num(11..13)? as u8,
+            minute: num(13..15)? as u8,
+            second: num(15..17)? as u8,
+        };
+        let valid = (1..=12).contains(&time.month)
+            && (1..=31).contains(&time.day)
+            && time.hour < 24
+            && time.minute < 60
+            && time.second < 60;
+        valid.then_some(time)
+    }
+
+    /// The UTC time `seconds` after 1970-01-01.
+    pub(crate) fn from_unix(seconds: i64) -> Option<Self> {
+        let (days, time) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+        // Civil date from days since the epoch (H. Hinnant's algorithm).
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        Some(BackupTime {
+            year: u16::try_from(yoe + era * 400 + i64::from(month <= 2)).ok()?,
+            month: month as u8,
+            day: (doy - (153 * mp + 2) / 5 + 1) as u8,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        })
+    }
+
+    /// Reads the time from a `Backup Set ...` or `Backup Files ...` name.
+    pub(crate) fn of_folder(name: &str) -> Option<Self> {
+        let time = name.strip_prefix(SET_PREFIX).or_else(|| name.strip_prefix(FILES_PREFIX))?;
+        BackupTime::parse(time)
+    }
+}
+
+impl fmt::Display for BackupTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+impl From<zip::DateTime> for BackupTime {
+    fn from(t: zip::DateTime) -> BackupTime {
+        BackupTime { year: t.year(), month: t.month(), day: t.day(), hour: t.hour(), minute: t.minute(), second: t.second() }
+    }
+}
+
+/// One backup run recorded in a catalog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupRun {
+    /// `Backup Set ...` folder of the full backup this run belongs to.
+    pub set: String,
+    /// `Backup Files ...` folder holding this run's archives, if the
+    /// catalog names it.
+    pub files: Option<String>,
+    pub time: BackupTime,
+}
+
+impl BackupRun {
+    /// The first run of a backup set is the full backup; later runs of the
+    /// same set only contain changed files.
+    pub fn is_full(&self) -> bool {
+        self.files.as_deref().and_then(BackupTime::of_folder).is_none_or(|time| BackupTime::of_folder(&self.set) == Some(time))
+    }
+
+    /// Whether `archive` lies in this run's folder.
+    pub fn contains(&self, archive: &Path) -> bool {
+        let mut in_set = false;
+        for component in archive.components() {
+            let name = component.as_os_str().to_string_lossy();
+            if in_set {
+                return self.files.as_deref().is_none_or(|files| name.eq_ignore_ascii_case(files));
+            }
+            in_set = name.eq_ignore_ascii_case(&self.set);
+        }
+        false
+    }
+}
+
+/// What a Windows Backup catalog says about a backup folder.
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    /// The catalog file that was read.
+    pub path: PathBuf,
+    /// Name of the backed-up machine: the folder holding the catalog.
+    pub machine: Option<String>,
+    /// Identifier of the backup medium from `MediaID.bin`, if found.
+    pub media_id: Option<String>,
+    /// Backup runs, oldest first.
+    pub runs: Vec<BackupRun>,
+}
+
+impl Catalog {
+    /// Looks for a catalog belonging to `source`, which may be the drive
+    /// root, the machine folder, or a `Backup Set`/`Backup Files` folder.
+    pub fn find(source: &Path) -> Option<Catalog> {
+        let catalog_in = |dir: &Path| Some(dir.join("Catalogs").join(CATALOG)).filter(|p| p.is_file());
+        let found = source.ancestors().take(3).find_map(catalog_in).or_else(|| {
+            let mut dirs: Vec<PathBuf> = fs::read_dir(source).ok()?.flatten().map(|e| e.path()).collect();
+            dirs.sort();
+            dirs.iter().find_map(|dir| catalog_in(dir))
+        })?;
+        Catalog::read(&found).ok()
+    }
+
+    /// Reads the catalog at `path`, plus `MediaID.bin` from the drive root.
+    pub fn read(path: &Path) -> io::Result<Catalog> {
+        let data = fs::read(path)?;
+        let machine_dir = path.parent().and_then(Path::parent);
+        let machine = machine_dir.and_then(Path::file_name).map(|name| name.to_string_lossy().into_owned());
+        let media_id = machine_dir
+            .into_iter()
+            .flat_map(|dir| [dir.join(MEDIA_ID), dir.parent().map(|root| root.join(MEDIA_ID)).unwrap_or_default()])
+            .find_map(|candidate| fs::read(candidate).ok())
+            .and_then(|media| find_guid(&media));
+        Ok(Catalog { path: path.to_path_buf(), machine, media_id, runs: runs(&data) })
+    }
+
+    /// The run `archive` belongs to.
+    pub fn run_of(&self, archive: &Path) -> Option<&BackupRun> {
+        self.runs.iter().find(|run| run.files.is_some() && run.contains(archive)).or_else(|| self.runs.iter().find(|run| run.contains(archive)))
+    }
+}
+
+/// Printable UTF-16LE strings in `data` with their byte offsets, scanning
+/// both alignments since records are not necessarily aligned.
+fn utf16_strings(data: &[u8]) -> Vec<(usize, String)> {
+    let mut strings = Vec::new();
+    for alignment in 0..2 {
+        let mut current = String::new();
+        let mut start = alignment;
+        for (i, unit) in data[alignment.min(data.len())..].chunks_exact(2).enumerate() {
+            let c = char::from_u32(u32::from(u16::from_le_bytes([unit[0], unit[1]])));
+            match c {
+                Some(c) if !c.is_control() && c != '\u{fffd}' => {
+                    if current.is_empty() {
+                        start = alignment + i * 2;
+                    }
+                    current.push(c);
+                }
+                _ => {
+                    if current.chars().count() >= 8 {
+                        strings.push((start, std::mem::take(&mut current)));
+                    }
+                    current.clear();
+                }
+            }
+        }
+        if current.chars().count() >= 8 {
+            strings.push((start, current));
+        }
+    }
+    strings.sort_by_key(|(offset, _)| *offset);
+    strings
+}
+
+/// Run folder names starting with `prefix` in `s`, with their positions.
+fn folder_names<'a>(s: &'a str, prefix: &'a str) -> impl Iterator<Item = (usize, String)> + 'a {
+    s.match_indices(prefix).filter_map(move |(i, _)| {
+        let name = s.get(i..i + prefix.len() + TIME_LEN)?;
+        BackupTime::of_folder(name).map(|_| (i, name.to_string()))
+    })
+}
+
+/// Pairs every `Backup Files` name with the `Backup Set` named before it.
+fn runs(data: &[u8]) -> Vec<BackupRun> {
+    let mut runs: Vec<BackupRun> = Vec::new();
+    let mut set: Option<String> = None;
+    for (_, s) in utf16_strings(data) {
+        let sets = folder_names(&s, SET_PREFIX).map(|(i, name)| (i, true, name));
+        let files = folder_names(&s, FILES_PREFIX).map(|(i, name)| (i, false, name));
+        let mut names: Vec<(usize, bool, String)> = sets.chain(files).collect();
+        names.sort();
+        for (_, is_set, name) in names {
+            if is_set {
+                set = Some(name);
+                continue;
+            }
+            let Some(set) = &set else { continue };
+            let time = BackupTime::of_folder(&name).expect("validated name");
+            let run = BackupRun { set: set.clone(), files: Some(name), time };
+            if !runs.contains(&run) {
+                runs.push(run);
+            }
+        }
+        if let Some(set) = &set
+            && !runs.iter().any(|run| &run.set == set)
+        {
+            let time = BackupTime::of_folder(set).expect("validated name");
+            runs.push(BackupRun { set: set.clone(), files: None, time });
+        }
+    }
+    // A set first seen without files may have gained runs later on.
+    let with_files: Vec<String> = runs.iter().filter(|r| r.files.is_some()).map(|r| r.set.clone()).collect();
+    runs.retain(|run| run.files.is_some() || !with_files.contains(&run.set));
+    runs.sort_by(|a, b| a.time.cmp(&b.time).then_with(|| a.set.cmp(&b.set)));
+    runs
+}
+
+/// The first `{xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx}` style GUID stored as
+/// ASCII or UTF-16 text in `data`.
+fn find_guid(data: &[u8]) -> Option<String> {
+    let is_guid = |s: &str| {
+        s.len() == 36
+            && s.char_indices().all(|(i, c)| if matches!(i, 8 | 13 | 18 | 23) { c == '-' } else { c.is_ascii_hexdigit() })
+    };
+    let ascii = String::from_utf8_lossy(data).into_owned();
+    std::iter::once(ascii)
+        .chain(utf16_strings(data).into_iter().map(|(_, s)| s))
+        .find_map(|s| (0..s.len().saturating_sub(35)).find_map(|i| s.get(i..i + 36).filter(|g| is_guid(g)).map(|g| format!("{{{}}}", g.to_uppercase()))))
+}
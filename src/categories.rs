@@ -0,0 +1,101 @@
+//! Kinds of files by extension, such as photos and documents, for telling
+//! what a backup holds at a glance. The built-in mapping can be replaced by
+//! a file with one category per line:
+//!
+//! ```text
+//! # comment
+//! Photos: jpg jpeg png heic raw cr2
+//! Scans: tif tiff
+//! ```
+//!
+//! Extensions are matched ignoring case; files whose extension no category
+//! names are [`Categories::OTHER`].
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::analyze::ExtensionStats;
+
+const BUILT_IN: &[(&str, &[&str])] = &[
+    ("Documents", &["doc", "docx", "odt", "rtf", "txt", "pdf", "md", "pages", "xls", "xlsx", "ods", "csv", "ppt", "pptx", "odp", "key", "numbers", "one", "epub"]),
+    ("Photos", &["jpg", "jpeg", "png", "gif", "bmp", "tif", "tiff", "heic", "heif", "webp", "raw", "cr2", "cr3", "nef", "arw", "dng", "orf", "psd", "svg"]),
+    ("Videos", &["mp4", "m4v", "mov", "avi", "mkv", "wmv", "mpg", "mpeg", "3gp", "mts", "m2ts", "webm", "flv", "vob"]),
+    ("Music", &["mp3", "m4a", "aac", "flac", "wav", "wma", "ogg", "opus", "aiff", "mid"]),
+    ("Mail", &["pst", "ost", "eml", "msg", "mbox"]),
+    ("Archives", &["zip", "7z", "rar", "tar", "gz", "tgz", "bz2", "xz", "zst", "cab", "iso", "img", "vhd", "vhdx"]),
+    ("Programs", &["exe", "msi", "dll", "sys", "bat", "cmd", "ps1", "jar", "apk", "dmg", "pkg"]),
+];
+
+/// A mapping of extensions to categories, see the [module](self) docs.
+#[derive(Debug, Clone)]
+pub struct Categories {
+    /// Category names in the order given.
+    names: Vec<String>,
+    /// Lowercase extension to index in `names`.
+    extensions: HashMap<String, usize>,
+}
+
+impl Default for Categories {
+    /// Documents, photos, videos, music, mail, archives and programs.
+    fn default() -> Self {
+        let mut categories = Categories { names: Vec::new(), extensions: HashMap::new() };
+        for (name, extensions) in BUILT_IN {
+            categories.add(name, extensions.iter().copied());
+        }
+        categories
+    }
+}
+
+impl Categories {
+    /// The category of files no other one takes.
+    pub const OTHER: &str = "Other";
+
+    /// Reads a mapping file. Lines without a `:` are an error, naming the
+    /// line; an extension named twice counts for the first category.
+    pub fn load(path: &Path) -> io::Result<Categories> {
+        let text = fs::read_to_string(path)?;
+        let mut categories = Categories { names: Vec::new(), extensions: HashMap::new() };
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((name, extensions)) = line.split_once(':') else {
+                let message = format!("line {}: expected \"Category: ext ext ...\", got {:?}", n + 1, line);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            };
+            let extensions = extensions.split([' ', ',', '\t']).map(|ext| ext.trim_start_matches('.')).filter(|ext| !ext.is_empty());
+            categories.add(name.trim(), extensions);
+        }
+        Ok(categories)
+    }
+
+    fn add<'a>(&mut self, name: &str, extensions: impl Iterator<Item = &'a str>) {
+        let index = match self.names.iter().position(|known| known == name) {
+            Some(index) => index,
+            None => {
+                self.names.push(name.to_string());
+                self.names.len() - 1
+            }
+        };
+        for ext in extensions {
+            self.extensions.entry(ext.to_ascii_lowercase()).or_insert(index);
+        }
+    }
+
+    /// Adds up `extensions`, the statistics of each lowercase extension, by
+    /// category: in the order of the mapping, with [`Categories::OTHER`]
+    /// last, leaving out those without files.
+    pub fn tally<'a>(&'a self, extensions: &HashMap<String, ExtensionStats>) -> Vec<(&'a str, ExtensionStats)> {
+        let mut totals = vec![ExtensionStats::default(); self.names.len() + 1];
+        for (ext, stats) in extensions {
+            let index = self.extensions.get(ext).copied().unwrap_or(self.names.len());
+            totals[index].files += stats.files;
+            totals[index].bytes += stats.bytes;
+        }
+        let names = self.names.iter().map(String::as_str).chain([Self::OTHER]);
+        names.zip(totals).filter(|(_, stats)| stats.files > 0).collect()
+    }
+}
@@ -0,0 +1,239 @@
+//! Minimal streaming reader for ZIP central directories.
+//!
+//! The zip crate parses the whole central directory up front and only
+//! exposes entry details after seeking to each local header. Listing and
+//! indexing only need the central directory records, so they are read here
+//! sequentially from one buffered pass over the directory region.
+
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+use crate::paths::strip_drive_letter;
+use crate::placeholder::is_cloud_placeholder;
+
+const EOCD_SIG: u32 = 0x0605_4b50;
+const ZIP64_LOCATOR_SIG: u32 = 0x0706_4b50;
+const ZIP64_EOCD_SIG: u32 = 0x0606_4b50;
+const CD_HEADER_SIG: u32 = 0x0201_4b50;
+const EOCD_LEN: u64 = 22;
+
+const CP437_HIGH: &str = "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}";
+
+/// One central directory record.
+#[derive(Debug, Clone)]
+pub(crate) struct RawEntry {
+    pub(crate) version_made_by: u16,
+    pub(crate) flags: u16,
+    pub(crate) method: u16,
+    pub(crate) dos_time: u16,
+    pub(crate) dos_date: u16,
+    pub(crate) crc32: u32,
+    pub(crate) compressed_size: u64,
+    pub(crate) size: u64,
+    pub(crate) external_attributes: u32,
+    pub(crate) local_header_offset: u64,
+    pub(crate) name: String,
+    pub(crate) extra: Vec<u8>,
+}
+
+impl RawEntry {
+    /// The Windows file attributes, kept by archives made on DOS or Windows.
+    pub(crate) fn windows_attributes(&self) -> Option<u32> {
+        matches!(self.version_made_by >> 8, 0 | 10 | 14).then_some(self.external_attributes)
+    }
+
+    /// Whether the entry is a cloud-only placeholder, see
+    /// [`is_cloud_placeholder`].
+    pub(crate) fn is_cloud_placeholder(&self) -> bool {
+        let name = self.name.replace('\\', "/");
+        !name.ends_with('/') && is_cloud_placeholder(strip_drive_letter(&name), self.size, self.windows_attributes())
+    }
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn u16_at(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(buf[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(buf[at..at + 8].try_into().unwrap())
+}
+
+pub(crate) fn decode_cp437(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| if b < 0x80 { b as char } else { CP437_HIGH.chars().nth(usize::from(b - 0x80)).unwrap() })
+        .collect()
+}
+
+/// Iterates over the extra field blocks of an entry as `(id, data)`.
+pub(crate) fn extra_fields(extra: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    let mut rest = extra;
+    std::iter::from_fn(move || {
+        if rest.len() < 4 {
+            return None;
+        }
+        let id = u16_at(rest, 0);
+        let len = usize::from(u16_at(rest, 2));
+        let data = rest.get(4..4 + len)?;
+        rest = &rest[4 + len..];
+        Some((id, data))
+    })
+}
+
+/// Location of the central directory inside the archive file.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Location {
+    pub(crate) offset: u64,
+    pub(crate) size: u64,
+    pub(crate) entries: u64,
+}
+
+pub(crate) fn locate<R: Read + Seek>(reader: &mut R) -> io::Result<Location> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    if file_len < EOCD_LEN {
+        return Err(invalid("file too small to be a ZIP archive"));
+    }
+    // The EOCD record is followed by a comment of at most 64 KiB.
+    let tail_len = file_len.min(EOCD_LEN + 0xFFFF);
+    let tail_start = file_len - tail_len;
+    reader.seek(SeekFrom::Start(tail_start))?;
+    let mut tail = vec![0u8; tail_len as usize];
+    reader.read_exact(&mut tail)?;
+    let eocd_at = (0..=tail.len() - EOCD_LEN as usize)
+        .rev()
+        .find(|&i| u32_at(&tail, i) == EOCD_SIG)
+        .ok_or_else(|| invalid("end of central directory not found"))?;
+    let eocd = &tail[eocd_at..];
+    let eocd_pos = tail_start + eocd_at as u64;
+
+    let mut location = Location {
+        offset: u64::from(u32_at(eocd, 16)),
+        size: u64::from(u32_at(eocd, 12)),
+        entries: u64::from(u16_at(eocd, 10)),
+    };
+    let needs_zip64 = location.offset == 0xFFFF_FFFF || location.size == 0xFFFF_FFFF || location.entries == 0xFFFF;
+    if needs_zip64 && eocd_pos >= 20 {
+        reader.seek(SeekFrom::Start(eocd_pos - 20))?;
+        let mut locator = [0u8; 20];
+        reader.read_exact(&mut locator)?;
+        if u32_at(&locator, 0) == ZIP64_LOCATOR_SIG {
+            reader.seek(SeekFrom::Start(u64_at(&locator, 8)))?;
+            let mut record = [0u8; 56];
+            reader.read_exact(&mut record)?;
+            if u32_at(&record, 0) != ZIP64_EOCD_SIG {
+                return Err(invalid("invalid zip64 end of central directory"));
+            }
+            location = Location { offset: u64_at(&record, 48), size: u64_at(&record, 40), entries: u64_at(&record, 32) };
+            return Ok(location);
+        }
+    }
+    // Archives with data prepended (self-extractors) store offsets relative
+    // to the start of the ZIP data, not the file.
+    if let Some(expected) = eocd_pos.checked_sub(location.size)
+        && expected > location.offset
+    {
+        location.offset = expected;
+    }
+    Ok(location)
+}
+
+/// Streams the central directory records of an archive.
+pub(crate) struct CentralDirectory<R> {
+    reader: io::Take<BufReader<R>>,
+    remaining: u64,
+}
+
+impl<R: Read + Seek> CentralDirectory<R> {
+    pub(crate) fn open(mut reader: R) -> io::Result<Self> {
+        let location = locate(&mut reader)?;
+        reader.seek(SeekFrom::Start(location.offset))?;
+        Ok(CentralDirectory { reader: BufReader::new(reader).take(location.size), remaining: location.entries })
+    }
+}
+
+impl<R: Read> CentralDirectory<R> {
+    fn read_entry(&mut self) -> io::Result<RawEntry> {
+        let mut header = [0u8; 46];
+        self.reader.read_exact(&mut header)?;
+        if u32_at(&header, 0) != CD_HEADER_SIG {
+            return Err(invalid("invalid central directory header"));
+        }
+        let name_len = usize::from(u16_at(&header, 28));
+        let extra_len = usize::from(u16_at(&header, 30));
+        let comment_len = usize::from(u16_at(&header, 32));
+        let mut variable = vec![0u8; name_len + extra_len + comment_len];
+        self.reader.read_exact(&mut variable)?;
+        let raw_name = &variable[..name_len];
+        let extra = variable[name_len..name_len + extra_len].to_vec();
+        let flags = u16_at(&header, 8);
+
+        let mut entry = RawEntry {
+            version_made_by: u16_at(&header, 4),
+            flags,
+            method: u16_at(&header, 10),
+            dos_time: u16_at(&header, 12),
+            dos_date: u16_at(&header, 14),
+            crc32: u32_at(&header, 16),
+            compressed_size: u64::from(u32_at(&header, 20)),
+            size: u64::from(u32_at(&header, 24)),
+            external_attributes: u32_at(&header, 38),
+            local_header_offset: u64::from(u32_at(&header, 42)),
+            name: String::new(),
+            extra: Vec::new(),
+        };
+
+        let mut unicode_name = None;
+        for (id, data) in extra_fields(&extra) {
+            match id {
+                0x0001 => {
+                    // Zip64 values are present only for fields saturated in the header.
+                    let mut at = 0;
+                    for field in [&mut entry.size, &mut entry.compressed_size, &mut entry.local_header_offset] {
+                        if *field == 0xFFFF_FFFF && data.len() >= at + 8 {
+                            *field = u64_at(data, at);
+                            at += 8;
+                        }
+                    }
+                }
+                // Info-ZIP Unicode Path: version, CRC32 of the header name, UTF-8 name
+                0x7075 if data.len() > 5 && data[0] == 1 => {
+                    unicode_name = Some(String::from_utf8_lossy(&data[5..]).into_owned());
+                }
+                _ => {}
+            }
+        }
+
+        entry.name = if flags & 0x0800 != 0 {
+            String::from_utf8_lossy(raw_name).into_owned()
+        } else if let Some(name) = unicode_name {
+            name
+        } else {
+            decode_cp437(raw_name)
+        };
+        entry.extra = extra;
+        Ok(entry)
+    }
+}
+
+impl<R: Read> Iterator for CentralDirectory<R> {
+    type Item = io::Result<RawEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let entry = self.read_entry();
+        if entry.is_err() {
+            self.remaining = 0;
+        }
+        Some(entry)
+    }
+}
@@ -0,0 +1,79 @@
+//! Deciding conflicts one file at a time, for [`ConflictPolicy::Ask`]: the
+//! restore hands each file that exists at the destination with other
+//! contents to a [`ConflictResolver`], such as the prompt of the command
+//! line, and does what it answers.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::catalog::BackupTime;
+use crate::options::ConflictPolicy;
+
+/// A file of the backup whose restore path is taken by another file.
+#[derive(Debug, Clone, Copy)]
+pub struct Conflict<'a> {
+    pub archive: &'a Path,
+    /// Restore path of the entry, e.g. `Users/bob/Documents/a.txt`.
+    pub path: &'a str,
+    /// The file at the destination.
+    pub target: &'a Path,
+    pub existing_size: u64,
+    pub existing_modified: Option<SystemTime>,
+    /// The file in the backup.
+    pub size: u64,
+    pub modified: Option<BackupTime>,
+}
+
+/// What to do with a [`Conflict`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    /// Keep the file at the destination and skip the entry.
+    Keep,
+    /// Replace it with the file of the backup.
+    Replace,
+    /// Restore the file of the backup next to it, as `name (1).ext`.
+    Rename,
+}
+
+impl From<Resolution> for ConflictPolicy {
+    fn from(resolution: Resolution) -> ConflictPolicy {
+        match resolution {
+            Resolution::Keep => ConflictPolicy::Skip,
+            Resolution::Replace => ConflictPolicy::Overwrite,
+            Resolution::Rename => ConflictPolicy::Rename,
+        }
+    }
+}
+
+/// Decides the conflicts of a restore with [`ConflictPolicy::Ask`],
+/// see [`crate::RestorerBuilder::conflict_resolver`].
+///
+/// Calls come from the threads of the restore, one at a time; the restore
+/// waits for the answer, so a resolver may ask the user.
+pub trait ConflictResolver: Send + Sync {
+    fn resolve(&self, conflict: &Conflict<'_>) -> Resolution;
+}
+
+/// Whether the file at `target` holds `size` bytes with the CRC-32 `crc32`:
+/// the same file, restored before, is no conflict to ask about.
+pub(crate) fn same_contents(target: &Path, size: u64, crc32: u32) -> bool {
+    if fs::metadata(target).map_or(true, |metadata| !metadata.is_file() || metadata.len() != size) {
+        return false;
+    }
+    let Ok(mut file) = File::open(target) else { return false };
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => return hasher.finalize() == crc32,
+            Ok(n) => hasher.update(&buffer[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => return false,
+        }
+    }
+}
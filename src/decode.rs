@@ -0,0 +1,60 @@
+//! Decompression of raw entry data outside the zip crate, so the pipeline
+//! can read compressed bytes on one thread and decode them on another.
+
+use std::io::{self, BufReader, Read};
+
+pub(crate) const STORED: u16 = 0;
+pub(crate) const DEFLATE: u16 = 8;
+pub(crate) const DEFLATE64: u16 = 9;
+pub(crate) const BZIP2: u16 = 12;
+pub(crate) const ZSTD: u16 = 93;
+
+/// Whether [`decoder`] handles `method`. Other methods are decoded by the
+/// zip crate while reading.
+pub(crate) fn supported(method: u16) -> bool {
+    matches!(method, STORED | DEFLATE | DEFLATE64 | BZIP2 | ZSTD)
+}
+
+/// Wraps `raw` compressed data in a decoder for `method`.
+pub(crate) fn decoder<'a, R: Read + Send + 'a>(method: u16, raw: R) -> io::Result<Box<dyn Read + Send + 'a>> {
+    Ok(match method {
+        STORED => Box::new(raw),
+        DEFLATE => Box::new(flate2::read::DeflateDecoder::new(raw)),
+        DEFLATE64 => Box::new(deflate64::Deflate64Decoder::new(raw)),
+        BZIP2 => Box::new(bzip2::read::BzDecoder::new(raw)),
+        ZSTD => Box::new(zstd::stream::read::Decoder::with_buffer(BufReader::new(raw))?),
+        _ => return Err(io::Error::new(io::ErrorKind::Unsupported, "Compression method not supported")),
+    })
+}
+
+/// Verifies the CRC32 and size of decoded data once the end is reached.
+pub(crate) struct CrcReader<R> {
+    inner: R,
+    hasher: crc32fast::Hasher,
+    expected_crc: u32,
+    expected_size: u64,
+    size: u64,
+}
+
+impl<R> CrcReader<R> {
+    pub(crate) fn new(inner: R, expected_crc: u32, expected_size: u64) -> Self {
+        CrcReader { inner, hasher: crc32fast::Hasher::new(), expected_crc, expected_size, size: 0 }
+    }
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            if self.hasher.clone().finalize() != self.expected_crc {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid checksum"));
+            }
+            if self.size != self.expected_size {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "decompressed size does not match header"));
+            }
+        }
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+}
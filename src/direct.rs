@@ -0,0 +1,153 @@
+//! Writing large files past the page cache, for
+//! [`crate::RestorerBuilder::direct_io`]: with `O_DIRECT` on Linux,
+//! `FILE_FLAG_NO_BUFFERING` on Windows and `F_NOCACHE` on macOS. A
+//! restore of multi-gigabyte videos otherwise fills the cache with data no
+//! one reads again and pushes out what the rest of the system needs.
+//!
+//! Unbuffered writes must start at and span whole sectors from a buffer
+//! aligned the same way; [`DirectFile`] collects the data in such a buffer
+//! and writes the tail of the file, which rarely ends on a sector, through
+//! a second, ordinary handle.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Alignment of unbuffered writes, a multiple of the sector size of any
+/// disk in use.
+const ALIGN: usize = 4096;
+/// Size of the unbuffered writes.
+const CHUNK: usize = 1 << 20;
+
+/// A file created for writing past the page cache.
+pub(crate) struct DirectFile {
+    file: File,
+    path: PathBuf,
+    /// `None` once the file is written through `file` as is: on macOS, and
+    /// where the file system refused unbuffered I/O.
+    buffer: Option<Aligned>,
+}
+
+impl DirectFile {
+    /// Creates the file at `path`, or truncates it. File systems without
+    /// unbuffered I/O, such as tmpfs, get an ordinary file.
+    pub(crate) fn create(path: &Path) -> io::Result<DirectFile> {
+        let path = path.to_path_buf();
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.custom_flags(libc::O_DIRECT);
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            const FILE_FLAG_NO_BUFFERING: u32 = 0x2000_0000;
+            options.custom_flags(FILE_FLAG_NO_BUFFERING);
+        }
+        if cfg!(any(target_os = "linux", target_os = "android", windows)) {
+            match options.open(&path) {
+                Ok(file) => return Ok(DirectFile { file, path, buffer: Some(Aligned::new()) }),
+                Err(e) if e.kind() != io::ErrorKind::InvalidInput => return Err(e),
+                Err(_) => {}
+            }
+        }
+        let file = File::create(&path)?;
+        #[cfg(target_os = "macos")]
+        {
+            use std::os::fd::AsRawFd;
+            // SAFETY: `file` owns the descriptor; F_NOCACHE only sets a flag.
+            unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) };
+        }
+        Ok(DirectFile { file, path, buffer: None })
+    }
+
+    pub(crate) fn as_file(&self) -> &File {
+        &self.file
+    }
+
+    /// Writes out the buffered chunk, or the part of it that spans whole
+    /// sectors unless `full`.
+    fn drain(&mut self, full: bool) -> io::Result<()> {
+        let Some(buffer) = &mut self.buffer else { return Ok(()) };
+        let aligned = if full { buffer.len } else { buffer.len / ALIGN * ALIGN };
+        match self.file.write_all(&buffer.as_slice()[..aligned]) {
+            Ok(()) => {
+                buffer.consume(aligned);
+                Ok(())
+            }
+            // Some file systems take the flag on open, but not the writes.
+            Err(e) if e.kind() == io::ErrorKind::InvalidInput => self.fall_back(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Goes on through an ordinary handle, starting with what is buffered.
+    fn fall_back(&mut self) -> io::Result<()> {
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        if let Some(buffer) = self.buffer.take() {
+            self.file.write_all(buffer.as_slice())?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for DirectFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(buffer) = &mut self.buffer else { return self.file.write(buf) };
+        let n = buffer.fill(buf);
+        if buffer.is_full() {
+            self.drain(true)?;
+        }
+        Ok(n)
+    }
+
+    /// Writes what is buffered, so the file is complete; further writes
+    /// go through an ordinary handle.
+    fn flush(&mut self) -> io::Result<()> {
+        self.drain(false)?;
+        if self.buffer.is_some() {
+            self.fall_back()?;
+        }
+        self.file.flush()
+    }
+}
+
+/// A [`CHUNK`] of memory aligned to [`ALIGN`].
+struct Aligned {
+    memory: Vec<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl Aligned {
+    fn new() -> Aligned {
+        let memory = vec![0; CHUNK + ALIGN];
+        let start = memory.as_ptr().align_offset(ALIGN);
+        Aligned { memory, start, len: 0 }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.memory[self.start..self.start + self.len]
+    }
+
+    fn is_full(&self) -> bool {
+        self.len == CHUNK
+    }
+
+    /// Appends as much of `data` as fits; returns how much that was.
+    fn fill(&mut self, data: &[u8]) -> usize {
+        let n = data.len().min(CHUNK - self.len);
+        let at = self.start + self.len;
+        self.memory[at..at + n].copy_from_slice(&data[..n]);
+        self.len += n;
+        n
+    }
+
+    /// Drops the first `n` bytes, which were written.
+    fn consume(&mut self, n: usize) {
+        self.memory.copy_within(self.start + n..self.start + self.len, self.start);
+        self.len -= n;
+    }
+}
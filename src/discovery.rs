@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::archive::ArchiveKind;
+use crate::catalog::{BackupRun, BackupTime};
+use crate::file_history::is_file_history_data;
+use crate::filter::glob_match;
+
+/// Collects what can be restored below `dir`: archive files into `files`,
+/// File History `Data` folders into `data_dirs`. `Data` folders are not
+/// searched: the ZIPs in there are user files, not backup archives. If
+/// `dir` is an archive file itself, that is all there is.
+fn collect(dir: &Path, files: &mut Vec<PathBuf>, data_dirs: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !dir.is_dir() {
+        if dir.is_file() && ArchiveKind::of(dir).is_some() {
+            files.push(dir.to_path_buf());
+        }
+        return Ok(());
+    }
+    if is_file_history_data(dir) {
+        data_dirs.push(dir.to_path_buf());
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_dir() {
+            collect(&path, files, data_dirs)?;
+        } else if ArchiveKind::of(&path).is_some() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Compares two strings using natural ordering, so `backup2` sorts before `backup10`.
+pub fn cmp_natural(a: &str, b: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    let ab = a.as_bytes();
+    let bb = b.as_bytes();
+    let mut ai = 0usize;
+    let mut bi = 0usize;
+
+    while ai < ab.len() && bi < bb.len() {
+        let a_digit = ab[ai].is_ascii_digit();
+        let b_digit = bb[bi].is_ascii_digit();
+
+        if a_digit && b_digit {
+            let a_start = ai;
+            while ai < ab.len() && ab[ai].is_ascii_digit() {
+                ai += 1;
+            }
+            let b_start = bi;
+            while bi < bb.len() && bb[bi].is_ascii_digit() {
+                bi += 1;
+            }
+
+            let a_run = &a[a_start..ai];
+            let b_run = &b[b_start..bi];
+            let a_trim = a_run.trim_start_matches('0');
+            let b_trim = b_run.trim_start_matches('0');
+
+            let a_len = a_trim.len();
+            let b_len = b_trim.len();
+            if a_len != b_len {
+                return a_len.cmp(&b_len);
+            }
+            let ord = a_trim.cmp(b_trim);
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            let ord = a_run.len().cmp(&b_run.len());
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        } else {
+            let a_byte = ab[ai].to_ascii_lowercase();
+            let b_byte = bb[bi].to_ascii_lowercase();
+            if a_byte != b_byte {
+                return a_byte.cmp(&b_byte);
+            }
+            ai += 1;
+            bi += 1;
+        }
+    }
+
+    ab.len().cmp(&bb.len())
+}
+
+fn sort_natural(paths: &mut [PathBuf]) {
+    paths.sort_by(|a, b| cmp_natural(&a.to_string_lossy(), &b.to_string_lossy()));
+}
+
+/// Recursively finds all `.zip` files below `source_dir`, in natural order.
+pub fn find_zip_files(source_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut zips = Vec::new();
+    collect(source_dir, &mut zips, &mut Vec::new())?;
+    zips.retain(|path| ArchiveKind::of(path) == Some(ArchiveKind::Zip));
+    sort_natural(&mut zips);
+    Ok(zips)
+}
+
+/// Finds everything below `source_dir` that can be restored, see
+/// [`ArchiveKind`]: the archive files, followed by the File History `Data`
+/// folders, each in natural order. `source_dir` may also be a single
+/// archive, such as a disc image.
+pub fn find_archives(source_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut data_dirs = Vec::new();
+    collect(source_dir, &mut files, &mut data_dirs)?;
+    sort_natural(&mut files);
+    sort_natural(&mut data_dirs);
+    files.extend(data_dirs);
+    Ok(files)
+}
+
+/// The archives of `archives` that `selector` names: a 1-based number in
+/// `archives`, the file name of an archive, or a glob matched against the
+/// file names, ignoring case.
+pub fn select_archives(archives: &[PathBuf], selector: &str) -> Vec<PathBuf> {
+    if let Ok(number) = selector.parse::<usize>() {
+        return number.checked_sub(1).and_then(|i| archives.get(i)).cloned().into_iter().collect();
+    }
+    let selector = selector.to_lowercase();
+    let name = |path: &PathBuf| path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+    let named: Vec<PathBuf> = archives.iter().filter(|path| name(path) == selector).cloned().collect();
+    if !named.is_empty() {
+        return named;
+    }
+    archives.iter().filter(|path| glob_match(selector.as_bytes(), name(path).as_bytes())).cloned().collect()
+}
+
+/// Where an archive sits in the folder hierarchy of Windows 7/8 backups,
+/// `<machine>/Backup Set <time>/Backup Files <time>/Backup files N.zip`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunLocation {
+    pub machine: Option<String>,
+    pub run: BackupRun,
+}
+
+/// Reads the machine and backup run from the folders `archive` lies in, or
+/// `None` if it is not stored in that hierarchy.
+pub fn run_location(archive: &Path) -> Option<RunLocation> {
+    let names: Vec<String> = archive.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+    let k = names.iter().position(|name| name.starts_with("Backup Set ") && BackupTime::of_folder(name).is_some())?;
+    let files = names.get(k + 1).filter(|name| name.starts_with("Backup Files ")).cloned();
+    let time = files.as_deref().or(Some(&names[k])).and_then(BackupTime::of_folder)?;
+    let machine = k.checked_sub(1).map(|m| names[m].clone()).filter(|m| !matches!(m.as_str(), "/" | "." | ".."));
+    Some(RunLocation { machine, run: BackupRun { set: names[k].clone(), files, time } })
+}
+
+/// Keeps the archives of the latest backup set of every machine, that is
+/// its full backup and the incremental runs after it. A full backup
+/// contains everything, so older sets are not needed for the latest state.
+/// Archives outside the backup folder hierarchy are always kept.
+pub fn latest_backup_sets(zips: &[PathBuf]) -> Vec<PathBuf> {
+    let locations: Vec<Option<RunLocation>> = zips.iter().map(|zip| run_location(zip)).collect();
+    let mut latest: HashMap<Option<String>, (BackupTime, String)> = HashMap::new();
+    for location in locations.iter().flatten() {
+        let set_time = BackupTime::of_folder(&location.run.set).expect("validated name");
+        let entry = latest.entry(location.machine.clone()).or_insert((set_time, location.run.set.clone()));
+        if set_time > entry.0 {
+            *entry = (set_time, location.run.set.clone());
+        }
+    }
+    zips.iter()
+        .zip(&locations)
+        .filter(|(_, location)| location.as_ref().is_none_or(|l| latest[&l.machine].1 == l.run.set))
+        .map(|(zip, _)| zip.clone())
+        .collect()
+}
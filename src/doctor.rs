@@ -0,0 +1,497 @@
+//! Diagnostics for `restore doctor`. [`diagnose`] checks the source, the
+//! destination and the limits of the process before a long restore, for
+//! what would otherwise make it fail hours in. A
+//! [`DiagnosticBundle`] gathers what a maintainer needs to reproduce a
+//! problem into one ZIP file: the environment, what the last restore into a
+//! destination left there, and the metadata of every archive of the backup
+//! with the names of the files replaced by hashes, so a damaged central
+//! directory can be looked at without the files or their names.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+use crate::archive::ArchiveKind;
+use crate::backup_set::{BackupSet, EntryInfo};
+use crate::analysis_cache::AnalysisCache;
+use crate::catalog::BackupTime;
+use crate::decode;
+use crate::discovery::find_archives;
+use crate::error::ArchiveError;
+use crate::restorer::QUARANTINE_DIR;
+use crate::state::RunState;
+
+/// The optional features this build was made with.
+pub fn compiled_features() -> Vec<&'static str> {
+    [
+        ("async", cfg!(feature = "async")),
+        ("vhd", cfg!(feature = "vhd")),
+        ("bkf", cfg!(feature = "bkf")),
+        ("sevenz", cfg!(feature = "sevenz")),
+        ("rar", cfg!(feature = "rar")),
+        ("iso", cfg!(feature = "iso")),
+        ("uring", cfg!(feature = "uring")),
+        ("parquet", cfg!(feature = "parquet")),
+        ("self-update", cfg!(feature = "self-update")),
+    ]
+    .into_iter()
+    .filter_map(|(name, on)| on.then_some(name))
+    .collect()
+}
+
+/// Extensions of the archives that only builds with a feature restore,
+/// with the feature.
+const FEATURE_EXTENSIONS: &[(&str, &[&str])] =
+    &[("sevenz", &["7z"]), ("rar", &["rar"]), ("iso", &["iso"]), ("vhd", &["vhd", "vhdx"]), ("bkf", &["bkf"])];
+
+/// ZIP compression methods the zip crate decodes besides those of
+/// [`decode::supported`]: LZMA, XZ and PPMd.
+const ZIP_CRATE_METHODS: [u16; 3] = [14, 95, 98];
+
+/// Longest file name most file systems take, in bytes.
+const MAX_NAME: usize = 255;
+
+/// What [`diagnose`] found.
+#[derive(Debug, Default)]
+pub struct Diagnosis {
+    /// The backup, unless none was given; an error if it has no archives
+    /// or cannot be read at all.
+    pub source: Option<io::Result<SourceCheck>>,
+    pub dest: Option<DestCheck>,
+    /// The optional features of this build, see [`compiled_features`].
+    pub features: Vec<&'static str>,
+    /// The files the process may have open at once, on Unix.
+    pub open_files: Option<u64>,
+    /// The largest file the process may write, on Unix, if it is limited.
+    pub file_size_limit: Option<u64>,
+}
+
+/// What [`diagnose`] found in the backup.
+#[derive(Debug, Default)]
+pub struct SourceCheck {
+    pub archives: usize,
+    /// Archives that cannot be opened, or whose entries cannot all be read.
+    pub unreadable: Vec<ArchiveError>,
+    /// Archives this build leaves out, by the feature that restores them.
+    pub needs_feature: Vec<(&'static str, usize)>,
+    pub files: u64,
+    /// Uncompressed size of the files.
+    pub bytes: u64,
+    pub largest: u64,
+    /// Files compressed with a method this build cannot decode, by method.
+    pub unsupported_methods: BTreeMap<u16, u64>,
+    /// Length in bytes of the longest restore path.
+    pub longest_path: usize,
+    /// Files with a folder or file name longer than 255 bytes.
+    pub long_names: u64,
+    /// Files whose path differs from that of another only in case.
+    pub case_collisions: u64,
+}
+
+/// What [`diagnose`] found in the destination.
+#[derive(Debug)]
+pub struct DestCheck {
+    pub dir: PathBuf,
+    pub exists: bool,
+    /// Why a file could not be created there, if it could not.
+    pub write_error: Option<String>,
+    /// Free space of its file system, if that could be found out.
+    pub available: Option<u64>,
+    /// Whether `a` and `A` are different files there, if that could be
+    /// found out.
+    pub case_sensitive: Option<bool>,
+    /// The length of the longest path that could be created there, when
+    /// shorter than the longest path of the backup needs.
+    pub path_limit: Option<usize>,
+}
+
+impl SourceCheck {
+    /// Whether every file fits into `dest`, as far as known.
+    pub fn fits(&self, dest: &DestCheck) -> bool {
+        dest.available.is_none_or(|available| available >= self.bytes)
+    }
+}
+
+/// Checks that `source` can be read and restored by this build and that
+/// `dest` takes the files: that it can be written to, has the space, takes
+/// the longest path and the file names of the backup differing only in
+/// case; and the limits of the process on open files and file size. The
+/// central directory of every archive is read, from the cache where it
+/// has them.
+pub fn diagnose(source: Option<&Path>, dest: Option<&Path>) -> Diagnosis {
+    let source = source.map(check_source);
+    let longest = source.as_ref().and_then(|check| check.as_ref().ok()).map_or(0, |check| check.longest_path);
+    let (open_files, file_size_limit) = limits();
+    Diagnosis {
+        dest: dest.map(|dest| check_dest(dest, longest)),
+        source,
+        features: compiled_features(),
+        open_files,
+        file_size_limit,
+    }
+}
+
+fn check_source(source: &Path) -> io::Result<SourceCheck> {
+    if !source.exists() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "not found"));
+    }
+    let archives = find_archives(source)?;
+    let mut check = SourceCheck { archives: archives.len(), needs_feature: archives_needing_features(source), ..SourceCheck::default() };
+    if archives.is_empty() {
+        return Ok(check);
+    }
+    let set = BackupSet::from_archives(source, archives).with_cache(AnalysisCache::user());
+    let mut paths: HashMap<String, String> = HashMap::new();
+    for entry in set.entries() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                check.unreadable.push(e);
+                continue;
+            }
+        };
+        if entry.is_dir {
+            continue;
+        }
+        check.files += 1;
+        check.bytes += entry.size;
+        check.largest = check.largest.max(entry.size);
+        if entry.compressed_size > 0 && !decode::supported(entry.method) && !ZIP_CRATE_METHODS.contains(&entry.method) {
+            *check.unsupported_methods.entry(entry.method).or_default() += 1;
+        }
+        let path = entry.path();
+        check.longest_path = check.longest_path.max(path.len());
+        if path.split('/').any(|name| name.len() > MAX_NAME) {
+            check.long_names += 1;
+        }
+        // Incremental runs hold the same path again, in the same case.
+        match paths.get(&path.to_lowercase()) {
+            Some(seen) if seen != path => check.case_collisions += 1,
+            Some(_) => {}
+            None => {
+                paths.insert(path.to_lowercase(), path.to_string());
+            }
+        }
+    }
+    Ok(check)
+}
+
+/// The archives in `source` of the kinds of [`FEATURE_EXTENSIONS`] this
+/// build was made without, by feature.
+fn archives_needing_features(source: &Path) -> Vec<(&'static str, usize)> {
+    let compiled = compiled_features();
+    let missing: Vec<_> = FEATURE_EXTENSIONS.iter().filter(|(feature, _)| !compiled.contains(feature)).collect();
+    let mut counts: BTreeMap<&'static str, usize> = BTreeMap::new();
+    let mut pending = vec![source.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(read) = fs::read_dir(&dir) else { continue };
+        for entry in read.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let Some(extension) = path.extension().map(|e| e.to_string_lossy().to_lowercase()) else { continue };
+            if let Some((feature, _)) = missing.iter().find(|(_, extensions)| extensions.contains(&extension.as_str())) {
+                *counts.entry(feature).or_default() += 1;
+            }
+        }
+    }
+    counts.into_iter().collect()
+}
+
+/// Checks `dest` by creating a scratch folder in it, or in the nearest
+/// folder above it that exists, and removing it again.
+fn check_dest(dest: &Path, longest_path: usize) -> DestCheck {
+    let exists = dest.is_dir();
+    let existing = dest.ancestors().find(|dir| dir.is_dir()).unwrap_or(dest);
+    let mut check = DestCheck {
+        dir: dest.to_path_buf(),
+        exists,
+        write_error: None,
+        available: fs4::available_space(existing).ok(),
+        case_sensitive: None,
+        path_limit: None,
+    };
+    let scratch = existing.join(format!(".restore-doctor-{}", std::process::id()));
+    if let Err(e) = fs::create_dir(&scratch).and_then(|()| fs::write(scratch.join("probe"), b"")) {
+        check.write_error = Some(e.to_string());
+        let _ = fs::remove_dir_all(&scratch);
+        return check;
+    }
+    check.case_sensitive = match fs::metadata(scratch.join("PROBE")) {
+        Ok(_) => Some(false),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Some(true),
+        Err(_) => None,
+    };
+    // The backup's paths go below `dest`, which may be deeper than the
+    // scratch folder.
+    let needed = dest.as_os_str().len() + 1 + longest_path;
+    check.path_limit = Some(longest_creatable(&scratch, needed)).filter(|&limit| limit < needed);
+    let _ = fs::remove_dir_all(&scratch);
+    check
+}
+
+/// Creates folders below `dir` until their path is `needed` bytes long or
+/// one cannot be created; returns the length of the longest path created.
+fn longest_creatable(dir: &Path, needed: usize) -> usize {
+    let mut path = dir.to_path_buf();
+    let mut longest = path.as_os_str().len();
+    while longest < needed {
+        let name = "d".repeat((needed - longest - 1).clamp(1, 200));
+        path.push(name);
+        if fs::create_dir(&path).is_err() {
+            return longest;
+        }
+        longest = path.as_os_str().len();
+    }
+    longest
+}
+
+/// The limits on open files and on the size of files written, where
+/// there are any.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // `rlim_t` is not `u64` everywhere.
+fn limits() -> (Option<u64>, Option<u64>) {
+    let limit = |resource| {
+        let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        // SAFETY: getrlimit only writes the struct it is given.
+        let ok = unsafe { libc::getrlimit(resource, &mut limit) } == 0;
+        (ok && limit.rlim_cur != libc::RLIM_INFINITY).then_some(limit.rlim_cur as u64)
+    };
+    (limit(libc::RLIMIT_NOFILE), limit(libc::RLIMIT_FSIZE))
+}
+
+#[cfg(not(unix))]
+fn limits() -> (Option<u64>, Option<u64>) {
+    (None, None)
+}
+
+/// What goes into a diagnostic bundle besides the environment.
+#[derive(Debug, Clone, Default)]
+pub struct DiagnosticBundle {
+    /// The backup, whose archives are described without file names.
+    pub source: Option<PathBuf>,
+    /// Destination of the last restore, with its log, state file and
+    /// quarantine.
+    pub dest: Option<PathBuf>,
+    /// The summary the restore wrote with `--stats-json`, listing its
+    /// errors.
+    pub stats_json: Option<PathBuf>,
+}
+
+/// What [`DiagnosticBundle::write`] put into the bundle.
+#[derive(Debug, Default)]
+pub struct Bundled {
+    /// Names of the files in the bundle.
+    pub files: Vec<String>,
+    pub archives: usize,
+    pub entries: u64,
+    /// Archives whose entries could not all be read.
+    pub unreadable: usize,
+}
+
+#[derive(Serialize)]
+struct Environment {
+    version: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    family: &'static str,
+    features: Vec<&'static str>,
+    cpus: usize,
+    locale: Vec<(&'static str, String)>,
+}
+
+#[derive(Serialize)]
+struct ArchiveRecord {
+    /// `archive-0001.zip` and so on: the position in the backup and the
+    /// extension, but not the name.
+    archive: String,
+    kind: Option<&'static str>,
+    size: Option<u64>,
+    entries: u64,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct EntryRecord<'a> {
+    archive: &'a str,
+    index: usize,
+    /// The first 16 hex digits of the SHA-256 of the path, with the
+    /// extension of the file.
+    name: String,
+    depth: usize,
+    size: u64,
+    compressed_size: u64,
+    modified: Option<String>,
+    crc32: u32,
+    method: u16,
+    encrypted: bool,
+    is_dir: bool,
+    version_made_by: u16,
+    attributes: u32,
+    /// Id and length of each extra field block; their data may hold the
+    /// name.
+    extra: Vec<(u16, usize)>,
+    placeholder: bool,
+    efs: bool,
+}
+
+impl DiagnosticBundle {
+    /// Writes the bundle as a ZIP file to `out`.
+    pub fn write(&self, out: &Path) -> io::Result<Bundled> {
+        let mut zip = ZipWriter::new(File::create(out)?);
+        let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        let mut bundled = Bundled::default();
+        let mut files = Vec::new();
+        let mut add = |zip: &mut ZipWriter<File>, name: &str, data: &[u8]| -> io::Result<()> {
+            zip.start_file(name, options)?;
+            zip.write_all(data)?;
+            files.push(name.to_string());
+            Ok(())
+        };
+
+        let environment = Environment {
+            version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            family: std::env::consts::FAMILY,
+            features: compiled_features(),
+            cpus: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            locale: ["LC_ALL", "LC_MESSAGES", "LANG", "TZ"]
+                .into_iter()
+                .filter_map(|name| std::env::var(name).ok().map(|value| (name, value)))
+                .collect(),
+        };
+        add(&mut zip, "environment.json", &serde_json::to_vec_pretty(&environment)?)?;
+
+        if let Some(dest) = &self.dest {
+            let state = RunState::path(dest);
+            if let Ok(data) = fs::read(&state) {
+                add(&mut zip, "restore-state.json", &data)?;
+            }
+            // Restores in the background log to a file the state names.
+            if let Some(log) = RunState::read(&state).ok().and_then(|state| state.log)
+                && let Ok(data) = fs::read(log)
+            {
+                add(&mut zip, "restore.log", &data)?;
+            }
+            let markers = quarantine_markers(&dest.join(QUARANTINE_DIR));
+            if !markers.is_empty() {
+                add(&mut zip, "quarantine.txt", markers.as_bytes())?;
+            }
+        }
+        if let Some(path) = &self.stats_json {
+            add(&mut zip, "stats.json", &fs::read(path)?)?;
+        }
+
+        if let Some(source) = &self.source {
+            let (archives, entries) = describe_archives(source, &mut bundled)?;
+            add(&mut zip, "archives.jsonl", &archives)?;
+            add(&mut zip, "entries.jsonl", &entries)?;
+        }
+        zip.finish()?;
+        bundled.files = files;
+        Ok(bundled)
+    }
+}
+
+/// The archives of `source` and their entries as JSON lines, anonymized.
+fn describe_archives(source: &Path, bundled: &mut Bundled) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let archives = find_archives(source)?;
+    let set = BackupSet::from_archives(source, archives.clone());
+    let mut archive_lines = Vec::new();
+    let mut entry_lines = Vec::new();
+    for (i, path) in archives.iter().enumerate() {
+        let kind = ArchiveKind::of(path);
+        let extension = path.extension().map(|e| format!(".{}", e.to_string_lossy().to_lowercase())).unwrap_or_default();
+        let mut record = ArchiveRecord {
+            archive: format!("archive-{:04}{}", i + 1, extension),
+            kind: kind.map(ArchiveKind::label),
+            size: fs::metadata(path).ok().map(|m| m.len()),
+            entries: 0,
+            error: None,
+        };
+        for entry in set.archive_entries(path) {
+            match entry {
+                Ok(entry) => {
+                    serde_json::to_writer(&mut entry_lines, &anonymize(&record.archive, &entry))?;
+                    entry_lines.push(b'\n');
+                    record.entries += 1;
+                }
+                Err(e) => {
+                    record.error = Some(e.source.to_string());
+                    bundled.unreadable += 1;
+                }
+            }
+        }
+        bundled.archives += 1;
+        bundled.entries += record.entries;
+        serde_json::to_writer(&mut archive_lines, &record)?;
+        archive_lines.push(b'\n');
+    }
+    Ok((archive_lines, entry_lines))
+}
+
+fn anonymize<'a>(archive: &'a str, entry: &EntryInfo) -> EntryRecord<'a> {
+    let path = entry.name.trim_end_matches('/');
+    let hash = Sha256::digest(path.as_bytes());
+    let hex: String = hash[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    // Only short, plain extensions, which say nothing about the file.
+    let extension = file_name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_lowercase())
+        .filter(|ext| !entry.is_dir && (1..=5).contains(&ext.len()) && ext.chars().all(|c| c.is_ascii_alphanumeric()));
+    EntryRecord {
+        archive,
+        index: entry.index,
+        name: match extension {
+            Some(ext) => format!("{}.{}", hex, ext),
+            None => hex,
+        },
+        depth: path.matches('/').count(),
+        size: entry.size,
+        compressed_size: entry.compressed_size,
+        modified: entry.modified.map(|t| BackupTime::from(t).to_string()),
+        crc32: entry.crc32,
+        method: entry.method,
+        encrypted: entry.encrypted,
+        is_dir: entry.is_dir,
+        version_made_by: entry.version_made_by,
+        attributes: entry.attributes,
+        extra: extra_blocks(&entry.extra),
+        placeholder: entry.placeholder,
+        efs: entry.efs,
+    }
+}
+
+fn extra_blocks(extra: &[u8]) -> Vec<(u16, usize)> {
+    crate::central_dir::extra_fields(extra).map(|(id, data)| (id, data.len())).collect()
+}
+
+/// The markers of the files moved to the quarantine folder `dir`, one line
+/// each with the error they name.
+fn quarantine_markers(dir: &Path) -> String {
+    let mut lines = String::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(read) = fs::read_dir(&dir) else { continue };
+        for entry in read.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|e| e == "crc-mismatch") {
+                let error = fs::read_to_string(&path).unwrap_or_default();
+                lines.push_str(&format!("{}\n", error.trim()));
+            }
+        }
+    }
+    lines
+}
@@ -0,0 +1,65 @@
+//! Flushing restored files to the disk, for [`crate::SyncPolicy`]. Without
+//! that, a crash or power cut shortly after a restore can leave files that
+//! were reported restored empty or missing.
+
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Flushes the data of the file at `path`, and the entry of its folder.
+pub(crate) fn sync_file(path: &Path) -> io::Result<()> {
+    OpenOptions::new().write(true).open(path)?.sync_all()?;
+    path.parent().map_or(Ok(()), sync_dir)
+}
+
+/// Flushes `files` and their folders, returning what failed. On Linux this
+/// flushes each file system they are on at once.
+pub(crate) fn sync_files(files: &[PathBuf]) -> Vec<(PathBuf, io::Error)> {
+    let mut failed = Vec::new();
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::fd::AsRawFd;
+        use std::os::unix::fs::MetadataExt;
+
+        let mut devices = HashSet::new();
+        for file in files {
+            let Ok(metadata) = file.metadata() else { continue };
+            if !devices.insert(metadata.dev()) {
+                continue;
+            }
+            let synced = File::open(file).and_then(|handle| {
+                // SAFETY: `handle` stays open across the call.
+                match unsafe { libc::syncfs(handle.as_raw_fd()) } {
+                    0 => Ok(()),
+                    _ => Err(io::Error::last_os_error()),
+                }
+            });
+            if let Err(e) = synced {
+                failed.push((file.clone(), e));
+            }
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let mut dirs = HashSet::new();
+        for file in files {
+            if let Err(e) = OpenOptions::new().write(true).open(file).and_then(|handle| handle.sync_all()) {
+                failed.push((file.clone(), e));
+            }
+            if let Some(dir) = file.parent()
+                && dirs.insert(dir)
+                && let Err(e) = sync_dir(dir)
+            {
+                failed.push((dir.to_path_buf(), e));
+            }
+        }
+    }
+    failed
+}
+
+/// Flushes the entries of folder `dir`. Windows has no such call for
+/// folders; NTFS journals them.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    if cfg!(unix) { File::open(dir)?.sync_all() } else { Ok(()) }
+}
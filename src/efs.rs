@@ -0,0 +1,22 @@
+//! Files encrypted with EFS, the Encrypting File System of NTFS. Their data
+//! can only be decrypted with the certificate and private key of the user
+//! account that encrypted them, so a restored copy is unreadable elsewhere
+//! until that key is imported.
+
+/// `FILE_ATTRIBUTE_ENCRYPTED`.
+const ENCRYPTED: u32 = 0x0000_4000;
+
+/// Bytes of file data [`is_raw_export`] looks at.
+pub(crate) const HEADER_LEN: usize = 8;
+
+/// Whether Windows file `attributes` mark an EFS-encrypted file.
+pub(crate) fn is_encrypted(attributes: Option<u32>) -> bool {
+    attributes.is_some_and(|attributes| attributes & ENCRYPTED != 0)
+}
+
+/// Whether file data starting with `head` is an EFS file as exported by
+/// `ReadEncryptedFileRaw`, the form Windows Backup stores these files in:
+/// a header with the signature `ROBS`, followed by the encrypted streams.
+pub(crate) fn is_raw_export(head: &[u8]) -> bool {
+    head.get(4..8) == Some(b"ROBS")
+}
@@ -0,0 +1,198 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Serialize, Serializer};
+use zip::result::ZipError;
+
+type Source = Arc<dyn StdError + Send + Sync>;
+
+/// What went wrong, independent of where it happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// The decompressed data does not match the stored CRC32.
+    BadCrc,
+    /// The archive or entry data is damaged.
+    Corrupt,
+    UnsupportedCompression,
+    Encrypted,
+    PermissionDenied,
+    DiskFull,
+    /// The path is too long or not valid on the destination filesystem.
+    PathTooLong,
+    NotFound,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn of_io(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => ErrorKind::DiskFull,
+            io::ErrorKind::InvalidFilename => ErrorKind::PathTooLong,
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::InvalidData if error.to_string().contains("checksum") => ErrorKind::BadCrc,
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => ErrorKind::Corrupt,
+            io::ErrorKind::Unsupported if error.to_string().contains("encrypted") => ErrorKind::Encrypted,
+            _ => match error.get_ref().and_then(|e| e.downcast_ref::<ZipError>()) {
+                Some(zip_error) => ErrorKind::of_zip(zip_error),
+                None => ErrorKind::Other,
+            },
+        }
+    }
+
+    pub fn of_zip(error: &ZipError) -> Self {
+        match error {
+            ZipError::Io(e) => ErrorKind::of_io(e),
+            ZipError::InvalidArchive(_) => ErrorKind::Corrupt,
+            ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED) | ZipError::InvalidPassword => ErrorKind::Encrypted,
+            ZipError::UnsupportedArchive(msg) if msg.contains("ompression") => ErrorKind::UnsupportedCompression,
+            ZipError::FileNotFound => ErrorKind::NotFound,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            ErrorKind::BadCrc => "bad CRC",
+            ErrorKind::Corrupt => "corrupt data",
+            ErrorKind::UnsupportedCompression => "unsupported compression",
+            ErrorKind::Encrypted => "encrypted",
+            ErrorKind::PermissionDenied => "permission denied",
+            ErrorKind::DiskFull => "disk full",
+            ErrorKind::PathTooLong => "path too long",
+            ErrorKind::NotFound => "not found",
+            ErrorKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// The step of extracting an entry that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    /// Reading the entry from the archive: its header, or the start of its
+    /// data read ahead of writing it.
+    Read,
+    CreateDir,
+    Create,
+    /// Decompressing the entry into the destination file.
+    Write,
+    /// Flushing the restored file to the disk, see [`crate::SyncPolicy`].
+    Sync,
+    /// Making the restored file read-only, see
+    /// [`crate::RestorerBuilder::make_readonly`].
+    Protect,
+    /// Handing the restored file to its owner, see
+    /// [`crate::RestorerBuilder::owner`].
+    Chown,
+    /// Setting the mode of the restored file, see
+    /// [`crate::RestorerBuilder::file_mode`].
+    Chmod,
+    /// Setting the times of the restored file, see
+    /// [`crate::RestorerBuilder::preserve_times`].
+    SetTimes,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Read => "entry",
+            Operation::CreateDir => "mkdir",
+            Operation::Create => "create",
+            Operation::Write => "write",
+            Operation::Sync => "sync",
+            Operation::Protect => "make read-only",
+            Operation::Chown => "chown",
+            Operation::Chmod => "chmod",
+            Operation::SetTimes => "set times",
+        })
+    }
+}
+
+fn archive_name(archive: &Path) -> std::borrow::Cow<'_, str> {
+    archive.file_name().unwrap_or_default().to_string_lossy()
+}
+
+fn serialize_display<T: fmt::Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+/// A single entry that could not be restored.
+#[derive(Debug, Clone, thiserror::Error, Serialize)]
+#[error("{}: {operation} {entry}: {source}", archive_name(.archive))]
+pub struct EntryError {
+    pub archive: PathBuf,
+    pub entry: String,
+    pub operation: Operation,
+    pub kind: ErrorKind,
+    #[serde(rename = "message", serialize_with = "serialize_display")]
+    pub source: Source,
+}
+
+impl EntryError {
+    pub fn io(archive: &Path, entry: &str, operation: Operation, source: io::Error) -> Self {
+        let kind = ErrorKind::of_io(&source);
+        EntryError { archive: archive.to_path_buf(), entry: entry.to_string(), operation, kind, source: Arc::new(source) }
+    }
+
+    pub fn zip(archive: &Path, entry: &str, operation: Operation, source: ZipError) -> Self {
+        let kind = ErrorKind::of_zip(&source);
+        EntryError { archive: archive.to_path_buf(), entry: entry.to_string(), operation, kind, source: Arc::new(source) }
+    }
+}
+
+/// An archive that could not be opened at all.
+#[derive(Debug, Clone, thiserror::Error, Serialize)]
+#[error("{}: {source}", archive_name(.archive))]
+pub struct ArchiveError {
+    pub archive: PathBuf,
+    pub kind: ErrorKind,
+    #[serde(rename = "message", serialize_with = "serialize_display")]
+    pub source: Source,
+}
+
+impl ArchiveError {
+    pub fn io(archive: &Path, source: io::Error) -> Self {
+        ArchiveError { archive: archive.to_path_buf(), kind: ErrorKind::of_io(&source), source: Arc::new(source) }
+    }
+
+    pub fn zip(archive: &Path, source: ZipError) -> Self {
+        ArchiveError { archive: archive.to_path_buf(), kind: ErrorKind::of_zip(&source), source: Arc::new(source) }
+    }
+}
+
+/// An error recorded during a run without aborting it.
+#[derive(Debug, Clone, thiserror::Error, Serialize)]
+#[serde(untagged)]
+pub enum Error {
+    #[error(transparent)]
+    Archive(#[from] ArchiveError),
+    #[error(transparent)]
+    Entry(#[from] EntryError),
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Archive(e) => e.kind,
+            Error::Entry(e) => e.kind,
+        }
+    }
+
+    pub fn archive(&self) -> &Path {
+        match self {
+            Error::Archive(e) => &e.archive,
+            Error::Entry(e) => &e.archive,
+        }
+    }
+}
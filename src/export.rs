@@ -0,0 +1,215 @@
+//! Exporting the entries of every archive of a backup, for
+//! `restore index export`, so the whole inventory can be analyzed with
+//! other tools: CSV for spreadsheets, Parquet (feature `parquet`) for pandas,
+//! DuckDB and Spark.
+
+use std::io::{self, Write};
+
+use crate::backup_set::{BackupSet, EntryInfo};
+use crate::catalog::BackupTime;
+use crate::error::ArchiveError;
+
+/// File format of [`export`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// Comma-separated values with a header line.
+    #[default]
+    Csv,
+    /// Apache Parquet, compressed with zstd; needs the `parquet` feature.
+    Parquet,
+}
+
+/// What [`export`] wrote.
+#[derive(Debug, Default)]
+pub struct Exported {
+    pub entries: u64,
+    pub errors: Vec<ArchiveError>,
+}
+
+/// The columns, in order: the archive, the restore path, the entry name as
+/// stored, sizes, the modification time, the CRC-32, the compression method
+/// and the flags of [`EntryInfo`].
+const COLUMNS: [&str; 12] = [
+    "archive",
+    "path",
+    "name",
+    "size",
+    "compressed_size",
+    "modified",
+    "crc32",
+    "method",
+    "encrypted",
+    "is_dir",
+    "placeholder",
+    "efs",
+];
+
+/// Writes the entries of every archive of `set` to `out` in `format`, one
+/// row each, folders included.
+pub fn export(set: &BackupSet, format: ExportFormat, out: impl Write + Send) -> io::Result<Exported> {
+    match format {
+        ExportFormat::Csv => export_csv(set, out),
+        #[cfg(feature = "parquet")]
+        ExportFormat::Parquet => parquet::export(set, out),
+        #[cfg(not(feature = "parquet"))]
+        ExportFormat::Parquet => {
+            let _ = out;
+            Err(io::Error::new(io::ErrorKind::Unsupported, "this build has no Parquet support (feature `parquet`)"))
+        }
+    }
+}
+
+fn export_csv(set: &BackupSet, out: impl Write) -> io::Result<Exported> {
+    let mut out = io::BufWriter::new(out);
+    let mut exported = Exported::default();
+    writeln!(out, "{}", COLUMNS.join(","))?;
+    for entry in set.entries() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                exported.errors.push(e);
+                continue;
+            }
+        };
+        let modified = entry.modified.map(|t| BackupTime::from(t).to_string()).unwrap_or_default();
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            csv_field(&entry.archive.to_string_lossy()),
+            csv_field(entry.path()),
+            csv_field(&entry.name),
+            entry.size,
+            entry.compressed_size,
+            modified,
+            entry.crc32,
+            entry.method,
+            entry.encrypted,
+            entry.is_dir,
+            entry.placeholder,
+            entry.efs
+        )?;
+        exported.entries += 1;
+    }
+    out.flush()?;
+    Ok(exported)
+}
+
+/// `text` as a CSV field, quoted if it holds a separator or quote.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) { format!("\"{}\"", text.replace('"', "\"\"")) } else { text.to_string() }
+}
+
+/// Milliseconds from the Unix epoch to the modification time of `entry`,
+/// taken as UTC: DOS times have no zone, so Parquet gets them as local
+/// times.
+#[cfg_attr(not(feature = "parquet"), allow(dead_code))]
+fn millis(entry: &EntryInfo) -> Option<i64> {
+    let t = entry.modified?;
+    let date = chrono::NaiveDate::from_ymd_opt(i32::from(t.year()), u32::from(t.month()), u32::from(t.day()))?;
+    let time = date.and_hms_opt(u32::from(t.hour()), u32::from(t.minute()), u32::from(t.second()))?;
+    Some(time.and_utc().timestamp_millis())
+}
+
+#[cfg(feature = "parquet")]
+mod parquet {
+    use std::io::{self, Write};
+    use std::sync::Arc;
+
+    use parquet::basic::{Compression, ZstdLevel};
+    use parquet::data_type::{BoolType, ByteArray, ByteArrayType, Int32Type, Int64Type};
+    use parquet::errors::ParquetError;
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+    use parquet::schema::parser::parse_message_type;
+
+    use super::{Exported, millis};
+    use crate::backup_set::{BackupSet, EntryInfo};
+
+    /// Entries per row group; a few megabytes of memory.
+    const ROW_GROUP: usize = 65_536;
+
+    const SCHEMA: &str = "message entry {
+        REQUIRED BYTE_ARRAY archive (STRING);
+        REQUIRED BYTE_ARRAY path (STRING);
+        REQUIRED BYTE_ARRAY name (STRING);
+        REQUIRED INT64 size (INTEGER(64, false));
+        REQUIRED INT64 compressed_size (INTEGER(64, false));
+        OPTIONAL INT64 modified (TIMESTAMP(MILLIS, false));
+        REQUIRED INT32 crc32 (INTEGER(32, false));
+        REQUIRED INT32 method (INTEGER(16, false));
+        REQUIRED BOOLEAN encrypted;
+        REQUIRED BOOLEAN is_dir;
+        REQUIRED BOOLEAN placeholder;
+        REQUIRED BOOLEAN efs;
+    }";
+
+    fn io_error(e: ParquetError) -> io::Error {
+        io::Error::other(e)
+    }
+
+    pub(super) fn export(set: &BackupSet, out: impl Write + Send) -> io::Result<Exported> {
+        let schema = Arc::new(parse_message_type(SCHEMA).map_err(io_error)?);
+        let properties = WriterProperties::builder().set_compression(Compression::ZSTD(ZstdLevel::default())).build();
+        let mut writer = SerializedFileWriter::new(out, schema, Arc::new(properties)).map_err(io_error)?;
+        let mut exported = Exported::default();
+        let mut rows = Vec::with_capacity(ROW_GROUP);
+        for entry in set.entries() {
+            match entry {
+                Ok(entry) => rows.push(entry),
+                Err(e) => exported.errors.push(e),
+            }
+            if rows.len() == ROW_GROUP {
+                write_group(writer.next_row_group().map_err(io_error)?, &rows).map_err(io_error)?;
+                exported.entries += rows.len() as u64;
+                rows.clear();
+            }
+        }
+        if !rows.is_empty() {
+            write_group(writer.next_row_group().map_err(io_error)?, &rows).map_err(io_error)?;
+            exported.entries += rows.len() as u64;
+        }
+        writer.close().map_err(io_error)?;
+        Ok(exported)
+    }
+
+    fn write_group<W: Write + Send>(mut group: SerializedRowGroupWriter<'_, W>, rows: &[EntryInfo]) -> Result<(), ParquetError> {
+        let text = |value: fn(&EntryInfo) -> String| rows.iter().map(|entry| ByteArray::from(value(entry).into_bytes())).collect::<Vec<_>>();
+        let texts = [
+            text(|entry| entry.archive.to_string_lossy().into_owned()),
+            text(|entry| entry.path().to_string()),
+            text(|entry| entry.name.clone()),
+        ];
+        for values in texts {
+            let mut column = group.next_column()?.expect("column of the schema");
+            column.typed::<ByteArrayType>().write_batch(&values, None, None)?;
+            column.close()?;
+        }
+        for value in [|entry: &EntryInfo| entry.size, |entry: &EntryInfo| entry.compressed_size] {
+            let values: Vec<i64> = rows.iter().map(|entry| value(entry) as i64).collect();
+            let mut column = group.next_column()?.expect("column of the schema");
+            column.typed::<Int64Type>().write_batch(&values, None, None)?;
+            column.close()?;
+        }
+        let modified: Vec<i64> = rows.iter().filter_map(millis).collect();
+        let defined: Vec<i16> = rows.iter().map(|entry| i16::from(millis(entry).is_some())).collect();
+        let mut column = group.next_column()?.expect("column of the schema");
+        column.typed::<Int64Type>().write_batch(&modified, Some(&defined), None)?;
+        column.close()?;
+        for value in [|entry: &EntryInfo| entry.crc32 as i32, |entry: &EntryInfo| i32::from(entry.method)] {
+            let values: Vec<i32> = rows.iter().map(value).collect();
+            let mut column = group.next_column()?.expect("column of the schema");
+            column.typed::<Int32Type>().write_batch(&values, None, None)?;
+            column.close()?;
+        }
+        let flags: [fn(&EntryInfo) -> bool; 4] =
+            [|entry| entry.encrypted, |entry| entry.is_dir, |entry| entry.placeholder, |entry| entry.efs];
+        for value in flags {
+            let values: Vec<bool> = rows.iter().map(value).collect();
+            let mut column = group.next_column()?.expect("column of the schema");
+            column.typed::<BoolType>().write_batch(&values, None, None)?;
+            column.close()?;
+        }
+        group.close()?;
+        Ok(())
+    }
+}
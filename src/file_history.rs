@@ -0,0 +1,109 @@
+//! Windows File History folders.
+//!
+//! File History does not write archives: it copies changed files to
+//! `FileHistory/<user>/<machine>/Data/<drive>/...` and appends the time of
+//! the copy to every file name, as in `report (2015_03_12 10_23_45 UTC).docx`.
+//! A `Data` folder is read like one archive whose entries are the files under
+//! their original names, each in its latest version.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::archive::{ArchiveReader, Member};
+use crate::catalog::BackupTime;
+
+/// Whether `dir` is the `Data` folder of a File History configuration.
+pub fn is_file_history_data(dir: &Path) -> bool {
+    dir.file_name().is_some_and(|name| name.eq_ignore_ascii_case("Data"))
+        && dir.parent().is_some_and(|parent| parent.join("Configuration").is_dir())
+        && dir.is_dir()
+}
+
+/// Splits the version time off a File History file name:
+/// `report (2015_03_12 10_23_45 UTC).docx` becomes `report.docx`.
+pub fn split_version(file_name: &str) -> Option<(String, BackupTime)> {
+    const SUFFIX_LEN: usize = " (2015_03_12 10_23_45 UTC)".len();
+    let (stem, ext) = match file_name.rfind('.') {
+        Some(dot) if file_name[..dot].ends_with(" UTC)") => file_name.split_at(dot),
+        _ => (file_name, ""),
+    };
+    let split = stem.len().checked_sub(SUFFIX_LEN)?;
+    let suffix = stem.get(split..)?;
+    let stamp = suffix.strip_prefix(" (")?.strip_suffix(" UTC)")?;
+    // `2015_03_12 10_23_45` into the `2015-03-12 102345` of backup folders.
+    let (date, time) = stamp.split_once(' ')?;
+    let time = BackupTime::parse(&format!("{} {}", date.replace('_', "-"), time.replace('_', "")))?;
+    Some((format!("{}{}", &stem[..split], ext), time))
+}
+
+/// One restorable file of a File History folder.
+#[derive(Debug, Clone)]
+pub(crate) struct TreeEntry {
+    /// Path below `Data` with `/` separators and without version suffix.
+    pub(crate) name: String,
+    pub(crate) path: PathBuf,
+    pub(crate) size: u64,
+    pub(crate) version: Option<BackupTime>,
+}
+
+/// The latest version of every file below a File History `Data` folder,
+/// sorted by name.
+#[derive(Debug)]
+pub(crate) struct FileTree {
+    pub(crate) entries: Vec<TreeEntry>,
+}
+
+impl FileTree {
+    pub(crate) fn scan(root: &Path) -> io::Result<Self> {
+        let mut latest: HashMap<String, TreeEntry> = HashMap::new();
+        let mut dirs = vec![(root.to_path_buf(), String::new())];
+        while let Some((dir, prefix)) = dirs.pop() {
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                let file_type = entry.file_type()?;
+                if file_type.is_dir() {
+                    dirs.push((entry.path(), format!("{}{}/", prefix, file_name)));
+                    continue;
+                }
+                let (name, version) = match split_version(&file_name) {
+                    Some((name, time)) => (name, Some(time)),
+                    None => (file_name, None),
+                };
+                let name = format!("{}{}", prefix, name);
+                let candidate = TreeEntry { name: name.clone(), path: entry.path(), size: entry.metadata()?.len(), version };
+                match latest.get(&name) {
+                    Some(existing) if existing.version >= candidate.version => {}
+                    _ => {
+                        latest.insert(name, candidate);
+                    }
+                }
+            }
+        }
+        let mut entries: Vec<TreeEntry> = latest.into_values().collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(FileTree { entries })
+    }
+
+    pub(crate) fn size(&self) -> u64 {
+        self.entries.iter().map(|e| e.size).sum()
+    }
+}
+
+impl ArchiveReader for FileTree {
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn member(&self, j: usize) -> Member<'_> {
+        let entry = &self.entries[j];
+        Member { name: &entry.name, size: entry.size, modified: entry.version, attributes: None }
+    }
+
+    fn read(&self, j: usize, f: &mut dyn FnMut(&mut dyn Read)) -> io::Result<()> {
+        f(&mut fs::File::open(&self.entries[j].path)?);
+        Ok(())
+    }
+}
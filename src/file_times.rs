@@ -0,0 +1,180 @@
+//! The times of restored files, for [`crate::RestorerBuilder::preserve_times`].
+//! ZIP entries always record the modification time in DOS form, local time
+//! to two seconds; archivers that run on NTFS or Unix add the exact times in
+//! an extra field, which is what tools syncing by timestamp compare later.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{Local, NaiveDate, TimeZone};
+use chrono_tz::Tz;
+use zip::ExtraField;
+
+use crate::catalog::BackupTime;
+
+/// Seconds from 1601, where Windows file times start, to 1970.
+const FILETIME_TO_UNIX: u64 = 11_644_473_600;
+
+/// The times an archive records for an entry.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timestamps {
+    modified: SystemTime,
+    accessed: Option<SystemTime>,
+    created: Option<SystemTime>,
+}
+
+impl Timestamps {
+    /// The times of the NTFS (`0x000a`) or, failing that, the extended
+    /// timestamp (`0x5455`) extra field among `fields`.
+    pub(crate) fn from_extra<'a>(fields: impl Iterator<Item = &'a ExtraField>) -> Option<Timestamps> {
+        let mut unix = None;
+        for field in fields {
+            match field {
+                ExtraField::Ntfs(ntfs) => {
+                    let Some(modified) = from_filetime(ntfs.mtime()) else { continue };
+                    return Some(Timestamps {
+                        modified,
+                        accessed: from_filetime(ntfs.atime()),
+                        created: from_filetime(ntfs.ctime()),
+                    });
+                }
+                ExtraField::ExtendedTimestamp(timestamp) => {
+                    let from_unix = |secs: u32| UNIX_EPOCH + Duration::from_secs(secs.into());
+                    unix = timestamp.mod_time().map(|modified| Timestamps {
+                        modified: from_unix(modified),
+                        accessed: timestamp.ac_time().map(from_unix),
+                        created: timestamp.cr_time().map(from_unix),
+                    });
+                }
+            }
+        }
+        unix
+    }
+
+    /// The DOS modification time `modified`, in the local time of `zone`,
+    /// that of the machine backed up, or of this one if it is not known.
+    pub(crate) fn from_dos(modified: BackupTime, zone: Option<Tz>) -> Option<Timestamps> {
+        let naive = NaiveDate::from_ymd_opt(modified.year.into(), modified.month.into(), modified.day.into())?
+            .and_hms_opt(modified.hour.into(), modified.minute.into(), modified.second.into())?;
+        let modified = match zone {
+            Some(zone) => zone.from_local_datetime(&naive).earliest()?.into(),
+            None => Local.from_local_datetime(&naive).earliest()?.into(),
+        };
+        Some(Timestamps { modified, accessed: None, created: None })
+    }
+
+    /// Gives the file at `path` these times. The creation time is set on
+    /// Windows and macOS, where the file system keeps one; elsewhere it
+    /// cannot be.
+    pub(crate) fn apply(&self, path: &Path) -> io::Result<()> {
+        self.apply_to(&OpenOptions::new().write(true).open(path)?)
+    }
+
+    fn apply_to(&self, file: &File) -> io::Result<()> {
+        let mut times = fs::FileTimes::new().set_modified(self.modified);
+        if let Some(accessed) = self.accessed {
+            times = times.set_accessed(accessed);
+        }
+        // File systems that keep no creation time may refuse all times
+        // along with it.
+        if let Some(all) = self.created.and_then(|created| with_created(times, created))
+            && file.set_times(all).is_ok()
+        {
+            return Ok(());
+        }
+        file.set_times(times)
+    }
+}
+
+/// `times` with the creation time `created`, where that can be set.
+fn with_created(times: fs::FileTimes, created: SystemTime) -> Option<fs::FileTimes> {
+    #[cfg(windows)]
+    return Some(std::os::windows::fs::FileTimesExt::set_created(times, created));
+    #[cfg(target_os = "macos")]
+    return Some(std::os::macos::fs::FileTimesExt::set_created(times, created));
+    #[cfg(not(any(windows, target_os = "macos")))]
+    {
+        let _ = (times, created);
+        None
+    }
+}
+
+/// The times of restored folders, set once everything is restored: the
+/// times of their entry in the archive if there is one, the modification
+/// time of the newest file in them otherwise. Writing a file into a folder
+/// changes its modification time, so it cannot be set earlier.
+#[derive(Default)]
+pub(crate) struct DirTimes {
+    dirs: Mutex<HashMap<PathBuf, DirTime>>,
+}
+
+#[derive(Default)]
+struct DirTime {
+    entry: Option<Timestamps>,
+    newest: Option<SystemTime>,
+}
+
+impl DirTimes {
+    /// Records the times of the entry of folder `dir`.
+    pub(crate) fn entry(&self, dir: PathBuf, times: Timestamps) {
+        self.dirs.lock().unwrap().entry(dir).or_default().entry = Some(times);
+    }
+
+    /// Records `times` of a file restored into each of `dirs`.
+    pub(crate) fn file<'a>(&self, dirs: impl Iterator<Item = &'a Path>, times: &Timestamps) {
+        let mut recorded = self.dirs.lock().unwrap();
+        for dir in dirs {
+            let newest = &mut recorded.entry(dir.to_path_buf()).or_default().newest;
+            *newest = (*newest).max(Some(times.modified));
+        }
+    }
+
+    /// Sets the times of the folders restored from an entry or with files
+    /// restored into them, the deepest first. Folders whose times cannot be
+    /// set keep those they have.
+    pub(crate) fn apply(&self) {
+        let mut dirs: Vec<_> = std::mem::take(&mut *self.dirs.lock().unwrap()).into_iter().collect();
+        dirs.sort_by_key(|(dir, _)| std::cmp::Reverse(dir.components().count()));
+        for (dir, time) in dirs {
+            let Some(times) = time.entry.or(time.newest.map(|modified| Timestamps { modified, accessed: None, created: None })) else {
+                continue;
+            };
+            if let Ok(file) = open_dir(&dir) {
+                let _ = times.apply_to(&file);
+            }
+        }
+    }
+}
+
+/// Opens folder `dir` to set its times. Windows opens folders only with
+/// backup semantics.
+fn open_dir(dir: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.read(true);
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_WRITE_ATTRIBUTES: u32 = 0x0100;
+        const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+        options.access_mode(FILE_WRITE_ATTRIBUTES).custom_flags(FILE_FLAG_BACKUP_SEMANTICS);
+    }
+    options.open(dir)
+}
+
+/// The Windows file time `time`, in 100 ns from 1601; `None` for 0, which
+/// archivers write for times they do not know.
+fn from_filetime(time: u64) -> Option<SystemTime> {
+    if time == 0 {
+        return None;
+    }
+    let since_1601 = Duration::from_nanos(time.saturating_mul(100));
+    let offset = Duration::from_secs(FILETIME_TO_UNIX);
+    match since_1601.checked_sub(offset) {
+        Some(since_epoch) => UNIX_EPOCH.checked_add(since_epoch),
+        None => UNIX_EPOCH.checked_sub(offset - since_1601),
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::HashSet;
+
+use regex::Regex;
+
+use crate::catalog::BackupTime;
+use crate::query::Query;
+
+/// Include/exclude rules applied to entry paths after the drive letter is
+/// stripped, e.g. `Users/bob/Documents/a.txt`.
+///
+/// Patterns are globs matched case-insensitively: `*` and `?` stay within one
+/// path segment, `**` spans segments and `[a-z]`/`[!0-9]` match character
+/// classes. A pattern without `/` is matched against the file name only.
+/// Regular expressions, for what globs cannot say such as dates in file
+/// names, are matched against the whole path anywhere in it. An entry is
+/// restored when it matches any include pattern or expression (or none are
+/// given) and no exclude pattern or expression, and the personal folders,
+/// [`Query`] and selection if there are.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    include_regex: Vec<RegexPattern>,
+    exclude_regex: Vec<RegexPattern>,
+    /// Patterns of the personal folders, of which a file must match one.
+    user_data: Vec<String>,
+    query: Option<Query>,
+    /// Paths of the selection, in lowercase.
+    selection: Option<HashSet<String>>,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Includes the entries whose path matches `regex`; build it with
+    /// `(?i)` or case-insensitive to ignore case as globs do.
+    pub fn include_regex(mut self, regex: Regex) -> Self {
+        self.include_regex.push(RegexPattern(regex));
+        self
+    }
+
+    pub fn exclude_regex(mut self, regex: Regex) -> Self {
+        self.exclude_regex.push(RegexPattern(regex));
+        self
+    }
+
+    /// Keeps only the files matching `query` too, such as those of
+    /// `restore query` for `--from-query`.
+    pub fn query(mut self, query: Query) -> Self {
+        self.query = Some(query);
+        self
+    }
+
+    /// Keeps only the files at `paths` too, such as those of a selection
+    /// file for `--selection`. Paths compare ignoring case, with or without
+    /// a drive letter.
+    pub fn selection<S: AsRef<str>>(mut self, paths: impl IntoIterator<Item = S>) -> Self {
+        let selection = self.selection.get_or_insert_with(HashSet::new);
+        selection.extend(paths.into_iter().map(|path| selection_key(path.as_ref())));
+        self
+    }
+
+    /// Keeps only the files in the personal folders (Documents, Desktop,
+    /// Pictures, Music, Videos and Downloads) of `user`, or of every user if
+    /// `None`, too; include patterns narrow them further. Calling this for
+    /// several users keeps the folders of each.
+    pub fn user_data(mut self, user: Option<&str>) -> Self {
+        let user = user.map_or_else(|| "*".to_string(), escape);
+        for (profiles, folders) in PERSONAL_FOLDERS {
+            for folder in *folders {
+                self.user_data.push(format!("**/{}/{}/{}/**", profiles, user, folder));
+            }
+        }
+        self
+    }
+
+    /// Includes the Outlook data files (`.pst`, `.ost`) in the profile of
+    /// `user`, or anywhere in the backup if `None`.
+    pub fn include_mail(mut self, user: Option<&str>) -> Self {
+        for ext in crate::mail::EXTENSIONS {
+            match user {
+                Some(user) => {
+                    for profiles in ["Users", "Documents and Settings"] {
+                        self.include.push(format!("**/{}/{}/**/*.{}", profiles, escape(user), ext));
+                    }
+                }
+                None => self.include.push(format!("*.{}", ext)),
+            }
+        }
+        self
+    }
+
+    /// Excludes every pattern of `preset`.
+    pub fn exclude_preset(mut self, preset: ExcludePreset) -> Self {
+        self.exclude.extend(preset.patterns().into_iter().map(String::from));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty()
+            && self.exclude.is_empty()
+            && self.include_regex.is_empty()
+            && self.exclude_regex.is_empty()
+            && self.user_data.is_empty()
+            && self.query.is_none()
+            && self.selection.is_none()
+    }
+
+    /// Whether `path` matches the patterns; the personal folders, query and
+    /// selection take [`matches_file`](Self::matches_file).
+    pub fn matches(&self, path: &str) -> bool {
+        let included = (self.include.is_empty() && self.include_regex.is_empty())
+            || self.include.iter().any(|p| glob_matches_path(p, path))
+            || self.include_regex.iter().any(|RegexPattern(r)| r.is_match(path));
+        included
+            && !self.exclude.iter().any(|p| glob_matches_path(p, path))
+            && !self.exclude_regex.iter().any(|RegexPattern(r)| r.is_match(path))
+    }
+
+    /// Whether the file at `path`, of `size` bytes last modified at
+    /// `modified`, matches the patterns, the personal folders, the query
+    /// and the selection.
+    pub fn matches_file(&self, path: &str, size: u64, modified: Option<BackupTime>) -> bool {
+        self.matches(path)
+            && (self.user_data.is_empty() || self.user_data.iter().any(|p| glob_matches_path(p, path)))
+            && self.query.as_ref().is_none_or(|query| query.matches(path, size, modified))
+            && self.selection.as_ref().is_none_or(|selection| selection.contains(&selection_key(path)))
+    }
+}
+
+/// A regular expression of a [`Filter`], equal to another of the same
+/// source.
+#[derive(Debug, Clone)]
+struct RegexPattern(Regex);
+
+impl PartialEq for RegexPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for RegexPattern {}
+
+/// Named sets of exclude patterns for files that are rarely worth
+/// restoring, see [`Filter::exclude_preset`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExcludePreset {
+    /// Temporary folders of the users and of Windows.
+    Temp,
+    /// Browser caches, thumbnail caches and crash dumps, which are rebuilt
+    /// on demand.
+    Caches,
+    /// Dependencies and build output of software projects, such as
+    /// `node_modules`, which are downloaded or built again.
+    Dev,
+    /// All of the above.
+    Junk,
+}
+
+impl ExcludePreset {
+    /// The glob patterns of the preset, in [`Filter`] syntax.
+    pub fn patterns(self) -> Vec<&'static str> {
+        let patterns: &[&str] = match self {
+            ExcludePreset::Temp => &[
+                "**/AppData/Local/Temp/**",
+                // Windows XP profiles
+                "**/Local Settings/Temp/**",
+                "Windows/Temp/**",
+                "$Recycle.Bin/**",
+            ],
+            ExcludePreset::Caches => &[
+                "**/AppData/Local/Google/Chrome/User Data/*/Cache/**",
+                "**/AppData/Local/Google/Chrome/User Data/*/Code Cache/**",
+                "**/AppData/Local/Google/Chrome/User Data/*/GPUCache/**",
+                "**/AppData/Local/Microsoft/Edge/User Data/*/Cache/**",
+                "**/AppData/Local/Microsoft/Edge/User Data/*/Code Cache/**",
+                "**/AppData/Local/Microsoft/Edge/User Data/*/GPUCache/**",
+                "**/AppData/Local/Mozilla/Firefox/Profiles/*/cache2/**",
+                "**/AppData/Local/Microsoft/Windows/INetCache/**",
+                "**/Local Settings/Temporary Internet Files/**",
+                "**/AppData/Local/Microsoft/Windows/Explorer/thumbcache_*.db",
+                "**/AppData/Local/Microsoft/Windows/Explorer/iconcache_*.db",
+                "**/AppData/Local/CrashDumps/**",
+                "Thumbs.db",
+            ],
+            ExcludePreset::Dev => &[
+                "**/node_modules/**",
+                "**/__pycache__/**",
+                "**/.gradle/caches/**",
+                "**/AppData/Local/npm-cache/**",
+                "**/AppData/Local/pip/cache/**",
+                "**/.nuget/packages/**",
+            ],
+            ExcludePreset::Junk => {
+                let all = [ExcludePreset::Temp, ExcludePreset::Caches, ExcludePreset::Dev];
+                return all.into_iter().flat_map(ExcludePreset::patterns).collect();
+            }
+        };
+        patterns.to_vec()
+    }
+}
+
+/// The folders below each profile that hold the user's own files, for
+/// Windows Vista and later (`Users`, also when redirected to OneDrive) and
+/// Windows XP (`Documents and Settings`, with Pictures, Music and Videos
+/// inside My Documents).
+const PERSONAL_FOLDERS: &[(&str, &[&str])] = &[
+    (
+        "Users",
+        &[
+            "Documents",
+            "Desktop",
+            "Pictures",
+            "Music",
+            "Videos",
+            "Downloads",
+            "OneDrive/Documents",
+            "OneDrive/Desktop",
+            "OneDrive/Pictures",
+        ],
+    ),
+    ("Documents and Settings", &["My Documents", "Desktop"]),
+];
+
+/// `path` as compared against a selection: the restore path, in lowercase,
+/// also for a Windows path such as `C:\Users\bob\a.txt`.
+fn selection_key(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let path = match path.as_bytes() {
+        [drive, b':', ..] if drive.is_ascii_alphabetic() => &path[2..],
+        _ => crate::paths::strip_drive_letter(&path),
+    };
+    path.trim_start_matches('/').to_lowercase()
+}
+
+/// `name` as a pattern that matches only itself.
+pub(crate) fn escape(name: &str) -> String {
+    name.chars().map(|c| if matches!(c, '*' | '?' | '[') { format!("[{}]", c) } else { c.to_string() }).collect()
+}
+
+pub(crate) fn glob_matches_path(pattern: &str, path: &str) -> bool {
+    if pattern.contains('/') {
+        glob_match(pattern.trim_start_matches('/').as_bytes(), path.as_bytes())
+    } else {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        glob_match(pattern.as_bytes(), name.as_bytes())
+    }
+}
+
+/// Matches `path` against a glob `pattern`, see [`Filter`] for the syntax.
+pub fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let mut rest = &pattern[2..];
+            while rest.first() == Some(&b'*') {
+                rest = &rest[1..];
+            }
+            if let Some(after) = rest.strip_prefix(b"/") {
+                // "**/" also matches zero directories
+                glob_match(after, path)
+                    || path.iter().enumerate().any(|(i, &c)| c == b'/' && glob_match(after, &path[i + 1..]))
+            } else {
+                (0..=path.len()).any(|i| glob_match(rest, &path[i..]))
+            }
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            for i in 0..=path.len() {
+                if glob_match(rest, &path[i..]) {
+                    return true;
+                }
+                if path.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => match path.first() {
+            Some(&c) if c != b'/' => glob_match(&pattern[1..], &path[1..]),
+            _ => false,
+        },
+        Some(b'[') => match (path.first(), match_class(&pattern[1..], path.first().copied())) {
+            (Some(_), Some((true, len))) => glob_match(&pattern[1 + len..], &path[1..]),
+            _ => false,
+        },
+        Some(&p) => match path.first() {
+            Some(&c) if c.eq_ignore_ascii_case(&p) => glob_match(&pattern[1..], &path[1..]),
+            _ => false,
+        },
+    }
+}
+
+/// Matches a `[...]` class (without the opening bracket) against `c`,
+/// returning whether it matched and the length of the class pattern.
+fn match_class(class: &[u8], c: Option<u8>) -> Option<(bool, usize)> {
+    let c = c?.to_ascii_lowercase();
+    let (negated, mut i) = match class.first() {
+        Some(b'!') | Some(b'^') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut matched = false;
+    let mut first = true;
+    while i < class.len() {
+        if class[i] == b']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        let lo = class[i].to_ascii_lowercase();
+        if class.get(i + 1) == Some(&b'-') && class.get(i + 2).is_some_and(|&hi| hi != b']') {
+            let hi = class[i + 2].to_ascii_lowercase();
+            matched |= lo <= c && c <= hi;
+            i += 3;
+        } else {
+            matched |= lo == c;
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_data_narrows_the_includes() {
+        let filter = Filter::new().user_data(Some("bob")).include("*.pdf");
+        assert!(filter.matches_file("Users/bob/Documents/a.pdf", 0, None));
+        assert!(!filter.matches_file("Users/bob/Documents/a.txt", 0, None));
+        assert!(!filter.matches_file("Users/alice/Documents/a.pdf", 0, None));
+        assert!(!filter.matches_file("Program Files/a.pdf", 0, None));
+        let everyone = Filter::new().user_data(None).exclude("**/Downloads/**");
+        assert!(everyone.matches_file("Users/alice/Desktop/a.txt", 0, None));
+        assert!(!everyone.matches_file("Users/alice/Downloads/a.txt", 0, None));
+        assert!(!everyone.matches_file("Windows/a.txt", 0, None));
+    }
+}
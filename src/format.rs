@@ -0,0 +1,233 @@
+//! Telling archive formats apart by their content.
+//!
+//! Every format this build can restore has an entry in [`FORMATS`]: how its
+//! files are usually named, the signature its content starts with, and how
+//! it is opened. Files are recognized by their signature first, so a ZIP
+//! renamed to `.bak` is still restored; the name only decides for files
+//! without a recognizable signature, such as damaged archives, which are
+//! then reported instead of silently left out.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::archive::{ArchiveKind, ArchiveReader};
+use crate::tarball::{self, TarArchive};
+
+/// Bytes read from the start of a file to recognize it.
+const HEAD_LEN: usize = 512;
+
+/// Offset of the ISO 9660 and UDF volume descriptors, sector 16.
+#[cfg(feature = "iso")]
+const VOLUME_DESCRIPTORS: u64 = 16 * 2048;
+
+/// Volume descriptor sectors looked at; a UDF bridge disc has its UDF
+/// descriptors after the ISO 9660 ones.
+#[cfg(feature = "iso")]
+const VOLUME_DESCRIPTOR_COUNT: usize = 8;
+
+/// Names of the first file of ZIPs that are documents or packages rather
+/// than archives of backed up files: Office, OpenDocument, EPUB, JAR, APK.
+const DOCUMENT_MEMBERS: &[&[u8]] = &[b"[Content_Types].xml", b"_rels/", b"docProps/", b"mimetype", b"META-INF/", b"AndroidManifest.xml"];
+
+/// What is known of a file before it is opened as an archive.
+struct Probe<'a> {
+    path: &'a Path,
+    /// Lower-case file name.
+    name: String,
+    /// The first [`HEAD_LEN`] bytes, fewer for short files.
+    head: Vec<u8>,
+    /// The last 512 bytes, where a fixed VHD has its footer.
+    #[cfg(feature = "vhd")]
+    tail: Vec<u8>,
+    /// Sectors 16 and up, where a disc image has its volume descriptors.
+    #[cfg(feature = "iso")]
+    sectors: Vec<u8>,
+}
+
+impl<'a> Probe<'a> {
+    fn read(path: &'a Path) -> io::Result<Probe<'a>> {
+        let mut file = File::open(path)?;
+        let read_at = |file: &mut File, offset: u64, len: usize| -> io::Result<Vec<u8>> {
+            let mut buf = Vec::with_capacity(len);
+            file.seek(SeekFrom::Start(offset))?;
+            file.take(len as u64).read_to_end(&mut buf)?;
+            Ok(buf)
+        };
+        let name = path.file_name().map(|n| n.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+        Ok(Probe {
+            path,
+            name,
+            head: read_at(&mut file, 0, HEAD_LEN)?,
+            #[cfg(feature = "vhd")]
+            tail: {
+                let len = file.metadata()?.len();
+                read_at(&mut file, len.saturating_sub(512), 512)?
+            },
+            #[cfg(feature = "iso")]
+            sectors: read_at(&mut file, VOLUME_DESCRIPTORS, VOLUME_DESCRIPTOR_COUNT * 2048)?,
+        })
+    }
+
+    fn starts_with(&self, magic: &[u8]) -> bool {
+        self.head.starts_with(magic)
+    }
+}
+
+type Open = fn(&Path, bool) -> io::Result<Arc<dyn ArchiveReader>>;
+
+/// An archive format this build can restore.
+pub(crate) struct ArchiveFormat {
+    pub(crate) kind: ArchiveKind,
+    /// Lower-case endings of the names files of this format usually have.
+    suffixes: &'static [&'static str],
+    /// Whether a file's content is of this format.
+    sniff: fn(&Probe) -> bool,
+    /// Opens a file of this format; `None` for ZIPs, which the pipeline
+    /// reads itself.
+    pub(crate) open: Option<Open>,
+}
+
+/// The formats of this build, in the order their signatures are tried.
+pub(crate) const FORMATS: &[ArchiveFormat] = &[
+    ArchiveFormat { kind: ArchiveKind::Zip, suffixes: &[".zip"], sniff: is_zip, open: None },
+    #[cfg(feature = "sevenz")]
+    ArchiveFormat {
+        kind: ArchiveKind::SevenZip,
+        suffixes: &[".7z"],
+        sniff: |probe| probe.starts_with(b"7z\xbc\xaf\x27\x1c"),
+        open: Some(|path, mmap| Ok(Arc::new(crate::sevenz::SevenZipArchive::open(path, mmap)?))),
+    },
+    #[cfg(feature = "rar")]
+    ArchiveFormat {
+        kind: ArchiveKind::Rar,
+        suffixes: &[".rar"],
+        sniff: is_first_rar_volume,
+        open: Some(|path, _| Ok(Arc::new(crate::rar::RarArchive::open(path)?))),
+    },
+    #[cfg(feature = "bkf")]
+    ArchiveFormat {
+        kind: ArchiveKind::Bkf,
+        suffixes: &[".bkf"],
+        sniff: |probe| probe.starts_with(b"TAPE"),
+        open: Some(|path, mmap| Ok(Arc::new(crate::bkf::BkfArchive::open(path, mmap)?))),
+    },
+    #[cfg(feature = "vhd")]
+    ArchiveFormat {
+        kind: ArchiveKind::DiskImage,
+        suffixes: &[".vhd", ".vhdx"],
+        sniff: |probe| probe.starts_with(b"vhdxfile") || probe.starts_with(b"conectix") || probe.tail.starts_with(b"conectix"),
+        open: Some(|path, mmap| Ok(Arc::new(crate::vhd::DiskImage::open(path, mmap)?))),
+    },
+    #[cfg(feature = "iso")]
+    ArchiveFormat {
+        kind: ArchiveKind::Iso,
+        suffixes: &[".iso"],
+        sniff: |probe| {
+            probe.sectors.chunks(2048).any(|s| s.len() > 6 && matches!(&s[1..6], b"CD001" | b"BEA01" | b"NSR02" | b"NSR03"))
+        },
+        open: Some(|path, mmap| Ok(Arc::new(crate::iso::DiscImage::open(path, mmap)?))),
+    },
+    // Last: compressed tarballs are only recognized by decompressing them.
+    ArchiveFormat {
+        kind: ArchiveKind::Tar,
+        suffixes: &[".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tbz2", ".tar.zst", ".tzst"],
+        sniff: |probe| tarball::is_tarball(probe.path, &probe.head),
+        open: Some(|path, mmap| Ok(Arc::new(TarArchive::open(path, mmap)?))),
+    },
+];
+
+/// The format of the file at `path`: the one its content matches, else the
+/// one its name suggests.
+pub(crate) fn detect(path: &Path) -> Option<&'static ArchiveFormat> {
+    let probe = match Probe::read(path) {
+        Ok(probe) => probe,
+        Err(_) => {
+            let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+            return by_name(&name);
+        }
+    };
+    FORMATS.iter().find(|format| (format.sniff)(&probe)).or_else(|| by_name(&probe.name))
+}
+
+fn by_name(name: &str) -> Option<&'static ArchiveFormat> {
+    let format = FORMATS.iter().find(|format| format.suffixes.iter().any(|s| name.ends_with(s)))?;
+    // Later volumes are read along with the first.
+    (format.kind != ArchiveKind::Rar || is_first_volume(name)).then_some(format)
+}
+
+/// Whether the file is a ZIP, and not a document that happens to be stored
+/// as one.
+fn is_zip(probe: &Probe) -> bool {
+    if probe.starts_with(b"PK\x05\x06") {
+        return true;
+    }
+    // A spanned archive may carry a marker before its first local header.
+    let head = probe.head.strip_prefix(b"PK\x30\x30").unwrap_or(&probe.head);
+    if !head.starts_with(b"PK\x03\x04") || head.len() < 30 {
+        return false;
+    }
+    let name_len = u16::from_le_bytes([head[26], head[27]]) as usize;
+    let first = &head[30..head.len().min(30 + name_len)];
+    !DOCUMENT_MEMBERS.iter().any(|member| first.starts_with(member))
+}
+
+/// Whether the RAR file `name` starts its archive: anything but
+/// `name.part2.rar`, `name.part03.rar`, ...
+fn is_first_volume(name: &str) -> bool {
+    let stem = name.trim_end_matches(".rar");
+    match stem.rsplit_once(".part") {
+        Some((_, n)) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => n.trim_start_matches('0') == "1",
+        _ => true,
+    }
+}
+
+/// Whether the file is a RAR archive or the first volume of one. Later
+/// volumes, `name.r00` as well as `name.part2.rar`, are left to the first.
+#[cfg(feature = "rar")]
+fn is_first_rar_volume(probe: &Probe) -> bool {
+    let later = if let Some(header) = probe.head.strip_prefix(b"Rar!\x1a\x07\x00") {
+        // RAR 1.5 to 4: the main header flags mark volumes, and since
+        // RAR 3 also the first one.
+        let Some(&[_, _, 0x73, low, high]) = header.get(..5) else { return false };
+        let flags = u16::from_le_bytes([low, high]);
+        flags & 0x0001 != 0 && flags & 0x0100 == 0
+    } else if let Some(header) = probe.head.strip_prefix(b"Rar!\x1a\x07\x01\x00") {
+        // RAR 5: the main header has a volume number from the second
+        // volume on.
+        let mut fields = header.get(4..).unwrap_or_default();
+        let mut vint = || {
+            let mut value = 0u64;
+            for shift in (0..64).step_by(7) {
+                let (&byte, rest) = fields.split_first()?;
+                fields = rest;
+                value |= u64::from(byte & 0x7f) << shift;
+                if byte & 0x80 == 0 {
+                    return Some(value);
+                }
+            }
+            None
+        };
+        let archive_flags = (|| {
+            let (_size, kind, flags) = (vint()?, vint()?, vint()?);
+            if kind != 1 {
+                return None;
+            }
+            if flags & 0x0001 != 0 {
+                vint()?;
+            }
+            if flags & 0x0002 != 0 {
+                vint()?;
+            }
+            vint()
+        })();
+        let Some(archive_flags) = archive_flags else { return false };
+        archive_flags & 0x0002 != 0
+    } else {
+        return false;
+    };
+    // Volumes of RAR 2 and older do not tell the first apart; it is the
+    // one named `.rar`.
+    !later || (probe.name.ends_with(".rar") && is_first_volume(&probe.name))
+}
@@ -0,0 +1,179 @@
+//! Searching the contents of the small text files of a backup, for
+//! `restore grep`, which finds a file by what it says when its name is
+//! forgotten. Entries up to a size are decompressed in memory, one archive
+//! per worker, and searched line by line; larger ones, binary data (a NUL
+//! byte near the start, as in Office files and photos) and encrypted
+//! entries are skipped. Text in UTF-16, as Notepad saved it for years, is
+//! searched too.
+
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use zip::ZipArchive;
+
+use crate::analyze::read_each;
+use crate::archive::{self, ArchiveKind, ArchiveReader};
+use crate::backup_set::BackupSet;
+use crate::decode;
+use crate::error::ArchiveError;
+use crate::paths::strip_drive_letter;
+use crate::reader::ArchiveFile;
+
+/// How far into a file a NUL byte makes it binary.
+const SNIFF: usize = 8192;
+
+/// Longest line reported, in characters; the rest is cut off.
+const MAX_LINE: usize = 200;
+
+/// A file whose contents match, with the lines that do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrepMatch {
+    pub archive: PathBuf,
+    /// Restore path, e.g. `Users/bob/Documents/invoices.txt`.
+    pub path: String,
+    /// Line numbers, from 1, and lines.
+    pub lines: Vec<(usize, String)>,
+}
+
+/// What [`grep`] searched.
+#[derive(Debug, Default)]
+pub struct Grepped {
+    /// Files read and searched, and those of them that match.
+    pub searched: u64,
+    pub matching: u64,
+    /// Files not searched: larger than the limit, binary or encrypted.
+    pub skipped: u64,
+    /// Files whose data could not be read.
+    pub unreadable: u64,
+    pub errors: Vec<ArchiveError>,
+}
+
+/// What one archive held.
+#[derive(Default)]
+struct Found {
+    matches: Vec<GrepMatch>,
+    searched: u64,
+    skipped: u64,
+    unreadable: u64,
+}
+
+/// Searches the files of `set` of up to `max_size` bytes for lines matching
+/// `pattern`, reading `threads` archives at once, and hands each matching
+/// file to `found` as soon as its archive is done.
+pub fn grep(set: &BackupSet, pattern: &Regex, max_size: u64, threads: usize, mut found: impl FnMut(&GrepMatch)) -> Grepped {
+    let mut grepped = Grepped::default();
+    let read = |path: &PathBuf| {
+        let mut result = Found::default();
+        match ArchiveKind::of(path).unwrap_or(ArchiveKind::Zip) {
+            ArchiveKind::Zip => grep_zip(&mut result, path, set.mmap(), pattern, max_size)?,
+            kind => {
+                let reader = archive::open(kind, path, set.mmap()).map_err(|e| ArchiveError::io(path, e))?;
+                grep_reader(&mut result, path, reader.as_ref(), pattern, max_size);
+            }
+        }
+        Ok(result)
+    };
+    read_each(set.archives(), threads, read, |result: Result<Found, ArchiveError>| match result {
+        Ok(result) => {
+            for file in &result.matches {
+                found(file);
+            }
+            grepped.searched += result.searched;
+            grepped.matching += result.matches.len() as u64;
+            grepped.skipped += result.skipped;
+            grepped.unreadable += result.unreadable;
+        }
+        Err(e) => grepped.errors.push(e),
+    });
+    grepped
+}
+
+fn grep_zip(result: &mut Found, path: &Path, mmap: bool, pattern: &Regex, max_size: u64) -> Result<(), ArchiveError> {
+    let file = ArchiveFile::open(path, mmap).map_err(|e| ArchiveError::io(path, e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| ArchiveError::zip(path, e))?;
+    for j in 0..archive.len() {
+        let raw = archive.by_index_raw(j).map_err(|e| ArchiveError::zip(path, e))?;
+        if raw.is_dir() || raw.size() == 0 {
+            continue;
+        }
+        if raw.encrypted() || raw.size() > max_size {
+            result.skipped += 1;
+            continue;
+        }
+        let name = strip_drive_letter(&raw.name().replace('\\', "/")).to_string();
+        #[allow(deprecated)]
+        let method = raw.compression().to_u16();
+        let data = if decode::supported(method) {
+            decode::decoder(method, raw).and_then(|data| read_at_most(data, max_size))
+        } else {
+            drop(raw);
+            // Methods the decoders do not know are decoded by the zip crate.
+            archive.by_index(j).map_err(io::Error::other).and_then(|data| read_at_most(data, max_size))
+        };
+        result.search(path, name, data, pattern);
+    }
+    Ok(())
+}
+
+fn grep_reader(result: &mut Found, path: &Path, reader: &dyn ArchiveReader, pattern: &Regex, max_size: u64) {
+    reader.read_in_order(&mut |j, data| {
+        let member = reader.member(j);
+        if member.size == 0 {
+            return true;
+        }
+        if member.size > max_size {
+            result.skipped += 1;
+            return true;
+        }
+        let data = data.and_then(|data| read_at_most(data, max_size));
+        result.search(path, member.name.to_string(), data, pattern);
+        true
+    });
+}
+
+impl Found {
+    /// Searches `data`, the contents of the file at restore path `name`.
+    fn search(&mut self, archive: &Path, name: String, data: io::Result<Vec<u8>>, pattern: &Regex) {
+        let Ok(data) = data else {
+            self.unreadable += 1;
+            return;
+        };
+        let Some(text) = text(&data) else {
+            self.skipped += 1;
+            return;
+        };
+        self.searched += 1;
+        let lines: Vec<_> = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| pattern.is_match(line))
+            .map(|(i, line)| (i + 1, line.trim_end().chars().take(MAX_LINE).collect()))
+            .collect();
+        if !lines.is_empty() {
+            self.matches.push(GrepMatch { archive: archive.to_path_buf(), path: name, lines });
+        }
+    }
+}
+
+/// The data of `reader`, which the header gives as at most `max` bytes.
+fn read_at_most(reader: impl Read, max: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(max).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// `data` as text, or `None` if it is binary: UTF-16 with a byte order
+/// mark, otherwise UTF-8 with anything else replaced.
+fn text(data: &[u8]) -> Option<String> {
+    let utf16 = |data: &[u8], unit: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = data.chunks_exact(2).map(|pair| unit([pair[0], pair[1]])).collect();
+        String::from_utf16_lossy(&units)
+    };
+    match data {
+        [0xFF, 0xFE, rest @ ..] => Some(utf16(rest, u16::from_le_bytes)),
+        [0xFE, 0xFF, rest @ ..] => Some(utf16(rest, u16::from_be_bytes)),
+        _ if data[..data.len().min(SNIFF)].contains(&0) => None,
+        _ => Some(String::from_utf8_lossy(data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data)).into_owned()),
+    }
+}
@@ -0,0 +1,163 @@
+//! Entries a backup holds no or not all data for: files stored with zero
+//! bytes, and files whose data does not decompress to the size their header
+//! gives. Both usually mean the backup tool could not read the file, for
+//! instance because it was locked while the backup ran.
+
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use zip::ZipArchive;
+
+use crate::archive::{self, ArchiveKind, ArchiveReader};
+use crate::backup_set::BackupSet;
+use crate::decode;
+use crate::error::ArchiveError;
+use crate::paths::strip_drive_letter;
+use crate::placeholder::is_cloud_placeholder;
+use crate::reader::ArchiveFile;
+
+/// What is missing of an [`IncompleteEntry`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Incompleteness {
+    /// The entry was stored with no data.
+    Empty,
+    /// The data ended, or could not be read any further, after `actual` of
+    /// the `expected` bytes, or ran past them.
+    SizeMismatch { expected: u64, actual: u64, error: Option<String> },
+}
+
+/// An entry the backup does not hold all data for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncompleteEntry {
+    pub archive: PathBuf,
+    /// Restore path, e.g. `Users/bob/Documents/Outlook Files/archive.pst`.
+    pub path: String,
+    pub kind: Incompleteness,
+}
+
+/// What [`find_incomplete`] found.
+#[derive(Debug, Clone, Default)]
+pub struct IncompleteEntries {
+    /// Files looked at.
+    pub checked: u64,
+    /// Entries whose data this build cannot read, such as encrypted ones,
+    /// and so could not be checked.
+    pub unchecked: u64,
+    pub entries: Vec<IncompleteEntry>,
+    pub errors: Vec<ArchiveError>,
+}
+
+impl IncompleteEntries {
+    pub fn empty(&self) -> impl Iterator<Item = &IncompleteEntry> {
+        self.entries.iter().filter(|e| e.kind == Incompleteness::Empty)
+    }
+
+    pub fn size_mismatches(&self) -> impl Iterator<Item = &IncompleteEntry> {
+        self.entries.iter().filter(|e| e.kind != Incompleteness::Empty)
+    }
+
+    /// Records entry `name` of `archive` if it is incomplete. Its header
+    /// gives `expected` bytes; `read` is the number of bytes read and the
+    /// error that stopped reading, if the data was read.
+    fn check(&mut self, archive: &Path, name: &str, expected: u64, read: Option<(u64, Option<io::Error>)>) {
+        self.checked += 1;
+        let path = strip_drive_letter(name);
+        let kind = match read {
+            // Cloud-only placeholders are empty by nature and reported as such.
+            _ if expected == 0 && is_cloud_placeholder(path, 0, None) => return,
+            _ if expected == 0 => Incompleteness::Empty,
+            None => return,
+            Some((actual, None)) if actual == expected => return,
+            Some((0, Some(e))) if e.kind() == io::ErrorKind::Unsupported => {
+                self.unchecked += 1;
+                return;
+            }
+            Some((actual, error)) => Incompleteness::SizeMismatch { expected, actual, error: error.map(|e| e.to_string()) },
+        };
+        self.entries.push(IncompleteEntry { archive: archive.to_path_buf(), path: path.to_string(), kind });
+    }
+}
+
+/// Reads `data` to its end. Returns the number of bytes read and the error
+/// that stopped it early, if any.
+fn drain(data: &mut dyn Read) -> (u64, Option<io::Error>) {
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut total = 0u64;
+    loop {
+        match data.read(&mut buf) {
+            Ok(0) => return (total, None),
+            Ok(n) => total += n as u64,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return (total, Some(e)),
+        }
+    }
+}
+
+/// Looks for empty entries in the archives of `set` and, with `decompress`,
+/// reads the data of every other entry to compare its size with the header.
+pub fn find_incomplete(set: &BackupSet, decompress: bool) -> IncompleteEntries {
+    let mut result = IncompleteEntries::default();
+    for path in set.archives() {
+        let checked = match ArchiveKind::of(path).unwrap_or(ArchiveKind::Zip) {
+            ArchiveKind::Zip => check_zip(&mut result, path, set.mmap(), decompress),
+            kind => match archive::open(kind, path, set.mmap()) {
+                Ok(reader) => {
+                    check_reader(&mut result, path, reader.as_ref(), decompress);
+                    Ok(())
+                }
+                Err(e) => Err(ArchiveError::io(path, e)),
+            },
+        };
+        if let Err(e) = checked {
+            result.errors.push(e);
+        }
+    }
+    result
+}
+
+fn check_zip(result: &mut IncompleteEntries, path: &Path, mmap: bool, decompress: bool) -> Result<(), ArchiveError> {
+    let file = ArchiveFile::open(path, mmap).map_err(|e| ArchiveError::io(path, e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| ArchiveError::zip(path, e))?;
+    for j in 0..archive.len() {
+        let raw = archive.by_index_raw(j).map_err(|e| ArchiveError::zip(path, e))?;
+        if raw.is_dir() {
+            continue;
+        }
+        let (name, size, encrypted) = (raw.name().replace('\\', "/"), raw.size(), raw.encrypted());
+        #[allow(deprecated)]
+        let method = raw.compression().to_u16();
+        if !decompress || size == 0 {
+            result.check(path, &name, size, None);
+        } else if encrypted {
+            result.unchecked += 1;
+        } else if decode::supported(method) {
+            let read = decode::decoder(method, raw).map_or_else(|e| (0, Some(e)), |mut data| drain(&mut data));
+            result.check(path, &name, size, Some(read));
+        } else {
+            drop(raw);
+            // Methods the decoders do not know are decoded by the zip crate.
+            let read = archive.by_index(j).map_or_else(|e| (0, Some(io::Error::other(e))), |mut data| drain(&mut data));
+            result.check(path, &name, size, Some(read));
+        }
+    }
+    Ok(())
+}
+
+fn check_reader(result: &mut IncompleteEntries, path: &Path, reader: &dyn ArchiveReader, decompress: bool) {
+    if !decompress {
+        for j in 0..reader.len() {
+            let member = reader.member(j);
+            result.check(path, member.name, member.size, None);
+        }
+        return;
+    }
+    reader.read_in_order(&mut |j, data| {
+        let member = reader.member(j);
+        let read = match data {
+            Ok(data) => drain(data),
+            Err(e) => (0, Some(e)),
+        };
+        result.check(path, member.name, member.size, Some(read));
+        true
+    });
+}
@@ -0,0 +1,576 @@
+//! Optical disc images: ISO 9660 with Joliet names, and UDF (feature
+//! `iso`).
+//!
+//! Backups burned to CD, DVD or Blu-ray and later imaged are read without
+//! mounting them. Discs with both file systems, as Windows burns them, are
+//! read through UDF, which keeps long names and files over 4 GB; plain ISO
+//! 9660 discs through their Joliet tree if they have one. UDF virtual
+//! partitions (CD-R packet writing) are not supported.
+
+use std::collections::HashSet;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::archive::{ArchiveReader, Member};
+use crate::catalog::BackupTime;
+use crate::reader::ArchiveFile;
+
+const SECTOR: u64 = 2048;
+/// Directories larger than this are taken to be damaged.
+const MAX_DIR_LEN: u64 = 64 * 1024 * 1024;
+
+fn u16_at(b: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([b[at], b[at + 1]])
+}
+
+fn u32_at(b: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(b[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(b: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(b[at..at + 8].try_into().unwrap())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_at(file: &mut ArchiveFile, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// A piece of a file's data.
+#[derive(Debug, Clone)]
+enum Extent {
+    Data { offset: u64, len: u64 },
+    /// Allocated but unrecorded, or sparse.
+    Zeros(u64),
+    /// Small UDF files are stored in their file entry.
+    Inline(Vec<u8>),
+}
+
+/// Reader over `extents`, cut to `size` bytes.
+fn extent_reader(file: &ArchiveFile, extents: &[Extent], size: u64) -> io::Result<Box<dyn Read + Send>> {
+    let mut reader: Box<dyn Read + Send> = Box::new(io::empty());
+    for extent in extents {
+        let part: Box<dyn Read + Send> = match extent {
+            Extent::Data { offset, len } => {
+                let mut file = file.clone();
+                file.seek(SeekFrom::Start(*offset))?;
+                Box::new(file.take(*len))
+            }
+            Extent::Zeros(len) => Box::new(io::repeat(0).take(*len)),
+            Extent::Inline(data) => Box::new(io::Cursor::new(data.clone())),
+        };
+        reader = Box::new(reader.chain(part));
+    }
+    Ok(Box::new(Exact { inner: reader.take(size), left: size }))
+}
+
+/// Fails with `UnexpectedEof` if the image ends before the file does.
+struct Exact<R> {
+    inner: R,
+    left: u64,
+}
+
+impl<R: Read> Read for Exact<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 && self.left > 0 && !buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "disc image ends before the file's data"));
+        }
+        self.left -= n as u64;
+        Ok(n)
+    }
+}
+
+fn read_extents(file: &ArchiveFile, extents: &[Extent], size: u64) -> io::Result<Vec<u8>> {
+    if size > MAX_DIR_LEN {
+        return Err(invalid("directory too large"));
+    }
+    let mut data = Vec::with_capacity(size as usize);
+    extent_reader(file, extents, size)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// One file of a disc image.
+#[derive(Debug, Clone)]
+struct DiscEntry {
+    /// Path from the root of the disc with `/` separators.
+    name: String,
+    size: u64,
+    modified: Option<BackupTime>,
+    extents: Vec<Extent>,
+    /// Why the file cannot be read, if its metadata is damaged.
+    damage: Option<String>,
+}
+
+impl DiscEntry {
+    fn damaged(name: String, e: io::Error) -> DiscEntry {
+        DiscEntry { name, size: 0, modified: None, extents: Vec::new(), damage: Some(e.to_string()) }
+    }
+}
+
+/// The files of an ISO 9660 or UDF image.
+pub(crate) struct DiscImage {
+    file: ArchiveFile,
+    entries: Vec<DiscEntry>,
+}
+
+impl DiscImage {
+    pub(crate) fn open(path: &Path, mmap: bool) -> io::Result<DiscImage> {
+        let mut file = ArchiveFile::open(path, mmap)?;
+        let entries = match udf(&mut file) {
+            Ok(Some(entries)) => entries,
+            Ok(None) => iso9660(&mut file)?,
+            // A broken UDF tree may still have an ISO 9660 one next to it.
+            Err(e) => iso9660(&mut file).map_err(|_| e)?,
+        };
+        Ok(DiscImage { file, entries })
+    }
+}
+
+impl ArchiveReader for DiscImage {
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn member(&self, j: usize) -> Member<'_> {
+        let entry = &self.entries[j];
+        Member { name: &entry.name, size: entry.size, modified: entry.modified, attributes: None }
+    }
+
+    fn read(&self, j: usize, f: &mut dyn FnMut(&mut dyn Read)) -> io::Result<()> {
+        let entry = &self.entries[j];
+        if let Some(damage) = &entry.damage {
+            return Err(invalid(damage));
+        }
+        f(&mut extent_reader(&self.file, &entry.extents, entry.size)?);
+        Ok(())
+    }
+}
+
+// ISO 9660 (ECMA-119) and Joliet.
+
+/// A directory record.
+struct Record {
+    extent: u64,
+    len: u64,
+    flags: u8,
+    name: String,
+    modified: Option<BackupTime>,
+}
+
+const RECORD_DIR: u8 = 0x02;
+const RECORD_ASSOCIATED: u8 = 0x04;
+/// The file continues in the next record.
+const RECORD_MULTI_EXTENT: u8 = 0x80;
+
+impl Record {
+    fn parse(b: &[u8], joliet: bool) -> Option<Record> {
+        if b.len() < 34 || b.len() < 33 + usize::from(b[32]) {
+            return None;
+        }
+        let raw = &b[33..33 + usize::from(b[32])];
+        let name = match raw {
+            [0] => ".".to_string(),
+            [1] => "..".to_string(),
+            _ if joliet => {
+                let units: Vec<u16> = raw.chunks_exact(2).map(|u| u16::from_be_bytes([u[0], u[1]])).collect();
+                String::from_utf16_lossy(&units)
+            }
+            _ => String::from_utf8_lossy(raw).into_owned(),
+        };
+        // `NAME.TXT;1` into `NAME.TXT`, `README.;1` into `README`.
+        let name = name.split_once(';').map_or(name.as_str(), |(n, _)| n).trim_end_matches('.').to_string();
+        let t = &b[18..25];
+        let time = BackupTime {
+            year: 1900 + u16::from(t[0]),
+            month: t[1],
+            day: t[2],
+            hour: t[3],
+            minute: t[4],
+            second: t[5],
+        };
+        let valid = (1..=12).contains(&time.month) && (1..=31).contains(&time.day) && time.hour < 24 && time.minute < 60 && time.second < 60;
+        Some(Record {
+            extent: u64::from(u32_at(b, 2)),
+            len: u64::from(u32_at(b, 10)),
+            flags: b[25],
+            name,
+            modified: valid.then_some(time),
+        })
+    }
+}
+
+fn is_joliet(descriptor: &[u8]) -> bool {
+    matches!(&descriptor[88..91], b"%/@" | b"%/C" | b"%/E")
+}
+
+fn iso9660(file: &mut ArchiveFile) -> io::Result<Vec<DiscEntry>> {
+    let mut primary = None;
+    let mut joliet = None;
+    for sector in 16..16 + 32 {
+        let Ok(descriptor) = read_at(file, sector * SECTOR, SECTOR as usize) else { break };
+        if &descriptor[1..6] != b"CD001" {
+            break;
+        }
+        match descriptor[0] {
+            1 => primary = Some(descriptor),
+            2 if is_joliet(&descriptor) => joliet = Some(descriptor),
+            255 => break,
+            _ => {}
+        }
+    }
+    let is_joliet = joliet.is_some();
+    let descriptor = joliet.or(primary).ok_or_else(|| invalid("not an ISO 9660 or UDF image"))?;
+    let block = match u16_at(&descriptor, 128) {
+        0 => SECTOR,
+        size => u64::from(size),
+    };
+    let root = Record::parse(&descriptor[156..190], is_joliet).ok_or_else(|| invalid("damaged ISO 9660 root directory"))?;
+
+    let mut entries: Vec<DiscEntry> = Vec::new();
+    let mut visited = HashSet::new();
+    let mut dirs = vec![(root.extent, root.len, String::new())];
+    while let Some((extent, len, prefix)) = dirs.pop() {
+        if !visited.insert(extent) {
+            continue;
+        }
+        let data = match read_extents(file, &[Extent::Data { offset: extent * block, len }], len) {
+            Ok(data) => data,
+            Err(e) => {
+                entries.push(DiscEntry::damaged(prefix.trim_end_matches('/').to_string(), e));
+                continue;
+            }
+        };
+        let mut continued = false;
+        let mut pos = 0;
+        while pos < data.len() {
+            let record_len = usize::from(data[pos]);
+            if record_len == 0 {
+                // Records do not cross sector boundaries; the rest is padding.
+                pos = (pos / SECTOR as usize + 1) * SECTOR as usize;
+                continue;
+            }
+            let Some(record) = data.get(pos..pos + record_len).and_then(|b| Record::parse(b, is_joliet)) else { break };
+            pos += record_len;
+            if record.name == "." || record.name == ".." || record.name.is_empty() || record.flags & RECORD_ASSOCIATED != 0 {
+                continue;
+            }
+            let path = format!("{}{}", prefix, record.name);
+            let extent = Extent::Data { offset: record.extent * block, len: record.len };
+            if record.flags & RECORD_DIR != 0 {
+                dirs.push((record.extent, record.len, format!("{}/", path)));
+                continue;
+            }
+            match entries.last_mut() {
+                Some(last) if continued && last.name == path => {
+                    last.size += record.len;
+                    last.extents.push(extent);
+                }
+                _ => entries.push(DiscEntry {
+                    name: path,
+                    size: record.len,
+                    modified: record.modified,
+                    extents: vec![extent],
+                    damage: None,
+                }),
+            }
+            continued = record.flags & RECORD_MULTI_EXTENT != 0;
+        }
+    }
+    Ok(entries)
+}
+
+// UDF (ECMA-167 as profiled by OSTA UDF 1.02 to 2.60).
+
+const TAG_PARTITION: u16 = 5;
+const TAG_LOGICAL_VOLUME: u16 = 6;
+const TAG_TERMINATING: u16 = 8;
+const TAG_FILE_SET: u16 = 256;
+const TAG_FILE_ID: u16 = 257;
+const TAG_ALLOCATION_EXTENT: u16 = 258;
+const TAG_FILE_ENTRY: u16 = 261;
+const TAG_EXTENDED_FILE_ENTRY: u16 = 266;
+
+const FILE_TYPE_DIR: u8 = 4;
+const FILE_TYPE_FILE: u8 = 5;
+
+const FID_DIR: u8 = 0x02;
+const FID_DELETED: u8 = 0x04;
+const FID_PARENT: u8 = 0x08;
+
+/// Whether `b` starts with a valid descriptor tag with identifier `id`.
+fn tag_is(b: &[u8], id: u16) -> bool {
+    b.len() >= 16
+        && u16_at(b, 0) == id
+        && b[..16].iter().enumerate().filter(|&(i, _)| i != 4).fold(0u8, |sum, (_, &x)| sum.wrapping_add(x)) == b[4]
+}
+
+/// A UDF `dstring`/`d-characters` name: 8-bit or UTF-16BE after the
+/// compression ID byte.
+fn udf_name(raw: &[u8]) -> String {
+    match raw.split_first() {
+        Some((16, rest)) => {
+            let units: Vec<u16> = rest.chunks_exact(2).map(|u| u16::from_be_bytes([u[0], u[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        Some((_, rest)) => rest.iter().map(|&b| char::from(b)).collect(),
+        None => String::new(),
+    }
+}
+
+fn udf_time(b: &[u8]) -> Option<BackupTime> {
+    let time = BackupTime { year: u16_at(b, 2), month: b[4], day: b[5], hour: b[6], minute: b[7], second: b[8] };
+    let valid = time.year > 0 && (1..=12).contains(&time.month) && (1..=31).contains(&time.day) && time.hour < 24 && time.minute < 60 && time.second < 60;
+    valid.then_some(time)
+}
+
+/// Where the logical blocks of a partition lie in the image.
+enum Partition {
+    Physical { start: u64 },
+    /// UDF 2.50 metadata partition: its blocks are the data of the metadata
+    /// file, whose extents lie in a physical partition.
+    Metadata { extents: Vec<(u64, u64)> },
+}
+
+/// A file or directory read from its file entry.
+struct Node {
+    file_type: u8,
+    size: u64,
+    modified: Option<BackupTime>,
+    extents: Vec<Extent>,
+}
+
+struct Udf<'a> {
+    file: &'a mut ArchiveFile,
+    block: u64,
+    /// By partition reference number, the index in the partition map table.
+    partitions: Vec<Partition>,
+}
+
+impl Udf<'_> {
+    /// Image ranges holding `len` bytes from logical block `lbn` of partition
+    /// `part`.
+    fn ranges(&self, part: u16, lbn: u32, len: u64) -> io::Result<Vec<(u64, u64)>> {
+        let start = u64::from(lbn) * self.block;
+        match self.partitions.get(usize::from(part)) {
+            Some(Partition::Physical { start: base }) => Ok(vec![(base + start, len)]),
+            Some(Partition::Metadata { extents }) => {
+                let (mut skip, mut left) = (start, len);
+                let mut ranges = Vec::new();
+                for &(offset, extent_len) in extents {
+                    if left == 0 {
+                        break;
+                    }
+                    if skip >= extent_len {
+                        skip -= extent_len;
+                        continue;
+                    }
+                    let n = (extent_len - skip).min(left);
+                    ranges.push((offset + skip, n));
+                    skip = 0;
+                    left -= n;
+                }
+                if left > 0 {
+                    return Err(invalid("UDF block outside the metadata partition"));
+                }
+                Ok(ranges)
+            }
+            None => Err(invalid("UDF partition reference out of range")),
+        }
+    }
+
+    fn read_block(&mut self, part: u16, lbn: u32) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        for (offset, len) in self.ranges(part, lbn, self.block)? {
+            data.extend(read_at(self.file, offset, len as usize)?);
+        }
+        Ok(data)
+    }
+
+    /// Reads the file entry at `lbn` of partition `part`.
+    fn node(&mut self, part: u16, lbn: u32) -> io::Result<Node> {
+        let b = self.read_block(part, lbn)?;
+        let (size, modified, l_ea, l_ad, base) = if tag_is(&b, TAG_FILE_ENTRY) {
+            (u64_at(&b, 56), udf_time(&b[84..96]), u32_at(&b, 168), u32_at(&b, 172), 176)
+        } else if tag_is(&b, TAG_EXTENDED_FILE_ENTRY) {
+            (u64_at(&b, 56), udf_time(&b[92..104]), u32_at(&b, 208), u32_at(&b, 212), 216)
+        } else {
+            return Err(invalid("damaged UDF file entry"));
+        };
+        let start = base + l_ea as usize;
+        let ads = b.get(start..start + l_ad as usize).ok_or_else(|| invalid("damaged UDF file entry"))?.to_vec();
+        let extents = match u16_at(&b, 34) & 7 {
+            0 => self.allocation(part, ads, false)?,
+            1 => self.allocation(part, ads, true)?,
+            3 => vec![Extent::Inline(ads)],
+            _ => return Err(io::Error::new(io::ErrorKind::Unsupported, "UDF extended allocation descriptors are not supported")),
+        };
+        Ok(Node { file_type: b[27], size, modified, extents })
+    }
+
+    /// Extents of short (`long == false`) or long allocation descriptors,
+    /// following allocation extent descriptors.
+    fn allocation(&mut self, part: u16, mut ads: Vec<u8>, long: bool) -> io::Result<Vec<Extent>> {
+        let ad_len = if long { 16 } else { 8 };
+        let mut extents = Vec::new();
+        let mut followed = 0;
+        let mut pos = 0;
+        while pos + ad_len <= ads.len() {
+            let raw_len = u32_at(&ads, pos);
+            let (kind, len) = (raw_len >> 30, u64::from(raw_len & 0x3fff_ffff));
+            let lbn = u32_at(&ads, pos + 4);
+            let ad_part = if long { u16_at(&ads, pos + 8) } else { part };
+            pos += ad_len;
+            if len == 0 {
+                break;
+            }
+            match kind {
+                0 => extents.extend(self.ranges(ad_part, lbn, len)?.into_iter().map(|(offset, len)| Extent::Data { offset, len })),
+                1 | 2 => extents.push(Extent::Zeros(len)),
+                _ => {
+                    followed += 1;
+                    let next = self.read_block(ad_part, lbn)?;
+                    if followed > 1024 || !tag_is(&next, TAG_ALLOCATION_EXTENT) {
+                        return Err(invalid("damaged UDF allocation extent"));
+                    }
+                    let l_ad = u32_at(&next, 20) as usize;
+                    ads = next.get(24..24 + l_ad).ok_or_else(|| invalid("damaged UDF allocation extent"))?.to_vec();
+                    pos = 0;
+                }
+            }
+        }
+        Ok(extents)
+    }
+
+    fn walk(&mut self, root: (u16, u32)) -> Vec<DiscEntry> {
+        let mut entries = Vec::new();
+        let mut visited = HashSet::new();
+        let mut dirs = vec![(root, String::new())];
+        while let Some(((part, lbn), prefix)) = dirs.pop() {
+            if !visited.insert((part, lbn)) {
+                continue;
+            }
+            let listing = self.node(part, lbn).and_then(|dir| read_extents(self.file, &dir.extents, dir.size));
+            let data = match listing {
+                Ok(data) => data,
+                Err(e) => {
+                    entries.push(DiscEntry::damaged(prefix.trim_end_matches('/').to_string(), e));
+                    continue;
+                }
+            };
+            let mut pos = 0;
+            while pos + 38 <= data.len() && tag_is(&data[pos..], TAG_FILE_ID) {
+                let characteristics = data[pos + 18];
+                let l_fi = usize::from(data[pos + 19]);
+                let icb = (u16_at(&data, pos + 28), u32_at(&data, pos + 24));
+                let name_at = pos + 38 + usize::from(u16_at(&data, pos + 36));
+                let name = data.get(name_at..name_at + l_fi).map(udf_name).unwrap_or_default();
+                pos = (name_at + l_fi).next_multiple_of(4);
+                if characteristics & (FID_DELETED | FID_PARENT) != 0 || name.is_empty() {
+                    continue;
+                }
+                let path = format!("{}{}", prefix, name);
+                if characteristics & FID_DIR != 0 {
+                    dirs.push((icb, format!("{}/", path)));
+                    continue;
+                }
+                entries.push(match self.node(icb.0, icb.1) {
+                    Ok(node) if node.file_type == FILE_TYPE_FILE => {
+                        DiscEntry { name: path, size: node.size, modified: node.modified, extents: node.extents, damage: None }
+                    }
+                    // Symbolic links, devices and the like.
+                    Ok(_) => continue,
+                    Err(e) => DiscEntry::damaged(path, e),
+                });
+            }
+        }
+        entries
+    }
+}
+
+/// The files of the UDF file system in `file`, or `None` if it has none.
+fn udf(file: &mut ArchiveFile) -> io::Result<Option<Vec<DiscEntry>>> {
+    let Ok(anchor) = read_at(file, 256 * SECTOR, SECTOR as usize) else { return Ok(None) };
+    if !tag_is(&anchor, 2) {
+        return Ok(None);
+    }
+    let (vds_len, vds_start) = (u64::from(u32_at(&anchor, 16)), u64::from(u32_at(&anchor, 20)));
+
+    // Partition descriptors by partition number, and the logical volume.
+    let mut starts = Vec::new();
+    let mut volume = None;
+    for sector in vds_start..vds_start + (vds_len / SECTOR).min(64) {
+        let d = read_at(file, sector * SECTOR, SECTOR as usize)?;
+        if tag_is(&d, TAG_PARTITION) {
+            starts.push((u16_at(&d, 22), u64::from(u32_at(&d, 188))));
+        } else if tag_is(&d, TAG_LOGICAL_VOLUME) {
+            volume = Some(d);
+        } else if tag_is(&d, TAG_TERMINATING) {
+            break;
+        }
+    }
+    let volume = volume.ok_or_else(|| invalid("UDF image without a logical volume descriptor"))?;
+    let block = u64::from(u32_at(&volume, 212));
+    if !(512..=65536).contains(&block) {
+        return Err(invalid("damaged UDF logical volume descriptor"));
+    }
+    let start_of = |number: u16| {
+        starts.iter().find(|&&(n, _)| n == number).map(|&(_, start)| start * block).ok_or_else(|| invalid("UDF partition descriptor missing"))
+    };
+
+    let map_count = u32_at(&volume, 268);
+    let mut udf = Udf { file, block, partitions: Vec::new() };
+    let mut metadata = Vec::new();
+    let mut pos = 440;
+    for _ in 0..map_count.min(64) {
+        let Some(&[kind, len]) = volume.get(pos..pos + 2) else { break };
+        let map = volume.get(pos..pos + usize::from(len)).ok_or_else(|| invalid("damaged UDF partition map"))?;
+        let partition = match (kind, len) {
+            (1, 6) => Partition::Physical { start: start_of(u16_at(map, 4))? },
+            (2, 64) if map[5..].starts_with(b"*UDF Metadata Partition") => {
+                metadata.push((udf.partitions.len(), u16_at(map, 38), u32_at(map, 40)));
+                Partition::Metadata { extents: Vec::new() }
+            }
+            // Sparing tables only matter for defects on rewritable media.
+            (2, 64) if map[5..].starts_with(b"*UDF Sparable Partition") => Partition::Physical { start: start_of(u16_at(map, 38))? },
+            _ => return Err(io::Error::new(io::ErrorKind::Unsupported, "UDF virtual partitions are not supported")),
+        };
+        udf.partitions.push(partition);
+        pos += usize::from(len);
+    }
+    // The metadata file is recorded in the physical partition it refers to.
+    for (index, number, lbn) in metadata {
+        let physical = udf
+            .partitions
+            .iter()
+            .position(|p| matches!(p, Partition::Physical { start } if Some(*start) == start_of(number).ok()))
+            .ok_or_else(|| invalid("UDF metadata partition without a physical partition"))?;
+        let node = udf.node(physical as u16, lbn)?;
+        let mut extents = Vec::new();
+        for extent in node.extents {
+            match extent {
+                Extent::Data { offset, len } => extents.push((offset, len)),
+                _ => return Err(invalid("damaged UDF metadata file")),
+            }
+        }
+        udf.partitions[index] = Partition::Metadata { extents };
+    }
+
+    let fsd = (u16_at(&volume, 256), u32_at(&volume, 252));
+    let set = udf.read_block(fsd.0, fsd.1)?;
+    if !tag_is(&set, TAG_FILE_SET) {
+        return Err(invalid("damaged UDF file set descriptor"));
+    }
+    let root = (u16_at(&set, 408), u32_at(&set, 404));
+    let root_node = udf.node(root.0, root.1)?;
+    if root_node.file_type != FILE_TYPE_DIR {
+        return Err(invalid("damaged UDF root directory"));
+    }
+    Ok(Some(udf.walk(root)))
+}
@@ -0,0 +1,124 @@
+//! Extract Windows backup ZIP files and restore their folder structure.
+//!
+//! The blocking API lives at the crate root; [`aio`] offers the same
+//! operations for tokio-based applications (feature `async`).
+
+mod allocate;
+mod analysis_cache;
+mod analyze;
+mod answers;
+mod archive;
+mod apple_double;
+mod audit;
+mod backup_set;
+mod benchmark;
+#[cfg(feature = "bkf")]
+mod bkf;
+mod builder;
+mod cancel;
+mod catalog;
+mod categories;
+mod central_dir;
+mod conflict;
+mod decode;
+mod direct;
+mod discovery;
+mod doctor;
+mod durability;
+mod efs;
+mod error;
+mod export;
+mod file_history;
+mod file_times;
+mod filter;
+mod format;
+mod grep;
+mod incomplete;
+#[cfg(feature = "iso")]
+mod iso;
+mod locale;
+mod mail;
+mod mac_quarantine;
+mod manifest;
+mod options;
+mod ntfs_compression;
+mod owner;
+mod page_cache;
+mod paths;
+mod pipeline;
+mod placeholder;
+mod profile;
+mod progress;
+mod query;
+#[cfg(feature = "rar")]
+mod rar;
+mod reader;
+mod recoverables;
+mod remap;
+mod restorer;
+mod state;
+#[cfg(feature = "sevenz")]
+mod sevenz;
+mod search;
+mod selection;
+mod selinux;
+#[cfg(feature = "self-update")]
+mod self_update;
+mod signing;
+mod simulate;
+mod size;
+mod size_tree;
+mod snapshot;
+mod system_files;
+mod systemd;
+mod tarball;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uring;
+#[cfg(feature = "vhd")]
+mod vhd;
+
+#[cfg(feature = "async")]
+pub mod aio;
+
+pub use analysis_cache::{AnalysisCache, Indexed};
+pub use analyze::{Analysis, DeepStats, Duplicates, Estimate, ExtensionStats, MailArchive, RunSummary, Sample, SampledStats, analyze, deep_analyze, sample_analyze};
+pub use answers::{Answers, OnError};
+pub use archive::ArchiveKind;
+pub use audit::{AuditCheck, verify_audit_log};
+pub use backup_set::{BackupSet, Entries, EntryInfo, read_central_directory};
+pub use benchmark::Throughput;
+pub use builder::{Missing, RestorerBuilder};
+pub use cancel::CancellationToken;
+pub use categories::Categories;
+pub use catalog::{BackupRun, BackupTime, Catalog};
+pub use conflict::{Conflict, ConflictResolver, Resolution};
+pub use doctor::{Bundled, DestCheck, Diagnosis, DiagnosticBundle, SourceCheck, compiled_features, diagnose};
+pub use discovery::{RunLocation, cmp_natural, find_archives, find_zip_files, latest_backup_sets, run_location, select_archives};
+pub use file_history::{is_file_history_data, split_version};
+pub use export::{ExportFormat, Exported, export};
+pub use error::{ArchiveError, EntryError, Error, ErrorKind, Operation};
+pub use filter::{ExcludePreset, Filter, glob_match};
+pub use grep::{GrepMatch, Grepped, grep};
+pub use incomplete::{IncompleteEntries, IncompleteEntry, Incompleteness, find_incomplete};
+pub use manifest::{ManifestCheck, ManifestDiff, compare_manifests, verify_manifest};
+pub use locale::{FluentArgs, Language, Messages};
+pub use options::{AbsoluteEntries, ConflictPolicy, DestLayout, ExtractionOrder, MacQuarantine, ManifestFormat, PlaceholderPolicy, SyncPolicy, SystemFilePolicy};
+pub use paths::strip_drive_letter;
+pub use pipeline::{OrderStats, PipelineStats, QueueStats};
+pub use profile::Browser;
+pub use progress::{NoProgress, ProgressSink};
+pub use query::{Query, QueryError, QueryMatches, run_query};
+pub use recoverables::{Recoverable, RecoverableKind, Recoverables, find_recoverables};
+pub use remap::{backup_profiles, detect_user_remap, target_profiles};
+pub use restorer::{ArchiveStats, ArchiveSummary, Outcome, QUARANTINE_DIR, Restorer, Space, Stats, Totals};
+pub use size::{format_size, parse_size};
+pub use size_tree::{FolderSize, Hotspots, SizeTree, hotspots, list_tree, size_tree};
+pub use search::{Search, SearchMatches, search};
+pub use selection::read_selection;
+#[cfg(feature = "self-update")]
+pub use self_update::Release;
+pub use signing::{Signer, signature_path, verify_signature};
+pub use simulate::{CaseCollision, PlannedAction, PlannedConflict, Simulation};
+pub use snapshot::{LinkDest, Snapshot, snapshots};
+pub use state::{Phase, RunState, STATE_FILE};
+pub use systemd::{KeepAlive, Notifier};
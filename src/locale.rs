@@ -0,0 +1,84 @@
+//! The messages of the command line in the language of the user, from
+//! [Fluent](https://projectfluent.org) catalogs built into the program:
+//! `locales/en.ftl` and `locales/de.ftl`. A message missing from a catalog
+//! is taken from the English one, so a translation may lag behind.
+
+use fluent_bundle::FluentResource;
+use fluent_bundle::concurrent::FluentBundle;
+use unic_langid::LanguageIdentifier;
+
+pub use fluent_bundle::FluentArgs;
+
+/// A language of the messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Language {
+    #[default]
+    En,
+    De,
+}
+
+impl Language {
+    /// The language of the locale set in the environment, by `LC_ALL`,
+    /// `LC_MESSAGES` and `LANG` as `setlocale(3)` reads them; English if it
+    /// is none of the catalogs, as for `C` and `POSIX`.
+    pub fn from_env() -> Language {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"].into_iter().filter_map(|name| std::env::var(name).ok()).find(|value| !value.is_empty());
+        locale.map_or(Language::En, |locale| Language::of_locale(&locale))
+    }
+
+    /// The language of a locale such as `de_AT.UTF-8` or `de-CH`.
+    pub fn of_locale(locale: &str) -> Language {
+        let language = locale.split(['_', '-', '.', '@']).next().unwrap_or_default();
+        match language.to_ascii_lowercase().as_str() {
+            "de" => Language::De,
+            _ => Language::En,
+        }
+    }
+
+    fn catalog(self) -> (&'static str, &'static str) {
+        match self {
+            Language::En => ("en", include_str!("../locales/en.ftl")),
+            Language::De => ("de", include_str!("../locales/de.ftl")),
+        }
+    }
+}
+
+/// The messages of one language.
+pub struct Messages {
+    bundle: FluentBundle<FluentResource>,
+    /// English, for the messages the catalog lacks; `None` for English.
+    fallback: Option<FluentBundle<FluentResource>>,
+}
+
+impl Messages {
+    pub fn new(language: Language) -> Messages {
+        let fallback = (language != Language::En).then(|| bundle(Language::En));
+        Messages { bundle: bundle(language), fallback }
+    }
+
+    /// The message `id` with the values of its placeables from `args`; the
+    /// id itself if no catalog has it.
+    pub fn get(&self, id: &str, args: Option<&FluentArgs>) -> String {
+        [Some(&self.bundle), self.fallback.as_ref()]
+            .into_iter()
+            .flatten()
+            .find_map(|bundle| {
+                let pattern = bundle.get_message(id)?.value()?;
+                let mut errors = Vec::new();
+                Some(bundle.format_pattern(pattern, args, &mut errors).into_owned())
+            })
+            .unwrap_or_else(|| id.to_string())
+    }
+}
+
+fn bundle(language: Language) -> FluentBundle<FluentResource> {
+    let (tag, catalog) = language.catalog();
+    let tag: LanguageIdentifier = tag.parse().expect("valid language tag");
+    let resource = FluentResource::try_new(catalog.to_string()).expect("valid catalog");
+    let mut bundle = FluentBundle::new_concurrent(vec![tag]);
+    // The marks isolating placeables from the text around them show up as
+    // stray characters on a terminal.
+    bundle.set_use_isolating(false);
+    bundle.add_resource(resource).expect("no message defined twice");
+    bundle
+}
@@ -0,0 +1,61 @@
+//! The `com.apple.quarantine` attribute macOS puts on downloaded files, for
+//! [`crate::RestorerBuilder::mac_quarantine`]. Gatekeeper checks the files
+//! that carry it when they are first opened; files restored without it
+//! are trusted as if created on the Mac, unless the app opening them sets
+//! it itself, so whether an old download is checked depends on what opens
+//! it.
+
+use std::fs::File;
+
+use crate::options::MacQuarantine;
+
+const ATTRIBUTE: &str = "com.apple.quarantine";
+
+/// Extensions of programs, installers and scripts.
+const EXECUTABLE: &[&str] = &[
+    "app", "bat", "cmd", "command", "dmg", "exe", "jar", "js", "mpkg", "msi", "pkg", "ps1", "py", "scpt", "sh", "tool",
+    "vbs", "workflow",
+];
+
+/// Whether the file at restore path `name` is an executable or a download,
+/// one [`MacQuarantine::Set`] applies to.
+pub(crate) fn quarantinable(name: &str) -> bool {
+    let (dir, file) = name.rsplit_once('/').unwrap_or(("", name));
+    let executable = file.rsplit_once('.').is_some_and(|(_, ext)| EXECUTABLE.iter().any(|e| ext.eq_ignore_ascii_case(e)));
+    executable || dir.split('/').any(|component| component.eq_ignore_ascii_case("Downloads"))
+}
+
+/// Sets or clears the attribute of `file`, restored to `name`. File
+/// systems without extended attributes, and every platform but macOS,
+/// leave it as it is.
+pub(crate) fn apply(file: &File, name: &str, policy: MacQuarantine) {
+    if policy == MacQuarantine::Set && !quarantinable(name) {
+        return;
+    }
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::fd::AsRawFd;
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let attribute = std::ffi::CString::new(ATTRIBUTE).expect("no NUL in the name");
+        match policy {
+            MacQuarantine::Set => {
+                // Downloaded, by `restore`, now; see `QuarantineProperties`
+                // in LaunchServices.
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+                let value = format!("0081;{:08x};restore;", now);
+                // SAFETY: `file` stays open across the call, which only
+                // reads `attribute` and `value`.
+                unsafe {
+                    libc::fsetxattr(file.as_raw_fd(), attribute.as_ptr(), value.as_ptr().cast(), value.len(), 0, 0)
+                };
+            }
+            // SAFETY: as above.
+            MacQuarantine::Clear => unsafe {
+                libc::fremovexattr(file.as_raw_fd(), attribute.as_ptr(), 0);
+            },
+        }
+    }
+    #[cfg(not(target_os = "macos"))]
+    let _ = (file, ATTRIBUTE);
+}
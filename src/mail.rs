@@ -0,0 +1,11 @@
+//! Outlook data files: `.pst` mail archives and `.ost` offline copies of a
+//! mailbox, often the reason an old backup is dug out at all.
+
+/// Lower-case extensions of Outlook data files.
+pub(crate) const EXTENSIONS: &[&str] = &["pst", "ost"];
+
+/// Whether `path` is an Outlook data file.
+pub(crate) fn is_mail_archive(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.rsplit_once('.').is_some_and(|(stem, ext)| !stem.is_empty() && EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
@@ -1,282 +1,3171 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use clap::Parser;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::process;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Days, Local, NaiveDateTime, NaiveTime, TimeZone};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use restore::{AbsoluteEntries, Analysis, AnalysisCache, Answers, ArchiveError, ArchiveKind, BackupSet, Browser, Bundled, Catalog, Categories, Estimate, Conflict, ConflictResolver, Diagnosis, DiagnosticBundle, EntryError, ErrorKind, ExportFormat, Exported, GrepMatch, Grepped, ArchiveStats, ConflictPolicy, DeepStats, ExcludePreset, ExtensionStats, FluentArgs, Language, Messages, ExtractionOrder, Filter, MacQuarantine, IncompleteEntries, LinkDest, Outcome, IncompleteEntry, Incompleteness, ManifestCheck, ManifestDiff, ManifestFormat, Notifier, Phase, PipelineStats, PlaceholderPolicy, QueueStats, SyncPolicy, SystemFilePolicy, DestLayout, ProgressSink, QUARANTINE_DIR, RecoverableKind, Recoverables, Restorer, RestorerBuilder, SampledStats, RunState, Signer, Simulation, PlannedAction, SizeTree, Throughput, FolderSize, Hotspots, Indexed, Query, Resolution, Search, EntryInfo, BackupTime, STATE_FILE, Stats, Totals, format_size};
+
+/// The message `id` of the catalog, with its placeables given as
+/// `name = value`.
+macro_rules! t {
+    ($id:expr $(, $name:ident = $value:expr)* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut args = FluentArgs::new();
+        $(args.set(stringify!($name), $value);)*
+        messages().get($id, Some(&args))
+    }};
+}
 
 #[derive(Parser, Debug)]
 #[command(
     name = "restore",
     about = "Extract Windows backup ZIP files and restore folder structure",
-    version
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    group = clap::ArgGroup::new("signed").args(["manifest", "audit_log", "stats_json"]).multiple(true)
 )]
 struct Args {
-    #[arg(short, long, value_name = "PATH", help = "Path to the backup folder")]
-    source: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(short, long, value_name = "PATH", value_parser = expand_path, required = true, help = "Path to the backup folder, or to a single archive or disc image")]
+    source: Option<PathBuf>,
     #[arg(
         short,
         long,
         value_name = "PATH",
+        value_parser = expand_path,
+        env = "RESTORE_DEST",
         help = "Destination path for restored files",
         required_unless_present = "analyze_only"
     )]
     dest: Option<PathBuf>,
+    #[arg(long, value_name = "PATH", value_parser = expand_path, conflicts_with = "analyze_only", help = "Also write every restored file to this folder, in the same pass (repeatable)")]
+    mirror: Vec<PathBuf>,
+    #[arg(long, value_enum, value_name = "LAYOUT", default_value_t = DestLayout::Tree, conflicts_with = "mirror", help = "tree: files at their paths; cas: each distinct content once under objects/ab/cdef... by SHA-256, with tree.jsonl mapping the paths to them")]
+    dest_layout: DestLayout,
+    #[arg(long, conflicts_with_all = ["analyze_only", "dest_layout"], help = "Restore each archive into <dest>/<archive name without extension> instead of merging them, to compare what each contained")]
+    subdir_per_zip: bool,
+    #[arg(long, help = "Keep the drive letters of the backup as top-level folders (C/..., D/...) instead of merging the drives into one tree")]
+    keep_drives: bool,
+    #[arg(long, help = "Restore the AppleDouble files macOS leaves in ZIP files (._name, __MACOSX/) instead of skipping them")]
+    keep_apple_double: bool,
+    #[arg(long, visible_alias = "map-drive", value_name = "DRIVE=PATH", value_parser = parse_drive_map, conflicts_with_all = ["analyze_only", "dest_layout"], help = "Restore the files of a drive into this folder instead of the destination, e.g. \"D=/mnt/data\" (repeatable)")]
+    drive_map: Vec<(char, PathBuf)>,
     #[arg(short = 'a', long, help = "Analyze only, do not extract")]
     analyze_only: bool,
+    #[arg(long, help = "Read every archive's central directory for full statistics (uses --threads workers)")]
+    deep: bool,
+    #[arg(long, value_name = "PATH", value_parser = expand_path, requires = "deep", help = "File mapping extensions to the categories --deep counts, one \"Category: ext ext ...\" per line, instead of documents, photos, videos and so on")]
+    categories: Option<PathBuf>,
+    #[arg(long, value_name = "N", requires = "deep", help = "With --deep, read only N archives spread over the backup and estimate the totals of all from them")]
+    sample: Option<usize>,
+    #[arg(long, value_name = "M", requires = "deep", help = "With --deep, read only the first M entries of each archive and estimate the rest from them")]
+    sample_entries: Option<usize>,
+    #[arg(long, help = "Memory-map archives instead of buffered reads (fast on local SSDs)")]
+    mmap: bool,
+    #[arg(long, help = "Read the central directory of every archive, not the entries cached from an earlier run, and cache none")]
+    no_cache: bool,
+    #[arg(long, help = "Do not open the next archive ahead of time")]
+    no_prefetch: bool,
+    #[arg(long, conflicts_with = "no_prefetch", help = "Read the next archive into the page cache while extracting the current one")]
+    warm_cache: bool,
+    #[arg(long, conflicts_with = "analyze_only", help = "Drop large restored files and finished archives from the page cache, so a long restore does not evict what other programs keep there (Linux)")]
+    drop_caches: bool,
+    #[arg(long, help = "Leave restored files with the time they were restored at instead of the times the archive records")]
+    no_preserve_times: bool,
+    #[arg(long, value_name = "ZONE", value_parser = parse_zone, conflicts_with = "no_preserve_times", help = "Time zone of the machine backed up, e.g. Europe/Berlin, for the modification times ZIP files record in its local time (default: that of this machine)")]
+    source_tz: Option<chrono_tz::Tz>,
+    #[arg(long, conflicts_with = "analyze_only", help = "Reserve the space of files of 1 MB and more before writing them, so they are not fragmented and a full disk shows at once")]
+    preallocate: bool,
+    #[arg(long, conflicts_with = "analyze_only", help = "Mark restored files NTFS-compressed, except photos, videos, archives and other formats compressed already, to fit a smaller disk (Windows)")]
+    ntfs_compress: bool,
+    #[arg(long, conflicts_with = "analyze_only", help = "Make every restored file read-only (the read-only attribute on Windows), for restores kept as reference material")]
+    make_readonly: bool,
+    #[arg(long, value_name = "USER[:GROUP]", value_parser = parse_owner, conflicts_with = "analyze_only", help = "Hand restored files and the folders created for them to this user and group, by name or id, e.g. when restoring as root into another user's home (Unix, needs root)")]
+    chown: Option<Owner>,
+    #[arg(long, value_name = "MODE", value_parser = parse_mode, conflicts_with = "analyze_only", help = "Give restored files this octal mode, e.g. 0644, instead of 0666 less the umask (Unix)")]
+    file_mode: Option<u32>,
+    #[arg(long, value_name = "MODE", value_parser = parse_mode, conflicts_with = "analyze_only", help = "Give the folders the restore creates this octal mode, e.g. 0755, instead of 0777 less the umask (Unix)")]
+    dir_mode: Option<u32>,
+    #[arg(long, help = "Take the umask off --file-mode and --dir-mode, as if the files were created with those modes")]
+    respect_umask: bool,
+    #[arg(long, conflicts_with = "analyze_only", help = "Reset the SELinux labels of the restored files with restorecon afterwards, so web roots and homes restored as root work (SELinux systems)")]
+    selinux_relabel: bool,
+    #[arg(long, value_enum, value_name = "POLICY", conflicts_with = "analyze_only", help = "set: mark restored executables, installers, scripts and downloads quarantined so Gatekeeper checks them when opened; clear: remove the mark from every restored file (macOS)")]
+    quarantine: Option<MacQuarantine>,
+    #[arg(long, help = "Restore every backup set, not only the latest full backup and its incremental runs")]
+    all_runs: bool,
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), conflicts_with_all = ["analyze_only", "skip_zips", "snapshot_per_set"], help = "Start at the Nth archive, as numbered [N/total] in the output of an earlier run, to continue it without a state file")]
+    start_at_zip: Option<u64>,
+    #[arg(long, value_name = "N", conflicts_with_all = ["analyze_only", "snapshot_per_set"], help = "Leave out the first N archives the restore would read")]
+    skip_zips: Option<usize>,
+    #[arg(
+        long,
+        conflicts_with_all = ["analyze_only", "mirror", "dest_layout", "subdir_per_zip", "keep_drives", "drive_map", "manifest", "background"],
+        help = "Restore every backup set into <dest>/<machine>/<set time>, with files unchanged since the set before hard-linked to its copy"
+    )]
+    snapshot_per_set: bool,
+    #[arg(long, help = "Stop at the first entry or archive that cannot be restored")]
+    strict: bool,
+    #[arg(long, value_name = "PATH", value_parser = expand_path, conflicts_with = "analyze_only", help = "Write the SHA-256 of every restored file to this file, for `restore verify-manifest`")]
+    manifest: Option<PathBuf>,
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = ManifestFormat::Gnu, requires = "manifest", help = "Line format of the manifest: gnu (sha256sum), bsd (shasum --tag, sha256 on BSD and macOS) or json (one object per line, with size, modification time and archive)")]
+    manifest_format: ManifestFormat,
+    #[arg(long, value_name = "PATH", value_parser = expand_path, conflicts_with = "analyze_only", help = "Record every extracted, overwritten, renamed, skipped, quarantined and failed entry in this hash-chained log of JSON lines, for `restore verify-audit-log`; runs add to it")]
+    audit_log: Option<PathBuf>,
+    #[arg(long, value_name = "PATH", value_parser = expand_path, requires = "signed", help = "Sign the manifest, the audit log and the JSON summary with this minisign secret key, into <file>.minisig; the password comes from RESTORE_SIGN_PASSWORD or the terminal")]
+    sign_key: Option<PathBuf>,
+    #[arg(long, value_name = "PATH", value_parser = expand_path, help = "Also write the summary as JSON to this file, or to stdout for -")]
+    stats_json: Option<PathBuf>,
+    #[arg(short = 'y', long, help = "Restore without asking for confirmation, as scripts need to")]
+    yes: bool,
+    #[arg(long, conflicts_with_all = ["yes", "systemd", "background"], help = "Show what would be restored and stop, without asking")]
+    no: bool,
+    #[arg(long, global = true, value_enum, value_name = "LANG", help = "Language of the messages [default: from LC_ALL, LC_MESSAGES or LANG]")]
+    lang: Option<Language>,
+    #[arg(long, help = "Run as a systemd service (Type=notify): report readiness, progress and watchdog pings, log for the journal and do not ask for confirmation")]
+    systemd: bool,
+    #[arg(long, value_name = "TIME", value_parser = parse_start_at, conflicts_with = "analyze_only", help = "Wait until this local time before restoring: HH:MM for the next time the clock shows it, or \"YYYY-MM-DD HH:MM\"")]
+    start_at: Option<DateTime<Local>>,
+    #[arg(long, requires = "dest", conflicts_with_all = ["analyze_only", "systemd"], help = "Restore in the background without asking, logging to the destination folder; follow it with `restore status`")]
+    background: bool,
+    /// Set for the process `--background` starts.
+    #[arg(long, hide = true)]
+    detached: bool,
+    #[command(flatten)]
+    selection: SelectionArgs,
+    #[arg(long, value_name = "OLD=NEW|auto", value_parser = parse_user_remap, conflicts_with = "analyze_only", help = "Restore the profile of user OLD as that of NEW (Users/OLD/... to Users/NEW/...); auto renames the only user of the backup to the only one in <dest>/Users (repeatable)")]
+    remap_user: Vec<UserRemap>,
+    #[arg(long, requires = "remap_user", help = "With --remap-user, also rename the profile in the absolute paths inside restored .lnk shortcuts")]
+    remap_shortcuts: bool,
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = ConflictPolicy::Overwrite, help = "What to do when a file already exists; ask shows both versions of each file that differs and asks")]
+    on_conflict: ConflictPolicy,
+    #[arg(long, value_name = "FILE", value_parser = parse_answers, help = "Decide conflicts and errors of the files matching the rules of this TOML file, and with --on-conflict ask add the answers given to it, to replay them unattended later")]
+    answers: Option<AnswersFile>,
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = PlaceholderPolicy::Skip, help = "What to do with cloud-only placeholders (e.g. OneDrive files that were online only), whose data is not in the backup")]
+    placeholders: PlaceholderPolicy,
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = SystemFilePolicy::Skip, help = "What to do with registry hives, page files and Windows/System32 content")]
+    system_files: SystemFilePolicy,
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = AbsoluteEntries::StripRoot, help = "What to do with entries named from the root, such as /etc/hosts or C:\\Users\\...: restore them below the destination, or leave them out")]
+    absolute_entries: AbsoluteEntries,
+    #[arg(long, value_enum, value_name = "ORDER", default_value_t = ExtractionOrder::Archive, help = "Order in which the entries of each archive are extracted")]
+    order: ExtractionOrder,
+    #[arg(short = 'j', long, default_value_t = 1, value_name = "N", env = "RESTORE_THREADS", help = "Number of reader threads; they share archives, so even one huge archive is read in parallel (also used by --deep and --sample)")]
+    threads: usize,
+    #[arg(long, value_name = "N", conflicts_with = "analyze_only", help = "Number of reader threads of the restore only, leaving -j to --deep and --sample")]
+    read_threads: Option<usize>,
+    #[arg(long, value_name = "N", conflicts_with = "analyze_only", help = "Number of threads writing restored files (default: 4); more help on fast SSDs and network shares, fewer on a single spinning disk")]
+    write_threads: Option<usize>,
+    #[arg(long, conflicts_with = "analyze_only", help = "Before restoring, measure the archives and the destination for a few seconds, as `restore estimate` does, and choose the reader and writer threads not given by --read-threads or --write-threads from that")]
+    auto_threads: bool,
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, conflicts_with = "analyze_only", help = "Write files larger than this (e.g. 512M or 2G) past the page cache, with O_DIRECT or FILE_FLAG_NO_BUFFERING, so large videos do not slow down the rest of the system")]
+    direct_io: Option<u64>,
+    #[arg(long, value_name = "PATH", value_parser = expand_path, conflicts_with = "analyze_only", help = "Write each file as a .part file in this folder first and move it into place once complete; on another file system, such as for FAT or network destinations, it is copied")]
+    temp_dir: Option<PathBuf>,
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = SyncPolicy::None, conflicts_with = "analyze_only", help = "When to flush restored files to the disk: none (leave it to the system), per-file (slowest, each file is safe once counted), per-zip (once each archive is done) or final (once at the end)")]
+    sync: SyncPolicy,
+}
+
+/// A `--remap-user`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum UserRemap {
+    /// Found by [`restore::detect_user_remap`].
+    Auto,
+    Rename(String, String),
 }
 
-fn collect_zips(dir: &Path, zips: &mut Vec<PathBuf>) -> io::Result<()> {
-    if !dir.is_dir() {
-        return Ok(());
+/// A `--chown`, with the user and group resolved to ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Owner {
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+
+/// Exit statuses, so scripts can tell a clean run from one with errors.
+/// Invalid arguments exit with 2, as reported by clap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    /// Everything was restored.
+    Success = 0,
+    /// The run could not start or was aborted: the source is missing or
+    /// holds no archives, or reading or writing failed as a whole.
+    SetupFailed = 1,
+    /// The run completed, but some entries or archives could not be
+    /// restored.
+    Errors = 3,
+    /// The run stopped before all files were processed: with `--strict`
+    /// at the first error, or because it was not confirmed.
+    Stopped = 4,
+}
+
+impl Status {
+    fn of(outcome: &Outcome) -> Status {
+        let stats = outcome.stats();
+        if outcome.is_cancelled() {
+            Status::Stopped
+        } else if !stats.errors.is_empty() || !stats.quarantined.is_empty() {
+            Status::Errors
+        } else {
+            Status::Success
+        }
     }
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_dir() {
-            collect_zips(&path, zips)?;
-        } else if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("zip")) {
-            zips.push(path);
+
+    fn name(self) -> &'static str {
+        match self {
+            Status::Success => "success",
+            Status::SetupFailed => "setup-failed",
+            Status::Errors => "errors",
+            Status::Stopped => "stopped",
         }
     }
-    Ok(())
+
+    fn from_code(code: i32) -> Status {
+        match code {
+            0 => Status::Success,
+            3 => Status::Errors,
+            4 => Status::Stopped,
+            _ => Status::SetupFailed,
+        }
+    }
+
+    fn exit(self) -> ! {
+        std::process::exit(self as i32)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Restore only the bookmarks, history and saved passwords of a browser
+    /// into a staging folder, with instructions for moving them over
+    ExtractProfile(ProfileArgs),
+    /// Restore only the Outlook data files (.pst, .ost) into a staging
+    /// folder, with instructions for opening them
+    ExtractMail(StagingArgs),
+    /// Report on the contents of a backup
+    #[command(subcommand)]
+    Report(Report),
+    /// Show the files of all archives merged as a tree of folders, from
+    /// their directories
+    Tree(ListTreeArgs),
+    /// Show the progress of a restore into a destination folder, such as one
+    /// running in the background
+    Status(StatusArgs),
+    /// Check the files of a restore against the manifest written with
+    /// --manifest: hash them again and list changed and missing ones
+    VerifyManifest(VerifyManifestArgs),
+    /// Compare two manifests written with --manifest, such as those of
+    /// restores onto two machines: list the files added, removed or with
+    /// other contents
+    CompareManifests(CompareManifestsArgs),
+    /// Check that no line of a log written with --audit-log was changed,
+    /// removed or inserted since
+    VerifyAuditLog(VerifyAuditLogArgs),
+    /// List the files matching a query on their path, name, extension, size
+    /// and modification time, such as "ext = 'jpg' AND size > 5M"
+    Query(QueryArgs),
+    /// Find files by the words of their name, or with --fuzzy by words
+    /// that are nearly right, such as "thesys final v2"
+    Search(SearchArgs),
+    /// Find files by their contents: search the lines of the small text
+    /// files of every archive, such as "invoice 2018-334"
+    Grep(GrepArgs),
+    /// Read the entries of every archive into the cache, so later analyses,
+    /// reports and restores of the backup need not; `index export` writes
+    /// them all to a CSV or Parquet file
+    Index(IndexArgs),
+    /// Manage the entries of archives cached between runs
+    #[command(subcommand)]
+    Cache(Cache),
+    /// Write the man pages of restore and each of its subcommands, made
+    /// from the options of this program, for packages to install
+    Manpages(ManpagesArgs),
+    /// Check the source, the destination and the limits of this system
+    /// before a long restore; --bundle collects what a bug report needs
+    /// into one ZIP file
+    Doctor(DoctorArgs),
+    /// Estimate how long a restore takes, from the size of the files it
+    /// restores and a few seconds of reading the archives and writing to the
+    /// destination
+    Estimate(EstimateArgs),
+    /// Work out what a restore would do with every file, without writing
+    /// anything: list each conflict with a file at the destination or
+    /// within the backup, the rename or skip it leads to, and paths that
+    /// differ only in case
+    Simulate(SimulateArgs),
+    /// Restore one archive, or those matching a glob, of all backup sets,
+    /// such as one that failed in an earlier run, without reading the others
+    Extract(ExtractArgs),
+    /// Replace this program by the latest release on GitHub, after checking
+    /// its checksum and signature
+    #[cfg(feature = "self-update")]
+    SelfUpdate(SelfUpdateArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct QueryArgs {
+    #[command(flatten)]
+    report: ReportArgs,
+    #[arg(value_name = "QUERY", value_parser = parse_query, help = "Conditions on path, name, ext, size and mtime joined with AND, OR, NOT and parentheses, e.g. \"ext = 'jpg' AND size > 5M AND mtime > '2019-01-01'\"")]
+    query: Query,
+    #[arg(long, value_enum, default_value_t = QueryFormat::Table, help = "How to print the files")]
+    format: QueryFormat,
+}
+
+#[derive(clap::Args, Debug)]
+struct SearchArgs {
+    #[command(flatten)]
+    report: ReportArgs,
+    #[arg(value_name = "WORDS", required = true, help = "Words of the path, or with --fuzzy of the file name as remembered")]
+    words: Vec<String>,
+    #[arg(long, help = "Rank the files by how well their names match, allowing typos and words left out")]
+    fuzzy: bool,
+    #[arg(long, conflicts_with = "fuzzy", help = "Take the words as a regular expression matched anywhere in the path, ignoring case")]
+    regex: bool,
+    #[arg(long, value_name = "N", help = "List at most N files [default: 20 with --fuzzy, all without]")]
+    limit: Option<usize>,
+    #[arg(long, value_enum, default_value_t = QueryFormat::Table, help = "How to print the files")]
+    format: QueryFormat,
 }
 
-fn cmp_natural(a: &str, b: &str) -> std::cmp::Ordering {
-    use std::cmp::Ordering;
+#[derive(clap::Args, Debug)]
+struct GrepArgs {
+    #[command(flatten)]
+    report: ReportArgs,
+    #[arg(value_name = "TEXT", help = "Text to look for in the lines of the files, ignoring case")]
+    text: String,
+    #[arg(long, help = "Take the text as a regular expression")]
+    regex: bool,
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "1M", help = "Leave out files larger than this")]
+    max_size: u64,
+    #[arg(short = 'j', long, default_value_t = 1, value_name = "N", help = "Number of archives read at once")]
+    threads: usize,
+}
 
-    let ab = a.as_bytes();
-    let bb = b.as_bytes();
-    let mut ai = 0usize;
-    let mut bi = 0usize;
+/// Output of `restore query` and `restore search`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum QueryFormat {
+    /// Aligned columns, with the total at the end.
+    Table,
+    /// One JSON object per line.
+    Json,
+    /// Comma-separated values with a header line.
+    Csv,
+}
 
-    while ai < ab.len() && bi < bb.len() {
-        let a_digit = ab[ai].is_ascii_digit();
-        let b_digit = bb[bi].is_ascii_digit();
+#[derive(clap::Args, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct IndexArgs {
+    #[command(subcommand)]
+    command: Option<IndexCommand>,
+    #[arg(short, long, required = true, value_name = "PATH", value_parser = expand_path, help = "Path to the backup folder, or to a single archive or disc image")]
+    source: Option<PathBuf>,
+    #[arg(long, help = "Only read the archives added or changed since they were last indexed")]
+    update: bool,
+    #[arg(short = 'j', long, default_value_t = 1, value_name = "N", help = "Number of archives read at once")]
+    threads: usize,
+    #[arg(long, help = "Memory-map archives instead of using buffered reads")]
+    mmap: bool,
+}
 
-        if a_digit && b_digit {
-            let a_start = ai;
-            while ai < ab.len() && ab[ai].is_ascii_digit() {
-                ai += 1;
-            }
-            let b_start = bi;
-            while bi < bb.len() && bb[bi].is_ascii_digit() {
-                bi += 1;
+#[derive(Subcommand, Debug)]
+enum IndexCommand {
+    /// Write the entries of every archive to a file, for pandas, DuckDB or a
+    /// spreadsheet
+    Export(ExportArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ExportArgs {
+    #[command(flatten)]
+    report: ReportArgs,
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv, help = "File format; parquet needs a build with the parquet feature")]
+    format: ExportFormat,
+    #[arg(short, long, value_name = "PATH", help = "File to write; CSV goes to the standard output without it")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Cache {
+    /// Remove every cached archive, so the next run reads all of them again
+    Clear,
+}
+
+#[derive(clap::Args, Debug)]
+struct ManpagesArgs {
+    #[arg(long, value_name = "DIR", value_parser = expand_path, help = "Folder to write the pages to, such as share/man/man1; created if missing")]
+    out_dir: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct DoctorArgs {
+    #[arg(short, long, value_name = "PATH", value_parser = expand_path, help = "Backup folder, or a single archive, to check and describe")]
+    source: Option<PathBuf>,
+    #[arg(short, long, value_name = "PATH", value_parser = expand_path, help = "Destination folder to check; for --bundle, that of the last restore, with its state file, background log and quarantined files")]
+    dest: Option<PathBuf>,
+    #[arg(long, value_name = "ZIP", value_parser = expand_path, help = "Also write a ZIP file with the environment, the last restore and the archive metadata without file names, for attaching to a bug report")]
+    bundle: Option<PathBuf>,
+    #[arg(long, value_name = "PATH", value_parser = expand_path, requires = "bundle", help = "Summary written by the restore with --stats-json, listing its errors, for the bundle")]
+    stats_json: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct EstimateArgs {
+    #[arg(short, long, value_name = "PATH", value_parser = expand_path, help = "Path to the backup folder, or to a single archive or disc image")]
+    source: PathBuf,
+    #[arg(short, long, value_name = "PATH", value_parser = expand_path, help = "Destination folder of the restore")]
+    dest: PathBuf,
+    #[arg(long, value_name = "GLOB", help = "Only count entries matching this pattern, as the restore would (repeatable)")]
+    include: Vec<String>,
+    #[arg(long, value_name = "GLOB", help = "Leave out entries matching this pattern, as the restore would (repeatable)")]
+    exclude: Vec<String>,
+    #[arg(long, help = "Take the --include and --exclude patterns as regular expressions matched anywhere in the path, ignoring case")]
+    regex: bool,
+    #[arg(long, help = "Count every backup set, not only the latest full backup and its incremental runs")]
+    all_runs: bool,
+    #[arg(long, help = "Read the central directory of every archive, not the entries cached from an earlier run")]
+    no_cache: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct SimulateArgs {
+    #[arg(short, long, value_name = "PATH", value_parser = expand_path, help = "Path to the backup folder, or to a single archive or disc image")]
+    source: PathBuf,
+    #[arg(short, long, value_name = "PATH", value_parser = expand_path, help = "Destination folder of the restore, which is only read")]
+    dest: PathBuf,
+    #[arg(long, help = "Restore every backup set, not only the latest full backup and its incremental runs")]
+    all_runs: bool,
+    #[command(flatten)]
+    placement: PlacementArgs,
+    #[arg(long, help = "Read the central directory of every archive, not the entries cached from an earlier run")]
+    no_cache: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct ExtractArgs {
+    #[arg(short, long, value_name = "PATH", value_parser = expand_path, help = "Path to the backup folder")]
+    source: PathBuf,
+    #[arg(short, long, value_name = "PATH", value_parser = expand_path, help = "Destination folder of the restore")]
+    dest: PathBuf,
+    #[arg(long, value_name = "NAME|GLOB|N", help = "Archive to restore: its file name, a glob matching file names such as \"Backup files 1?.zip\", or its number as --analyze-only lists it and a restore's progress shows it")]
+    zip: String,
+    #[arg(long, help = "Number the archives of all backup sets for --zip N, as a restore with --all-runs does, rather than those of the latest set")]
+    all_runs: bool,
+    #[command(flatten)]
+    placement: PlacementArgs,
+    #[arg(short = 'j', long, default_value_t = 1, value_name = "N", env = "RESTORE_THREADS", help = "Number of reader threads")]
+    threads: usize,
+}
+
+/// The options of a restore deciding which files it restores, for the
+/// restore and the subcommands restoring or simulating one.
+#[derive(clap::Args, Debug)]
+struct SelectionArgs {
+    #[arg(long, value_name = "GLOB", help = "Only restore entries matching this pattern (repeatable)")]
+    include: Vec<String>,
+    #[arg(long, value_name = "GLOB", help = "Skip entries matching this pattern (repeatable)")]
+    exclude: Vec<String>,
+    #[arg(long, help = "Take the --include and --exclude patterns as regular expressions matched anywhere in the path, ignoring case")]
+    regex: bool,
+    #[arg(long, help = "Only restore the Documents, Desktop, Pictures, Music, Videos and Downloads folders of the users")]
+    user_data_only: bool,
+    #[arg(long, value_name = "NAME", requires = "user_data_only", help = "With --user-data-only, only restore the folders of this user (repeatable)")]
+    user: Vec<String>,
+    #[arg(long, value_enum, value_name = "PRESET", help = "Skip a well-known set of junk paths: temp, caches, dev or junk for all three (repeatable)")]
+    exclude_preset: Vec<ExcludePreset>,
+    #[arg(long, value_name = "QUERY", value_parser = parse_query, help = "Only restore the files matching this query, as listed by `restore query`")]
+    from_query: Option<Query>,
+    #[arg(long, value_name = "FILE", value_parser = parse_selection, help = "Only restore the files listed in this file: the JSON printed by `restore query --format json`, or one path per line")]
+    selection: Option<Selection>,
+}
+
+impl SelectionArgs {
+    /// The filter of these options.
+    fn filter(&self) -> Result<Filter, regex::Error> {
+        let mut filter = with_patterns(Filter::new(), &self.include, &self.exclude, self.regex)?;
+        if self.user_data_only && self.user.is_empty() {
+            filter = filter.user_data(None);
+        }
+        for user in &self.user {
+            filter = filter.user_data(Some(user));
+        }
+        for &preset in &self.exclude_preset {
+            filter = filter.exclude_preset(preset);
+        }
+        if let Some(query) = &self.from_query {
+            filter = filter.query(query.clone());
+        }
+        if let Some(Selection(paths)) = &self.selection {
+            filter = filter.selection(paths);
+        }
+        Ok(filter)
+    }
+}
+
+/// The options of a restore deciding which files it restores and where,
+/// for subcommands restoring or simulating a restore.
+#[derive(clap::Args, Debug)]
+struct PlacementArgs {
+    #[command(flatten)]
+    selection: SelectionArgs,
+    #[arg(long, help = "Restore each archive into <dest>/<archive name without extension> instead of merging them")]
+    subdir_per_zip: bool,
+    #[arg(long, help = "Keep the drive letters of the backup as top-level folders (C/..., D/...) instead of merging the drives into one tree")]
+    keep_drives: bool,
+    #[arg(long, help = "Restore the AppleDouble files macOS leaves in ZIP files (._name, __MACOSX/) instead of skipping them")]
+    keep_apple_double: bool,
+    #[arg(long, visible_alias = "map-drive", value_name = "DRIVE=PATH", value_parser = parse_drive_map, help = "Restore the files of a drive into this folder instead of the destination, e.g. \"D=/mnt/data\" (repeatable)")]
+    drive_map: Vec<(char, PathBuf)>,
+    #[arg(long, value_name = "OLD=NEW", value_parser = parse_user_remap, help = "Restore the profile of user OLD as that of NEW (Users/OLD/... to Users/NEW/...) (repeatable)")]
+    remap_user: Vec<UserRemap>,
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = ConflictPolicy::Overwrite, help = "What to do when a file already exists")]
+    on_conflict: ConflictPolicy,
+    #[arg(long, value_name = "FILE", value_parser = parse_answers, help = "Decide the conflicts of the files matching the rules of this TOML file")]
+    answers: Option<AnswersFile>,
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = PlaceholderPolicy::Skip, help = "What to do with cloud-only placeholders, whose data is not in the backup")]
+    placeholders: PlaceholderPolicy,
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = SystemFilePolicy::Skip, help = "What to do with registry hives, page files and Windows/System32 content")]
+    system_files: SystemFilePolicy,
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = AbsoluteEntries::StripRoot, help = "What to do with entries named from the root, such as /etc/hosts or C:\\Users\\...: restore them below the destination, or leave them out")]
+    absolute_entries: AbsoluteEntries,
+}
+
+impl PlacementArgs {
+    /// Sets these options on `builder`.
+    fn apply<S, D>(&self, builder: RestorerBuilder<S, D>) -> io::Result<RestorerBuilder<S, D>> {
+        let filter = self.selection.filter().map_err(invalid_regex)?;
+        let mut builder = builder
+            .filter(filter)
+            .conflict_policy(self.on_conflict)
+            .placeholders(self.placeholders)
+            .system_files(self.system_files)
+            .absolute_entries(self.absolute_entries)
+            .subdir_per_archive(self.subdir_per_zip)
+            .keep_drives(self.keep_drives)
+            .keep_apple_double(self.keep_apple_double);
+        for (drive, dir) in &self.drive_map {
+            builder = builder.drive_map(*drive, dir);
+        }
+        for remap in &self.remap_user {
+            if let UserRemap::Rename(old, new) = remap {
+                builder = builder.remap_user(old, new);
             }
+        }
+        if let Some(file) = &self.answers {
+            builder = builder.answers(file.answers.clone());
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(feature = "self-update")]
+#[derive(clap::Args, Debug)]
+struct SelfUpdateArgs {
+    #[arg(long, help = "Only show whether a newer release exists")]
+    check: bool,
+    #[arg(long, value_name = "PATH", value_parser = expand_path, help = "Check the signature of the release with this minisign public key instead of the one built in")]
+    public_key: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct VerifyAuditLogArgs {
+    #[arg(long, value_name = "PATH", value_parser = expand_path, help = "Audit log written by the restore with --audit-log")]
+    audit_log: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct CompareManifestsArgs {
+    #[arg(value_name = "A", value_parser = expand_path, help = "Manifest written by the first restore with --manifest")]
+    a: PathBuf,
+    #[arg(value_name = "B", value_parser = expand_path, help = "Manifest written by the second restore with --manifest")]
+    b: PathBuf,
+    #[arg(long, value_name = "PATH", value_parser = expand_path, help = "Check the signatures of both manifests from --sign-key with this minisign public key first")]
+    public_key: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct VerifyManifestArgs {
+    #[arg(long, value_name = "PATH", value_parser = expand_path, help = "Manifest written by the restore with --manifest")]
+    manifest: PathBuf,
+    #[arg(short, long, value_name = "PATH", value_parser = expand_path, help = "Destination folder of the restore; a mirror works too")]
+    dest: PathBuf,
+    #[arg(long, value_name = "PATH", value_parser = expand_path, help = "Check the manifest's signature from --sign-key with this minisign public key first")]
+    public_key: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct StatusArgs {
+    #[arg(short, long, value_name = "PATH", value_parser = expand_path, help = "Destination folder of the restore")]
+    dest: PathBuf,
+    #[arg(short, long, help = "Keep showing the progress until the restore ends, then exit with its status")]
+    follow: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Report {
+    /// List the saved games, SSH keys, certificates, wallets and license
+    /// files in the backup, so they can be restored first
+    Recoverables(ReportArgs),
+    /// List the files stored with zero bytes or with less data than their
+    /// header gives, which the backup tool most likely could not read
+    Incomplete(IncompleteArgs),
+    /// Add up the size of the files by folder, as du does, to see where the
+    /// bulk of the backup lives before choosing filters
+    Tree(TreeArgs),
+    /// List the folders holding the most bytes and the most files, such as
+    /// caches and photo dumps worth excluding
+    Hotspots(HotspotsArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct HotspotsArgs {
+    #[command(flatten)]
+    report: ReportArgs,
+    #[arg(long, default_value_t = 20, value_name = "N", help = "How many folders to list of each")]
+    top: usize,
+}
+
+#[derive(clap::Args, Debug)]
+struct ListTreeArgs {
+    #[command(flatten)]
+    report: ReportArgs,
+    #[arg(long, default_value = "", value_name = "PATH", help = "Folder to show, as restored, e.g. Users/bob (default: all)")]
+    path: String,
+    #[arg(long, default_value_t = 2, value_name = "N", help = "How many levels deep to show")]
+    depth: usize,
+    #[arg(long, value_name = "PATTERN", help = "Only show files matching this glob pattern (repeatable)")]
+    include: Vec<String>,
+    #[arg(long, value_name = "PATTERN", help = "Leave out files matching this glob pattern (repeatable)")]
+    exclude: Vec<String>,
+    #[arg(long, help = "Take the --include and --exclude patterns as regular expressions matched anywhere in the path, ignoring case")]
+    regex: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct TreeArgs {
+    #[command(flatten)]
+    report: ReportArgs,
+    #[arg(long, default_value_t = 3, value_name = "N", help = "How many folders deep to go; files below count for their folder at that depth")]
+    depth: usize,
+}
+
+#[derive(clap::Args, Debug)]
+struct IncompleteArgs {
+    #[command(flatten)]
+    report: ReportArgs,
+    #[arg(long, help = "Only list zero-byte files, without decompressing every entry to check its size")]
+    quick: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct ReportArgs {
+    #[arg(short, long, value_name = "PATH", value_parser = expand_path, help = "Path to the backup folder, or to a single archive or disc image")]
+    source: PathBuf,
+    #[arg(long, help = "Memory-map archives instead of using buffered reads")]
+    mmap: bool,
+    #[arg(long, help = "Read the central directory of every archive, not the entries cached from an earlier run")]
+    no_cache: bool,
+}
+
+/// Arguments of the commands restoring a few files into a staging folder.
+#[derive(clap::Args, Debug)]
+struct StagingArgs {
+    #[arg(short, long, value_name = "PATH", value_parser = expand_path, help = "Path to the backup folder, or to a single archive or disc image")]
+    source: PathBuf,
+    #[arg(short, long, value_name = "PATH", value_parser = expand_path, help = "Staging folder the files are restored to")]
+    dest: PathBuf,
+    #[arg(long, value_name = "NAME", help = "Only restore the files of this Windows user")]
+    user: Option<String>,
+    #[arg(long, help = "Restore every backup set, not only the latest full backup and its incremental runs")]
+    all_runs: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct ProfileArgs {
+    #[command(flatten)]
+    staging: StagingArgs,
+    #[arg(long, value_enum, help = "Browser whose profiles to restore")]
+    browser: Browser,
+}
 
-            let a_run = &a[a_start..ai];
-            let b_run = &b[b_start..bi];
-            let a_trim = a_run.trim_start_matches('0');
-            let b_trim = b_run.trim_start_matches('0');
+const MAIL_INSTRUCTIONS: &str = "\
+The restored Outlook data files keep their paths from the backup, usually
+  Users/<name>/Documents/Outlook Files/ (.pst) or
+  Users/<name>/AppData/Local/Microsoft/Outlook/ (.pst and .ost).
 
-            let a_len = a_trim.len();
-            let b_len = b_trim.len();
-            if a_len != b_len {
-                return a_len.cmp(&b_len);
+To open a .pst file in Outlook, choose File > Open & Export > Open Outlook
+Data File. To copy its mail into the current mailbox instead, choose
+File > Open & Export > Import/Export > Import from another program or file
+> Outlook Data File (.pst).
+
+.ost files are offline copies of an Exchange, Microsoft 365 or IMAP mailbox
+and cannot be opened by Outlook. If the account still exists, setting it up
+again downloads the mail; otherwise an OST to PST converter can read them.
+";
+
+impl Command {
+    fn source(&self) -> Option<&Path> {
+        match self {
+            Command::ExtractProfile(args) => Some(&args.staging.source),
+            Command::ExtractMail(args) => Some(&args.source),
+            Command::Report(Report::Recoverables(args)) => Some(&args.source),
+            Command::Report(Report::Incomplete(args)) => Some(&args.report.source),
+            Command::Report(Report::Tree(args)) => Some(&args.report.source),
+            Command::Report(Report::Hotspots(args)) => Some(&args.report.source),
+            Command::Tree(args) => Some(&args.report.source),
+            Command::Query(args) => Some(&args.report.source),
+            Command::Search(args) => Some(&args.report.source),
+            Command::Grep(args) => Some(&args.report.source),
+            Command::Index(IndexArgs { command: Some(IndexCommand::Export(args)), .. }) => Some(&args.report.source),
+            Command::Index(args) => args.source.as_deref(),
+            Command::Estimate(args) => Some(&args.source),
+            Command::Simulate(args) => Some(&args.source),
+            Command::Extract(args) => Some(&args.source),
+            Command::Status(_) | Command::VerifyManifest(_) | Command::CompareManifests(_) | Command::VerifyAuditLog(_) | Command::Cache(_) | Command::Manpages(_) | Command::Doctor(_) => None,
+            #[cfg(feature = "self-update")]
+            Command::SelfUpdate(_) => None,
+        }
+    }
+
+    fn run(&self) -> io::Result<Status> {
+        match self {
+            Command::ExtractProfile(args) => {
+                let name = format!("{} profile", args.browser.name());
+                let filter = args.browser.filter(args.staging.user.as_deref());
+                extract_staged(&args.staging, filter, &name, args.browser.instructions())
             }
-            let ord = a_trim.cmp(b_trim);
-            if ord != Ordering::Equal {
-                return ord;
+            Command::ExtractMail(args) => {
+                extract_staged(args, Filter::new().include_mail(args.user.as_deref()), "Outlook mail", MAIL_INSTRUCTIONS)
             }
-            let ord = a_run.len().cmp(&b_run.len());
-            if ord != Ordering::Equal {
-                return ord;
+            Command::Report(Report::Recoverables(args)) => {
+                let analysis = restore::analyze(&args.source)?;
+                let set = BackupSet::from_archives(&analysis.source, analysis.zips).with_mmap(args.mmap).with_cache(analysis_cache(args.no_cache));
+                let recoverables = restore::find_recoverables(&set);
+                print_recoverables(&recoverables);
+                Ok(if recoverables.errors.is_empty() { Status::Success } else { Status::Errors })
             }
-        } else {
-            let a_byte = ab[ai].to_ascii_lowercase();
-            let b_byte = bb[bi].to_ascii_lowercase();
-            if a_byte != b_byte {
-                return a_byte.cmp(&b_byte);
+            Command::Report(Report::Incomplete(args)) => {
+                let analysis = restore::analyze(&args.report.source)?;
+                let set = BackupSet::from_archives(&analysis.source, analysis.zips).with_mmap(args.report.mmap).with_cache(analysis_cache(args.report.no_cache));
+                let incomplete = restore::find_incomplete(&set, !args.quick);
+                print_incomplete(&incomplete);
+                Ok(if incomplete.errors.is_empty() { Status::Success } else { Status::Errors })
+            }
+            Command::Report(Report::Tree(args)) => {
+                let analysis = restore::analyze(&args.report.source)?;
+                let set = BackupSet::from_archives(&analysis.source, analysis.zips).with_mmap(args.report.mmap).with_cache(analysis_cache(args.report.no_cache));
+                let tree = restore::size_tree(&set, args.depth);
+                print_size_tree(&tree, args.depth);
+                Ok(if tree.errors.is_empty() { Status::Success } else { Status::Errors })
+            }
+            Command::Report(Report::Hotspots(args)) => {
+                let analysis = restore::analyze(&args.report.source)?;
+                let set = BackupSet::from_archives(&analysis.source, analysis.zips).with_mmap(args.report.mmap).with_cache(analysis_cache(args.report.no_cache));
+                let hotspots = restore::hotspots(&set);
+                print_hotspots(&hotspots, args.top);
+                Ok(if hotspots.errors.is_empty() { Status::Success } else { Status::Errors })
+            }
+            Command::Tree(args) => {
+                let analysis = restore::analyze(&args.report.source)?;
+                let set = BackupSet::from_archives(&analysis.source, analysis.zips).with_mmap(args.report.mmap).with_cache(analysis_cache(args.report.no_cache));
+                let filter = with_patterns(Filter::new(), &args.include, &args.exclude, args.regex).map_err(invalid_regex)?;
+                let tree = restore::list_tree(&set, &args.path, args.depth, &filter);
+                print_tree(&tree, args.path.trim_matches('/'));
+                Ok(if tree.errors.is_empty() { Status::Success } else { Status::Errors })
+            }
+            Command::Status(args) => show_status(args),
+            Command::Manpages(args) => {
+                fs::create_dir_all(&args.out_dir)?;
+                let mut command = Args::command().disable_help_subcommand(true);
+                // Names the subcommands `restore-index-export` and so on.
+                command.build();
+                for path in write_manpages(command, &args.out_dir)? {
+                    println!("{}", path.display());
+                }
+                Ok(Status::Success)
+            }
+            Command::Doctor(args) => {
+                let diagnosis = restore::diagnose(args.source.as_deref(), args.dest.as_deref());
+                let healthy = print_diagnosis(&diagnosis);
+                if let Some(path) = &args.bundle {
+                    let bundle = DiagnosticBundle { source: args.source.clone(), dest: args.dest.clone(), stats_json: args.stats_json.clone() };
+                    let bundled = bundle.write(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+                    print_bundled(&bundled, path);
+                }
+                Ok(if healthy { Status::Success } else { Status::Errors })
+            }
+            Command::Estimate(args) => {
+                let filter = with_patterns(Filter::new(), &args.include, &args.exclude, args.regex).map_err(invalid_regex)?;
+                let mut builder = Restorer::builder().source(&args.source).dest(&args.dest).filter(filter).all_runs(args.all_runs);
+                if let Some(cache) = analysis_cache(args.no_cache) {
+                    builder = builder.analysis_cache(cache);
+                }
+                let restorer = builder.build();
+                let zips = restorer.archives()?;
+                let archive_bytes: u64 = zips.iter().filter_map(|zip| fs::metadata(zip).ok()).map(|m| m.len()).sum();
+                let totals = restorer.count(&zips);
+                println!("Measuring the speed of {} and {}...", args.source.display(), args.dest.display());
+                let throughput = Throughput::measure(&zips, &args.dest)?;
+                print_estimate(&throughput, zips.len(), archive_bytes, &totals);
+                Ok(Status::Success)
+            }
+            Command::Simulate(args) => {
+                let mut builder = args.placement.apply(Restorer::builder().source(&args.source).dest(&args.dest).all_runs(args.all_runs))?;
+                if let Some(cache) = analysis_cache(args.no_cache) {
+                    builder = builder.analysis_cache(cache);
+                }
+                let restorer = builder.build();
+                let zips = restorer.archives()?;
+                let simulation = restorer.simulate(&zips);
+                print_simulation(&simulation, &args.dest);
+                Ok(if simulation.errors.is_empty() { Status::Success } else { Status::Errors })
+            }
+            Command::Extract(args) => {
+                let builder = Restorer::builder().source(&args.source).dest(&args.dest).archive(&args.zip).all_runs(args.all_runs).threads(args.threads);
+                let restorer = args.placement.apply(builder)?.conflict_resolver(Arc::new(ConflictPrompt::new(args.placement.answers.as_ref()))).build();
+                let state = StateFile::new(&args.source, &args.dest, None);
+                let Some(outcome) = extract(restorer, args.placement.system_files, None, &state)? else { return Ok(Status::SetupFailed) };
+                Ok(Status::of(&outcome))
+            }
+            #[cfg(feature = "self-update")]
+            Command::SelfUpdate(args) => self_update(args),
+            Command::Query(args) => {
+                let analysis = restore::analyze(&args.report.source)?;
+                let set = BackupSet::from_archives(&analysis.source, analysis.zips).with_mmap(args.report.mmap).with_cache(analysis_cache(args.report.no_cache));
+                let matches = restore::run_query(&set, &args.query);
+                // Output piped into `head` ends early.
+                print_query(&matches.files, &matches.errors, args.format).or_else(|e| if e.kind() == io::ErrorKind::BrokenPipe { Ok(()) } else { Err(e) })?;
+                Ok(if matches.errors.is_empty() { Status::Success } else { Status::Errors })
+            }
+            Command::Search(args) => {
+                let analysis = restore::analyze(&args.report.source)?;
+                let set = BackupSet::from_archives(&analysis.source, analysis.zips).with_mmap(args.report.mmap).with_cache(analysis_cache(args.report.no_cache));
+                let words = args.words.join(" ");
+                let search = match (args.fuzzy, args.regex) {
+                    (true, _) => Search::fuzzy(&words),
+                    (false, true) => Search::regex(regex(&words).map_err(invalid_regex)?),
+                    (false, false) => Search::new(&words),
+                };
+                let limit = args.limit.or(args.fuzzy.then_some(20));
+                let matches = restore::search(&set, &search, limit);
+                print_query(&matches.files, &matches.errors, args.format).or_else(|e| if e.kind() == io::ErrorKind::BrokenPipe { Ok(()) } else { Err(e) })?;
+                Ok(if matches.errors.is_empty() { Status::Success } else { Status::Errors })
+            }
+            Command::Grep(args) => {
+                let pattern = regex(&if args.regex { args.text.clone() } else { regex::escape(&args.text) }).map_err(invalid_regex)?;
+                let analysis = restore::analyze(&args.report.source)?;
+                let set = BackupSet::from_archives(&analysis.source, analysis.zips).with_mmap(args.report.mmap);
+                let grepped = restore::grep(&set, &pattern, args.max_size, args.threads, print_grep_match);
+                print_grepped(&grepped, args.max_size);
+                Ok(if grepped.errors.is_empty() { Status::Success } else { Status::Errors })
+            }
+            Command::Index(IndexArgs { command: Some(IndexCommand::Export(args)), .. }) => export_index(args),
+            Command::Index(args) => {
+                let cache = user_cache()?;
+                let source = args.source.as_ref().expect("required without a subcommand");
+                let analysis = restore::analyze(source)?;
+                let set = BackupSet::from_archives(&analysis.source, analysis.zips).with_mmap(args.mmap);
+                let indexed = cache.index(&set, args.threads, args.update);
+                print_indexed(&indexed, cache.dir());
+                Ok(if indexed.errors.is_empty() { Status::Success } else { Status::Errors })
+            }
+            Command::Cache(Cache::Clear) => {
+                let cache = user_cache()?;
+                let removed = cache.clear().map_err(|e| io::Error::new(e.kind(), format!("{}: {}", cache.dir().display(), e)))?;
+                println!("Removed {} cached archives from {}.", removed, cache.dir().display());
+                Ok(Status::Success)
+            }
+            Command::VerifyAuditLog(args) => {
+                let check = restore::verify_audit_log(&args.audit_log)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", args.audit_log.display(), e)))?;
+                match check.broken {
+                    None => println!("Audit log intact: {} records.", check.records),
+                    Some(line) => println!(
+                        "Audit log broken at line {} of {}: it was changed, or a line before it was changed, removed or inserted.",
+                        line, check.records
+                    ),
+                }
+                Ok(if check.is_intact() { Status::Success } else { Status::Errors })
+            }
+            Command::VerifyManifest(args) => {
+                let signed = match &args.public_key {
+                    Some(key) => match restore::verify_signature(&args.manifest, key) {
+                        Ok(comment) => Some(comment),
+                        Err(e) => {
+                            eprintln!("ERROR: The signature of {} does not check out: {}", args.manifest.display(), e);
+                            return Ok(Status::Errors);
+                        }
+                    },
+                    None => None,
+                };
+                let check = restore::verify_manifest(&args.manifest, &args.dest)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", args.manifest.display(), e)))?;
+                print_manifest_check(&check, signed.as_deref());
+                Ok(if check.is_intact() { Status::Success } else { Status::Errors })
+            }
+            Command::CompareManifests(args) => {
+                if let Some(key) = &args.public_key {
+                    for manifest in [&args.a, &args.b] {
+                        if let Err(e) = restore::verify_signature(manifest, key) {
+                            eprintln!("ERROR: The signature of {} does not check out: {}", manifest.display(), e);
+                            return Ok(Status::Errors);
+                        }
+                    }
+                }
+                let diff = restore::compare_manifests(&args.a, &args.b)?;
+                print_manifest_diff(&diff, &args.a, &args.b);
+                Ok(if diff.is_identical() { Status::Success } else { Status::Errors })
             }
-            ai += 1;
-            bi += 1;
         }
     }
-
-    ab.len().cmp(&bb.len())
 }
 
-fn find_zip_files(source_dir: &Path) -> io::Result<Vec<PathBuf>> {
-    let mut zips = Vec::new();
-    collect_zips(source_dir, &mut zips)?;
-    zips.sort_by(|a, b| cmp_natural(&a.to_string_lossy(), &b.to_string_lossy()));
-    Ok(zips)
+/// Writes the man page of `command` and of its subcommands into `dir`,
+/// returning their paths.
+fn write_manpages(command: clap::Command, dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let page = clap_mangen::Man::new(command.clone()).source(format!("restore {}", env!("CARGO_PKG_VERSION")));
+    let mut written = vec![page.generate_to(dir)?];
+    for subcommand in command.get_subcommands().filter(|subcommand| !subcommand.is_hide_set()) {
+        written.extend(write_manpages(subcommand.clone(), dir)?);
+    }
+    Ok(written)
 }
 
-fn strip_drive_letter(path: &str) -> &str {
-    let bytes = path.as_bytes();
-    // Match patterns like "C/" or "C\" at start
-    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && (bytes[1] == b'/' || bytes[1] == b'\\') {
-        &path[2..]
-    } else {
-        path
+/// Installs the latest release if it is newer than this program, or with
+/// `--check` only tells.
+#[cfg(feature = "self-update")]
+fn self_update(args: &SelfUpdateArgs) -> io::Result<Status> {
+    let release = restore::Release::latest()?;
+    if !release.is_newer() {
+        println!("restore {} is up to date (latest release: {}).", env!("CARGO_PKG_VERSION"), release.version);
+        return Ok(Status::Success);
     }
+    if args.check {
+        println!("restore {} is available (running {}).", release.version, env!("CARGO_PKG_VERSION"));
+        return Ok(Status::Success);
+    }
+    println!("Downloading {} of restore {}...", release.asset, release.version);
+    let exe = release.install(args.public_key.as_deref())?;
+    println!("Updated {} to restore {}.", exe.display(), release.version);
+    Ok(Status::Success)
 }
 
-fn analyze(source_dir: &Path) -> io::Result<Vec<PathBuf>> {
-    let zips = find_zip_files(source_dir)?;
-    let total_size: u64 = zips.iter().filter_map(|z| fs::metadata(z).ok()).map(|m| m.len()).sum();
+/// The cache of archive entries of the user, unless `no_cache`.
+fn analysis_cache(no_cache: bool) -> Option<AnalysisCache> {
+    if no_cache { None } else { AnalysisCache::user() }
+}
 
-    println!("\n{}", "=".repeat(60));
-    println!(" Windows Backup Analyzer");
-    println!("{}", "=".repeat(60));
-    println!(" Source directory:  {}", source_dir.display());
-    println!(" ZIP files:         {}", zips.len());
-    println!(" Total size:        {:.2} GB", total_size as f64 / (1024.0 * 1024.0 * 1024.0));
+/// The cache of archive entries of the user, for the commands that work on
+/// it.
+fn user_cache() -> io::Result<AnalysisCache> {
+    AnalysisCache::user().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No home folder for the cache"))
+}
 
-    // Show sample from first ZIP
-    if let Some(first) = zips.first()
-        && let Ok(file) = fs::File::open(first)
-        && let Ok(mut archive) = zip::ZipArchive::new(file)
-    {
-        let mut extensions: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
-        for i in 0..archive.len() {
-            if let Ok(entry) = archive.by_index(i)
-                && let Some(ext) = Path::new(entry.name()).extension()
-            {
-                *extensions.entry(ext.to_string_lossy().to_lowercase()).or_insert(0) += 1;
-            }
+impl Args {
+    fn restorer(&self, source: &Path, dest: &Path) -> Restorer {
+        self.builder(source, dest).build()
+    }
+
+    /// Replaces a `--remap-user auto` by the user it finds, or drops it with
+    /// a warning.
+    fn detect_user_remap(&mut self) {
+        let (Some(source), Some(dest)) = (&self.source, &self.dest) else { return };
+        if !self.remap_user.contains(&UserRemap::Auto) || !source.exists() {
+            return;
         }
-        println!("\n Sample from: {}", first.file_name().unwrap_or_default().to_string_lossy());
-        let mut sorted: Vec<_> = extensions.into_iter().collect();
-        sorted.sort_by(|a, b| b.1.cmp(&a.1));
-        for (ext, count) in sorted.iter().take(10) {
-            println!("   .{:<11} -> {} files", ext, count);
+        let detected = restore::find_archives(source)
+            .ok()
+            .and_then(|zips| restore::detect_user_remap(&BackupSet::from_archives(source, zips).with_mmap(self.mmap).with_cache(analysis_cache(self.no_cache)), dest));
+        if detected.is_none() {
+            eprintln!(
+                "{}WARNING: --remap-user auto needs exactly one user profile in the backup and one other in {}; not renaming",
+                warning_priority(),
+                dest.join("Users").display()
+            );
         }
+        self.remap_user.retain(|remap| *remap != UserRemap::Auto);
+        self.remap_user.extend(detected.map(|(old, new)| UserRemap::Rename(old, new)));
     }
-    println!("{}\n", "=".repeat(60));
-    Ok(zips)
-}
 
-fn extract(source_dir: &Path, dest_dir: &Path) -> io::Result<()> {
-    let zips = find_zip_files(source_dir)?;
-    if zips.is_empty() {
-        eprintln!("ERROR: No ZIP files found!");
-        return Ok(());
+    /// Drops a `--chown` with a warning where it cannot be applied.
+    fn check_chown(&mut self) {
+        if self.chown.is_none() {
+            return;
+        }
+        #[cfg(unix)]
+        // SAFETY: geteuid only reads the process's credentials.
+        let privileged = unsafe { libc::geteuid() } == 0;
+        #[cfg(not(unix))]
+        let privileged = false;
+        if !privileged {
+            let reason = if cfg!(unix) { "needs root" } else { "only works on Unix" };
+            eprintln!("{}WARNING: --chown {}; restored files stay owned by the user restoring", warning_priority(), reason);
+            self.chown = None;
+        }
     }
 
-    fs::create_dir_all(dest_dir)?;
-
-    let total = zips.len();
-    let mut files_extracted: usize = 0;
-    let mut errors: Vec<String> = Vec::new();
+    /// Takes the umask off `--file-mode` and `--dir-mode` for
+    /// `--respect-umask`.
+    fn apply_umask(&mut self) {
+        if !self.respect_umask {
+            return;
+        }
+        #[cfg(unix)]
+        {
+            // SAFETY: umask only swaps the process's mask, restored at
+            // once; no other thread creates files yet.
+            let mask = unsafe {
+                let mask = libc::umask(0);
+                libc::umask(mask);
+                mask
+            } as u32;
+            self.file_mode = self.file_mode.map(|mode| mode & !mask);
+            self.dir_mode = self.dir_mode.map(|mode| mode & !mask);
+        }
+    }
 
-    println!("\nStarting extraction of {} ZIP files...", total);
-    println!("Destination: {}\n", dest_dir.display());
+    /// With `--auto-threads`, sets `--read-threads` and `--write-threads`
+    /// where not given to what measuring the speed of the archives in
+    /// `source` and of `dest` suggests. If it cannot be measured, the
+    /// options stay as they are.
+    fn tune_threads(&mut self, source: &Path, dest: &Path) {
+        if !self.auto_threads || (self.read_threads.is_some() && self.write_threads.is_some()) {
+            return;
+        }
+        let Ok(zips) = self.restorer(source, dest).archives() else { return };
+        println!("{}", t!("tuning-threads"));
+        let Ok(throughput) = Throughput::measure(&zips, dest) else { return };
+        let readers = *self.read_threads.get_or_insert(throughput.read_threads());
+        let writers = *self.write_threads.get_or_insert(throughput.write_threads());
+        println!("{}", t!("tuned-threads", readers = readers, writers = writers, read = format_size(throughput.read as u64), write = format_size(throughput.write as u64)));
+    }
 
-    for (i, zip_path) in zips.iter().enumerate() {
-        let zip_name = zip_path.file_name().unwrap_or_default().to_string_lossy();
-        print!("[{}/{}] {}... ", i + 1, total, zip_name);
-        io::stdout().flush().ok();
+    /// The archives `--start-at-zip` or `--skip-zips` leave out.
+    fn skipped_zips(&self) -> usize {
+        match (self.start_at_zip, self.skip_zips) {
+            (Some(start), _) => usize::try_from(start - 1).unwrap_or(usize::MAX),
+            (None, skip) => skip.unwrap_or(0),
+        }
+    }
 
-        match fs::File::open(zip_path) {
-            Ok(file) => match zip::ZipArchive::new(file) {
-                Ok(mut archive) => {
-                    let mut count = 0usize;
-                    for j in 0..archive.len() {
-                        match archive.by_index(j) {
-                            Ok(mut entry) => {
-                                if entry.is_dir() {
-                                    continue;
-                                }
-                                let raw_name = entry.name().replace('\\', "/");
-                                let clean = strip_drive_letter(&raw_name);
-                                let target = dest_dir.join(clean);
-
-                                if let Some(parent) = target.parent()
-                                    && let Err(e) = fs::create_dir_all(parent)
-                                {
-                                    errors.push(format!("{}: mkdir {}: {}", zip_name, parent.display(), e));
-                                    continue;
-                                }
-
-                                match fs::File::create(&target) {
-                                    Ok(mut outfile) => {
-                                        if let Err(e) = io::copy(&mut entry, &mut outfile) {
-                                            errors.push(format!("{}: write {}: {}", zip_name, clean, e));
-                                        } else {
-                                            count += 1;
-                                        }
-                                    }
-                                    Err(e) => {
-                                        errors.push(format!("{}: create {}: {}", zip_name, clean, e));
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                errors.push(format!("{}: entry {}: {}", zip_name, j, e));
-                            }
-                        }
-                    }
-                    files_extracted += count;
-                    println!("{} files", count);
-                }
-                Err(e) => {
-                    println!("ERROR: {}", e);
-                    errors.push(format!("{}: {}", zip_name, e));
-                }
-            },
-            Err(e) => {
-                println!("ERROR: {}", e);
-                errors.push(format!("{}: {}", zip_name, e));
+    fn builder(&self, source: &Path, dest: &Path) -> RestorerBuilder<PathBuf, PathBuf> {
+        let filter = self.selection.filter().expect("regular expressions checked before");
+        let mut builder = self.mirror.iter().fold(Restorer::builder(), |builder, mirror| builder.mirror(mirror));
+        for (drive, dir) in &self.drive_map {
+            builder = builder.drive_map(*drive, dir);
+        }
+        for remap in &self.remap_user {
+            if let UserRemap::Rename(old, new) = remap {
+                builder = builder.remap_user(old, new);
             }
         }
+        if let Some(threads) = self.write_threads {
+            builder = builder.write_threads(threads);
+        }
+        if let Some(threshold) = self.direct_io {
+            builder = builder.direct_io(threshold);
+        }
+        if let Some(dir) = &self.temp_dir {
+            builder = builder.temp_dir(dir);
+        }
+        if let Some(owner) = self.chown {
+            builder = builder.owner(owner.uid, owner.gid);
+        }
+        if let Some(mode) = self.file_mode {
+            builder = builder.file_mode(mode);
+        }
+        if let Some(mode) = self.dir_mode {
+            builder = builder.dir_mode(mode);
+        }
+        if let Some(zone) = self.source_tz {
+            builder = builder.source_tz(zone);
+        }
+        if let Some(cache) = analysis_cache(self.no_cache) {
+            builder = builder.analysis_cache(cache);
+        }
+        if let Some(policy) = self.quarantine {
+            builder = builder.mac_quarantine(policy);
+        }
+        if let Some(manifest) = &self.manifest {
+            builder = builder.manifest(manifest).manifest_format(self.manifest_format);
+        }
+        if let Some(audit_log) = &self.audit_log {
+            builder = builder.audit_log(audit_log);
+        }
+        if let Some(file) = &self.answers {
+            builder = builder.answers(file.answers.clone());
+        }
+        builder
+            .source(source)
+            .dest(dest)
+            .filter(filter)
+            .all_runs(self.all_runs)
+            .skip_archives(self.skipped_zips())
+            .conflict_policy(self.on_conflict)
+            .conflict_resolver(Arc::new(ConflictPrompt::new(self.answers.as_ref())))
+            .placeholders(self.placeholders)
+            .system_files(self.system_files)
+            .absolute_entries(self.absolute_entries)
+            .order(self.order)
+            .sync(self.sync)
+            .dest_layout(self.dest_layout)
+            .subdir_per_archive(self.subdir_per_zip)
+            .keep_drives(self.keep_drives)
+            .keep_apple_double(self.keep_apple_double)
+            .remap_shortcuts(self.remap_shortcuts)
+            .threads(self.read_threads.unwrap_or(self.threads))
+            .mmap(self.mmap)
+            .prefetch(!self.no_prefetch)
+            .warm_cache(self.warm_cache)
+            .drop_caches(self.drop_caches)
+            .preallocate(self.preallocate)
+            .preserve_times(!self.no_preserve_times)
+            .ntfs_compress(self.ntfs_compress)
+            .make_readonly(self.make_readonly)
+            .selinux_relabel(self.selinux_relabel)
+            .fail_fast(self.strict)
     }
+}
 
-    println!("\n{}", "=".repeat(60));
-    println!(" Extraction completed!");
-    println!(" Files extracted:   {}", files_extracted);
-    println!(" Errors:            {}", errors.len());
-    println!(" Destination:       {}", dest_dir.display());
-    println!("{}", "=".repeat(60));
+/// Prints the totals of `deep`, estimates from `sampled` if it is one.
+fn print_deep(deep: &DeepStats, sampled: Option<&SampledStats>, categories: &Categories) {
+    println!("\n Deep analysis ({} archives)", deep.archives);
+    match sampled {
+        Some(sampled) => {
+            println!(
+                "   Sampled:         {} of {} archives, {} of {} read; figures below are estimates",
+                sampled.sample.archives,
+                sampled.archives,
+                format_size(sampled.covered_size),
+                format_size(sampled.total_size)
+            );
+            let margin = |estimate: Estimate, format: &dyn Fn(u64) -> String| match estimate.margin {
+                Some(margin) => format!("~{} (± {}, 95% confidence)", format(estimate.value), format(margin)),
+                None => format!("~{} (confidence unknown: sample more than one archive)", format(estimate.value)),
+            };
+            println!("   Files:           {}", margin(sampled.files(), &|n| n.to_string()));
+            println!("   Folders:         ~{}", deep.dirs);
+            println!("   Uncompressed:    {}", margin(sampled.bytes(), &format_size));
+        }
+        None => {
+            println!("   Files:           {}", deep.files);
+            println!("   Folders:         {}", deep.dirs);
+            println!("   Uncompressed:    {}", format_size(deep.uncompressed_bytes));
+        }
+    }
+    println!("   Compressed:      {}", format_size(deep.compressed_bytes));
+    if deep.placeholders > 0 {
+        println!("   Cloud-only:      {} placeholders, their data is NOT in the backup", deep.placeholders);
+    }
+    if deep.system_files > 0 {
+        println!("   System files:    {} (registry hives, page files, Windows/System32)", deep.system_files);
+    }
+    if deep.apple_double > 0 {
+        println!("   Mac metadata:    {} AppleDouble files (._ files, __MACOSX)", deep.apple_double);
+    }
+    if deep.efs > 0 {
+        println!("   EFS-encrypted:   {} files, unreadable without the old account's EFS key", deep.efs);
+    }
+    let duplicates = deep.duplicates();
+    if duplicates.files > 0 {
+        println!(
+            "   Duplicates:      ~{} files ({}) repeat the content of others; hard links or reflinks would save that",
+            duplicates.files,
+            format_size(duplicates.bytes)
+        );
+    }
+    if !deep.mail.is_empty() {
+        let bytes = deep.mail.iter().map(|m| m.size).sum();
+        println!("   Outlook mail:    {} data files ({}), restore them with extract-mail", deep.mail.len(), format_size(bytes));
+        for mail in deep.mail.iter().take(10) {
+            let owner = mail.owner.as_deref().unwrap_or("-");
+            println!("     {:>10}  {:<16} {}", format_size(mail.size), owner, mail.path);
+        }
+        if deep.mail.len() > 10 {
+            println!("     ... and {} more", deep.mail.len() - 10);
+        }
+    }
+    if !deep.errors.is_empty() {
+        println!("   Unreadable ZIPs: {}", deep.errors.len());
+        for err in deep.errors.iter().take(5) {
+            println!("     {}", err);
+        }
+    }
+    println!("\n By category:");
+    for (category, stats) in categories.tally(&deep.extensions) {
+        println!("   {:<12} -> {} files ({})", category, stats.files, format_size(stats.bytes));
+    }
+    print_timeline(deep);
+    println!("\n Top extensions:");
+    for (ext, stats) in deep.top_extensions().iter().take(10) {
+        let ext = if ext.is_empty() { "(none)".to_string() } else { format!(".{}", ext) };
+        println!("   {:<12} -> {} files ({})", ext, stats.files, format_size(stats.bytes));
+    }
+}
+
+/// Prints when the files were modified: by year, or by month for a backup
+/// of two years or less, and the longer runs of months without any.
+fn print_timeline(deep: &DeepStats) {
+    const WIDTH: u64 = 30;
+    const GAP_MONTHS: u32 = 3;
+    let years = deep.years();
+    let rows: Vec<(String, ExtensionStats)> = if years.len() <= 2 {
+        deep.months.iter().map(|(&(year, month), stats)| (format!("{}-{:02}", year, month), *stats)).collect()
+    } else {
+        years.iter().map(|(year, stats)| (year.to_string(), *stats)).collect()
+    };
+    let Some(most) = rows.iter().map(|(_, stats)| stats.files).max() else { return };
+    println!("\n Modified:");
+    for (label, stats) in &rows {
+        let bar = "#".repeat((stats.files * WIDTH).div_ceil(most) as usize);
+        println!("   {:<8} {:<30} {} files ({})", label, bar, stats.files, format_size(stats.bytes));
+    }
+    if deep.undated > 0 {
+        println!("   {:<8} {} files", "undated", deep.undated);
+    }
+    let months = |(year, month): (u16, u8)| u32::from(year) * 12 + u32::from(month);
+    let gaps: Vec<_> = deep.gaps().into_iter().filter(|&(first, last)| months(last) - months(first) + 1 >= GAP_MONTHS).collect();
+    for (first, last) in gaps.iter().take(10) {
+        println!("   No files modified from {}-{:02} to {}-{:02}", first.0, first.1, last.0, last.1);
+    }
+    if gaps.len() > 10 {
+        println!("   ... and {} more gaps of {} months or longer", gaps.len() - 10, GAP_MONTHS);
+    }
+}
+
+/// Prints the folders and files of `tree`, the listing of folder `path`,
+/// folders first.
+fn print_tree(tree: &SizeTree, path: &str) {
+    fn print_folder(folder: &FolderSize, level: usize) {
+        let indent = "  ".repeat(level);
+        let mut folders: Vec<_> = folder.folders.iter().collect();
+        folders.sort_by(|a, b| restore::cmp_natural(a.0, b.0));
+        for (name, child) in folders {
+            println!("{}{}/  ({} files, {})", indent, name, child.files, format_size(child.bytes));
+            print_folder(child, level + 1);
+        }
+        let mut names: Vec<_> = folder.names.iter().collect();
+        names.sort_by(|a, b| restore::cmp_natural(a.0, b.0));
+        for (name, size) in names {
+            println!("{}{}  ({})", indent, name, format_size(*size));
+        }
+    }
+
+    let root = &tree.root;
+    if root.files == 0 {
+        println!("No files in {}", if path.is_empty() { "the backup" } else { path });
+    } else {
+        println!("{}/  ({} files, {})", if path.is_empty() { "." } else { path }, root.files, format_size(root.bytes));
+        print_folder(root, 1);
+    }
+    for err in &tree.errors {
+        eprintln!("{}WARNING: {}", warning_priority(), err);
+    }
+}
+
+/// A file found by `restore query`, as printed in JSON.
+#[derive(Serialize)]
+struct QueryRow<'a> {
+    path: &'a str,
+    size: u64,
+    modified: Option<String>,
+    archive: &'a Path,
+}
 
-    if !errors.is_empty() {
-        println!("\nError details:");
-        for err in errors.iter().take(20) {
-            println!("  {}", err);
+/// Prints the files found by `restore query` or `restore search`.
+fn print_query(files: &[EntryInfo], errors: &[ArchiveError], format: QueryFormat) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    let modified = |entry: &EntryInfo| entry.modified.map(|t| BackupTime::from(t).to_string());
+    match format {
+        QueryFormat::Table => {
+            for entry in files {
+                let archive = entry.archive.file_name().unwrap_or_default().to_string_lossy();
+                let time = modified(entry).unwrap_or_else(|| "-".to_string());
+                writeln!(out, "{:>12}  {:<19}  {}  ({})", format_size(entry.size), time, entry.path(), archive)?;
+            }
+            let bytes = files.iter().map(|entry| entry.size).sum();
+            writeln!(out, "\n {} files, {}", files.len(), format_size(bytes))?;
+        }
+        QueryFormat::Json => {
+            for entry in files {
+                let row = QueryRow { path: entry.path(), size: entry.size, modified: modified(entry), archive: &entry.archive };
+                serde_json::to_writer(&mut out, &row)?;
+                writeln!(out)?;
+            }
         }
-        if errors.len() > 20 {
-            println!("  ... and {} more errors", errors.len() - 20);
+        QueryFormat::Csv => {
+            let field = |text: &str| {
+                if text.contains([',', '"', '\n', '\r']) { format!("\"{}\"", text.replace('"', "\"\"")) } else { text.to_string() }
+            };
+            writeln!(out, "path,size,modified,archive")?;
+            for entry in files {
+                let time = modified(entry).unwrap_or_default();
+                writeln!(out, "{},{},{},{}", field(entry.path()), entry.size, field(&time), field(&entry.archive.to_string_lossy()))?;
+            }
         }
     }
+    for err in errors {
+        eprintln!("{}WARNING: {}", warning_priority(), err);
+    }
     Ok(())
 }
 
+fn print_grep_match(file: &GrepMatch) {
+    let archive = file.archive.file_name().unwrap_or_default().to_string_lossy();
+    println!("{}  ({})", file.path, archive);
+    for (number, line) in &file.lines {
+        println!("{:>8}: {}", number, line);
+    }
+}
 
-fn main() {
-    let args = Args::parse();
+fn print_grepped(grepped: &Grepped, max_size: u64) {
+    println!("\n {} of {} files searched match", grepped.matching, grepped.searched);
+    if grepped.skipped > 0 {
+        println!(" {} files not searched: larger than {}, binary or encrypted", grepped.skipped, format_size(max_size));
+    }
+    if grepped.unreadable > 0 {
+        println!(" {} files could not be read", grepped.unreadable);
+    }
+    if !grepped.errors.is_empty() {
+        println!("\n Unreadable archives: {}", grepped.errors.len());
+        for err in grepped.errors.iter().take(5) {
+            println!("   {}", err);
+        }
+    }
+}
 
-    let source_path = args.source.as_path();
-    if !source_path.is_dir() {
-        eprintln!("ERROR: Directory not found: {}", source_path.display());
-        std::process::exit(1);
+fn export_index(args: &ExportArgs) -> io::Result<Status> {
+    if args.format == ExportFormat::Parquet && args.output.is_none() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Parquet needs a file to write: pass --output"));
     }
+    let analysis = restore::analyze(&args.report.source)?;
+    let set = BackupSet::from_archives(&analysis.source, analysis.zips).with_mmap(args.report.mmap).with_cache(analysis_cache(args.report.no_cache));
+    let exported = match &args.output {
+        Some(path) => {
+            let file = fs::File::create(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            // A partial export is no use to anyone.
+            restore::export(&set, args.format, file).inspect_err(|_| {
+                let _ = fs::remove_file(path);
+            })?
+        }
+        // Output piped into `head` ends early.
+        None => match restore::export(&set, args.format, io::stdout()) {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(Status::Success),
+            exported => exported?,
+        },
+    };
+    print_exported(&exported, args.output.as_deref());
+    Ok(if exported.errors.is_empty() { Status::Success } else { Status::Errors })
+}
 
-    match analyze(source_path) {
-        Ok(zips) => {
-            if args.analyze_only || zips.is_empty() {
-                return;
-            }
+/// Goes to the standard error, so as not to mix with a CSV export on the
+/// standard output.
+fn print_exported(exported: &Exported, output: Option<&Path>) {
+    let to = output.map_or_else(|| "the standard output".to_string(), |path| path.display().to_string());
+    eprintln!("Exported {} entries to {}.", exported.entries, to);
+    if !exported.errors.is_empty() {
+        eprintln!("\n Unreadable archives: {}", exported.errors.len());
+        for err in exported.errors.iter().take(5) {
+            eprintln!("   {}", err);
         }
-        Err(e) => {
-            eprintln!("Error during analysis: {}", e);
-            std::process::exit(1);
+    }
+}
+
+fn print_indexed(indexed: &Indexed, dir: &Path) {
+    println!("\n{}", "=".repeat(60));
+    println!(" Index: {}", dir.display());
+    println!("{}", "=".repeat(60));
+    println!("   Read:       {} archives, {} entries", indexed.read, indexed.entries);
+    if indexed.unchanged > 0 {
+        println!("   Unchanged:  {} archives, indexed already", indexed.unchanged);
+    }
+    if indexed.uncached > 0 {
+        println!("   Not cached: {} (File History folders, or the index cannot be written)", indexed.uncached);
+    }
+    if !indexed.errors.is_empty() {
+        println!("\n Unreadable archives: {}", indexed.errors.len());
+        for err in indexed.errors.iter().take(5) {
+            println!("   {}", err);
         }
     }
+    println!("{}", "=".repeat(60));
+}
 
-    let dest = args.dest.expect("Destination path is required");
+fn print_estimate(throughput: &Throughput, archives: usize, archive_bytes: u64, totals: &Totals) {
+    let per_second = |bytes: f64| format!("{}/s", format_size(bytes as u64));
+    let duration = throughput.duration(archive_bytes, totals);
+    let done = Local::now() + duration;
+    println!("\n{}", "=".repeat(60));
+    println!(" Estimate");
+    println!("{}", "=".repeat(60));
+    println!("   Restores:    {} files, {}, from {} archives of {}", totals.files, format_size(totals.bytes), archives, format_size(archive_bytes));
+    println!("   Reading:     {} at {}", format_duration(throughput.read_time(archive_bytes)), per_second(throughput.read));
+    println!(
+        "   Writing:     {} at {}, {:.2} ms per file",
+        format_duration(throughput.write_time(totals)),
+        per_second(throughput.write),
+        throughput.per_file.as_secs_f64() * 1000.0
+    );
+    println!("   Duration:    about {}, done around {}", format_duration(duration), done.format("%Y-%m-%d %H:%M"));
+    println!("   Threads:     {} reading, {} writing", throughput.read_threads(), throughput.write_threads());
+    println!("\n Reading and writing overlap, so the slower of the two sets the pace.");
+    println!(" Other programs using the disks, and caches filling up on long runs,");
+    println!(" make it take longer.");
+    println!("{}", "=".repeat(60));
+}
 
-    println!("\n  Source: {}", source_path.display());
-    println!("  Dest:   {}", dest.display());
-    print!("\nProceed? (y/n): ");
-    io::stdout().flush().ok();
-    let mut confirm = String::new();
-    io::stdin().read_line(&mut confirm).unwrap();
+/// Prints the archives a restore reads, `zips`, with the numbers its
+/// progress and `extract --zip` give them.
+fn print_archive_numbers(source: &Path, zips: &[PathBuf]) {
+    println!("\n {}", t!("analysis-archives"));
+    for (i, zip) in zips.iter().enumerate() {
+        println!("   [{}/{}] {}", i + 1, zips.len(), zip.strip_prefix(source).unwrap_or(zip).display());
+    }
+}
 
-    if confirm.trim().to_lowercase().starts_with('j') || confirm.trim().to_lowercase().starts_with('y') {
-        if let Err(e) = extract(source_path, dest.as_path()) {
-            eprintln!("Error during extraction: {}", e);
-            std::process::exit(1);
+/// Prints every conflict, rename and case collision `restore simulate`
+/// found, with paths relative to `dest` where they are under it.
+fn print_simulation(simulation: &Simulation, dest: &Path) {
+    let show = |path: &Path| path.strip_prefix(dest).unwrap_or(path).display().to_string();
+    let action = |action: &PlannedAction| match action {
+        PlannedAction::Overwrite => "overwrite".to_string(),
+        PlannedAction::Skip => "skip".to_string(),
+        PlannedAction::Rename(renamed) => format!("rename to {}", show(renamed)),
+        PlannedAction::Ask => "ask".to_string(),
+    };
+    let existing: Vec<_> = simulation.existing().collect();
+    let within: Vec<_> = simulation.within_backup().collect();
+    if !existing.is_empty() {
+        println!("\n Files already at the destination: {}", existing.len());
+        for conflict in &existing {
+            println!("   {}: {}", show(&conflict.target), action(&conflict.action));
+        }
+    }
+    if !within.is_empty() {
+        println!("\n Files restored more than once: {}", within.len());
+        for conflict in &within {
+            let archive = conflict.archive.file_name().unwrap_or_default().to_string_lossy();
+            println!("   {} (from {}): {}", show(&conflict.target), archive, action(&conflict.action));
         }
-    } else {
-        println!("Cancelled.");
     }
-}
\ No newline at end of file
+    if !simulation.case_collisions.is_empty() {
+        println!("\n Paths differing only in case: {}", simulation.case_collisions.len());
+        for collision in &simulation.case_collisions {
+            println!("   {} and {}", show(&collision.first), show(&collision.second));
+        }
+    }
+    if !simulation.rejected.is_empty() {
+        println!("\n Names not restored (out of the destination, absolute or empty): {}", simulation.rejected.len());
+        for name in &simulation.rejected {
+            println!("   {}", name);
+        }
+    }
+    if !simulation.errors.is_empty() {
+        println!("\n Unreadable archives: {}", simulation.errors.len());
+        for err in &simulation.errors {
+            println!("   {}", err);
+        }
+    }
+    let count = |action: fn(&PlannedAction) -> bool| simulation.conflicts.iter().filter(|conflict| action(&conflict.action)).count();
+    println!("\n{}", "=".repeat(60));
+    println!(" Simulation");
+    println!("{}", "=".repeat(60));
+    println!("   Restores:        {} files, {}", simulation.files, format_size(simulation.bytes));
+    if simulation.linked > 0 {
+        println!("   Linked:          {}", simulation.linked);
+    }
+    println!("   Overwrites:      {}", count(|action| *action == PlannedAction::Overwrite));
+    println!("   Renames:         {}", count(|action| matches!(action, PlannedAction::Rename(_))));
+    println!("   Skips:           {}", count(|action| *action == PlannedAction::Skip));
+    println!("   Questions:       {}", count(|action| *action == PlannedAction::Ask));
+    println!("   Case collisions: {}", simulation.case_collisions.len());
+    println!("   Filtered out:    {}", simulation.filtered);
+    if simulation.placeholders > 0 {
+        println!("   Cloud-only:      {}", simulation.placeholders);
+    }
+    if simulation.system_files > 0 {
+        println!("   System files:    {}", simulation.system_files);
+    }
+    if simulation.apple_double > 0 {
+        println!("   AppleDouble:     {}", simulation.apple_double);
+    }
+    if simulation.normalized > 0 {
+        println!("   Names cleaned:   {}", simulation.normalized);
+    }
+    if simulation.absolute > 0 {
+        println!("   Absolute names:  {}", simulation.absolute);
+    }
+    println!("\n Nothing was written to the destination.");
+    println!("{}", "=".repeat(60));
+}
+
+/// Open-file limits below this may run out with many threads.
+const MIN_OPEN_FILES: u64 = 256;
+
+/// Prints the checks of `restore doctor`, each with what to do about it.
+/// Returns whether none failed.
+fn print_diagnosis(diagnosis: &Diagnosis) -> bool {
+    let mut healthy = true;
+    let ok = |text: String| println!(" [ OK ] {}", text);
+    let warn = |text: String| println!(" [WARN] {}", text);
+    let mut fail = |text: String| {
+        println!(" [FAIL] {}", text);
+        healthy = false;
+    };
+    println!("\n{}", "=".repeat(60));
+    println!(" Doctor");
+    println!("{}", "=".repeat(60));
+    let source = diagnosis.source.as_ref().and_then(|source| source.as_ref().ok());
+    match &diagnosis.source {
+        None => {}
+        Some(Err(e)) => fail(format!("Source: {}", e)),
+        Some(Ok(source)) if source.archives == 0 => fail("Source: no archives found".to_string()),
+        Some(Ok(source)) => {
+            ok(format!("Source: {} archives, {} files, {} uncompressed", source.archives, source.files, format_size(source.bytes)));
+            if !source.unreadable.is_empty() {
+                fail(format!("{} archives cannot be read; copy them from the backup drive again:", source.unreadable.len()));
+                for err in source.unreadable.iter().take(5) {
+                    println!("          {}", err);
+                }
+            }
+            for (feature, count) in &source.needs_feature {
+                warn(format!("{} archives are left out: build with --features {} to restore them", count, feature));
+            }
+            if !source.unsupported_methods.is_empty() {
+                let methods: Vec<String> = source.unsupported_methods.iter().map(|(method, files)| format!("method {}: {} files", method, files)).collect();
+                warn(format!("Compression methods this build cannot decode, those files will fail ({})", methods.join(", ")));
+            }
+            if source.long_names > 0 {
+                warn(format!("{} files have a folder or file name longer than 255 bytes, which most file systems refuse", source.long_names));
+            }
+        }
+    }
+    if let Some(dest) = &diagnosis.dest {
+        let dir = dest.dir.display();
+        match &dest.write_error {
+            Some(e) => fail(format!("Destination {}: cannot write: {}", dir, e)),
+            None if dest.exists => ok(format!("Destination {}: writable", dir)),
+            None => ok(format!("Destination {}: can be created", dir)),
+        }
+        match (dest.available, source) {
+            (None, _) => warn("Free space: unknown".to_string()),
+            (Some(free), Some(source)) if !source.fits(dest) => {
+                fail(format!("Free space: {} free, {} needed; make room, or leave files out with --include or --exclude", format_size(free), format_size(source.bytes)))
+            }
+            (Some(free), Some(source)) => ok(format!("Free space: {} free, {} needed", format_size(free), format_size(source.bytes))),
+            (Some(free), None) => ok(format!("Free space: {} free", format_size(free))),
+        }
+        match (dest.case_sensitive, source) {
+            (Some(false), Some(source)) if source.case_collisions > 0 => warn(format!(
+                "Case-insensitive file system: {} files differ from another only in case and would overwrite it; restore with --on-conflict rename to keep both",
+                source.case_collisions
+            )),
+            (Some(false), _) => ok("Case-insensitive file system, no paths of the backup collide".to_string()),
+            (Some(true), _) => ok("Case-sensitive file system".to_string()),
+            (None, _) => {}
+        }
+        if let (Some(limit), Some(source)) = (dest.path_limit, source) {
+            warn(format!(
+                "Long paths: the destination takes paths of {} characters, the backup needs {}; enable long paths on Windows (LongPathsEnabled) or restore into a shorter folder",
+                limit,
+                dest.dir.as_os_str().len() + 1 + source.longest_path
+            ));
+        }
+    }
+    let features = if diagnosis.features.is_empty() { "none".to_string() } else { diagnosis.features.join(", ") };
+    ok(format!("Features: {}", features));
+    if let Some(open_files) = diagnosis.open_files {
+        if open_files < MIN_OPEN_FILES {
+            warn(format!("Open files: limited to {}; raise it with `ulimit -n 4096` before restoring with many threads", open_files));
+        } else {
+            ok(format!("Open files: limited to {}", open_files));
+        }
+    }
+    if let Some(limit) = diagnosis.file_size_limit {
+        match source {
+            Some(source) if source.largest > limit => fail(format!(
+                "File size: limited to {}, the largest file has {}; raise it with `ulimit -f unlimited`",
+                format_size(limit),
+                format_size(source.largest)
+            )),
+            Some(_) => ok(format!("File size: limited to {}, enough for the largest file", format_size(limit))),
+            None => warn(format!("File size: limited to {}; larger files fail", format_size(limit))),
+        }
+    }
+    println!("{}", "=".repeat(60));
+    healthy
+}
+
+fn print_bundled(bundled: &Bundled, path: &Path) {
+    println!("\n{}", "=".repeat(60));
+    println!(" Diagnostic bundle: {}", path.display());
+    println!("{}", "=".repeat(60));
+    println!("   Files:      {}", bundled.files.join(", "));
+    if bundled.archives > 0 {
+        println!("   Archives:   {} with {} entries, file names replaced by hashes", bundled.archives, bundled.entries);
+    }
+    if bundled.unreadable > 0 {
+        println!("   Unreadable: {} archives, with the error of each", bundled.unreadable);
+    }
+    println!("\n The log and the summary name restored files; look them over before");
+    println!(" attaching the bundle to a public bug report.");
+    println!("{}", "=".repeat(60));
+}
+
+fn print_hotspots(hotspots: &Hotspots, top: usize) {
+    println!("\n{}", "=".repeat(60));
+    println!(" Folders holding the most");
+    println!("{}", "=".repeat(60));
+    let folder = |folder: &str| if folder.is_empty() { "(top level)".to_string() } else { format!("{}/", folder) };
+    println!("\n By size:");
+    for (path, stats) in hotspots.by_bytes(top) {
+        println!("   {:>12} {:>9} files  {}", format_size(stats.bytes), stats.files, folder(path));
+    }
+    println!("\n By number of files:");
+    for (path, stats) in hotspots.by_files(top) {
+        println!("   {:>9} files {:>12}  {}", stats.files, format_size(stats.bytes), folder(path));
+    }
+    println!("\n Counted are the files directly in each folder. Leave one out with --exclude \"<folder>/**\".");
+    if !hotspots.errors.is_empty() {
+        println!("\n Unreadable archives: {}", hotspots.errors.len());
+        for err in hotspots.errors.iter().take(5) {
+            println!("   {}", err);
+        }
+    }
+    println!("{}", "=".repeat(60));
+}
+
+fn print_size_tree(tree: &SizeTree, depth: usize) {
+    /// Folders shown per folder; the rest are summed up in one line.
+    const SHOWN: usize = 10;
+
+    fn print_folder(folder: &FolderSize, path: &str, level: usize, total: u64) {
+        let largest = folder.largest();
+        for (name, child) in largest.iter().take(SHOWN) {
+            let path = if path.is_empty() { name.to_string() } else { format!("{}/{}", path, name) };
+            println!("{:>12} {:>4.0}%  {}{}/", format_size(child.bytes), percent(child.bytes, total), "  ".repeat(level), path);
+            print_folder(child, &path, level + 1, total);
+        }
+        if largest.len() > SHOWN {
+            let rest = &largest[SHOWN..];
+            let bytes = rest.iter().map(|(_, child)| child.bytes).sum();
+            println!("{:>12} {:>4.0}%  {}... {} more folders", format_size(bytes), percent(bytes, total), "  ".repeat(level), rest.len());
+        }
+    }
+    fn percent(bytes: u64, total: u64) -> f64 {
+        if total == 0 { 0.0 } else { bytes as f64 * 100.0 / total as f64 }
+    }
+
+    println!("\n{}", "=".repeat(60));
+    println!(" Size by folder, {} deep", depth);
+    println!("{}", "=".repeat(60));
+    let root = &tree.root;
+    println!("{:>12} {:>4.0}%  (all {} files)", format_size(root.bytes), 100.0, root.files);
+    print_folder(root, "", 0, root.bytes);
+    if !tree.errors.is_empty() {
+        println!("\n Unreadable archives: {}", tree.errors.len());
+        for err in tree.errors.iter().take(5) {
+            println!("   {}", err);
+        }
+    }
+    println!("{}", "=".repeat(60));
+}
+
+fn print_recoverables(recoverables: &Recoverables) {
+    println!("\n{}", "=".repeat(60));
+    println!(" Recoverable files");
+    println!("{}", "=".repeat(60));
+    for kind in RecoverableKind::ALL {
+        // One line per folder: saved games and key stores are many small
+        // files that are restored together.
+        let mut folders: Vec<(&str, Option<&str>, usize, u64)> = Vec::new();
+        for found in recoverables.of_kind(kind) {
+            let folder = found.path.rsplit_once('/').map_or("", |(dir, _)| dir);
+            match folders.iter_mut().find(|f| f.0 == folder) {
+                Some(f) => {
+                    f.2 += 1;
+                    f.3 += found.size;
+                }
+                None => folders.push((folder, found.owner.as_deref(), 1, found.size)),
+            }
+        }
+        if folders.is_empty() {
+            continue;
+        }
+        folders.sort_by(|a, b| restore::cmp_natural(a.0, b.0));
+        let files: usize = folders.iter().map(|f| f.2).sum();
+        let bytes: u64 = folders.iter().map(|f| f.3).sum();
+        println!("\n {}: {} files ({})", kind.label(), files, format_size(bytes));
+        for (folder, owner, files, bytes) in folders.iter().take(15) {
+            let folder = if folder.is_empty() { "(root)" } else { folder };
+            println!("   {:>10}  {:<16} {} ({} files)", format_size(*bytes), owner.unwrap_or("-"), folder, files);
+        }
+        if folders.len() > 15 {
+            println!("   ... and {} more folders", folders.len() - 15);
+        }
+    }
+    if recoverables.found.is_empty() {
+        println!("\n No saved games, keys, certificates, wallets or license files found.");
+    } else {
+        println!("\n Restore a folder first with --include \"<folder>/**\".");
+    }
+    if !recoverables.errors.is_empty() {
+        println!("\n Unreadable archives: {}", recoverables.errors.len());
+        for err in recoverables.errors.iter().take(5) {
+            println!("   {}", err);
+        }
+    }
+}
+
+fn print_incomplete(incomplete: &IncompleteEntries) {
+    println!("\n{}", "=".repeat(60));
+    println!(" Incomplete files ({} checked)", incomplete.checked);
+    println!("{}", "=".repeat(60));
+    let archive_name = |entry: &IncompleteEntry| entry.archive.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let empty: Vec<&IncompleteEntry> = incomplete.empty().collect();
+    if !empty.is_empty() {
+        println!("\n Zero-byte files: {}", empty.len());
+        for entry in empty.iter().take(50) {
+            println!("   {}  [{}]", entry.path, archive_name(entry));
+        }
+        if empty.len() > 50 {
+            println!("   ... and {} more", empty.len() - 50);
+        }
+    }
+    let mismatches: Vec<&IncompleteEntry> = incomplete.size_mismatches().collect();
+    if !mismatches.is_empty() {
+        println!("\n Size does not match the header: {}", mismatches.len());
+        for entry in mismatches.iter().take(50) {
+            let Incompleteness::SizeMismatch { expected, actual, error } = &entry.kind else { continue };
+            let error = error.as_ref().map(|e| format!(", {}", e)).unwrap_or_default();
+            println!("   {}: {} of {} bytes{}  [{}]", entry.path, actual, expected, error, archive_name(entry));
+        }
+        if mismatches.len() > 50 {
+            println!("   ... and {} more", mismatches.len() - 50);
+        }
+    }
+    if incomplete.entries.is_empty() {
+        println!("\n No zero-byte or truncated files found.");
+    } else {
+        println!("\n The backup tool most likely could not read these files, for instance because");
+        println!(" they were in use; their data is not, or not completely, in the backup.");
+    }
+    if incomplete.unchecked > 0 {
+        println!("\n Not checked: {} encrypted or unsupported entries", incomplete.unchecked);
+    }
+    if !incomplete.errors.is_empty() {
+        println!("\n Unreadable archives: {}", incomplete.errors.len());
+        for err in incomplete.errors.iter().take(5) {
+            println!("   {}", err);
+        }
+    }
+}
+
+fn print_manifest_check(check: &ManifestCheck, signed: Option<&str>) {
+    println!("\n{}", "=".repeat(60));
+    println!(" Manifest check");
+    println!("{}", "=".repeat(60));
+    if let Some(comment) = signed {
+        println!(" Signature:   good, {}", comment.replace('\t', ", "));
+    }
+    println!(" Unchanged:   {}", check.verified);
+    println!(" Changed:     {}", check.mismatched.len());
+    println!(" Missing:     {}", check.missing.len());
+    if !check.unreadable.is_empty() {
+        println!(" Unreadable:  {}", check.unreadable.len());
+    }
+    if !check.malformed.is_empty() {
+        println!(" Bad lines:   {}", check.malformed.len());
+    }
+    if !check.outside.is_empty() {
+        println!(" Outside:     {}", check.outside.len());
+    }
+    let sections: [(&str, Vec<String>); 5] = [
+        ("Changed since the restore", check.mismatched.clone()),
+        ("Missing", check.missing.clone()),
+        ("Unreadable", check.unreadable.iter().map(|(path, e)| format!("{}: {}", path, e)).collect()),
+        ("Lines of the manifest not understood", check.malformed.iter().map(|line| format!("line {}", line)).collect()),
+        ("Not read, absolute or out of the destination", check.outside.clone()),
+    ];
+    for (title, lines) in &sections {
+        if lines.is_empty() {
+            continue;
+        }
+        println!("\n {}:", title);
+        for line in lines.iter().take(50) {
+            println!("   {}", line);
+        }
+        if lines.len() > 50 {
+            println!("   ... and {} more", lines.len() - 50);
+        }
+    }
+    if check.is_intact() {
+        println!("\n All files match the manifest.");
+    }
+}
+
+fn print_manifest_diff(diff: &ManifestDiff, a: &Path, b: &Path) {
+    println!("\n{}", "=".repeat(60));
+    println!(" Manifest comparison");
+    println!("{}", "=".repeat(60));
+    println!(" A:           {}", a.display());
+    println!(" B:           {}", b.display());
+    println!(" Identical:   {}", diff.identical);
+    println!(" Changed:     {}", diff.changed.len());
+    println!(" Only in A:   {}", diff.removed.len());
+    println!(" Only in B:   {}", diff.added.len());
+    let (a_malformed, b_malformed) = &diff.malformed;
+    if !a_malformed.is_empty() || !b_malformed.is_empty() {
+        println!(" Bad lines:   {} in A, {} in B", a_malformed.len(), b_malformed.len());
+    }
+    let sections: [(&str, Vec<String>); 5] = [
+        ("Different contents", diff.changed.clone()),
+        ("Only in A", diff.removed.clone()),
+        ("Only in B", diff.added.clone()),
+        ("Lines of A not understood", a_malformed.iter().map(|line| format!("line {}", line)).collect()),
+        ("Lines of B not understood", b_malformed.iter().map(|line| format!("line {}", line)).collect()),
+    ];
+    for (title, lines) in &sections {
+        if lines.is_empty() {
+            continue;
+        }
+        println!("\n {}:", title);
+        for line in lines.iter().take(50) {
+            println!("   {}", line);
+        }
+        if lines.len() > 50 {
+            println!("   ... and {} more", lines.len() - 50);
+        }
+    }
+    if diff.is_identical() {
+        println!("\n Both manifests list the same files with the same contents.");
+    }
+}
+
+fn print_analysis(analysis: &Analysis, deep: Option<&DeepStats>, sampled: Option<&SampledStats>, categories: &Categories) {
+    println!("\n{}", "=".repeat(60));
+    println!(" {}", t!("analysis-title"));
+    println!("{}", "=".repeat(60));
+    println!(" {}", t!("analysis-source", path = analysis.source.display().to_string()));
+    let kinds: Vec<Option<ArchiveKind>> = analysis.zips.iter().map(|z| ArchiveKind::of(z)).collect();
+    let count = |kind| kinds.iter().filter(|&&k| k == Some(kind)).count();
+    println!(" {}", t!("analysis-zips", count = count(ArchiveKind::Zip)));
+    for kind in [ArchiveKind::Tar, ArchiveKind::FileHistory, ArchiveKind::DiskImage, ArchiveKind::Bkf, ArchiveKind::SevenZip, ArchiveKind::Rar, ArchiveKind::Iso] {
+        let n = count(kind);
+        if n > 0 {
+            println!(" {}", t!("analysis-other", count = n, kind = kind.label()));
+        }
+    }
+    println!(" {}", t!("analysis-size", size = format!("{:.2}", analysis.total_size as f64 / (1024.0 * 1024.0 * 1024.0))));
+
+    // Show sample from first ZIP
+    if let Some(sample) = &analysis.sample {
+        let archive = sample.archive.file_name().unwrap_or_default().to_string_lossy();
+        println!("\n {}", t!("analysis-sample", archive = archive.as_ref()));
+        for (ext, count) in sample.extensions.iter().take(10) {
+            println!("   .{:<11} -> {}", ext, t!("analysis-sample-files", count = *count));
+        }
+    }
+    if let Some(catalog) = &analysis.catalog {
+        print_catalog(catalog);
+    }
+    print_runs(analysis);
+    if let Some(deep) = deep {
+        print_deep(deep, sampled, categories);
+    }
+    println!("{}\n", "=".repeat(60));
+}
+
+fn print_catalog(catalog: &Catalog) {
+    println!("\n Catalog:           {}", catalog.path.display());
+    if let Some(machine) = &catalog.machine {
+        println!(" Machine:           {}", machine);
+    }
+    if let Some(media_id) = &catalog.media_id {
+        println!(" Media ID:          {}", media_id);
+    }
+}
+
+/// Per-run breakdown of a Windows 7/8 backup folder.
+fn print_runs(analysis: &Analysis) {
+    if analysis.runs.is_empty() {
+        return;
+    }
+    let machines: Vec<_> = analysis.runs.iter().map(|r| &r.machine).collect();
+    let several_machines = machines.iter().any(|m| *m != machines[0]);
+    println!("\n Backup runs:       {}", analysis.runs.len());
+    for summary in &analysis.runs {
+        let kind = if summary.run.is_full() { "full" } else { "incremental" };
+        let machine = match (&summary.machine, several_machines) {
+            (Some(machine), true) => format!("  {}", machine),
+            _ => String::new(),
+        };
+        println!(
+            "   {}  {:<12} {:>5} ZIP files {:>10}{}",
+            summary.run.time,
+            kind,
+            summary.archives,
+            format_size(summary.size),
+            machine
+        );
+    }
+    let listed: usize = analysis.runs.iter().map(|r| r.archives).sum();
+    if listed < analysis.zips.len() {
+        println!("   Not in a backup run:     {:>5} ZIP files", analysis.zips.len() - listed);
+    }
+}
+
+/// Asks on the terminal what to do with each conflict of `--on-conflict
+/// ask`. An answer in capitals goes for every later file of the same
+/// extension. With `--answers`, each answer is added to the file.
+struct ConflictPrompt {
+    /// Answers for all files, by lowercase extension.
+    all: Mutex<HashMap<String, Resolution>>,
+    record: Option<(PathBuf, Mutex<Answers>)>,
+}
+
+impl ConflictPrompt {
+    fn new(answers: Option<&AnswersFile>) -> ConflictPrompt {
+        let record = answers.map(|file| (file.path.clone(), Mutex::new(file.answers.clone())));
+        ConflictPrompt { all: Mutex::new(HashMap::new()), record }
+    }
+
+    /// Adds `resolution` for the files matching `pattern` to the answers
+    /// file, written again at once so an interrupted restore keeps them.
+    fn record(&self, pattern: String, resolution: Resolution) {
+        let Some((path, answers)) = &self.record else { return };
+        let mut answers = answers.lock().unwrap();
+        answers.add_conflict(pattern, resolution);
+        if let Err(e) = answers.save(path) {
+            eprintln!("{}WARNING: Cannot write the answers to {}: {}", warning_priority(), path.display(), e);
+        }
+    }
+}
+
+impl ConflictResolver for ConflictPrompt {
+    fn resolve(&self, conflict: &Conflict<'_>) -> Resolution {
+        let ext = Path::new(conflict.path).extension().map_or_else(String::new, |ext| ext.to_string_lossy().to_lowercase());
+        if let Some(&resolution) = self.all.lock().unwrap().get(&ext) {
+            return resolution;
+        }
+        // Holding the lock keeps the status line from drawing over the question.
+        let mut out = io::stdout().lock();
+        let existing = conflict.existing_modified.map_or_else(|| "-".to_string(), |t| DateTime::<Local>::from(t).format("%Y-%m-%d %H:%M:%S").to_string());
+        let backed_up = conflict.modified.map_or_else(|| "-".to_string(), |t| t.to_string());
+        let archive = conflict.archive.file_name().unwrap_or_default().to_string_lossy();
+        let files = if ext.is_empty() { t!("conflict-files-without-extension") } else { t!("conflict-files-of-type", extension = ext.as_str()) };
+        writeln!(out, "\n\n {}", t!("conflict-exists", path = conflict.path)).ok();
+        writeln!(out, " {}", t!("conflict-existing", size = format!("{:>10}", format_size(conflict.existing_size)), modified = existing)).ok();
+        writeln!(out, " {}", t!("conflict-backed-up", size = format!("{:>10}", format_size(conflict.size)), modified = backed_up, archive = archive.as_ref())).ok();
+        loop {
+            write!(out, " {}", t!("conflict-prompt", files = files.as_str())).ok();
+            out.flush().ok();
+            let mut answer = String::new();
+            // Without an answer, what is there stays.
+            if io::stdin().read_line(&mut answer).map_or(true, |n| n == 0) {
+                return Resolution::Keep;
+            }
+            let answer = answer.trim();
+            let resolution = match answer.to_lowercase().as_str() {
+                // The German answers too, as the confirmation takes j for yes.
+                "k" | "keep" | "b" | "behalten" => Resolution::Keep,
+                "r" | "replace" | "e" | "ersetzen" => Resolution::Replace,
+                "n" | "rename" | "u" | "umbenennen" => Resolution::Rename,
+                _ => continue,
+            };
+            if answer.starts_with(|c: char| c.is_uppercase()) {
+                // Files without extension take no glob of their own.
+                let pattern = if ext.is_empty() { Answers::exact(conflict.path) } else { format!("*.{}", ext) };
+                self.record(pattern, resolution);
+                self.all.lock().unwrap().insert(ext, resolution);
+            } else {
+                self.record(Answers::exact(conflict.path), resolution);
+            }
+            return resolution;
+        }
+    }
+}
+
+/// Prints one `[i/n] name... N files` line per archive once it is done.
+/// The stages of the pipeline work on several archives at once, so lines are
+/// numbered in completion order. Progress is measured against the totals
+/// counted before the run; on a terminal a status line with the overall
+/// percentage, the files remaining and an ETA is kept below the archive
+/// lines.
+struct ConsoleProgress {
+    archives: usize,
+    totals: Totals,
+    counter: AtomicUsize,
+    files_done: AtomicUsize,
+    bytes_done: AtomicU64,
+    errors: AtomicUsize,
+    /// The archive last started, for the state file.
+    current: Mutex<Option<PathBuf>>,
+    started: Instant,
+    /// Time of the last status line redraw; `None` when not on a terminal.
+    status: Option<Mutex<Instant>>,
+    /// With `--systemd`, where the status line goes instead, and when it
+    /// was last sent.
+    notifier: Option<(Arc<Notifier>, Mutex<Instant>)>,
+}
+
+const STATUS_WIDTH: usize = 72;
+
+impl ProgressSink for ConsoleProgress {
+    fn archive_started(&self, archive: &Path) {
+        *self.current.lock().unwrap() = Some(archive.to_path_buf());
+    }
+
+    fn entry_extracted(&self, _archive: &Path, _entry: &str, _target: &Path) {
+        self.files_done.fetch_add(1, Ordering::Relaxed);
+        self.redraw(false);
+    }
+
+    fn bytes_written(&self, bytes: u64) {
+        self.bytes_done.fetch_add(bytes, Ordering::Relaxed);
+        self.redraw(false);
+    }
+
+    fn error(&self, _error: &EntryError) {
+        self.files_done.fetch_add(1, Ordering::Relaxed);
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn archive_finished(&self, archive: &Path, stats: &ArchiveStats) {
+        self.files_done.fetch_add(stats.skipped, Ordering::Relaxed);
+        self.finish(archive, format!("{} files ({:.1}% overall)", stats.files, self.fraction() * 100.0), "");
+    }
+
+    fn archive_failed(&self, archive: &Path, error: &ArchiveError) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        self.finish(archive, format!("ERROR: {}", error.source), error_priority());
+    }
+}
+
+impl ConsoleProgress {
+    /// Numbers the archives after the `skipped` ones of `archives`, so
+    /// they keep the numbers of a run that read them all.
+    fn new(archives: usize, skipped: usize, totals: Totals, notifier: Option<Arc<Notifier>>) -> Self {
+        ConsoleProgress {
+            archives,
+            totals,
+            counter: AtomicUsize::new(skipped),
+            files_done: AtomicUsize::new(0),
+            bytes_done: AtomicU64::new(0),
+            errors: AtomicUsize::new(0),
+            current: Mutex::new(None),
+            started: Instant::now(),
+            status: io::stdout().is_terminal().then(|| Mutex::new(Instant::now())),
+            notifier: notifier.map(|notifier| (notifier, Mutex::new(Instant::now()))),
+        }
+    }
+
+    /// Copies the progress into the state file.
+    fn fill(&self, state: &mut RunState) {
+        state.archives_done = self.counter.load(Ordering::Relaxed);
+        state.archives_total = self.archives;
+        state.files_done = self.files_done.load(Ordering::Relaxed);
+        state.files_total = self.totals.files;
+        state.bytes_done = self.bytes_done.load(Ordering::Relaxed);
+        state.bytes_total = self.totals.bytes;
+        state.errors = self.errors.load(Ordering::Relaxed);
+        state.current_archive = self.current.lock().unwrap().clone();
+    }
+
+    /// Share of the work done, by bytes unless the run only has empty files.
+    fn fraction(&self) -> f64 {
+        let fraction = if self.totals.bytes > 0 {
+            self.bytes_done.load(Ordering::Relaxed) as f64 / self.totals.bytes as f64
+        } else if self.totals.files > 0 {
+            self.files_done.load(Ordering::Relaxed) as f64 / self.totals.files as f64
+        } else {
+            1.0
+        };
+        fraction.min(1.0)
+    }
+
+    /// Prints the result line of an archive, after the journal `priority`
+    /// prefix.
+    fn finish(&self, archive: &Path, result: String, priority: &str) {
+        let i = self.counter.fetch_add(1, Ordering::Relaxed) + 1;
+        let zip_name = archive.file_name().unwrap_or_default().to_string_lossy();
+        let line = format!("{}[{}/{}] {}... {}", priority, i, self.archives, zip_name, result);
+        if self.status.is_some() {
+            println!("\r{:<width$}", line, width = STATUS_WIDTH);
+            self.redraw(true);
+        } else {
+            println!("{}", line);
+            self.notify(true);
+        }
+    }
+
+    /// Redraws the status line, at most every 200 ms unless `force` is set,
+    /// and passes it on to systemd.
+    fn redraw(&self, force: bool) {
+        self.notify(force);
+        let Some(status) = &self.status else { return };
+        let Ok(mut last) = status.try_lock() else { return };
+        if !force && last.elapsed() < Duration::from_millis(200) {
+            return;
+        }
+        *last = Instant::now();
+        print!("\r{:<width$}", self.status_line(), width = STATUS_WIDTH);
+        io::stdout().flush().ok();
+    }
+
+    /// Sends the status line to systemd and pings its watchdog, at most
+    /// every second unless `force` is set. While no file makes progress
+    /// the watchdog is not pinged, so systemd can tell a stuck restore.
+    fn notify(&self, force: bool) {
+        let Some((notifier, last)) = &self.notifier else { return };
+        let Ok(mut last) = last.try_lock() else { return };
+        if !force && last.elapsed() < Duration::from_secs(1) {
+            return;
+        }
+        *last = Instant::now();
+        notifier.status(&format!("Restoring: {}", self.status_line().trim_start()));
+        notifier.ping();
+    }
+
+    fn status_line(&self) -> String {
+        let fraction = self.fraction();
+        let remaining = self.totals.files.saturating_sub(self.files_done.load(Ordering::Relaxed));
+        let eta = if fraction > 0.0 {
+            format_duration(self.started.elapsed().mul_f64((1.0 - fraction) / fraction))
+        } else {
+            "-".to_string()
+        };
+        format!(" {:5.1}% | {} files remaining | ETA {}", fraction * 100.0, remaining, eta)
+    }
+
+    /// Removes the status line before the summary is printed.
+    fn clear(&self) {
+        if self.status.is_some() {
+            print!("\r{:width$}\r", "", width = STATUS_WIDTH);
+            io::stdout().flush().ok();
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+fn print_pipeline(pipeline: &PipelineStats) {
+    println!(" {}", t!("summary-pipeline", readers = pipeline.readers, decoders = pipeline.decoders, writers = pipeline.writers));
+    let queue = |id: &str, queue: &QueueStats| t!(id, max = queue.max_depth, capacity = queue.capacity, mean = format!("{:.1}", queue.mean_depth));
+    println!(" {}", queue("summary-decode-queue", &pipeline.decode_queue));
+    println!(" {}", queue("summary-write-queue", &pipeline.write_queue));
+}
+
+/// Restores each backup set below `source` into a snapshot folder of its
+/// own below `dest`, oldest first, linking files unchanged since the
+/// machine's previous snapshot. Returns how the runs ended together.
+fn extract_snapshots(
+    args: &Args,
+    source: &Path,
+    dest: &Path,
+    notifier: Option<&Arc<Notifier>>,
+    state: &StateFile,
+) -> io::Result<Option<Outcome>> {
+    let snapshots = restore::snapshots(&restore::find_archives(source)?);
+    if snapshots.is_empty() {
+        eprintln!("{}{}", error_priority(), t!("error-no-sets"));
+        state.finish(Status::SetupFailed);
+        return Ok(None);
+    }
+    let mut previous: HashMap<Option<String>, LinkDest> = HashMap::new();
+    let mut outcome: Option<Outcome> = None;
+    for (i, snapshot) in snapshots.iter().enumerate() {
+        let snapshot_dest = snapshot.dest(dest);
+        println!("\n{}", t!("snapshot", number = i + 1, count = snapshots.len(), path = snapshot_dest.display().to_string()));
+        let mut builder = args.builder(&snapshot.dir, &snapshot_dest).all_runs(true);
+        if let Some(link_dest) = previous.get(&snapshot.machine) {
+            builder = builder.link_dest(link_dest.clone());
+        }
+        let Some(next) = extract(builder.build(), args.system_files, notifier, state)? else { continue };
+        previous.insert(snapshot.machine.clone(), LinkDest::new(&snapshot_dest, &snapshot.archives));
+        let cancelled = next.is_cancelled();
+        outcome = Some(match outcome {
+            Some(outcome) => outcome.merge(next),
+            None => next,
+        });
+        if cancelled {
+            break;
+        }
+    }
+    if let Some(outcome) = &outcome {
+        state.finish(Status::of(outcome));
+    }
+    Ok(outcome)
+}
+
+/// Restores the archives of `restorer` and prints the summary. Returns how
+/// the run ended, `None` if there was nothing to restore.
+fn extract(
+    mut restorer: Restorer,
+    system_files: SystemFilePolicy,
+    notifier: Option<&Arc<Notifier>>,
+    state: &StateFile,
+) -> io::Result<Option<Outcome>> {
+    let dest_dir = restorer.dest().to_path_buf();
+    let mirrors = restorer.mirrors().to_vec();
+    let tree_file = restorer.tree_file();
+    let zips = restorer.archives()?;
+    if zips.is_empty() {
+        eprintln!("{}{}", error_priority(), t!("error-no-archives"));
+        state.finish(Status::SetupFailed);
+        return Ok(None);
+    }
+
+    let skipped = restorer.skipped_archives();
+    let all = restore::find_archives(restorer.source())?.len();
+    if all > zips.len() + skipped && restorer.archive_selector().is_none() {
+        println!("\n{}", t!("latest-sets-only", count = all - zips.len() - skipped));
+    }
+    if skipped > 0 {
+        println!("\n{}", t!("skipping-archives", count = skipped, archive = zips[0].file_name().unwrap_or_default().to_string_lossy().to_string()));
+    }
+
+    let total = zips.len();
+    println!("\n{}", t!("counting", count = total));
+    state.update(|state| state.phase = Phase::Counting);
+    let totals = state.tick_during(|| {
+        let _alive = notifier.map(|notifier| {
+            notifier.status(&format!("Counting files in {} archives", total));
+            notifier.keep_alive()
+        });
+        restorer.count(&zips)
+    });
+    let progress = Arc::new(ConsoleProgress::new(skipped + total, skipped, totals, notifier.cloned()));
+    state.follow(Arc::clone(&progress));
+    restorer = restorer.with_progress(progress.clone());
+
+    println!("{}", t!("starting", files = totals.files, size = format_size(totals.bytes)));
+    println!("{}\n", t!("destination", path = dest_dir.display().to_string()));
+
+    let outcome = match state.tick_during(|| restorer.extract_all(&zips)) {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            state.finish(Status::SetupFailed);
+            return Err(e);
+        }
+    };
+    state.finish(Status::of(&outcome));
+    progress.clear();
+    let stats = outcome.stats();
+
+    println!("\n{}", "=".repeat(60));
+    if outcome.is_cancelled() && (!stats.errors.is_empty() || !stats.quarantined.is_empty()) {
+        println!(" {}", t!("summary-stopped"));
+    } else if outcome.is_cancelled() {
+        println!(" {}", t!("summary-cancelled"));
+    } else {
+        println!(" {}", t!("summary-completed"));
+    }
+    println!(" {}", t!("summary-extracted", count = stats.files_extracted));
+    if stats.filtered > 0 {
+        println!(" {}", t!("summary-filtered", count = stats.filtered));
+    }
+    if stats.skipped > 0 {
+        println!(" {}", t!("summary-skipped", count = stats.skipped));
+    }
+    if stats.overwritten > 0 {
+        println!(" {}", t!("summary-overwritten", count = stats.overwritten));
+    }
+    if stats.renamed > 0 {
+        println!(" {}", t!("summary-renamed", count = stats.renamed));
+    }
+    if stats.placeholders > 0 {
+        println!(" {}", t!("summary-placeholders", count = stats.placeholders));
+    }
+    match system_files {
+        _ if stats.system_files == 0 => {}
+        SystemFilePolicy::Isolate => println!(" {}", t!("summary-system-isolated", count = stats.system_files, path = dest_dir.join(SystemFilePolicy::ISOLATED_DIR).display().to_string())),
+        _ => println!(" {}", t!("summary-system-skipped", count = stats.system_files)),
+    }
+    if stats.apple_double > 0 {
+        println!(" {}", t!("summary-apple-double", count = stats.apple_double));
+    }
+    if stats.normalized > 0 {
+        println!(" {}", t!("summary-normalized", count = stats.normalized));
+    }
+    if stats.absolute > 0 {
+        println!(" {}", t!("summary-absolute", count = stats.absolute));
+    }
+    if !stats.rejected.is_empty() {
+        println!(" {}", t!("summary-rejected", count = stats.rejected.len()));
+    }
+    if !stats.quarantined.is_empty() {
+        println!(" {}", t!("summary-quarantined", count = stats.quarantined.len(), path = dest_dir.join(QUARANTINE_DIR).display().to_string()));
+    }
+    if !stats.efs_paths.is_empty() {
+        println!(" {}", t!("summary-efs", count = stats.efs_paths.len()));
+    }
+    println!(" {}", t!("summary-errors", count = stats.errors.len()));
+    let by_kind = stats.errors_by_kind();
+    for (kind, count) in &by_kind {
+        println!("   {:<22} {}", format!("{}:", kind), count);
+    }
+    println!(" {}", t!("summary-destination", path = dest_dir.display().to_string()));
+    if stats.linked > 0 {
+        println!(" {}", t!("summary-linked", count = stats.linked));
+    }
+    if let Some(tree) = &tree_file {
+        println!(" {}", t!("summary-tree", path = tree.display().to_string()));
+        println!(" {}", t!("summary-deduplicated", count = stats.deduplicated));
+    }
+    for mirror in &mirrors {
+        println!(" {}", t!("summary-mirrored", path = mirror.display().to_string()));
+    }
+    for (dir, relabeled) in restorer.relabel() {
+        match relabeled {
+            Ok(()) => println!(" {}", t!("summary-relabeled", path = dir.display().to_string())),
+            Err(e) => println!(" {}", t!("summary-relabel-failed", path = dir.display().to_string(), error = e.to_string())),
+        }
+    }
+    println!(" {}", t!("summary-elapsed", seconds = format!("{:.1}", stats.elapsed.as_secs_f64())));
+    let order = stats.ordering.order.to_possible_value().expect("no skipped variants");
+    println!(
+        " {}",
+        t!(
+            "summary-order",
+            order = order.get_name(),
+            switches = stats.ordering.dir_switches,
+            seconds = format!("{:.1}", stats.ordering.mean_completion.as_secs_f64())
+        )
+    );
+    print_pipeline(&stats.pipeline);
+    println!("{}", "=".repeat(60));
+
+    if !stats.placeholder_paths.is_empty() {
+        println!("\n{}", t!("summary-placeholder-list"));
+        for path in &stats.placeholder_paths {
+            println!("  {}", path);
+        }
+    }
+
+    if !stats.rejected.is_empty() {
+        println!("\n{}", t!("summary-rejected-list"));
+        for name in &stats.rejected {
+            println!("  {}", name);
+        }
+    }
+
+    if !stats.quarantined.is_empty() {
+        println!("\n{}", t!("summary-damaged-list", path = QUARANTINE_DIR));
+        for path in &stats.quarantined {
+            println!("  {}", path);
+        }
+    }
+
+    if !stats.efs_paths.is_empty() {
+        println!("\n{}", t!("summary-efs-list"));
+        for path in &stats.efs_paths {
+            println!("  {}", path);
+        }
+    }
+
+    let slowest = stats.slowest_archives(5);
+    if stats.per_archive.len() > 1 && slowest.iter().any(|a| !a.elapsed.is_zero()) {
+        println!("\n{}", t!("summary-slowest"));
+        for archive in slowest {
+            let secs = archive.elapsed.as_secs_f64();
+            let rate = if secs > 0.0 { format!("{}/s", format_size((archive.bytes as f64 / secs) as u64)) } else { "-".to_string() };
+            let name = archive.archive.file_name().unwrap_or(archive.archive.as_os_str()).to_string_lossy();
+            let line = t!(
+                "summary-slow-archive",
+                seconds = format!("{:>8.2}", secs),
+                size = format!("{:>10}", format_size(archive.bytes)),
+                rate = format!("{:>12}", rate),
+                errors = format!("{:>4}", archive.errors),
+                name = name.as_ref()
+            );
+            println!("  {}", line);
+        }
+    }
+
+    // A few examples of each kind of error; the first 20 alone are often
+    // all of the same kind and hide the others.
+    for (kind, count) in &by_kind {
+        println!("\n{}", t!("summary-error-kind", kind = kind.to_string(), count = *count));
+        for err in stats.errors.iter().filter(|e| e.kind() == *kind).take(5) {
+            println!("{}  {}", error_priority(), err);
+        }
+        if *count > 5 {
+            println!("{}", t!("summary-more-errors", count = count - 5));
+        }
+    }
+    Ok(Some(outcome))
+}
+
+/// Restores the files of `args.source` matching `filter` into the staging
+/// folder and leaves `instructions` for them there.
+fn extract_staged(args: &StagingArgs, filter: Filter, name: &str, instructions: &str) -> io::Result<Status> {
+    let restorer = Restorer::builder().source(&args.source).dest(&args.dest).filter(filter).all_runs(args.all_runs).build();
+    println!("\nRestoring the {} files to {}", name, args.dest.display());
+    let state = StateFile::new(&args.source, &args.dest, None);
+    let Some(outcome) = extract(restorer, SystemFilePolicy::Skip, None, &state)? else { return Ok(Status::SetupFailed) };
+    if outcome.stats().files_extracted == 0 {
+        println!("\nNo {} files found in the backup.", name);
+        return Ok(Status::of(&outcome));
+    }
+    let path = args.dest.join(format!("How to restore {}.txt", name));
+    fs::write(&path, instructions)?;
+    println!("\n{}", instructions);
+    println!("These instructions are also in {}", path.display());
+    Ok(Status::of(&outcome))
+}
+
+/// Name of the log a restore started with `--background` writes to its
+/// destination folder.
+const BACKGROUND_LOG: &str = ".restore.log";
+
+/// The state file of a restore, see [`RunState`]. Writing it is best effort:
+/// a destination that cannot take it fails the restore anyway.
+struct StateFile {
+    path: PathBuf,
+    state: Mutex<RunState>,
+    progress: OnceLock<Arc<ConsoleProgress>>,
+}
+
+impl StateFile {
+    fn new(source: &Path, dest: &Path, log: Option<PathBuf>) -> StateFile {
+        let state = StateFile {
+            path: RunState::path(dest),
+            state: Mutex::new(RunState { log, ..RunState::new(source, dest) }),
+            progress: OnceLock::new(),
+        };
+        if fs::create_dir_all(dest).is_ok() {
+            state.update(|_| {});
+        }
+        state
+    }
+
+    /// Applies `change`, takes the current progress and writes the file.
+    fn update(&self, change: impl FnOnce(&mut RunState)) {
+        let mut state = self.state.lock().unwrap();
+        change(&mut state);
+        if let Some(progress) = self.progress.get() {
+            progress.fill(&mut state);
+        }
+        state.write(&self.path).ok();
+    }
+
+    /// Starts restoring, with the progress taken from `progress`.
+    fn follow(&self, progress: Arc<ConsoleProgress>) {
+        self.progress.set(progress).ok();
+        self.update(|state| state.phase = Phase::Restoring);
+    }
+
+    fn finish(&self, status: Status) {
+        self.update(|state| {
+            state.phase = Phase::Finished;
+            state.exit_code = Some(status as i32);
+        });
+    }
+
+    /// Runs `f`, rewriting the file every second meanwhile, so its age tells
+    /// whether the restore still runs.
+    fn tick_during<T>(&self, f: impl FnOnce() -> T) -> T {
+        let (done, ticks) = mpsc::channel::<()>();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = ticks.recv_timeout(Duration::from_secs(1)) {
+                    self.update(|_| {});
+                }
+            });
+            let result = f();
+            drop(done);
+            result
+        })
+    }
+}
+
+/// Seconds without an update after which a restore that has not finished
+/// counts as no longer running.
+const STALE_AFTER: u64 = 10;
+
+/// `restore status`: prints the state of the restore into `args.dest`;
+/// with `--follow`, until it ends.
+fn show_status(args: &StatusArgs) -> io::Result<Status> {
+    let path = RunState::path(&args.dest);
+    let read = || {
+        RunState::read(&path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(e.kind(), format!("No restore into {} found ({} is missing)", args.dest.display(), STATE_FILE)),
+            _ => e,
+        })
+    };
+    let mut state = read()?;
+    if args.follow {
+        let terminal = io::stdout().is_terminal();
+        let mut last = String::new();
+        while state.exit_code.is_none() && state.age() < STALE_AFTER {
+            let line = format_run_progress(&state);
+            if terminal {
+                print!("\r{:<width$}", line, width = STATUS_WIDTH);
+                io::stdout().flush().ok();
+            } else if line != last {
+                println!("{}", line);
+            }
+            last = line;
+            thread::sleep(Duration::from_secs(1));
+            state = read()?;
+        }
+        if terminal {
+            print!("\r{:width$}\r", "", width = STATUS_WIDTH);
+        }
+    }
+    print_run_state(&state);
+    Ok(match state.exit_code {
+        Some(code) => Status::from_code(code),
+        None if state.age() >= STALE_AFTER => Status::Stopped,
+        None => Status::Success,
+    })
+}
+
+fn format_run_progress(state: &RunState) -> String {
+    let phase = match state.phase {
+        Phase::Analyzing => return "Analyzing the backup...".to_string(),
+        Phase::Waiting => {
+            let at = state.start_at.and_then(|secs| DateTime::from_timestamp(secs as i64, 0));
+            let at = at.map_or_else(|| "-".to_string(), |at| at.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string());
+            return format!("Waiting to start at {}", at);
+        }
+        Phase::Counting => return "Counting files...".to_string(),
+        Phase::Restoring | Phase::Finished => "Restoring",
+    };
+    let fraction = if state.bytes_total > 0 {
+        state.bytes_done as f64 / state.bytes_total as f64
+    } else if state.files_total > 0 {
+        state.files_done as f64 / state.files_total as f64
+    } else {
+        1.0
+    };
+    format!(
+        "{} {:5.1}% | archive {}/{} | {} files remaining | {} errors",
+        phase,
+        fraction.min(1.0) * 100.0,
+        (state.archives_done + 1).min(state.archives_total),
+        state.archives_total,
+        state.files_total.saturating_sub(state.files_done),
+        state.errors
+    )
+}
+
+fn print_run_state(state: &RunState) {
+    println!("Restore of {}", state.source.display());
+    println!("  into {} (process {})", state.dest.display(), state.pid);
+    let running = format_duration(Duration::from_secs(state.updated.saturating_sub(state.started)));
+    match state.exit_code {
+        Some(code) => {
+            let status = Status::from_code(code);
+            println!("  Finished:  exit status {} ({}) after {}", code, status.name(), running);
+        }
+        None if state.age() >= STALE_AFTER => {
+            println!("  Stopped:   no longer running, last update {} ago", format_duration(Duration::from_secs(state.age())));
+        }
+        None => println!("  Running:   {}, {}", running, format_run_progress(state)),
+    }
+    if state.archives_total > 0 {
+        println!("  Archives:  {} of {} done", state.archives_done, state.archives_total);
+        println!("  Files:     {} of {}", state.files_done.min(state.files_total), state.files_total);
+        println!("  Data:      {} of {}", format_size(state.bytes_done), format_size(state.bytes_total));
+        println!("  Errors:    {}", state.errors);
+    }
+    if let Some(archive) = state.current_archive.as_ref().filter(|_| state.exit_code.is_none()) {
+        println!("  Archive:   {}", archive.display());
+    }
+    if let Some(log) = &state.log {
+        println!("  Log:       {}", log.display());
+    }
+}
+
+/// Prints the space the restore needs in each folder it writes to, with a
+/// warning for those whose file system lacks it.
+fn print_space(args: &Args, source: &Path, dest: &Path) {
+    let restorer = args.builder(source, dest).all_runs(args.all_runs || args.snapshot_per_set).build();
+    let Ok(zips) = restorer.archives() else { return };
+    for space in restorer.space(&zips) {
+        let available = space.available.map_or_else(|| t!("plan-free-unknown"), |free| t!("plan-free", size = format_size(free)));
+        println!("{}", t!("plan-space", size = format_size(space.needed), path = space.dir.display().to_string(), available = available));
+        if !space.fits() {
+            eprintln!("{}{}", warning_priority(), t!("warning-no-space", path = space.dir.display().to_string()));
+        }
+    }
+}
+
+/// A path argument with a leading `~` and the variables `$NAME`, `${NAME}`
+/// and `%NAME%` expanded, for scripts and containers that pass them quoted.
+/// Variables that are not set are kept as written, as `$RECYCLE.BIN` is a
+/// folder name; `HOME` and `USERPROFILE` stand in for each other.
+fn expand_path(value: &str) -> Result<PathBuf, String> {
+    let var = |name: &str| match name {
+        "HOME" | "USERPROFILE" => std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE")).ok(),
+        _ => std::env::var(name).ok(),
+    };
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    if let Some(after) = rest.strip_prefix('~')
+        && (after.is_empty() || after.starts_with(['/', '\\']))
+    {
+        expanded.push_str(&var("HOME").ok_or("cannot expand ~: neither HOME nor USERPROFILE is set")?);
+        rest = after;
+    }
+    while let Some(i) = rest.find(['$', '%']) {
+        expanded.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        let (name, end) = match rest.as_bytes()[i] {
+            b'%' => after.find('%').map_or(("", 0), |end| (&after[..end], end + 1)),
+            _ if after.starts_with('{') => after.find('}').map_or(("", 0), |end| (&after[1..end], end + 1)),
+            _ => {
+                let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match var(name).filter(|_| !name.is_empty()) {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&rest[i..i + 1 + end]),
+        }
+        rest = &after[end..];
+    }
+    expanded.push_str(rest);
+    Ok(PathBuf::from(expanded))
+}
+
+/// Parses an option taking a size, see [`restore::parse_size`].
+fn parse_size(value: &str) -> Result<u64, String> {
+    restore::parse_size(value).ok_or_else(|| format!("expected a size such as 512M or 2G, got {:?}", value))
+}
+
+/// `filter` with the `include` and `exclude` patterns: globs, or with
+/// `regex` regular expressions.
+fn with_patterns(mut filter: Filter, include: &[String], exclude: &[String], regex: bool) -> Result<Filter, regex::Error> {
+    for pattern in include {
+        filter = if regex { filter.include_regex(self::regex(pattern)?) } else { filter.include(pattern) };
+    }
+    for pattern in exclude {
+        filter = if regex { filter.exclude_regex(self::regex(pattern)?) } else { filter.exclude(pattern) };
+    }
+    Ok(filter)
+}
+
+/// `pattern` as a regular expression that ignores case, as globs do.
+fn regex(pattern: &str) -> Result<regex::Regex, regex::Error> {
+    regex::RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+fn invalid_regex(e: regex::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("invalid regular expression: {}", e))
+}
+
+fn parse_query(value: &str) -> Result<Query, String> {
+    Query::parse(value).map_err(|e| e.to_string())
+}
+
+/// The rules of `--answers`, and where to add new ones.
+#[derive(Debug, Clone)]
+struct AnswersFile {
+    path: PathBuf,
+    answers: Answers,
+}
+
+fn parse_answers(value: &str) -> Result<AnswersFile, String> {
+    let path = expand_path(value)?;
+    let answers = Answers::load(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(AnswersFile { path, answers })
+}
+
+/// The paths of a selection file, for `--selection`.
+#[derive(Debug, Clone)]
+struct Selection(Vec<String>);
+
+fn parse_selection(value: &str) -> Result<Selection, String> {
+    let path = expand_path(value)?;
+    restore::read_selection(&path).map(Selection).map_err(|e| e.to_string())
+}
+
+/// An octal permission mode such as `0644` or `755`.
+fn parse_mode(value: &str) -> Result<u32, String> {
+    match u32::from_str_radix(value.trim_start_matches("0o"), 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(format!("expected an octal mode such as 0644, got {:?}", value)),
+    }
+}
+
+/// An IANA time zone such as `Europe/Berlin` or `UTC`.
+fn parse_zone(value: &str) -> Result<chrono_tz::Tz, String> {
+    value.parse().map_err(|_| format!("expected a time zone such as Europe/Berlin, got {:?}", value))
+}
+
+/// `USER[:GROUP]` as `chown` takes it: names or ids, `USER:` for the
+/// user's login group and `:GROUP` for the group alone.
+fn parse_owner(value: &str) -> Result<Owner, String> {
+    let (user, group) = value.split_once(':').map_or((value, None), |(user, group)| (user, Some(group)));
+    let mut owner = Owner { uid: None, gid: None };
+    if !user.is_empty() {
+        let (uid, login_group) = lookup_user(user).ok_or_else(|| format!("no such user: {}", user))?;
+        owner.uid = Some(uid);
+        owner.gid = group.filter(|group| group.is_empty()).and(login_group);
+    }
+    if let Some(group) = group.filter(|group| !group.is_empty()) {
+        owner.gid = Some(lookup_group(group).ok_or_else(|| format!("no such group: {}", group))?);
+    }
+    if owner.uid.is_none() && owner.gid.is_none() {
+        return Err(format!("expected USER, USER:GROUP or :GROUP, got {:?}", value));
+    }
+    Ok(owner)
+}
+
+/// The id and login group of the user named, or numbered, `user`.
+fn lookup_user(user: &str) -> Option<(u32, Option<u32>)> {
+    #[cfg(unix)]
+    {
+        let name = std::ffi::CString::new(user).ok()?;
+        // SAFETY: the arguments are parsed before any other thread runs,
+        // so nothing else uses the static entry getpwnam returns.
+        let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+        if !entry.is_null() {
+            // SAFETY: checked for null above.
+            let entry = unsafe { &*entry };
+            return Some((entry.pw_uid, Some(entry.pw_gid)));
+        }
+    }
+    user.parse().ok().map(|uid| (uid, None))
+}
+
+/// The id of the group named, or numbered, `group`.
+fn lookup_group(group: &str) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        let name = std::ffi::CString::new(group).ok()?;
+        // SAFETY: as for `lookup_user`.
+        let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+        if !entry.is_null() {
+            // SAFETY: checked for null above.
+            return Some(unsafe { (*entry).gr_gid });
+        }
+    }
+    group.parse().ok()
+}
+
+fn parse_user_remap(value: &str) -> Result<UserRemap, String> {
+    if value == "auto" {
+        return Ok(UserRemap::Auto);
+    }
+    // A folder name of its own, which keeps the profile in `Users`.
+    let is_name = |name: &str| !matches!(name, "" | "." | "..") && !name.contains(['/', '\\']);
+    match value.split_once('=') {
+        Some((old, new)) if is_name(old) && is_name(new) => Ok(UserRemap::Rename(old.to_string(), new.to_string())),
+        _ => Err(format!("expected OLD=NEW with two user names, or auto, got {:?}", value)),
+    }
+}
+
+/// Parses `--drive-map`: a drive letter, with or without a colon, and a
+/// folder, `D=/mnt/data`.
+fn parse_drive_map(value: &str) -> Result<(char, PathBuf), String> {
+    let (drive, dir) = value.split_once('=').ok_or_else(|| format!("expected DRIVE=PATH, got {:?}", value))?;
+    let mut letters = drive.trim_end_matches(':').chars();
+    match (letters.next(), letters.next()) {
+        (Some(letter), None) if letter.is_ascii_alphabetic() && !dir.is_empty() => Ok((letter.to_ascii_uppercase(), expand_path(dir)?)),
+        _ => Err(format!("expected a drive letter and a folder, e.g. \"D=/mnt/data\", got {:?}", value)),
+    }
+}
+
+/// Parses `--start-at`: `HH:MM`, the next time the clock shows it, or a
+/// date and time, `YYYY-MM-DD HH:MM`.
+fn parse_start_at(value: &str) -> Result<DateTime<Local>, String> {
+    let now = Local::now();
+    let start = if let Ok(time) = NaiveTime::parse_from_str(value, "%H:%M") {
+        let today = now.date_naive().and_time(time);
+        if today > now.naive_local() { today } else { today.checked_add_days(Days::new(1)).ok_or("time out of range")? }
+    } else {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").map_err(|_| format!("expected HH:MM or \"YYYY-MM-DD HH:MM\", got {:?}", value))?
+    };
+    let start = Local.from_local_datetime(&start).earliest().ok_or_else(|| format!("{} does not exist in the local time zone", start))?;
+    if start <= now {
+        return Err(format!("{} lies in the past", start.format("%Y-%m-%d %H:%M")));
+    }
+    Ok(start)
+}
+
+/// `--start-at`: counts down to `start` on the terminal, in the state file
+/// and to systemd. Ctrl+C cancels the restore meanwhile.
+fn wait_until(start: DateTime<Local>, notifier: Option<&Arc<Notifier>>, state: &StateFile) {
+    let at = start.format("%Y-%m-%d %H:%M");
+    state.update(|state| {
+        state.phase = Phase::Waiting;
+        state.start_at = u64::try_from(start.timestamp()).ok();
+    });
+    let _alive = notifier.map(|notifier| {
+        notifier.status(&format!("Waiting to start at {}", at));
+        notifier.keep_alive()
+    });
+    let terminal = io::stdout().is_terminal();
+    if !terminal {
+        println!("\nWaiting to start at {} (Ctrl+C cancels)", at);
+    }
+    state.tick_during(|| {
+        // Measured against the clock every second, so a machine that slept
+        // meanwhile still starts on time.
+        while let Ok(remaining) = (start - Local::now()).to_std() {
+            if terminal {
+                print!("\r{:<width$}", format!(" Starting at {} in {} (Ctrl+C cancels)", at, format_duration(remaining)), width = STATUS_WIDTH);
+                io::stdout().flush().ok();
+            }
+            thread::sleep(remaining.min(Duration::from_secs(1)));
+        }
+    });
+    if terminal {
+        print!("\r{:width$}\r", "", width = STATUS_WIDTH);
+    }
+    println!("\nStarting the restore at {}", Local::now().format("%H:%M:%S"));
+}
+
+/// `--background`: starts this restore again as a detached process, with
+/// its output going to a log in `dest`, and returns its process id.
+fn spawn_background(dest: &Path) -> io::Result<u32> {
+    fs::create_dir_all(dest)?;
+    let log = fs::File::create(dest.join(BACKGROUND_LOG))?;
+    let mut command = process::Command::new(std::env::current_exe()?);
+    command
+        .args(std::env::args_os().skip(1).filter(|arg| arg != "--background"))
+        .arg("--detached")
+        .stdin(process::Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+    // Out of reach of the terminal or console window closing, or of the
+    // remote desktop session that started it dropping.
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const DETACHED_PROCESS: u32 = 0x0000_0008;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        command.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+    }
+    Ok(command.spawn()?.id())
+}
+
+/// The summary written by `--stats-json`.
+#[derive(Serialize)]
+struct StatsDocument<'a> {
+    status: &'static str,
+    exit_code: i32,
+    cancelled: bool,
+    errors_by_kind: BTreeMap<ErrorKind, usize>,
+    /// Paths of the five archives that took longest, slowest first.
+    slowest_archives: Vec<&'a Path>,
+    #[serde(flatten)]
+    stats: &'a Stats,
+}
+
+/// Writes the summary of `outcome` as JSON to `path`, or to stdout as the
+/// last line of output for `-`.
+fn write_stats_json(path: &Path, outcome: &Outcome, status: Status) -> io::Result<()> {
+    let stats = outcome.stats();
+    let document = StatsDocument {
+        status: status.name(),
+        exit_code: status as i32,
+        cancelled: outcome.is_cancelled(),
+        errors_by_kind: stats.errors_by_kind().into_iter().collect(),
+        slowest_archives: stats.slowest_archives(5).into_iter().map(|a| a.archive.as_path()).collect(),
+        stats,
+    };
+    if path == Path::new("-") {
+        let mut stdout = io::stdout().lock();
+        serde_json::to_writer(&mut stdout, &document)?;
+        writeln!(stdout)
+    } else {
+        let mut json = serde_json::to_vec_pretty(&document)?;
+        json.push(b'\n');
+        fs::write(path, json)
+    }
+}
+
+/// Reads the `--sign-key`. A service or background process has no terminal
+/// to ask for the password on, and keys without one take an empty password.
+fn load_signer(key: &Path) -> io::Result<Signer> {
+    let password = std::env::var("RESTORE_SIGN_PASSWORD").ok().or_else(|| (!io::stdin().is_terminal()).then(String::new));
+    Signer::load(key, password)
+}
+
+/// What the signature of `path` attests, in the `key:value` fields separated
+/// by tabs that `minisign` uses.
+fn trusted_comment(path: &Path, source: &Path, dest: &Path) -> String {
+    // The comment is one line of the signature file.
+    let field = |path: &Path| path.display().to_string().replace(['\t', '\n', '\r'], " ");
+    format!(
+        "timestamp:{}\tfile:{}\tsource:{}\tdest:{}\trestore:{}",
+        Local::now().timestamp(),
+        path.file_name().map_or_else(|| field(path), |name| field(Path::new(name))),
+        field(source),
+        field(dest),
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// The messages in the language of `--lang` or the locale.
+static MESSAGES: OnceLock<Messages> = OnceLock::new();
+
+fn messages() -> &'static Messages {
+    MESSAGES.get_or_init(|| Messages::new(Language::from_env()))
+}
+
+/// Set with `--systemd`, when output goes to the journal.
+static JOURNAL: AtomicBool = AtomicBool::new(false);
+
+/// The prefix that gives a line error priority in the journal, see
+/// `sd-daemon(3)`; empty outside of it.
+fn error_priority() -> &'static str {
+    if JOURNAL.load(Ordering::Relaxed) { "<3>" } else { "" }
+}
+
+/// Like [`error_priority`], for warnings.
+fn warning_priority() -> &'static str {
+    if JOURNAL.load(Ordering::Relaxed) { "<4>" } else { "" }
+}
+
+/// Connects to systemd for `--systemd` and tells it the service is up.
+/// Output from here on is meant for the journal.
+fn connect_systemd() -> Option<Arc<Notifier>> {
+    JOURNAL.store(true, Ordering::Relaxed);
+    let notifier = match Notifier::from_env() {
+        Ok(Some(notifier)) => Arc::new(notifier),
+        Ok(None) => {
+            eprintln!("<4>WARNING: NOTIFY_SOCKET is not set, not reporting to systemd (run as a Type=notify service)");
+            return None;
+        }
+        Err(e) => {
+            eprintln!("<4>WARNING: Cannot connect to systemd: {}", e);
+            return None;
+        }
+    };
+    notifier.ready("Analyzing the backup");
+    Some(notifier)
+}
+
+fn main() {
+    let mut args = Args::parse();
+    MESSAGES.get_or_init(|| Messages::new(args.lang.unwrap_or_else(Language::from_env)));
+
+    if let Some(command) = &args.command {
+        if let Some(source) = command.source()
+            && !source.exists()
+        {
+            eprintln!("{}", t!("error-source-not-found", path = source.display().to_string()));
+            Status::SetupFailed.exit();
+        }
+        match command.run() {
+            Ok(status) => status.exit(),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                Status::SetupFailed.exit();
+            }
+        }
+    }
+
+    args.detect_user_remap();
+    args.check_chown();
+    args.apply_umask();
+    let source = args.source.clone().expect("--source is required without a subcommand");
+    let source_path = source.as_path();
+    let notifier = if args.systemd { connect_systemd() } else { None };
+    // The process `--background` started keeps the state file from the start.
+    let background_state = args.detached.then(|| {
+        let dest = args.dest.as_deref().expect("--background requires --dest");
+        StateFile::new(source_path, dest, Some(dest.join(BACKGROUND_LOG)))
+    });
+    let stop = |status: Status, message: &str| -> ! {
+        if let Some(notifier) = &notifier {
+            notifier.stopping(message);
+        }
+        if let Some(state) = &background_state {
+            state.finish(status);
+        }
+        status.exit()
+    };
+    if !source_path.exists() {
+        eprintln!("{}{}", error_priority(), t!("error-source-not-found", path = source_path.display().to_string()));
+        stop(Status::SetupFailed, "Source not found");
+    }
+    if args.on_conflict == ConflictPolicy::Ask && (args.systemd || args.background || !io::stdin().is_terminal()) {
+        eprintln!("{}{}", error_priority(), t!("error-ask-no-terminal"));
+        stop(Status::SetupFailed, "Nothing to ask on");
+    }
+    if let Err(e) = args.selection.filter() {
+        eprintln!("{}{}", error_priority(), t!("error-invalid-regex", error = e.to_string()));
+        stop(Status::SetupFailed, "Invalid regular expression");
+    }
+
+    if args.background {
+        let dest = args.dest.as_deref().expect("--background requires --dest");
+        match spawn_background(dest) {
+            Ok(pid) => {
+                println!("{}", t!("background-started", pid = pid));
+                println!("{}", t!("background-log", path = dest.join(BACKGROUND_LOG).display().to_string()));
+                println!("{}", t!("background-progress", dest = dest.display().to_string()));
+                Status::Success.exit();
+            }
+            Err(e) => {
+                eprintln!("{}", t!("error-background", error = e.to_string()));
+                Status::SetupFailed.exit();
+            }
+        }
+    }
+
+    // Before the restore, which may take hours, so the password is asked
+    // while someone is there.
+    let signer = args.sign_key.as_deref().map(|key| match load_signer(key) {
+        Ok(signer) => signer,
+        Err(e) => {
+            eprintln!("{}{}", error_priority(), t!("error-signing-key", path = key.display().to_string(), error = e.to_string()));
+            stop(Status::SetupFailed, "Cannot read the signing key");
+        }
+    });
+
+    let categories = match &args.categories {
+        Some(path) => match Categories::load(path) {
+            Ok(categories) => categories,
+            Err(e) => {
+                eprintln!("{}{}", error_priority(), t!("error-categories", path = path.display().to_string(), error = e.to_string()));
+                stop(Status::SetupFailed, "Cannot read the categories");
+            }
+        },
+        None => Categories::default(),
+    };
+
+    let alive = notifier.as_ref().map(|notifier| notifier.keep_alive());
+    let analyze = || match restore::analyze(source_path) {
+        Ok(analysis) => {
+            let set = BackupSet::from_archives(&analysis.source, analysis.zips.clone()).with_mmap(args.mmap).with_cache(analysis_cache(args.no_cache));
+            let sampled = (args.sample.is_some() || args.sample_entries.is_some())
+                .then(|| restore::sample_analyze(&set, args.threads, args.sample, args.sample_entries));
+            let deep = match &sampled {
+                Some(sampled) => Some(sampled.estimated()),
+                None => args.deep.then(|| restore::deep_analyze(&set, args.threads)),
+            };
+            print_analysis(&analysis, deep.as_ref(), sampled.as_ref(), &categories);
+            if args.analyze_only {
+                let restored = if args.all_runs { analysis.zips.clone() } else { restore::latest_backup_sets(&analysis.zips) };
+                print_archive_numbers(&analysis.source, &restored);
+                let unreadable = deep.is_some_and(|deep| !deep.errors.is_empty());
+                let status = if unreadable { Status::Errors } else { Status::Success };
+                stop(status, "Analysis done");
+            }
+            if analysis.zips.is_empty() {
+                stop(Status::SetupFailed, "No archives found");
+            }
+        }
+        Err(e) => {
+            eprintln!("{}{}", error_priority(), t!("error-analysis", error = e.to_string()));
+            stop(Status::SetupFailed, "Analysis failed");
+        }
+    };
+    match &background_state {
+        Some(state) => state.tick_during(analyze),
+        None => analyze(),
+    }
+    drop(alive);
+
+    let dest = args.dest.clone().expect("Destination path is required");
+
+    println!("\n{}", t!("plan-source", path = source_path.display().to_string()));
+    println!("{}", t!("plan-dest", path = dest.display().to_string()));
+    for mirror in &args.mirror {
+        println!("{}", t!("plan-mirror", path = mirror.display().to_string()));
+    }
+    for (drive, dir) in &args.drive_map {
+        println!("  {}:      {}", drive, dir.display());
+    }
+    for remap in &args.remap_user {
+        if let UserRemap::Rename(old, new) = remap {
+            println!("{}", t!("plan-user", old = old.as_str(), new = new.as_str()));
+        }
+    }
+    print_space(&args, source_path, &dest);
+    // A service or background process has no one to answer, nor has a
+    // script; the prompt would wait for input that never comes.
+    let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+    let confirmed = !args.no && (args.yes || args.systemd || args.detached || interactive && {
+        print!("\n{}", t!("confirm-prompt"));
+        io::stdout().flush().ok();
+        let mut confirm = String::new();
+        io::stdin().read_line(&mut confirm).unwrap();
+        confirm.trim().to_lowercase().starts_with('j') || confirm.trim().to_lowercase().starts_with('y')
+    });
+    if !confirmed && !args.no && !interactive {
+        println!("\n{}", t!("confirm-no-terminal"));
+    }
+
+    if confirmed {
+        let foreground;
+        let state = match &background_state {
+            Some(state) => state,
+            None => {
+                foreground = StateFile::new(source_path, &dest, None);
+                &foreground
+            }
+        };
+        if let Some(start) = args.start_at {
+            wait_until(start, notifier.as_ref(), state);
+        }
+        args.tune_threads(source_path, &dest);
+        let extracted = if args.snapshot_per_set {
+            extract_snapshots(&args, source_path, &dest, notifier.as_ref(), state)
+        } else {
+            extract(args.restorer(source_path, &dest), args.system_files, notifier.as_ref(), state)
+        };
+        match extracted {
+            Ok(Some(outcome)) => {
+                let status = Status::of(&outcome);
+                if let Some(path) = &args.stats_json
+                    && let Err(e) = write_stats_json(path, &outcome, status)
+                {
+                    eprintln!("{}{}", error_priority(), t!("error-stats-json", path = path.display().to_string(), error = e.to_string()));
+                    stop(Status::SetupFailed, "Writing the JSON summary failed");
+                }
+                if let Some(signer) = &signer {
+                    let stats_json = args.stats_json.as_deref().filter(|path| *path != Path::new("-"));
+                    let reports = [args.manifest.as_deref(), args.audit_log.as_deref(), stats_json];
+                    for path in reports.into_iter().flatten() {
+                        match signer.sign(path, &trusted_comment(path, source_path, &dest)) {
+                            Ok(signature) => println!("{}", t!("signed", path = signature.display().to_string())),
+                            Err(e) => {
+                                eprintln!("{}{}", error_priority(), t!("error-signing", path = path.display().to_string(), error = e.to_string()));
+                                stop(Status::SetupFailed, "Signing failed");
+                            }
+                        }
+                    }
+                }
+                let stats = outcome.stats();
+                stop(status, &format!("Done ({}): {} files restored, {} errors", status.name(), stats.files_extracted, stats.errors.len()))
+            }
+            Ok(None) => stop(Status::SetupFailed, "No archives found"),
+            Err(e) => {
+                eprintln!("{}{}", error_priority(), t!("error-extraction", error = e.to_string()));
+                stop(Status::SetupFailed, "Extraction failed");
+            }
+        }
+    } else {
+        println!("{}", t!("cancelled"));
+        Status::Stopped.exit();
+    }
+}
@@ -0,0 +1,294 @@
+//! SHA-256 manifests of restored files, and checking a destination folder
+//! against one, possibly months later.
+//!
+//! Each line describes one file, by its path relative to the destination
+//! with `/` between folders, in one of the [`ManifestFormat`]s. In the GNU
+//! and BSD ones, names holding a backslash or a newline are escaped as
+//! `sha256sum` does: the line starts with `\` and these characters are
+//! written as `\\` and `\n`.
+//!
+//! A file overwritten during the restore, by a later backup run for
+//! instance, gets a line per write; the last one is what the destination
+//! holds.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::catalog::BackupTime;
+use crate::options::{AbsoluteEntries, ManifestFormat};
+use crate::paths::normalize_entry;
+
+/// A line of a [`ManifestFormat::Json`] manifest.
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonLine<'a> {
+    #[serde(borrow)]
+    path: Cow<'a, str>,
+    #[serde(default)]
+    size: Option<u64>,
+    /// As stored in the archive, usually the local time of the backed up
+    /// machine: `YYYY-MM-DD HH:MM:SS`.
+    #[serde(default)]
+    modified: Option<String>,
+    #[serde(borrow)]
+    sha256: Cow<'a, str>,
+    #[serde(default)]
+    archive: Option<PathBuf>,
+}
+
+/// What the manifest records of a restored file.
+pub(crate) struct ManifestEntry<'e> {
+    /// Relative to the destination.
+    pub(crate) path: &'e Path,
+    pub(crate) size: u64,
+    pub(crate) modified: Option<BackupTime>,
+    pub(crate) archive: &'e Path,
+    pub(crate) digest: &'e [u8],
+}
+
+/// Appends the lines of restored files to a manifest. Each line is one
+/// write to a file opened for appending, so runs writing the same manifest
+/// at once do not mix their lines.
+pub(crate) struct ManifestWriter {
+    file: Mutex<File>,
+    format: ManifestFormat,
+}
+
+impl ManifestWriter {
+    /// Starts a new manifest at `path`, replacing an existing one.
+    pub(crate) fn create(path: &Path, format: ManifestFormat) -> io::Result<ManifestWriter> {
+        File::create(path)?;
+        ManifestWriter::append(path, format)
+    }
+
+    /// Adds to the manifest at `path`, creating it if needed.
+    pub(crate) fn append(path: &Path, format: ManifestFormat) -> io::Result<ManifestWriter> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(ManifestWriter { file: Mutex::new(file), format })
+    }
+
+    pub(crate) fn add(&self, entry: &ManifestEntry) -> io::Result<()> {
+        let path = entry.path.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "/");
+        let hex: String = entry.digest.iter().map(|b| format!("{:02x}", b)).collect();
+        let escaped = path.contains(['\\', '\n']);
+        let escape = |path: &str| path.replace('\\', "\\\\").replace('\n', "\\n");
+        let line = match self.format {
+            ManifestFormat::Gnu if escaped => format!("\\{}  {}\n", hex, escape(&path)),
+            ManifestFormat::Gnu => format!("{}  {}\n", hex, path),
+            ManifestFormat::Bsd if escaped => format!("\\SHA256 ({}) = {}\n", escape(&path), hex),
+            ManifestFormat::Bsd => format!("SHA256 ({}) = {}\n", path, hex),
+            ManifestFormat::Json => {
+                let line = JsonLine {
+                    path: Cow::Borrowed(&path),
+                    size: Some(entry.size),
+                    modified: entry.modified.map(|t| t.to_string()),
+                    sha256: Cow::Borrowed(&hex),
+                    archive: Some(entry.archive.to_path_buf()),
+                };
+                let mut json = serde_json::to_string(&line)?;
+                json.push('\n');
+                json
+            }
+        };
+        self.file.lock().unwrap().write_all(line.as_bytes())
+    }
+}
+
+/// The outcome of [`verify_manifest`].
+#[derive(Debug, Default)]
+pub struct ManifestCheck {
+    /// Files whose content still matches.
+    pub verified: usize,
+    /// Files whose content differs from when they were restored.
+    pub mismatched: Vec<String>,
+    /// Files of the manifest no longer in the destination.
+    pub missing: Vec<String>,
+    /// Files that exist but could not be read.
+    pub unreadable: Vec<(String, io::Error)>,
+    /// Numbers of the manifest lines that could not be read as one.
+    pub malformed: Vec<usize>,
+    /// Files whose path is absolute or leads out of the destination with
+    /// `..`; they are not read.
+    pub outside: Vec<String>,
+}
+
+impl ManifestCheck {
+    /// Whether every file of the manifest is there, unchanged.
+    pub fn is_intact(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty() && self.unreadable.is_empty() && self.malformed.is_empty() && self.outside.is_empty()
+    }
+}
+
+/// Hashes the files listed in `manifest` below `dest` again and compares
+/// them with the manifest. Paths are checked as the names of entries are,
+/// so a manifest cannot have files outside `dest` read. Fails only if the
+/// manifest cannot be read.
+pub fn verify_manifest(manifest: &Path, dest: &Path) -> io::Result<ManifestCheck> {
+    let mut check = ManifestCheck::default();
+    let (files, malformed) = read_manifest(manifest)?;
+    check.malformed = malformed;
+    for (expected, path) in files {
+        let file = match normalize_entry(&path, AbsoluteEntries::Reject) {
+            Ok(normalized) => dest.join(&*normalized.name),
+            Err(_) => {
+                check.outside.push(path);
+                continue;
+            }
+        };
+        match fs::symlink_metadata(&file) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => check.missing.push(path),
+            Err(e) => check.unreadable.push((path, e)),
+            Ok(_) => match hash_file(&file) {
+                Ok(digest) if digest.eq_ignore_ascii_case(&expected) => check.verified += 1,
+                Ok(_) => check.mismatched.push(path),
+                Err(e) => check.unreadable.push((path, e)),
+            },
+        }
+    }
+    Ok(check)
+}
+
+/// The outcome of [`compare_manifests`].
+#[derive(Debug, Default)]
+pub struct ManifestDiff {
+    /// Files in both manifests with the same SHA-256.
+    pub identical: usize,
+    /// Files only in the second manifest.
+    pub added: Vec<String>,
+    /// Files only in the first manifest.
+    pub removed: Vec<String>,
+    /// Files in both manifests whose SHA-256 differs.
+    pub changed: Vec<String>,
+    /// Numbers of the lines of each manifest that could not be read as one.
+    pub malformed: (Vec<usize>, Vec<usize>),
+}
+
+impl ManifestDiff {
+    /// Whether both manifests list the same files with the same contents.
+    pub fn is_identical(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() && self.malformed.0.is_empty() && self.malformed.1.is_empty()
+    }
+}
+
+/// Compares two manifests, in any of the [`ManifestFormat`]s, such as those
+/// of restores of the same backup onto two machines, by path. Neither
+/// destination is read. The paths are sorted.
+pub fn compare_manifests(a: &Path, b: &Path) -> io::Result<ManifestDiff> {
+    let (a_files, a_malformed) = read_manifest(a)?;
+    let (b_files, b_malformed) = read_manifest(b)?;
+    let mut diff = ManifestDiff { malformed: (a_malformed, b_malformed), ..ManifestDiff::default() };
+    let mut remaining: HashMap<String, String> = b_files.into_iter().map(|(digest, path)| (path, digest)).collect();
+    for (digest, path) in a_files {
+        match remaining.remove(&path) {
+            None => diff.removed.push(path),
+            Some(other) if other.eq_ignore_ascii_case(&digest) => diff.identical += 1,
+            Some(_) => diff.changed.push(path),
+        }
+    }
+    diff.added = remaining.into_keys().collect();
+    diff.added.sort();
+    diff.removed.sort();
+    diff.changed.sort();
+    Ok(diff)
+}
+
+/// The hex digest and the path of a file of a manifest.
+type Listed = (String, String);
+
+/// The files of `manifest` with the digest of the last line of each, in
+/// the order they first appear, and the numbers of the malformed lines.
+fn read_manifest(manifest: &Path) -> io::Result<(Vec<Listed>, Vec<usize>)> {
+    let mut files: Vec<Listed> = Vec::new();
+    let mut malformed = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for (i, line) in BufReader::new(File::open(manifest)?).lines().enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let Some((expected, path)) = parse_line(&line) else {
+            malformed.push(i + 1);
+            continue;
+        };
+        match index.get(&path) {
+            Some(&j) => files[j].0 = expected,
+            None => {
+                index.insert(path.clone(), files.len());
+                files.push((expected, path));
+            }
+        }
+    }
+    Ok((files, malformed))
+}
+
+/// Splits a manifest line, in any of the [`ManifestFormat`]s, into the hex
+/// digest and the path. Accepts the `*` that `sha256sum --binary` puts
+/// before the path.
+fn parse_line(line: &str) -> Option<(String, String)> {
+    if line.starts_with('{') {
+        let line: JsonLine = serde_json::from_str(line).ok()?;
+        return is_digest(&line.sha256).then(|| (line.sha256.into_owned(), line.path.into_owned()));
+    }
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (digest, path) = match line.strip_prefix("SHA256 (") {
+        Some(rest) => {
+            let (path, digest) = rest.rsplit_once(") = ")?;
+            (digest, path)
+        }
+        None => {
+            let (digest, path) = line.split_once(' ')?;
+            (digest, path.strip_prefix([' ', '*'])?)
+        }
+    };
+    if !is_digest(digest) || path.is_empty() {
+        return None;
+    }
+    // Manifests written on Windows by other tools may have backslashes
+    // between folders.
+    let path = if escaped { unescape(path)? } else { path.replace('\\', "/") };
+    Some((digest.to_string(), path))
+}
+
+fn is_digest(digest: &str) -> bool {
+    digest.len() == 64 && digest.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn unescape(path: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(match c {
+            '\\' => match chars.next()? {
+                '\\' => '\\',
+                'n' => '\n',
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    Some(unescaped)
+}
+
+/// The hex SHA-256 of the file at `path`.
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
@@ -0,0 +1,54 @@
+//! Marking restored files NTFS-compressed on Windows, for
+//! [`crate::RestorerBuilder::ntfs_compress`], so a backup fits on a smaller
+//! disk than the one it came from. Only files of formats that are not
+//! compressed already are marked: NTFS would gain nothing on photos,
+//! videos or archives and only slow down reading them. Folders are left
+//! alone, as files created in a compressed folder are compressed whatever
+//! their type.
+
+use std::fs::File;
+
+/// Extensions of formats that compress their data themselves.
+const COMPRESSED: &[&str] = &[
+    "7z", "aac", "apk", "avi", "bz2", "cab", "docx", "epub", "flac", "gif", "gz", "heic", "jar", "jpeg", "jpg", "m4a",
+    "m4v", "mkv", "mov", "mp3", "mp4", "msi", "odp", "ods", "odt", "ogg", "opus", "pdf", "png", "pptx", "rar", "tgz",
+    "webm", "webp", "wma", "wmv", "xlsx", "xz", "zip", "zst",
+];
+
+/// Whether the file at restore path `name` is worth compressing.
+pub(crate) fn compressible(name: &str) -> bool {
+    let extension = name.rsplit_once('/').map_or(name, |(_, file)| file).rsplit_once('.').map(|(_, ext)| ext);
+    extension.is_none_or(|ext| !COMPRESSED.iter().any(|compressed| ext.eq_ignore_ascii_case(compressed)))
+}
+
+/// Marks `file` compressed, so what is written to it is. File systems
+/// without compression, such as FAT and most network shares, leave it as
+/// it is; so does every other platform.
+pub(crate) fn compress(file: &File) {
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::Storage::FileSystem::COMPRESSION_FORMAT_DEFAULT;
+        use windows_sys::Win32::System::IO::DeviceIoControl;
+        use windows_sys::Win32::System::Ioctl::FSCTL_SET_COMPRESSION;
+
+        let format = COMPRESSION_FORMAT_DEFAULT;
+        let mut returned = 0;
+        // SAFETY: `file` stays open across the call, which only reads
+        // `format` and writes `returned`.
+        unsafe {
+            DeviceIoControl(
+                file.as_raw_handle(),
+                FSCTL_SET_COMPRESSION,
+                &format as *const u16 as *const _,
+                size_of::<u16>() as u32,
+                std::ptr::null_mut(),
+                0,
+                &mut returned,
+                std::ptr::null_mut(),
+            )
+        };
+    }
+    #[cfg(not(windows))]
+    let _ = file;
+}
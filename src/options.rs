@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::analysis_cache::AnalysisCache;
+use crate::answers::Answers;
+use crate::filter::Filter;
+use crate::snapshot::LinkDest;
+
+/// What to do when a restored file already exists at the destination.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConflictPolicy {
+    /// Replace the existing file.
+    #[default]
+    Overwrite,
+    /// Keep the existing file and skip the entry.
+    Skip,
+    /// Restore next to the existing file as `name (1).ext`.
+    Rename,
+    /// Ask a [`crate::ConflictResolver`] about each file with other
+    /// contents; the same file, restored before, is skipped. Without a
+    /// resolver, existing files are kept.
+    Ask,
+}
+
+/// What to do with cloud-only placeholders, entries whose data was never in
+/// the backup, see [`crate::EntryInfo::placeholder`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PlaceholderPolicy {
+    /// Leave them out and count them.
+    #[default]
+    Skip,
+    /// Restore them as stored, as empty stubs.
+    Extract,
+    /// Leave them out and list their paths.
+    List,
+}
+
+/// What to do with system files: registry hives, page files and the
+/// contents of `Windows/System32`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SystemFilePolicy {
+    /// Leave them out and count them.
+    #[default]
+    Skip,
+    /// Restore them like any other file.
+    Extract,
+    /// Restore them below [`SystemFilePolicy::ISOLATED_DIR`] in the
+    /// destination, away from the user's files.
+    Isolate,
+}
+
+impl SystemFilePolicy {
+    /// Folder of the destination that [`SystemFilePolicy::Isolate`] restores
+    /// system files to.
+    pub const ISOLATED_DIR: &str = "System Files";
+}
+
+/// What to do with entries whose names are absolute, such as `/etc/hosts`
+/// or `C:\Users\bob\a.txt`, which archives made by other tools may hold.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum AbsoluteEntries {
+    /// Restore them below the destination, as if the name started after the
+    /// `/`; a drive letter stays, as in `C/Users/...` of Windows Backup.
+    #[default]
+    StripRoot,
+    /// Leave them out and count them.
+    Reject,
+}
+
+/// Order in which the entries of an archive are extracted. Archives are
+/// always processed in sequence, so later archives still win conflicts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExtractionOrder {
+    /// As stored in the archive.
+    #[default]
+    Archive,
+    /// Smallest files first, so most files are restored early on.
+    SmallFirst,
+    /// Grouped by destination directory, which creates each directory's
+    /// files in one go and reduces metadata churn on the filesystem.
+    ByDir,
+}
+
+/// How restored files are laid out in the destination.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DestLayout {
+    /// At their paths from the backup.
+    #[default]
+    Tree,
+    /// Content-addressed: each distinct content is stored once, in
+    /// [`DestLayout::OBJECTS_DIR`] under its SHA-256 as `ab/cdef...`, and
+    /// [`DestLayout::TREE_FILE`] maps the paths from the backup to it.
+    Cas,
+}
+
+impl DestLayout {
+    /// Folder of the destination holding the contents with
+    /// [`DestLayout::Cas`].
+    pub const OBJECTS_DIR: &str = "objects";
+    /// File of the destination listing the paths of a [`DestLayout::Cas`]
+    /// restore with the SHA-256 of their content, as JSON lines in the
+    /// format of [`ManifestFormat::Json`].
+    pub const TREE_FILE: &str = "tree.jsonl";
+}
+
+/// Layout of the manifest written with [`crate::RestorerBuilder::manifest`].
+/// [`crate::verify_manifest`] reads all of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ManifestFormat {
+    /// `<digest>  <path>`, as written and checked by GNU `sha256sum`.
+    #[default]
+    Gnu,
+    /// `SHA256 (<path>) = <digest>`, as written by the BSD `sha256` and
+    /// `sha256sum --tag`.
+    Bsd,
+    /// One JSON object per line with the path, size, modification time,
+    /// SHA-256 and source archive of each file.
+    Json,
+}
+
+/// When restored files are flushed to the disk, trading speed for what a
+/// crash or power cut during or after the restore can take away.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SyncPolicy {
+    /// Leave it to the operating system, which writes the data out within
+    /// seconds to minutes.
+    #[default]
+    None,
+    /// Flush every file and its folder before counting it restored.
+    PerFile,
+    /// Flush the files of each archive once it is done.
+    PerZip,
+    /// Flush all files once, at the end of the run.
+    Final,
+}
+
+/// Whether restored files carry the `com.apple.quarantine` attribute that
+/// makes Gatekeeper check them when first opened on macOS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MacQuarantine {
+    /// Set it on executables, installers, scripts and whatever is in a
+    /// `Downloads` folder, as if they had just been downloaded.
+    Set,
+    /// Clear it from every restored file, such as those overwritten.
+    Clear,
+}
+
+/// Settings shared by every archive of a run, set through [`crate::RestorerBuilder`].
+#[derive(Debug, Clone)]
+pub(crate) struct Options {
+    pub(crate) filter: Filter,
+    pub(crate) all_runs: bool,
+    pub(crate) archive: Option<String>,
+    pub(crate) skip_archives: usize,
+    pub(crate) conflict_policy: ConflictPolicy,
+    pub(crate) answers: Option<Answers>,
+    pub(crate) placeholders: PlaceholderPolicy,
+    pub(crate) system_files: SystemFilePolicy,
+    pub(crate) absolute_entries: AbsoluteEntries,
+    pub(crate) order: ExtractionOrder,
+    pub(crate) threads: usize,
+    pub(crate) decode_threads: usize,
+    pub(crate) write_threads: usize,
+    /// Files larger than this are written past the page cache.
+    pub(crate) direct_io: Option<u64>,
+    pub(crate) temp_dir: Option<PathBuf>,
+    pub(crate) sync: SyncPolicy,
+    pub(crate) preallocate: bool,
+    pub(crate) ntfs_compress: bool,
+    pub(crate) make_readonly: bool,
+    /// User and group ids to hand restored files to.
+    pub(crate) owner: Option<(Option<u32>, Option<u32>)>,
+    pub(crate) file_mode: Option<u32>,
+    pub(crate) dir_mode: Option<u32>,
+    pub(crate) preserve_times: bool,
+    pub(crate) source_tz: Option<chrono_tz::Tz>,
+    pub(crate) selinux_relabel: bool,
+    pub(crate) mac_quarantine: Option<MacQuarantine>,
+    pub(crate) mmap: bool,
+    pub(crate) analysis_cache: Option<AnalysisCache>,
+    pub(crate) prefetch: bool,
+    pub(crate) warm_cache: bool,
+    pub(crate) drop_caches: bool,
+    pub(crate) fail_fast: bool,
+    pub(crate) mirrors: Vec<PathBuf>,
+    pub(crate) manifest: Option<PathBuf>,
+    pub(crate) manifest_format: ManifestFormat,
+    pub(crate) audit_log: Option<PathBuf>,
+    pub(crate) dest_layout: DestLayout,
+    pub(crate) subdir_per_archive: bool,
+    pub(crate) keep_drives: bool,
+    pub(crate) keep_apple_double: bool,
+    /// Folders to restore drives to, by upper-case drive letter.
+    pub(crate) drive_map: BTreeMap<char, PathBuf>,
+    /// Old and new names of renamed user profiles.
+    pub(crate) user_map: Vec<(String, String)>,
+    pub(crate) remap_shortcuts: bool,
+    pub(crate) link_dest: Option<LinkDest>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            filter: Filter::default(),
+            all_runs: false,
+            archive: None,
+            skip_archives: 0,
+            conflict_policy: ConflictPolicy::default(),
+            answers: None,
+            placeholders: PlaceholderPolicy::default(),
+            system_files: SystemFilePolicy::default(),
+            absolute_entries: AbsoluteEntries::default(),
+            order: ExtractionOrder::default(),
+            threads: 1,
+            decode_threads: std::thread::available_parallelism().map_or(1, |n| n.get().min(8)),
+            write_threads: 4,
+            direct_io: None,
+            temp_dir: None,
+            sync: SyncPolicy::default(),
+            preallocate: false,
+            ntfs_compress: false,
+            make_readonly: false,
+            owner: None,
+            file_mode: None,
+            dir_mode: None,
+            preserve_times: true,
+            source_tz: None,
+            selinux_relabel: false,
+            mac_quarantine: None,
+            mmap: false,
+            analysis_cache: None,
+            prefetch: true,
+            warm_cache: false,
+            drop_caches: false,
+            fail_fast: false,
+            mirrors: Vec::new(),
+            manifest: None,
+            manifest_format: ManifestFormat::default(),
+            audit_log: None,
+            dest_layout: DestLayout::default(),
+            subdir_per_archive: false,
+            keep_drives: false,
+            keep_apple_double: false,
+            drive_map: BTreeMap::new(),
+            user_map: Vec::new(),
+            remap_shortcuts: false,
+            link_dest: None,
+        }
+    }
+}
@@ -0,0 +1,33 @@
+//! Owners and modes of restored files on Unix, for
+//! [`crate::RestorerBuilder::owner`], [`crate::RestorerBuilder::file_mode`]
+//! and [`crate::RestorerBuilder::dir_mode`]. Elsewhere files keep the owner
+//! that created them and the permissions they inherit.
+
+use std::io;
+use std::path::Path;
+
+/// Changes the owner of `path` to user `uid` and group `gid`, keeping what
+/// is `None`.
+pub(crate) fn chown(path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+    #[cfg(unix)]
+    return std::os::unix::fs::chown(path, uid, gid);
+    #[cfg(not(unix))]
+    {
+        let _ = (path, uid, gid);
+        Ok(())
+    }
+}
+
+/// Sets the permission bits of `path` to `mode`.
+pub(crate) fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (path, mode);
+        Ok(())
+    }
+}
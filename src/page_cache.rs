@@ -0,0 +1,129 @@
+//! Advice to the kernel about the page cache. Archives are read and files
+//! written front to back, so the kernel reads ahead further and, on
+//! Windows, keeps fewer of the pages it passed; the cache manager's
+//! default otherwise thrashes on backup drives that are hard disks. With
+//! [`crate::RestorerBuilder::drop_caches`], large restored files and the
+//! archives once done leave the cache on Linux, so a terabyte restore does
+//! not push out everything else the system keeps there. Advice the kernel
+//! does not take is ignored.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Files from this size on are dropped from the cache while they are
+/// written, a window of this size at a time.
+pub(crate) const LARGE_FILE: u64 = 8 << 20;
+
+/// `FILE_FLAG_SEQUENTIAL_SCAN`.
+#[cfg(windows)]
+const SEQUENTIAL_SCAN: u32 = 0x0800_0000;
+
+/// Opens the archive at `path` to be read sequentially.
+pub(crate) fn open_sequential(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.read(true);
+    #[cfg(windows)]
+    std::os::windows::fs::OpenOptionsExt::custom_flags(&mut options, SEQUENTIAL_SCAN);
+    let file = options.open(path)?;
+    #[cfg(target_os = "linux")]
+    advise(&file, 0, 0, libc::POSIX_FADV_SEQUENTIAL);
+    Ok(file)
+}
+
+/// Creates the file at `path`, or truncates it, to be written
+/// sequentially, as [`File::create`] does.
+pub(crate) fn create_sequential(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(windows)]
+    std::os::windows::fs::OpenOptionsExt::custom_flags(&mut options, SEQUENTIAL_SCAN);
+    options.open(path)
+}
+
+/// Drops the cached pages of the file at `path`, one no longer needed.
+pub(crate) fn drop_file(path: &Path) {
+    #[cfg(target_os = "linux")]
+    if let Ok(file) = File::open(path) {
+        advise(&file, 0, 0, libc::POSIX_FADV_DONTNEED);
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = path;
+}
+
+/// A file being written that drops its pages from the cache as they reach
+/// the disk. Dirty pages cannot be dropped, so each window is written out
+/// in the background once full and dropped after the next one is.
+pub(crate) struct Evicting {
+    file: File,
+    written: u64,
+    /// Where the window being filled starts; the one before is being
+    /// written out.
+    window: u64,
+    /// How far the file is dropped already.
+    dropped: u64,
+}
+
+impl Evicting {
+    pub(crate) fn new(file: File) -> Evicting {
+        Evicting { file, written: 0, window: 0, dropped: 0 }
+    }
+}
+
+impl Write for Evicting {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        if self.written - self.window >= LARGE_FILE {
+            if self.window > self.dropped {
+                write_out(&self.file, self.dropped, self.window - self.dropped, true);
+                drop_range(&self.file, self.dropped, self.window - self.dropped);
+            }
+            write_out(&self.file, self.window, self.written - self.window, false);
+            (self.dropped, self.window) = (self.window, self.written);
+        }
+        Ok(n)
+    }
+
+    /// Writes out and drops what is left.
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        write_out(&self.file, self.dropped, 0, true);
+        drop_range(&self.file, 0, 0);
+        Ok(())
+    }
+}
+
+/// Starts writing out `len` bytes of `file` from `offset`, to its end for
+/// 0, and waits for them if `wait` is set.
+#[cfg(target_os = "linux")]
+fn write_out(file: &File, offset: u64, len: u64, wait: bool) {
+    use std::os::fd::AsRawFd;
+
+    let flags = if wait {
+        libc::SYNC_FILE_RANGE_WAIT_BEFORE | libc::SYNC_FILE_RANGE_WRITE | libc::SYNC_FILE_RANGE_WAIT_AFTER
+    } else {
+        libc::SYNC_FILE_RANGE_WRITE
+    };
+    // SAFETY: `file` stays open across the call, which only takes advice.
+    unsafe { libc::sync_file_range(file.as_raw_fd(), offset as libc::off64_t, len as libc::off64_t, flags) };
+}
+
+#[cfg(not(target_os = "linux"))]
+fn write_out(_: &File, _: u64, _: u64, _: bool) {}
+
+#[cfg(target_os = "linux")]
+fn drop_range(file: &File, offset: u64, len: u64) {
+    advise(file, offset, len, libc::POSIX_FADV_DONTNEED);
+}
+
+#[cfg(not(target_os = "linux"))]
+fn drop_range(_: &File, _: u64, _: u64) {}
+
+#[cfg(target_os = "linux")]
+fn advise(file: &File, offset: u64, len: u64, advice: libc::c_int) {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: as for `write_out`.
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), offset as libc::off_t, len as libc::off_t, advice) };
+}
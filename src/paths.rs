@@ -0,0 +1,177 @@
+use std::borrow::Cow;
+
+use crate::options::AbsoluteEntries;
+
+/// Removes a leading Windows drive letter such as `C/` or `C\` from an entry name.
+pub fn strip_drive_letter(path: &str) -> &str {
+    let bytes = path.as_bytes();
+    // Match patterns like "C/" or "C\" at start
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && (bytes[1] == b'/' || bytes[1] == b'\\') {
+        &path[2..]
+    } else {
+        path
+    }
+}
+
+/// The drive letter [`strip_drive_letter`] removes from `path`, in upper case.
+pub(crate) fn drive_letter(path: &str) -> Option<char> {
+    let stripped = path.len() - strip_drive_letter(path).len();
+    (stripped > 0).then(|| path.as_bytes()[0].to_ascii_uppercase() as char)
+}
+
+/// The Windows user whose profile `path` lies in, the folder below `Users`
+/// or `Documents and Settings`.
+pub(crate) fn profile_owner(path: &str) -> Option<&str> {
+    let components: Vec<&str> = path.split('/').collect();
+    let (_, dirs) = components.split_last()?;
+    dirs.windows(2)
+        .find(|w| w[0].eq_ignore_ascii_case("users") || w[0].eq_ignore_ascii_case("documents and settings"))
+        .map(|w| w[1])
+}
+
+/// Why an entry is not restored, see [`normalize_entry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Unsafe {
+    /// A `..` segment, another of only dots and spaces, which Windows
+    /// takes for one, or one with a `:`, which Windows takes for a drive or
+    /// an alternate data stream.
+    Traversal,
+    /// An absolute name, with [`AbsoluteEntries::Reject`].
+    Absolute,
+    /// Nothing left of the name.
+    Empty,
+}
+
+impl Unsafe {
+    pub(crate) fn reason(self) -> &'static str {
+        match self {
+            Unsafe::Traversal => "leads out of the destination",
+            Unsafe::Absolute => "absolute path",
+            Unsafe::Empty => "empty name",
+        }
+    }
+}
+
+/// An entry name as restored, see [`normalize_entry`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Normalized<'n> {
+    pub(crate) name: Cow<'n, str>,
+    /// The name was absolute and its root removed.
+    pub(crate) absolute: bool,
+    /// `.` segments, repeated separators or trailing dots and spaces were
+    /// removed.
+    pub(crate) cleaned: bool,
+}
+
+/// Makes the entry name `name` safe to join to the destination: `/` between
+/// folders, without `.` segments, empty segments of repeated separators or
+/// the trailing dots and spaces Windows drops from file names. Absolute
+/// names lose their root, or are [`Unsafe::Absolute`] with
+/// [`AbsoluteEntries::Reject`]; `C:\` becomes `C/`. Names with `..`, or a
+/// `:` past the drive letter, are [`Unsafe::Traversal`].
+pub(crate) fn normalize_entry(name: &str, absolute: AbsoluteEntries) -> Result<Normalized<'_>, Unsafe> {
+    let slashed = if name.contains('\\') { Cow::Owned(name.replace('\\', "/")) } else { Cow::Borrowed(name) };
+    let bytes = slashed.as_bytes();
+    let (drive, rest) = if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        (Some(bytes[0] as char), &slashed[2..])
+    } else {
+        (None, &slashed[..])
+    };
+    let relative = rest.trim_start_matches('/');
+    let is_absolute = drive.is_some() || relative.len() < rest.len();
+    // A trailing `/` marks a folder rather than an empty segment.
+    let relative = relative.strip_suffix('/').unwrap_or(relative);
+    if is_absolute && absolute == AbsoluteEntries::Reject {
+        return Err(Unsafe::Absolute);
+    }
+    let mut segments = Vec::new();
+    let mut cleaned = false;
+    for segment in relative.split('/') {
+        if segment.is_empty() || segment == "." {
+            cleaned = true;
+            continue;
+        }
+        let trimmed = segment.trim_end_matches(['.', ' ']);
+        if trimmed.is_empty() || trimmed.contains(':') {
+            return Err(Unsafe::Traversal);
+        }
+        cleaned |= trimmed.len() < segment.len();
+        segments.push(trimmed);
+    }
+    if segments.is_empty() {
+        return Err(Unsafe::Empty);
+    }
+    if !cleaned && !is_absolute && relative.len() == slashed.len() {
+        return Ok(Normalized { name: slashed, absolute: false, cleaned: false });
+    }
+    let joined = segments.join("/");
+    let name = match drive {
+        Some(drive) => format!("{}/{}", drive, joined),
+        None => joined,
+    };
+    Ok(Normalized { name: Cow::Owned(name), absolute: is_absolute, cleaned })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The name and the `absolute` and `cleaned` flags, which the summary
+    /// counts as absolute and normalized entries.
+    fn normalize(name: &str, absolute: AbsoluteEntries) -> Result<(String, bool, bool), Unsafe> {
+        normalize_entry(name, absolute).map(|normalized| (normalized.name.into_owned(), normalized.absolute, normalized.cleaned))
+    }
+
+    fn ok(name: &str, absolute: bool, cleaned: bool) -> Result<(String, bool, bool), Unsafe> {
+        Ok((name.to_string(), absolute, cleaned))
+    }
+
+    #[test]
+    fn relative_names_are_kept() {
+        assert_eq!(normalize("Users/bob/a.txt", AbsoluteEntries::StripRoot), ok("Users/bob/a.txt", false, false));
+        assert_eq!(normalize(r"Users\bob\a.txt", AbsoluteEntries::Reject), ok("Users/bob/a.txt", false, false));
+        assert_eq!(normalize("a/.hidden/..b", AbsoluteEntries::StripRoot), ok("a/.hidden/..b", false, false));
+        assert!(matches!(normalize_entry("a/b", AbsoluteEntries::StripRoot).unwrap().name, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn traversal_is_rejected() {
+        for name in ["../x", "a/../../x", "a/..", r"a\..\x", "a/.../b", "a/ . /b", "a/. ./b", "a/.. /b", "C/D:/x", r"C:\D:\x", "a/b.txt:stream", "ab:c"] {
+            assert_eq!(normalize(name, AbsoluteEntries::StripRoot), Err(Unsafe::Traversal), "{}", name);
+        }
+    }
+
+    #[test]
+    fn absolute_names_lose_their_root() {
+        let strip = AbsoluteEntries::StripRoot;
+        assert_eq!(normalize("/etc/hosts", strip), ok("etc/hosts", true, false));
+        assert_eq!(normalize(r"\\server\share\a.txt", strip), ok("server/share/a.txt", true, false));
+        assert_eq!(normalize(r"C:\x", strip), ok("C/x", true, false));
+        assert_eq!(normalize("C:x", strip), ok("C/x", true, false));
+        assert_eq!(normalize("/a//b", strip), ok("a/b", true, true));
+    }
+
+    #[test]
+    fn absolute_names_are_rejected_if_asked() {
+        for name in ["/etc/hosts", r"\\server\share", r"C:\x", "C:x"] {
+            assert_eq!(normalize(name, AbsoluteEntries::Reject), Err(Unsafe::Absolute), "{}", name);
+        }
+    }
+
+    #[test]
+    fn names_are_cleaned() {
+        let strip = AbsoluteEntries::StripRoot;
+        assert_eq!(normalize("a//b/./c", strip), ok("a/b/c", false, true));
+        assert_eq!(normalize("./a", strip), ok("a", false, true));
+        assert_eq!(normalize("a. /b.txt. ", strip), ok("a/b.txt", false, true));
+        assert_eq!(normalize("dir/", strip), ok("dir", false, false));
+        assert_eq!(normalize("dir//", strip), ok("dir", false, true));
+    }
+
+    #[test]
+    fn names_without_segments_are_empty() {
+        for name in ["", "/", ".", "./", "//./", "C:"] {
+            assert_eq!(normalize(name, AbsoluteEntries::StripRoot), Err(Unsafe::Empty), "{:?}", name);
+        }
+    }
+}
@@ -0,0 +1,1681 @@
+//! Staged extraction: readers pull compressed entry data out of the
+//! archives, decoders decompress and verify it, writers create the files.
+//!
+//! The stages run in their own thread pools connected by bounded channels,
+//! so a slow destination no longer stalls decompression and vice versa.
+//! Entry data travels in chunks; small entries fit in a single chunk, large
+//! ones are streamed over a per-entry channel, which keeps memory bounded
+//! regardless of entry size.
+//!
+//! Readers share archives: each one reads through its own clone of the
+//! archive and picks up the next unread entry, so a single huge archive is
+//! read by all readers at once instead of by one thread.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use zip::ZipArchive;
+
+use crate::allocate;
+use crate::answers::OnError;
+use crate::archive::{self, ArchiveKind, ArchiveReader, Member};
+use crate::audit::{Action, AuditLog};
+use crate::catalog::BackupTime;
+use crate::central_dir::CentralDirectory;
+use crate::conflict::{Conflict, same_contents};
+use crate::decode::{self, CrcReader};
+use crate::direct::DirectFile;
+use crate::durability;
+use crate::error::{ArchiveError, EntryError, ErrorKind, Operation};
+use crate::options::{ConflictPolicy, DestLayout, ExtractionOrder, PlaceholderPolicy, SyncPolicy};
+use crate::efs;
+use crate::file_times::{DirTimes, Timestamps};
+use crate::mac_quarantine;
+use crate::manifest::{ManifestEntry, ManifestWriter};
+use crate::ntfs_compression;
+use crate::owner;
+use crate::page_cache::{self, Evicting};
+use crate::placeholder::is_cloud_placeholder;
+use crate::paths::{normalize_entry, strip_drive_letter};
+use crate::remap::{MAX_SHORTCUT_SIZE, remap_shortcut};
+use crate::progress::ProgressWriter;
+use crate::reader::ArchiveFile;
+use crate::restorer::{Admission, ArchiveStats, QUARANTINE_DIR, Restorer, serialize_secs};
+#[cfg(all(feature = "uring", target_os = "linux"))]
+use crate::uring;
+
+const CHUNK_SIZE: usize = 256 * 1024;
+/// Chunks buffered per streamed entry between two stages.
+const CHUNK_QUEUE: usize = 4;
+
+/// Depth of one bounded queue between two stages. The depth counts entries
+/// waiting for the next stage, including producers blocked on a full queue,
+/// so a queue that is often at capacity points at the slower consumer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct QueueStats {
+    pub capacity: usize,
+    pub max_depth: usize,
+    pub mean_depth: f64,
+}
+
+/// Thread counts and queue depths of a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct PipelineStats {
+    pub readers: usize,
+    pub decoders: usize,
+    pub writers: usize,
+    /// Between readers and decoders.
+    pub decode_queue: QueueStats,
+    /// Between decoders and writers.
+    pub write_queue: QueueStats,
+}
+
+impl PipelineStats {
+    /// Combines the stats of two runs, e.g. archives extracted separately.
+    pub fn merge(&mut self, other: &PipelineStats) {
+        fn merge_queue(a: &mut QueueStats, b: &QueueStats) {
+            a.capacity = a.capacity.max(b.capacity);
+            a.max_depth = a.max_depth.max(b.max_depth);
+            a.mean_depth = a.mean_depth.max(b.mean_depth);
+        }
+        self.readers = self.readers.max(other.readers);
+        self.decoders = self.decoders.max(other.decoders);
+        self.writers = self.writers.max(other.writers);
+        merge_queue(&mut self.decode_queue, &other.decode_queue);
+        merge_queue(&mut self.write_queue, &other.write_queue);
+    }
+}
+
+/// How the extraction order played out: directory switches count how often
+/// a restored file landed in a different directory than the one before, the
+/// mean completion time is how long after the start a file was restored on
+/// average.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct OrderStats {
+    pub order: ExtractionOrder,
+    pub dir_switches: usize,
+    #[serde(rename = "mean_completion_secs", serialize_with = "serialize_secs")]
+    pub mean_completion: Duration,
+}
+
+#[derive(Default)]
+struct Completions {
+    last_dir: Option<PathBuf>,
+    dir_switches: usize,
+    files: u32,
+    total: Duration,
+}
+
+struct Gauge {
+    capacity: usize,
+    current: AtomicUsize,
+    max: AtomicUsize,
+    samples: AtomicU64,
+    total: AtomicU64,
+}
+
+impl Gauge {
+    fn new(capacity: usize) -> Self {
+        Gauge {
+            capacity,
+            current: AtomicUsize::new(0),
+            max: AtomicUsize::new(0),
+            samples: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+        }
+    }
+
+    fn send<T>(&self, tx: &SyncSender<T>, item: T) -> bool {
+        let depth = self.current.fetch_add(1, Ordering::Relaxed) + 1;
+        self.max.fetch_max(depth, Ordering::Relaxed);
+        self.samples.fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(depth as u64, Ordering::Relaxed);
+        let sent = tx.send(item).is_ok();
+        if !sent {
+            self.current.fetch_sub(1, Ordering::Relaxed);
+        }
+        sent
+    }
+
+    fn recv<T>(&self, rx: &Mutex<Receiver<T>>) -> Option<T> {
+        let item = rx.lock().unwrap().recv().ok()?;
+        self.current.fetch_sub(1, Ordering::Relaxed);
+        Some(item)
+    }
+
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    fn recv_timeout<T>(&self, rx: &Mutex<Receiver<T>>, timeout: Duration) -> Result<T, mpsc::RecvTimeoutError> {
+        let item = rx.lock().unwrap().recv_timeout(timeout)?;
+        self.current.fetch_sub(1, Ordering::Relaxed);
+        Ok(item)
+    }
+
+    fn stats(&self) -> QueueStats {
+        let samples = self.samples.load(Ordering::Relaxed);
+        QueueStats {
+            capacity: self.capacity,
+            max_depth: self.max.load(Ordering::Relaxed),
+            mean_depth: if samples == 0 { 0.0 } else { self.total.load(Ordering::Relaxed) as f64 / samples as f64 },
+        }
+    }
+}
+
+/// Fails reads with the error it holds, once.
+#[cfg(all(feature = "uring", target_os = "linux"))]
+struct Failing(Option<io::Error>);
+
+#[cfg(all(feature = "uring", target_os = "linux"))]
+impl Read for Failing {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        self.0.take().map_or(Ok(0), Err)
+    }
+}
+
+/// Entry data handed from one stage to the next: the first chunk, plus a
+/// channel for the remaining chunks when the data did not fit.
+struct Chunks {
+    current: io::Cursor<Vec<u8>>,
+    rest: Option<Receiver<io::Result<Vec<u8>>>>,
+}
+
+impl Read for Chunks {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.current.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            let Some(rest) = &self.rest else { return Ok(0) };
+            match rest.recv() {
+                Ok(chunk) => self.current = io::Cursor::new(chunk?),
+                Err(_) => {
+                    self.rest = None;
+                    return Ok(0);
+                }
+            }
+        }
+    }
+}
+
+fn read_full<R: Read>(src: &mut R, buf: &mut Vec<u8>) -> io::Result<bool> {
+    buf.resize(CHUNK_SIZE, 0);
+    let mut filled = 0;
+    while filled < buf.len() {
+        match src.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                buf.truncate(filled);
+                return Err(e);
+            }
+        }
+    }
+    buf.truncate(filled);
+    Ok(filled == CHUNK_SIZE)
+}
+
+/// Reads `src` in chunks and passes them on through `hand_over`. Data that
+/// fits into one chunk is handed over complete; otherwise the remaining
+/// chunks follow over a bounded channel. Read errors are forwarded so the
+/// consuming stage reports them.
+fn stream<R: Read>(mut src: R, hand_over: impl FnOnce(Chunks) -> bool) {
+    let mut first = Vec::new();
+    let more = match read_full(&mut src, &mut first) {
+        Ok(more) => more,
+        Err(e) => {
+            let (tx, rx) = mpsc::sync_channel(1);
+            let _ = tx.send(Err(e));
+            hand_over(Chunks { current: io::Cursor::new(first), rest: Some(rx) });
+            return;
+        }
+    };
+    if !more {
+        hand_over(Chunks { current: io::Cursor::new(first), rest: None });
+        return;
+    }
+    let (tx, rx) = mpsc::sync_channel(CHUNK_QUEUE);
+    if !hand_over(Chunks { current: io::Cursor::new(first), rest: Some(rx) }) {
+        return;
+    }
+    loop {
+        let mut chunk = Vec::new();
+        match read_full(&mut src, &mut chunk) {
+            Ok(more) => {
+                if !chunk.is_empty() && tx.send(Ok(chunk)).is_err() {
+                    return;
+                }
+                if !more {
+                    return;
+                }
+            }
+            Err(e) => {
+                let _ = tx.send(Err(e));
+                return;
+            }
+        }
+    }
+}
+
+struct ArchiveRun<'a> {
+    path: &'a Path,
+    /// Where its entries go.
+    dest: PathBuf,
+    stats: Mutex<ArchiveStats>,
+    /// Entries in flight plus one per reader still enumerating.
+    pending: AtomicUsize,
+    /// Index of the next entry a reader picks up.
+    next_entry: AtomicUsize,
+    /// When the run turned to the archive.
+    started: Mutex<Option<Instant>>,
+    result: Mutex<Option<Result<ArchiveStats, ArchiveError>>>,
+    /// Files written and not yet flushed, with [`SyncPolicy::PerZip`] and
+    /// [`SyncPolicy::Final`].
+    unsynced: Mutex<Vec<PathBuf>>,
+}
+
+/// One entry travelling through the stages. Dropping it, wherever that
+/// happens, releases its destination path and completes the archive once
+/// its last entry is done.
+struct Ticket<'a> {
+    run: &'a Run<'a>,
+    archive: usize,
+    name: String,
+    target: PathBuf,
+    /// The attributes mark the entry as EFS-encrypted.
+    efs: bool,
+    /// The modification time the archive records, for the manifest.
+    modified: Option<BackupTime>,
+    /// The exact times of its extra fields, if any.
+    times: Option<Timestamps>,
+    /// Whether the target existed, for the audit log.
+    action: Action,
+}
+
+impl Drop for Ticket<'_> {
+    fn drop(&mut self) {
+        self.run.release_target(&self.target);
+        self.run.entry_done(self.archive);
+    }
+}
+
+struct DecodeJob<'a> {
+    ticket: Ticket<'a>,
+    /// `None` when the reader already decoded the data.
+    method: Option<u16>,
+    crc32: u32,
+    size: u64,
+    data: Chunks,
+}
+
+struct WriteJob<'a> {
+    ticket: Ticket<'a>,
+    /// The size the archive records.
+    size: u64,
+    data: Chunks,
+}
+
+/// An opened archive: a ZIP file, or any other kind read through its
+/// [`ArchiveReader`].
+#[derive(Clone)]
+enum Source {
+    /// A ZIP file and what its central directory records tell of its
+    /// entries.
+    Zip(ZipArchive<ArchiveFile>, Arc<Marked>),
+    Reader(Arc<dyn ArchiveReader>),
+}
+
+/// Names of the entries of a ZIP file that are cloud-only placeholders or
+/// EFS-encrypted.
+#[derive(Default)]
+struct Marked {
+    placeholders: HashSet<String>,
+    efs: HashSet<String>,
+}
+
+impl Source {
+    fn open(restorer: &Restorer, path: &Path) -> Result<Source, ArchiveError> {
+        match ArchiveKind::of(path).unwrap_or(ArchiveKind::Zip) {
+            ArchiveKind::Zip => {
+                let archive = restorer.open_archive(path)?;
+                let mut marked = Marked::default();
+                if let Ok(records) = CentralDirectory::open(archive.clone().into_inner()) {
+                    for record in records.map_while(Result::ok) {
+                        if efs::is_encrypted(record.windows_attributes()) {
+                            marked.efs.insert(record.name.clone());
+                        }
+                        if restorer.options.placeholders != PlaceholderPolicy::Extract && record.is_cloud_placeholder() {
+                            marked.placeholders.insert(record.name);
+                        }
+                    }
+                }
+                Ok(Source::Zip(archive, Arc::new(marked)))
+            }
+            kind => archive::open(kind, path, restorer.options.mmap).map(Source::Reader).map_err(|e| ArchiveError::io(path, e)),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Source::Zip(archive, _) => archive.len(),
+            Source::Reader(reader) => reader.len(),
+        }
+    }
+
+    fn name(&self, j: usize) -> Option<&str> {
+        match self {
+            Source::Zip(archive, _) => archive.name_for_index(j),
+            Source::Reader(reader) => (j < reader.len()).then(|| reader.member(j).name),
+        }
+    }
+
+    fn sequential(&self) -> bool {
+        matches!(self, Source::Reader(reader) if reader.sequential())
+    }
+}
+
+type Opened = (usize, Result<Source, ArchiveError>);
+
+struct Run<'a> {
+    restorer: &'a Restorer,
+    /// Where the SHA-256 of each restored file goes.
+    manifest: Option<&'a ManifestWriter>,
+    audit: Option<&'a AuditLog>,
+    /// The tree file of [`DestLayout::Cas`].
+    tree: Option<&'a ManifestWriter>,
+    /// Numbers the files a [`DestLayout::Cas`] restore writes before it
+    /// knows their hash.
+    partials: AtomicUsize,
+    archives: Vec<ArchiveRun<'a>>,
+    /// Destination paths with an entry in flight. A later entry for the
+    /// same path waits, so archives keep overwriting in order.
+    in_flight: Mutex<HashSet<PathBuf>>,
+    released: Condvar,
+    /// Held while the conflict resolver answers, so it is asked one
+    /// question at a time.
+    asking: Mutex<()>,
+    next_archive: AtomicUsize,
+    prefetched: Option<Mutex<Receiver<Opened>>>,
+    /// The archive readers currently pick entries from.
+    current: Mutex<Option<Current>>,
+    started: Instant,
+    completions: Mutex<Completions>,
+    /// Folders the run created and has not given their owner and mode yet,
+    /// see [`Run::adopt`].
+    created: Mutex<HashSet<PathBuf>>,
+    dir_times: DirTimes,
+}
+
+struct Current {
+    index: usize,
+    archive: Source,
+    order: Arc<[usize]>,
+}
+
+impl<'a> Run<'a> {
+    fn fail(&self, archive: usize, error: EntryError) {
+        self.restorer.progress.error(&error);
+        if let Some(audit) = self.audit {
+            // An error of the audit log itself would only come back here.
+            let _ = audit.record(Action::Error, &error.archive, Some(&error.entry), None, Some(&error.to_string()));
+        }
+        let answer = self.restorer.options.answers.as_ref().and_then(|answers| answers.on_error(&error.entry));
+        self.archives[archive].stats.lock().unwrap().errors.push(error);
+        match answer {
+            Some(OnError::Stop) => self.restorer.cancel.cancel(),
+            Some(OnError::Continue) => {}
+            None => self.stop_if_fail_fast(),
+        }
+    }
+
+    /// Records the decision on entry `name` of archive `i` in the audit log.
+    /// Failing to is an error of the entry.
+    fn audit(&self, i: usize, action: Action, name: &str, target: Option<&Path>, reason: Option<&str>) {
+        let Some(audit) = self.audit else { return };
+        let path = self.archives[i].path;
+        if let Err(e) = audit.record(action, path, Some(name), target, reason) {
+            let e = io::Error::new(e.kind(), format!("audit log: {}", e));
+            self.fail(i, EntryError::io(path, name, Operation::Write, e));
+        }
+    }
+
+    /// Cancels the run after an error with [`crate::RestorerBuilder::fail_fast`].
+    fn stop_if_fail_fast(&self) {
+        if self.restorer.options.fail_fast {
+            self.restorer.cancel.cancel();
+        }
+    }
+
+    fn entry_done(&self, archive: usize) {
+        let run = &self.archives[archive];
+        if run.pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+            if self.restorer.options.sync == SyncPolicy::PerZip {
+                let files = std::mem::take(&mut *run.unsynced.lock().unwrap());
+                for e in settle(run.path, &files, self.restorer.options.make_readonly) {
+                    self.fail(archive, e);
+                }
+            }
+            if self.restorer.options.drop_caches {
+                page_cache::drop_file(run.path);
+            }
+            let mut stats = std::mem::take(&mut *run.stats.lock().unwrap());
+            stats.elapsed = run.started.lock().unwrap().map_or_else(Duration::default, |started| started.elapsed());
+            self.restorer.progress.archive_finished(run.path, &stats);
+            *run.result.lock().unwrap() = Some(Ok(stats));
+        }
+    }
+
+    fn release_target(&self, target: &Path) {
+        self.in_flight.lock().unwrap().remove(target);
+        self.released.notify_all();
+    }
+
+    /// Waits until no other entry is writing `target`, applies the conflict
+    /// policy to the entry `name` of `size` bytes, last modified at
+    /// `modified` and with the CRC-32 `crc32` if known, and reserves the
+    /// resulting path. Returns `None` to skip.
+    fn claim_target(
+        &self,
+        archive: usize,
+        name: &str,
+        target: PathBuf,
+        size: u64,
+        modified: Option<BackupTime>,
+        crc32: Option<u32>,
+    ) -> Option<(PathBuf, Action)> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        while in_flight.contains(&target) {
+            in_flight = self.released.wait(in_flight).unwrap();
+        }
+        // The content-addressed layout has no files at these paths.
+        let exists = self.restorer.options.dest_layout == DestLayout::Tree && target.symlink_metadata().is_ok();
+        if !exists {
+            in_flight.insert(target.clone());
+            return Some((target, Action::Extracted));
+        }
+        // Reserved from here, so no later entry for it overtakes this one.
+        in_flight.insert(target.clone());
+        let policy = match self.restorer.conflict_policy(name) {
+            ConflictPolicy::Ask => {
+                // Comparing the contents and asking take long; entries for
+                // other paths go on meanwhile.
+                drop(in_flight);
+                let resolution = self.ask(archive, name, &target, size, modified, crc32);
+                in_flight = self.in_flight.lock().unwrap();
+                resolution
+            }
+            policy => policy,
+        };
+        let mut stats = self.archives[archive].stats.lock().unwrap();
+        match policy {
+            ConflictPolicy::Overwrite => {
+                stats.overwritten += 1;
+                Some((target, Action::Overwritten))
+            }
+            ConflictPolicy::Skip => {
+                stats.skipped += 1;
+                in_flight.remove(&target);
+                drop((stats, in_flight));
+                self.released.notify_all();
+                self.audit(archive, Action::Skipped, name, Some(&target), Some("exists"));
+                None
+            }
+            ConflictPolicy::Rename => {
+                stats.renamed += 1;
+                let renamed = free_name(&target, |candidate| in_flight.contains(candidate));
+                in_flight.insert(renamed.clone());
+                in_flight.remove(&target);
+                drop((stats, in_flight));
+                self.released.notify_all();
+                Some((renamed, Action::Renamed))
+            }
+            ConflictPolicy::Ask => unreachable!("asked above"),
+        }
+    }
+
+    /// What to do with the entry `name` whose `target` exists, as the
+    /// resolver answers. The caller reserves `target` but holds no lock;
+    /// questions come one at a time.
+    fn ask(&self, archive: usize, name: &str, target: &Path, size: u64, modified: Option<BackupTime>, crc32: Option<u32>) -> ConflictPolicy {
+        if crc32.is_some_and(|crc32| same_contents(target, size, crc32)) {
+            return ConflictPolicy::Skip;
+        }
+        let Some(resolver) = &self.restorer.resolver else { return ConflictPolicy::Skip };
+        let _asking = self.asking.lock().unwrap();
+        let metadata = fs::metadata(target).ok();
+        let conflict = Conflict {
+            archive: self.archives[archive].path,
+            path: name,
+            target,
+            existing_size: metadata.as_ref().map_or(0, |metadata| metadata.len()),
+            existing_modified: metadata.and_then(|metadata| metadata.modified().ok()),
+            size,
+            modified,
+        };
+        resolver.resolve(&conflict).into()
+    }
+
+    fn next_archive(&self) -> Option<Opened> {
+        if self.restorer.cancel.is_cancelled() {
+            return None;
+        }
+        if let Some(prefetched) = &self.prefetched {
+            return prefetched.lock().unwrap().recv().ok();
+        }
+        let i = self.next_archive.fetch_add(1, Ordering::Relaxed);
+        let path = self.archives.get(i)?.path;
+        Some((i, Source::open(self.restorer, path)))
+    }
+
+    /// Hands a reader an archive to work on: a clone of the current one
+    /// while it has unread entries, so several readers share a large
+    /// archive, otherwise the next archive.
+    fn join_archive(&self) -> Option<(usize, Source, Arc<[usize]>)> {
+        let mut current = self.current.lock().unwrap();
+        loop {
+            if self.restorer.cancel.is_cancelled() {
+                return None;
+            }
+            if let Some(Current { index, archive, order }) = &*current {
+                let run = &self.archives[*index];
+                // Once `pending` dropped to zero the archive is finished and
+                // must not be joined again. Sequential archives keep the one
+                // reader that opened them.
+                if !archive.sequential()
+                    && run.next_entry.load(Ordering::Relaxed) < archive.len()
+                    && run.pending.fetch_update(Ordering::AcqRel, Ordering::Acquire, |p| (p > 0).then_some(p + 1)).is_ok()
+                {
+                    return Some((*index, archive.clone(), Arc::clone(order)));
+                }
+            }
+            *current = None;
+            let started = Instant::now();
+            let (i, archive) = self.next_archive()?;
+            let run = &self.archives[i];
+            *run.started.lock().unwrap() = Some(started);
+            self.restorer.progress.archive_started(run.path);
+            match archive {
+                Ok(archive) => {
+                    run.pending.store(1, Ordering::Release);
+                    let order = entry_order(self.restorer.options.order, &archive);
+                    *current = Some(Current { index: i, archive: archive.clone(), order: Arc::clone(&order) });
+                    return Some((i, archive, order));
+                }
+                Err(e) => {
+                    self.restorer.progress.archive_failed(run.path, &e);
+                    if let Some(audit) = self.audit {
+                        // There is no entry to fail with an error of the log.
+                        let _ = audit.record(Action::Error, run.path, None, None, Some(&e.to_string()));
+                    }
+                    *run.result.lock().unwrap() = Some(Err(e));
+                    self.stop_if_fail_fast();
+                }
+            }
+        }
+    }
+
+    fn read_archives(&'a self, tx: &SyncSender<DecodeJob<'a>>, gauge: &Gauge) {
+        while let Some((i, mut archive, order)) = self.join_archive() {
+            let run = &self.archives[i];
+            if let Source::Reader(reader) = &archive
+                && reader.sequential()
+            {
+                run.next_entry.store(order.len(), Ordering::Relaxed);
+                self.read_in_order(i, reader.as_ref(), tx, gauge);
+                self.entry_done(i);
+                continue;
+            }
+            while let Some(&j) = order.get(run.next_entry.fetch_add(1, Ordering::Relaxed)) {
+                if self.restorer.cancel.is_cancelled() {
+                    run.stats.lock().unwrap().cancelled = true;
+                    break;
+                }
+                self.read_entry(i, &mut archive, j, tx, gauge);
+            }
+            self.entry_done(i);
+        }
+    }
+
+    fn read_entry(&'a self, i: usize, source: &mut Source, j: usize, tx: &SyncSender<DecodeJob<'a>>, gauge: &Gauge) {
+        match source {
+            Source::Zip(archive, marked) => self.read_zip_entry(i, archive, marked, j, tx, gauge),
+            Source::Reader(reader) => self.read_member(i, reader.as_ref(), j, tx, gauge),
+        }
+    }
+
+    /// Applies the filter, the placeholder, system file and conflict policies
+    /// to the entry `raw_name` of archive `i`, a file of `size` bytes last
+    /// modified at `modified`; returns the ticket to restore it with, if
+    /// any. Entries whose CRC-32, in `crc32`, and size match the previous
+    /// snapshot are linked to it instead.
+    fn admit(
+        &'a self,
+        i: usize,
+        raw_name: &str,
+        placeholder: bool,
+        size: u64,
+        modified: Option<BackupTime>,
+        crc32: Option<u32>,
+    ) -> Option<Ticket<'a>> {
+        let (normalized, admission) = match self.restorer.admit(raw_name, placeholder, size, modified) {
+            Ok(admitted) => admitted,
+            Err(reason) => {
+                self.archives[i].stats.lock().unwrap().rejected.push(raw_name.to_string());
+                self.audit(i, Action::Skipped, raw_name, None, Some(reason.reason()));
+                return None;
+            }
+        };
+        if normalized.absolute || normalized.cleaned {
+            let mut stats = self.archives[i].stats.lock().unwrap();
+            stats.absolute += usize::from(normalized.absolute);
+            stats.normalized += usize::from(normalized.cleaned);
+        }
+        let raw_name = &*normalized.name;
+        let clean = strip_drive_letter(raw_name);
+        let isolated = match admission {
+            Admission::Restore { isolated } => isolated,
+            Admission::Filtered => {
+                self.archives[i].stats.lock().unwrap().filtered += 1;
+                self.audit(i, Action::Skipped, clean, None, Some("filtered"));
+                return None;
+            }
+            Admission::Placeholder => {
+                let mut stats = self.archives[i].stats.lock().unwrap();
+                stats.placeholders += 1;
+                if self.restorer.options.placeholders == PlaceholderPolicy::List {
+                    stats.placeholder_paths.push(clean.to_string());
+                }
+                drop(stats);
+                self.audit(i, Action::Skipped, clean, None, Some("placeholder"));
+                return None;
+            }
+            Admission::AppleDouble => {
+                self.archives[i].stats.lock().unwrap().apple_double += 1;
+                self.audit(i, Action::Skipped, clean, None, Some("AppleDouble"));
+                return None;
+            }
+            Admission::SystemFile => {
+                self.archives[i].stats.lock().unwrap().system_files += 1;
+                self.audit(i, Action::Skipped, clean, None, Some("system file"));
+                return None;
+            }
+        };
+        if isolated {
+            self.archives[i].stats.lock().unwrap().system_files += 1;
+        }
+        let dest = &self.archives[i].dest;
+        let target = self.restorer.admitted_path(dest, raw_name, isolated);
+        let previous = self.restorer.previous_copy(dest, clean, &target, size, crc32);
+        let (target, action) = self.claim_target(i, clean, target, size, modified, crc32)?;
+        if let (Some(previous), Some(crc32)) = (previous, crc32)
+            && self.link_unchanged(&previous, &target, size, crc32)
+        {
+            self.release_target(&target);
+            self.archives[i].stats.lock().unwrap().linked += 1;
+            self.audit(i, Action::Linked, clean, Some(&target), None);
+            let sink = self.restorer.progress.as_ref();
+            sink.bytes_written(size);
+            sink.entry_extracted(self.archives[i].path, clean, &target);
+            return None;
+        }
+        self.archives[i].pending.fetch_add(1, Ordering::AcqRel);
+        Some(Ticket { run: self, archive: i, name: clean.to_string(), target, efs: false, modified: None, times: None, action })
+    }
+
+    /// Where the entry `raw_name` of archive `i` is restored to, see
+    /// [`Restorer::restore_path`].
+    fn restore_path(&self, i: usize, raw_name: &str) -> PathBuf {
+        self.restorer.restore_path(&self.archives[i].dest, raw_name)
+    }
+
+    fn read_zip_entry(
+        &'a self,
+        i: usize,
+        archive: &mut ZipArchive<ArchiveFile>,
+        marked: &Marked,
+        j: usize,
+        tx: &SyncSender<DecodeJob<'a>>,
+        gauge: &Gauge,
+    ) {
+        let path = self.archives[i].path;
+        let read_error = |archive: &ZipArchive<ArchiveFile>, error| {
+            let name = archive.name_for_index(j).map_or_else(|| format!("#{}", j), str::to_string);
+            self.fail(i, EntryError::zip(path, &name, Operation::Read, error));
+        };
+        let result = archive.by_index_raw(j).map(|raw| {
+            let placeholder = marked.placeholders.contains(raw.name());
+            let efs = marked.efs.contains(raw.name());
+            let modified = raw.last_modified().map(BackupTime::from);
+            let times = self.restorer.options.preserve_times.then(|| Timestamps::from_extra(raw.extra_data_fields())).flatten();
+            let meta = (raw.name().replace('\\', "/"), raw.is_dir(), raw.compression(), raw.encrypted(), raw.crc32(), raw.size());
+            (meta, placeholder, efs, modified, times)
+        });
+        let ((raw_name, is_dir, method, encrypted, crc32, size), placeholder, efs, modified, times) = match result {
+            Ok(meta) => meta,
+            Err(e) => return read_error(archive, e),
+        };
+        if is_dir {
+            if let Some(times) = self.preserved_times(times, modified)
+                && let Ok(normalized) = normalize_entry(&raw_name, self.restorer.options.absolute_entries)
+            {
+                let dir = self.restore_path(i, &normalized.name);
+                for mirror in self.restorer.mirrors() {
+                    self.dir_times.entry(self.mirror_target(mirror, &dir), times);
+                }
+                self.dir_times.entry(dir, times);
+            }
+            return;
+        }
+        let Some(mut ticket) = self.admit(i, &raw_name, placeholder, size, modified, Some(crc32)) else { return };
+        ticket.efs = efs;
+        ticket.modified = modified;
+        ticket.times = times;
+
+        #[allow(deprecated)]
+        let method = method.to_u16();
+        if decode::supported(method) && !encrypted {
+            let error = match archive.by_index_raw(j) {
+                Ok(raw) => {
+                    return stream(raw, |data| {
+                        gauge.send(tx, DecodeJob { ticket, method: Some(method), crc32, size, data })
+                    });
+                }
+                Err(e) => e,
+            };
+            drop(ticket);
+            read_error(archive, error);
+        } else {
+            // Methods the decoders do not know are decoded here by the zip crate.
+            let error = match archive.by_index(j) {
+                Ok(entry) => {
+                    return stream(entry, |data| gauge.send(tx, DecodeJob { ticket, method: None, crc32, size, data }));
+                }
+                Err(e) => e,
+            };
+            drop(ticket);
+            read_error(archive, error);
+        }
+    }
+
+    fn read_member(&'a self, i: usize, reader: &dyn ArchiveReader, j: usize, tx: &SyncSender<DecodeJob<'a>>, gauge: &Gauge) {
+        let member = reader.member(j);
+        let Some(mut ticket) = self.admit(i, member.name, is_placeholder(&member), member.size, member.modified, None) else { return };
+        ticket.efs = efs::is_encrypted(member.attributes);
+        ticket.modified = member.modified;
+        let name = ticket.name.clone();
+        let mut ticket = Some(ticket);
+        // Plain file data has no checksum to verify and goes straight to the writers.
+        let result = reader.read(j, &mut |data| {
+            let Some(ticket) = ticket.take() else { return };
+            stream(data, |data| gauge.send(tx, DecodeJob { ticket, method: None, crc32: 0, size: member.size, data }));
+        });
+        if let Err(e) = result {
+            drop(ticket);
+            self.fail(i, EntryError::io(self.archives[i].path, &name, Operation::Read, e));
+        }
+    }
+
+    /// Reads all entries of a sequential archive in one pass.
+    fn read_in_order(&'a self, i: usize, reader: &dyn ArchiveReader, tx: &SyncSender<DecodeJob<'a>>, gauge: &Gauge) {
+        reader.read_in_order(&mut |j, data| {
+            if self.restorer.cancel.is_cancelled() {
+                self.archives[i].stats.lock().unwrap().cancelled = true;
+                return false;
+            }
+            let member = reader.member(j);
+            let Some(mut ticket) = self.admit(i, member.name, is_placeholder(&member), member.size, member.modified, None) else { return true };
+            ticket.efs = efs::is_encrypted(member.attributes);
+            ticket.modified = member.modified;
+            match data {
+                Ok(data) => stream(data, |data| gauge.send(tx, DecodeJob { ticket, method: None, crc32: 0, size: member.size, data })),
+                Err(e) => {
+                    let name = ticket.name.clone();
+                    drop(ticket);
+                    self.fail(i, EntryError::io(self.archives[i].path, &name, Operation::Read, e));
+                }
+            }
+            true
+        });
+    }
+
+    fn decode(&self, rx: &Mutex<Receiver<DecodeJob<'a>>>, rx_gauge: &Gauge, tx: &SyncSender<WriteJob<'a>>, tx_gauge: &Gauge) {
+        while let Some(job) = rx_gauge.recv(rx) {
+            let DecodeJob { ticket, method, crc32, size, data } = job;
+            let decoded: Box<dyn Read + Send> = match method {
+                Some(method) => match decode::decoder(method, data) {
+                    Ok(decoder) => Box::new(CrcReader::new(decoder, crc32, size)),
+                    Err(e) => {
+                        self.fail(ticket.archive, EntryError::io(self.archives[ticket.archive].path, &ticket.name, Operation::Write, e));
+                        continue;
+                    }
+                },
+                None => Box::new(data),
+            };
+            stream(decoded, |data| tx_gauge.send(tx, WriteJob { ticket, size, data }));
+        }
+    }
+
+    fn completed(&self, target: &Path) {
+        let elapsed = self.started.elapsed();
+        let mut completions = self.completions.lock().unwrap();
+        let dir = target.parent();
+        if completions.last_dir.as_deref() != dir {
+            completions.dir_switches += 1;
+            completions.last_dir = dir.map(Path::to_path_buf);
+        }
+        completions.files += 1;
+        completions.total += elapsed;
+    }
+
+    /// Whether the entry `name` is a shortcut to rename user profiles in,
+    /// see [`crate::RestorerBuilder::remap_shortcuts`].
+    fn remaps_shortcut(&self, name: &str) -> bool {
+        let options = &self.restorer.options;
+        options.remap_shortcuts
+            && !options.user_map.is_empty()
+            && Path::new(name).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("lnk"))
+    }
+
+    /// Where the file restored to `target` goes in `mirror`; files of mapped
+    /// drives go below a folder named after the drive.
+    fn mirror_target(&self, mirror: &Path, target: &Path) -> PathBuf {
+        if let Ok(relative) = target.strip_prefix(&self.restorer.dest) {
+            return mirror.join(relative);
+        }
+        for (drive, dir) in &self.restorer.options.drive_map {
+            if let Ok(relative) = target.strip_prefix(dir) {
+                return mirror.join(drive.to_string()).join(relative);
+            }
+        }
+        mirror.join(target)
+    }
+
+    fn write(&self, rx: &Mutex<Receiver<WriteJob<'a>>>, gauge: &Gauge) {
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        if self.restorer.mirrors().is_empty()
+            && self.restorer.options.dest_layout == DestLayout::Tree
+            && let Some(ring) = uring::Ring::new()
+        {
+            return self.write_batched(rx, gauge, ring);
+        }
+        while let Some(WriteJob { ticket, size, data }) = gauge.recv(rx) {
+            self.write_file(ticket, size, data);
+        }
+    }
+
+    /// Writes files of up to [`uring::SMALL_FILE`] through `ring`, a batch
+    /// at a time, and larger ones as [`write`](Self::write) does. A batch is
+    /// written once it is full or no more data comes for a moment, as its
+    /// files hold their paths until then.
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    fn write_batched(&self, rx: &Mutex<Receiver<WriteJob<'a>>>, gauge: &Gauge, ring: uring::Ring) {
+        let mut ring = Some(ring);
+        let mut batch = Vec::with_capacity(uring::BATCH);
+        loop {
+            let job = if batch.is_empty() {
+                gauge.recv(rx)
+            } else {
+                match gauge.recv_timeout(rx, uring::BATCH_WAIT) {
+                    Ok(job) => Some(job),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        self.write_small(&mut ring, &mut batch);
+                        gauge.recv(rx)
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => None,
+                }
+            };
+            let Some(WriteJob { ticket, size, mut data }) = job else { break };
+            if ring.is_none() {
+                self.write_file(ticket, size, data);
+                continue;
+            }
+            let mut content = Vec::new();
+            match (&mut data).take(uring::SMALL_FILE + 1).read_to_end(&mut content) {
+                Ok(n) if n as u64 <= uring::SMALL_FILE => batch.push((ticket, content)),
+                Ok(_) => self.write_file(ticket, size, io::Cursor::new(content).chain(data)),
+                // Such as a CRC mismatch at the end, which the file is
+                // quarantined for.
+                Err(e) => self.write_file(ticket, size, io::Cursor::new(content).chain(Failing(Some(e)))),
+            }
+            if batch.len() == uring::BATCH {
+                self.write_small(&mut ring, &mut batch);
+            }
+        }
+        self.write_small(&mut ring, &mut batch);
+    }
+
+    /// Writes the files of `batch` through `ring`, or one by one once it
+    /// failed, and drops it then.
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    fn write_small(&self, ring: &mut Option<uring::Ring>, batch: &mut Vec<(Ticket<'a>, Vec<u8>)>) {
+        if batch.is_empty() {
+            return;
+        }
+        let mut ready = Vec::with_capacity(batch.len());
+        for (ticket, mut content) in batch.drain(..) {
+            let written = self.partial(&ticket.target);
+            if let Err(e) = self.prepare(&ticket, &written).and_then(|()| self.prepare(&ticket, &ticket.target)) {
+                self.fail(ticket.archive, e);
+                continue;
+            }
+            let efs = ticket.efs || efs::is_raw_export(&content);
+            if self.remaps_shortcut(&ticket.name)
+                && let Some(remapped) = remap_shortcut(&content, &self.restorer.options.user_map)
+            {
+                content = remapped;
+            }
+            ready.push((ticket, written, content, efs));
+        }
+        let files: Vec<(&Path, &[u8])> = ready.iter().map(|(_, written, content, _)| (written.as_path(), content.as_slice())).collect();
+        let results = ring.as_mut().and_then(|ring| ring.write_all(&files));
+        if ring.as_ref().is_some_and(uring::Ring::is_broken) {
+            *ring = None;
+        }
+        let Some(results) = results else {
+            for (ticket, _, content, _) in ready {
+                let size = content.len() as u64;
+                self.write_file(ticket, size, io::Cursor::new(content));
+            }
+            return;
+        };
+        let sink = self.restorer.progress.as_ref();
+        for ((ticket, written, content, efs), result) in ready.into_iter().zip(results) {
+            let path = self.archives[ticket.archive].path;
+            let result = result.map_err(|e| EntryError::io(path, &ticket.name, Operation::Write, e)).map(|()| {
+                sink.bytes_written(content.len() as u64);
+                let digest = self.manifest.is_some().then(|| Sha256::digest(&content).to_vec());
+                (content.len() as u64, digest)
+            });
+            self.finish(&ticket, &written, efs, result, &[]);
+        }
+    }
+
+    /// Restores one file from `data`, and its copies in the mirrors.
+    fn write_file(&self, ticket: Ticket<'a>, size: u64, mut data: impl Read) {
+        let sink = self.restorer.progress.as_ref();
+        let path = self.archives[ticket.archive].path;
+        let target = &ticket.target;
+        let cas = self.restorer.options.dest_layout == DestLayout::Cas;
+        // Files are written aside until complete, and content-addressed
+        // ones until their hash names them.
+        let written = self.partial(target);
+        let mut efs = ticket.efs;
+        let mut mirror_errors = Vec::new();
+        let mut copies = Vec::new();
+        let result = (|| {
+            self.prepare(&ticket, &written)?;
+            if !cas {
+                self.prepare(&ticket, target)?;
+            }
+            let options = &self.restorer.options;
+            let compress = options.ntfs_compress && ntfs_compression::compressible(&ticket.name);
+            let set_up = |file: &fs::File| {
+                if compress {
+                    ntfs_compression::compress(file);
+                }
+                if let Some(policy) = options.mac_quarantine {
+                    mac_quarantine::apply(file, &ticket.name, policy);
+                }
+                if options.preallocate && size >= allocate::MIN_SIZE { allocate::reserve(file, size) } else { Ok(()) }
+            };
+            let created = match options.direct_io {
+                // Compressed files cannot be written unbuffered.
+                Some(threshold) if size > threshold && !compress => DirectFile::create(&written).and_then(|file| {
+                    set_up(file.as_file())?;
+                    Ok(Box::new(file) as Box<dyn io::Write>)
+                }),
+                _ => page_cache::create_sequential(&written).and_then(|file| {
+                    set_up(&file)?;
+                    if options.drop_caches && size >= page_cache::LARGE_FILE {
+                        Ok(Box::new(Evicting::new(file)) as Box<dyn io::Write>)
+                    } else {
+                        Ok(Box::new(file) as Box<dyn io::Write>)
+                    }
+                }),
+            };
+            let outfile = created.map_err(|e| EntryError::io(path, &ticket.name, Operation::Create, e))?;
+            let hasher = (self.manifest.is_some() || cas).then(Sha256::new);
+            let mut tee = Tee { file: outfile, hasher, mirrors: Vec::new(), failed: &mut mirror_errors };
+            for mirror in self.restorer.mirrors() {
+                let copy = self.mirror_target(mirror, target);
+                let created = copy.parent().map_or(Ok(()), |dir| self.create_dirs(dir)).and_then(|()| {
+                    if options.make_readonly {
+                        let _ = set_readonly(&copy, false);
+                    }
+                    let file = page_cache::create_sequential(&copy)?;
+                    set_up(&file)?;
+                    Ok(file)
+                });
+                match created {
+                    Ok(file) => tee.mirrors.push((mirror.as_path(), copy, file)),
+                    Err(e) => tee.failed.push((mirror.as_path(), Operation::Create, e)),
+                }
+            }
+            let mut writer = ProgressWriter { inner: tee, sink };
+            let mut head = Vec::with_capacity(efs::HEADER_LEN);
+            // Windows Backup ZIPs do not keep attributes, but store EFS
+            // files in their raw export form.
+            (&mut data)
+                .take(efs::HEADER_LEN as u64)
+                .read_to_end(&mut head)
+                .map_err(|e| EntryError::io(path, &ticket.name, Operation::Read, e))?;
+            efs |= efs::is_raw_export(&head);
+            if self.remaps_shortcut(&ticket.name) {
+                (&mut data)
+                    .take(MAX_SHORTCUT_SIZE)
+                    .read_to_end(&mut head)
+                    .map_err(|e| EntryError::io(path, &ticket.name, Operation::Read, e))?;
+                // A larger one is no shortcut, and restored as it is.
+                if head.len() < MAX_SHORTCUT_SIZE as usize
+                    && let Some(remapped) = remap_shortcut(&head, &self.restorer.options.user_map)
+                {
+                    head = remapped;
+                }
+            }
+            let bytes = io::copy(&mut io::Cursor::new(head).chain(data), &mut writer)
+                .and_then(|bytes| io::Write::flush(&mut writer).map(|()| bytes))
+                .map_err(|e| EntryError::io(path, &ticket.name, Operation::Write, e))?;
+            copies.extend(writer.inner.mirrors.into_iter().map(|(mirror, copy, _)| (mirror, copy)));
+            Ok::<_, EntryError>((bytes, writer.inner.hasher.map(|hasher| hasher.finalize().to_vec())))
+        })();
+        let failed: Vec<&Path> = mirror_errors.iter().map(|(mirror, _, _)| *mirror).collect();
+        self.finish(&ticket, &written, efs, result, &failed);
+        for (mirror, copy) in &copies {
+            if let Err((operation, e)) = self.written(&ticket, copy) {
+                let e = io::Error::new(e.kind(), format!("mirror {}: {}", mirror.display(), e));
+                self.fail(ticket.archive, EntryError::io(path, &ticket.name, operation, e));
+            }
+        }
+        for (mirror, operation, e) in mirror_errors {
+            let e = io::Error::new(e.kind(), format!("mirror {}: {}", mirror.display(), e));
+            self.fail(ticket.archive, EntryError::io(path, &ticket.name, operation, e));
+        }
+    }
+
+    /// Makes way for the file of `ticket` at `written`.
+    fn prepare(&self, ticket: &Ticket<'a>, written: &Path) -> Result<(), EntryError> {
+        let path = self.archives[ticket.archive].path;
+        if let Some(parent) = written.parent() {
+            self.create_dirs(parent).map_err(|e| EntryError::io(path, &ticket.name, Operation::CreateDir, e))?;
+        }
+        // An earlier archive, or run, may have made the file read-only.
+        if ticket.action == Action::Overwritten && self.restorer.options.make_readonly {
+            let _ = set_readonly(written, false);
+        }
+        // A file of an earlier archive may be a link into the previous
+        // snapshot, which must keep its content.
+        if ticket.action == Action::Overwritten && self.restorer.options.link_dest.is_some() {
+            match fs::remove_file(written) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    return Err(EntryError::io(path, &ticket.name, Operation::Create, e));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the file of `ticket` written to `written`, with its size and
+    /// digest, or what went wrong. The copies in `failed_mirrors` were not
+    /// written whole.
+    fn finish(&self, ticket: &Ticket<'a>, written: &Path, efs: bool, result: Result<(u64, Option<Vec<u8>>), EntryError>, failed_mirrors: &[&Path]) {
+        let sink = self.restorer.progress.as_ref();
+        let path = self.archives[ticket.archive].path;
+        let target = &ticket.target;
+        let cas = self.restorer.options.dest_layout == DestLayout::Cas;
+        let relative = target.strip_prefix(&self.restorer.dest).unwrap_or(target);
+        // Whether `target` holds the file now, rather than what it held before.
+        let mut moved = false;
+        let result = result.and_then(|(bytes, digest)| {
+            let stored = match &digest {
+                Some(digest) if cas => Some(self.store_object(written, digest).map_err(|e| {
+                    let e = io::Error::new(e.kind(), format!("store {}: {}", written.display(), e));
+                    EntryError::io(path, &ticket.name, Operation::Write, e)
+                })?),
+                _ => None,
+            };
+            if !cas && written != target {
+                move_file(written, target).map_err(|e| {
+                    let e = io::Error::new(e.kind(), format!("move from {}: {}", written.display(), e));
+                    EntryError::io(path, &ticket.name, Operation::Write, e)
+                })?;
+                moved = true;
+            }
+            Ok((bytes, digest, stored))
+        });
+        let result = result.and_then(|(bytes, digest, stored)| {
+            let location = stored.as_ref().map_or(target, |(object, _)| object);
+            self.written(ticket, location).map_err(|(operation, e)| EntryError::io(path, &ticket.name, operation, e))?;
+            Ok((bytes, digest, stored))
+        });
+        match result {
+            Ok((bytes, digest, stored)) => {
+                let records = [(self.tree, "tree file"), (self.manifest, "manifest")];
+                for (writer, name) in records {
+                    let (Some(writer), Some(digest)) = (writer, &digest) else { continue };
+                    let entry = ManifestEntry {
+                        path: relative,
+                        size: bytes,
+                        modified: ticket.modified,
+                        archive: path,
+                        digest,
+                    };
+                    if let Err(e) = writer.add(&entry) {
+                        let e = io::Error::new(e.kind(), format!("{}: {}", name, e));
+                        self.fail(ticket.archive, EntryError::io(path, &ticket.name, Operation::Write, e));
+                    }
+                }
+                let location = stored.as_ref().map_or(target, |(object, _)| object);
+                {
+                    let mut stats = self.archives[ticket.archive].stats.lock().unwrap();
+                    stats.files += 1;
+                    stats.bytes += bytes;
+                    if efs {
+                        stats.efs_paths.push(ticket.name.clone());
+                    }
+                    if let Some((_, false)) = stored {
+                        stats.deduplicated += 1;
+                    }
+                }
+                self.completed(target);
+                self.audit(ticket.archive, ticket.action, &ticket.name, Some(location), None);
+                sink.entry_extracted(path, &ticket.name, location);
+            }
+            // The data is all there, but damaged somewhere: keep it
+            // apart rather than discard what may still be readable.
+            Err(e) if e.kind == ErrorKind::BadCrc && e.operation == Operation::Write => self.quarantine(ticket, written, e, failed_mirrors),
+            // Leave no partial file behind, nor one in place whose times or
+            // flush failed.
+            Err(e) => {
+                if moved {
+                    let _ = fs::remove_file(target);
+                } else if written != target {
+                    let _ = fs::remove_file(written);
+                }
+                self.fail(ticket.archive, e)
+            }
+        }
+    }
+
+    /// Gives `file`, restored for `ticket`, its times, flushes it and makes
+    /// it read-only, now or once it is flushed later, as the options ask.
+    fn written(&self, ticket: &Ticket<'a>, file: &Path) -> Result<(), (Operation, io::Error)> {
+        let options = &self.restorer.options;
+        let i = ticket.archive;
+        if let Some(times) = self.preserved_times(ticket.times, ticket.modified) {
+            times.apply(file).map_err(|e| (Operation::SetTimes, e))?;
+            if let Some(root) = self.root_of(file) {
+                self.dir_times.file(file.ancestors().skip(1).take_while(|dir| *dir != root), &times);
+            }
+        }
+        self.adopt(file)?;
+        match options.sync {
+            SyncPolicy::None => {}
+            SyncPolicy::PerFile => durability::sync_file(file).map_err(|e| (Operation::Sync, e))?,
+            // Windows flushes through a handle for writing.
+            SyncPolicy::PerZip | SyncPolicy::Final => {
+                self.archives[i].unsynced.lock().unwrap().push(file.to_path_buf());
+                return Ok(());
+            }
+        }
+        if options.make_readonly {
+            set_readonly(file, true).map_err(|e| (Operation::Protect, e))?;
+        }
+        Ok(())
+    }
+
+    /// The times to give a file or folder restored with `times` from extra
+    /// fields and DOS time `modified`, if any.
+    fn preserved_times(&self, times: Option<Timestamps>, modified: Option<BackupTime>) -> Option<Timestamps> {
+        let options = &self.restorer.options;
+        // Objects of a content-addressed restore are shared by every file
+        // with their content.
+        if !options.preserve_times || options.dest_layout != DestLayout::Tree {
+            return None;
+        }
+        times.or_else(|| Timestamps::from_dos(modified?, options.source_tz))
+    }
+
+    /// The destination, mirror or drive folder `path` is in.
+    fn root_of(&self, path: &Path) -> Option<&Path> {
+        let options = &self.restorer.options;
+        let roots = std::iter::once(&self.restorer.dest).chain(&options.mirrors).chain(options.drive_map.values());
+        roots.filter(|root| path.starts_with(root)).max_by_key(|root| root.components().count()).map(PathBuf::as_path)
+    }
+
+    /// Creates the folder `dir` and those above it that are missing, and
+    /// notes them for [`adopt`](Self::adopt).
+    fn create_dirs(&self, dir: &Path) -> io::Result<()> {
+        let options = &self.restorer.options;
+        if options.owner.is_none() && options.dir_mode.is_none() {
+            return fs::create_dir_all(dir);
+        }
+        let missing: Vec<PathBuf> = dir.ancestors().take_while(|dir| dir.symlink_metadata().is_err()).map(Path::to_path_buf).collect();
+        fs::create_dir_all(dir)?;
+        self.created.lock().unwrap().extend(missing);
+        Ok(())
+    }
+
+    /// Gives `file` the owner and mode the options name, and the folders
+    /// above it the run created their owner and mode. Folders that were
+    /// there before, such as a home folder the destination lies in, keep
+    /// theirs.
+    fn adopt(&self, file: &Path) -> Result<(), (Operation, io::Error)> {
+        let options = &self.restorer.options;
+        let chown = |path| match options.owner {
+            Some((uid, gid)) => owner::chown(path, uid, gid).map_err(|e| (Operation::Chown, e)),
+            None => Ok(()),
+        };
+        let chmod = |path, mode| match mode {
+            Some(mode) => owner::set_mode(path, mode).map_err(|e| (Operation::Chmod, e)),
+            None => Ok(()),
+        };
+        chown(file)?;
+        chmod(file, options.file_mode)?;
+        if options.owner.is_none() && options.dir_mode.is_none() {
+            return Ok(());
+        }
+        let Some(root) = self.root_of(file) else { return Ok(()) };
+        let mut created = self.created.lock().unwrap();
+        for dir in file.ancestors().skip(1).take_while(|dir| *dir != root) {
+            if !created.remove(dir) {
+                break;
+            }
+            chown(dir)?;
+            chmod(dir, options.dir_mode)?;
+        }
+        Ok(())
+    }
+
+    /// A new name for the file of `target` to be written to before it is
+    /// moved to its path or, in a [`DestLayout::Cas`] restore, stored: in
+    /// the staging folder, next to the store, or next to `target`.
+    fn partial(&self, target: &Path) -> PathBuf {
+        let options = &self.restorer.options;
+        let n = self.partials.fetch_add(1, Ordering::Relaxed);
+        match &options.temp_dir {
+            Some(dir) => dir.join(format!("restore-{}-{}.part", std::process::id(), n)),
+            None if options.dest_layout == DestLayout::Cas => {
+                self.restorer.dest.join(DestLayout::OBJECTS_DIR).join(format!(".partial-{}-{}", std::process::id(), n))
+            }
+            None => part_beside(target),
+        }
+    }
+
+    /// Moves the file at `written` into the store under its `digest`, or
+    /// removes it if the store has its content already. Returns the path in
+    /// the store and whether the content is new.
+    fn store_object(&self, written: &Path, digest: &[u8]) -> io::Result<(PathBuf, bool)> {
+        let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        let object = self.restorer.dest.join(DestLayout::OBJECTS_DIR).join(&hex[..2]).join(&hex[2..]);
+        if object.symlink_metadata().is_ok() {
+            fs::remove_file(written)?;
+            return Ok((object, false));
+        }
+        if let Some(parent) = object.parent() {
+            self.create_dirs(parent)?;
+        }
+        // Another writer storing the same content at once renames an equal
+        // file over it.
+        move_file(written, &object)?;
+        Ok((object, true))
+    }
+
+    /// Moves the file `written` for `ticket`, whose data failed its CRC
+    /// check, to the quarantine folder and writes the `error` into a marker
+    /// next to it. The copies in the mirrors, but those of `failed_mirrors`,
+    /// go to their quarantine folders; failing that is an error of the
+    /// mirror alone.
+    fn quarantine(&self, ticket: &Ticket<'a>, written: &Path, error: EntryError, failed_mirrors: &[&Path]) {
+        let path = self.archives[ticket.archive].path;
+        let quarantined = self.restorer.dest.join(QUARANTINE_DIR).join(&ticket.name);
+        let move_aside = |file: &Path, quarantined: &Path| -> io::Result<()> {
+            let mut marker = quarantined.as_os_str().to_owned();
+            marker.push(".crc-mismatch");
+            if let Some(parent) = quarantined.parent() {
+                self.create_dirs(parent)?;
+            }
+            move_file(file, quarantined)?;
+            fs::write(&marker, format!("{}\n", error))
+        };
+        let moved = move_aside(written, &quarantined);
+        // The mirrors got the same damaged data.
+        for mirror in self.restorer.mirrors().iter().filter(|mirror| !failed_mirrors.contains(&mirror.as_path())) {
+            let copy = self.mirror_target(mirror, &ticket.target);
+            if copy.symlink_metadata().is_err() {
+                continue;
+            }
+            if let Err(e) = move_aside(&copy, &mirror.join(QUARANTINE_DIR).join(&ticket.name)) {
+                let e = io::Error::new(e.kind(), format!("mirror {}: {}", mirror.display(), e));
+                self.fail(ticket.archive, EntryError::io(path, &ticket.name, Operation::Write, e));
+            }
+        }
+        match moved {
+            Ok(()) => {
+                self.archives[ticket.archive].stats.lock().unwrap().quarantined.push(ticket.name.clone());
+                self.audit(ticket.archive, Action::Quarantined, &ticket.name, Some(&quarantined), Some(&error.to_string()));
+                self.restorer.progress.entry_extracted(path, &ticket.name, &quarantined);
+                self.stop_if_fail_fast();
+            }
+            Err(_) => {
+                if written != ticket.target {
+                    let _ = fs::remove_file(written);
+                }
+                self.fail(ticket.archive, error)
+            }
+        }
+    }
+
+    /// Replaces `target` with a hard link to `previous`, unless that file is
+    /// missing or does not hold `size` bytes with the CRC-32 `crc32`: the
+    /// previous run may have failed to write it, or skipped or renamed it on a
+    /// conflict and kept another file there.
+    fn link_unchanged(&self, previous: &Path, target: &Path, size: u64, crc32: u32) -> bool {
+        if !previous.symlink_metadata().is_ok_and(|meta| meta.is_file()) || !same_contents(previous, size, crc32) {
+            return false;
+        }
+        if let Some(parent) = target.parent()
+            && self.create_dirs(parent).is_err()
+        {
+            return false;
+        }
+        match fs::remove_file(target) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(_) => return false,
+        }
+        fs::hard_link(previous, target).is_ok()
+    }
+}
+
+/// Writes a restored file and its copies in the mirrors at once. A mirror
+/// that fails drops out, with its error kept in `failed`; only the file in
+/// the destination fails the entry.
+struct Tee<'m, 'f> {
+    file: Box<dyn io::Write>,
+    /// Hashes the data for the manifest.
+    hasher: Option<Sha256>,
+    /// The mirror, the copy in it and the file of the copy.
+    mirrors: Vec<(&'m Path, PathBuf, fs::File)>,
+    failed: &'f mut Vec<(&'m Path, Operation, io::Error)>,
+}
+
+impl io::Write for Tee<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+        }
+        self.mirrors.retain_mut(|(mirror, _, file)| match file.write_all(&buf[..n]) {
+            Ok(()) => true,
+            Err(e) => {
+                self.failed.push((*mirror, Operation::Write, e));
+                false
+            }
+        });
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Extracts `zips` through the staged pipeline. Returns one result per
+/// archive, `None` for archives not started because the run was cancelled.
+pub(crate) fn run(
+    restorer: &Restorer,
+    zips: &[PathBuf],
+    manifest: Option<&ManifestWriter>,
+    audit: Option<&AuditLog>,
+    tree: Option<&ManifestWriter>,
+) -> (Vec<Option<Result<ArchiveStats, ArchiveError>>>, PipelineStats, OrderStats) {
+    let options = &restorer.options;
+    let readers = options.threads.max(1);
+    let decoders = options.decode_threads.max(1);
+    let writers = options.write_threads.max(1);
+
+    let (prefetch_tx, prefetched) = if options.prefetch {
+        let (tx, rx) = mpsc::sync_channel::<Opened>(0);
+        (Some(tx), Some(Mutex::new(rx)))
+    } else {
+        (None, None)
+    };
+    let run = Run {
+        restorer,
+        manifest,
+        audit,
+        tree,
+        partials: AtomicUsize::new(0),
+        archives: zips
+            .iter()
+            .map(|path| ArchiveRun {
+                path,
+                dest: restorer.archive_dest(path),
+                stats: Mutex::new(ArchiveStats::default()),
+                pending: AtomicUsize::new(0),
+                next_entry: AtomicUsize::new(0),
+                started: Mutex::new(None),
+                result: Mutex::new(None),
+                unsynced: Mutex::new(Vec::new()),
+            })
+            .collect(),
+        in_flight: Mutex::new(HashSet::new()),
+        released: Condvar::new(),
+        asking: Mutex::new(()),
+        next_archive: AtomicUsize::new(0),
+        prefetched,
+        current: Mutex::new(None),
+        started: Instant::now(),
+        completions: Mutex::new(Completions::default()),
+        created: Mutex::new(HashSet::new()),
+        dir_times: DirTimes::default(),
+    };
+    let decode_gauge = Gauge::new(decoders * 2);
+    let write_gauge = Gauge::new(writers * 2);
+    let (decode_tx, decode_rx) = mpsc::sync_channel(decode_gauge.capacity);
+    let (write_tx, write_rx) = mpsc::sync_channel(write_gauge.capacity);
+    let (decode_rx, write_rx) = (Mutex::new(decode_rx), Mutex::new(write_rx));
+
+    std::thread::scope(|scope| {
+        let (run, decode_gauge, write_gauge) = (&run, &decode_gauge, &write_gauge);
+        let (decode_rx, write_rx) = (&decode_rx, &write_rx);
+        if let Some(tx) = prefetch_tx {
+            scope.spawn(move || prefetch(restorer, zips, tx));
+        }
+        for _ in 0..writers {
+            scope.spawn(move || run.write(write_rx, write_gauge));
+        }
+        for _ in 0..decoders {
+            let write_tx = write_tx.clone();
+            scope.spawn(move || run.decode(decode_rx, decode_gauge, &write_tx, write_gauge));
+        }
+        drop(write_tx);
+        for _ in 0..readers {
+            let decode_tx = decode_tx.clone();
+            scope.spawn(move || run.read_archives(&decode_tx, decode_gauge));
+        }
+        drop(decode_tx);
+    });
+
+    let pipeline = PipelineStats {
+        readers,
+        decoders,
+        writers,
+        decode_queue: decode_gauge.stats(),
+        write_queue: write_gauge.stats(),
+    };
+    let completions = std::mem::take(&mut *run.completions.lock().unwrap());
+    let ordering = OrderStats {
+        order: options.order,
+        dir_switches: completions.dir_switches,
+        mean_completion: completions.total.checked_div(completions.files).unwrap_or_default(),
+    };
+    run.dir_times.apply();
+    let results = run
+        .archives
+        .iter()
+        .map(|a| {
+            let mut result = a.result.lock().unwrap().take();
+            if options.sync == SyncPolicy::Final
+                && let Some(Ok(stats)) = &mut result
+            {
+                let files = std::mem::take(&mut *a.unsynced.lock().unwrap());
+                for e in settle(a.path, &files, options.make_readonly) {
+                    restorer.progress.error(&e);
+                    stats.errors.push(e);
+                }
+            }
+            result
+        })
+        .collect();
+    (results, pipeline, ordering)
+}
+
+/// Flushes `files` of `archive`, then makes them read-only if `readonly`,
+/// with an error for each that failed.
+fn settle(archive: &Path, files: &[PathBuf], readonly: bool) -> Vec<EntryError> {
+    let mut failed: Vec<_> = durability::sync_files(files).into_iter().map(|(file, e)| (file, Operation::Sync, e)).collect();
+    if readonly {
+        for file in files {
+            if let Err(e) = set_readonly(file, true) {
+                failed.push((file.clone(), Operation::Protect, e));
+            }
+        }
+    }
+    failed.into_iter().map(|(file, operation, e)| EntryError::io(archive, &file.to_string_lossy(), operation, e)).collect()
+}
+
+/// Moves the file at `from` to `to`, replacing a file there. Across file
+/// systems, where renaming fails, it is copied next to `to` and renamed
+/// from there, so `to` is never half written.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let part = part_beside(to);
+            let copied = fs::copy(from, &part).and_then(|_| fs::rename(&part, to));
+            if copied.is_err() {
+                let _ = fs::remove_file(&part);
+            }
+            copied?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// The hidden `.part` file next to `target` it is written as before it is
+/// renamed into place. Only one writer has `target` at a time.
+fn part_beside(target: &Path) -> PathBuf {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    target.with_file_name(format!(".{}.restore-{}.part", name, std::process::id()))
+}
+
+/// Makes the file at `path` read-only, clearing all its write permissions,
+/// or lets its owner write it again.
+fn set_readonly(path: &Path, readonly: bool) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    if readonly {
+        permissions.set_readonly(true);
+    } else {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            permissions.set_mode(permissions.mode() | 0o200);
+        }
+        #[cfg(not(unix))]
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+    }
+    fs::set_permissions(path, permissions)
+}
+
+fn is_placeholder(member: &Member<'_>) -> bool {
+    is_cloud_placeholder(strip_drive_letter(member.name), member.size, member.attributes)
+}
+
+/// Indices of the entries of `archive` in the order they are extracted.
+fn entry_order(order: ExtractionOrder, source: &Source) -> Arc<[usize]> {
+    let mut indices: Vec<usize> = (0..source.len()).collect();
+    match (order, source) {
+        // Sequential archives cannot be read in any other order.
+        _ if source.sequential() => {}
+        (ExtractionOrder::Archive, _) => {}
+        (ExtractionOrder::SmallFirst, Source::Reader(reader)) => indices.sort_by_key(|&j| reader.member(j).size),
+        (ExtractionOrder::SmallFirst, Source::Zip(archive, _)) => {
+            // The zip crate only knows an entry's size after seeking to its
+            // local header, so take the sizes from the central directory.
+            let sizes: HashMap<String, u64> = CentralDirectory::open(archive.clone().into_inner())
+                .map(|records| records.map_while(Result::ok).map(|e| (e.name, e.size)).collect())
+                .unwrap_or_default();
+            indices.sort_by_cached_key(|&j| archive.name_for_index(j).and_then(|n| sizes.get(n)).copied().unwrap_or(u64::MAX));
+        }
+        (ExtractionOrder::ByDir, _) => {
+            indices.sort_by_cached_key(|&j| {
+                let name = source.name(j).unwrap_or_default().replace('\\', "/");
+                name.rsplit_once('/').map_or_else(String::new, |(dir, _)| dir.to_string())
+            });
+        }
+    }
+    indices.into()
+}
+
+/// Opens archives ahead of the readers. The rendezvous channel keeps
+/// the read-ahead exactly one archive ahead of the extraction.
+fn prefetch(restorer: &Restorer, zips: &[PathBuf], tx: SyncSender<Opened>) {
+    for (i, zip_path) in zips.iter().enumerate() {
+        if restorer.cancel.is_cancelled() {
+            break;
+        }
+        let mut archive = Source::open(restorer, zip_path);
+        if restorer.options.warm_cache && archive.is_ok() {
+            match warm_page_cache(zip_path, &tx, (i, archive)) {
+                Some((_, unsent)) => archive = unsent,
+                None => continue,
+            }
+        }
+        if tx.send((i, archive)).is_err() {
+            break;
+        }
+    }
+}
+
+/// Reads through `zip_path` so its data is in the page cache once the
+/// extraction reaches it. Stops as soon as the extractor takes the archive,
+/// since from then on it reads the file itself. Returns the archive if it
+/// has not been handed over yet.
+fn warm_page_cache<T>(zip_path: &Path, tx: &SyncSender<T>, archive: T) -> Option<T> {
+    let mut archive = archive;
+    let Ok(mut file) = page_cache::open_sequential(zip_path) else { return Some(archive) };
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        match tx.try_send(archive) {
+            Ok(()) => return None,
+            Err(mpsc::TrySendError::Full(unsent)) => archive = unsent,
+            Err(mpsc::TrySendError::Disconnected(_)) => return None,
+        }
+        match file.read(&mut buf) {
+            Ok(0) | Err(_) => return Some(archive),
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Returns `path` with ` (n)` appended to the file stem, using the first `n`
+/// that neither exists nor is `taken`.
+pub(crate) fn free_name(path: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|candidate| !taken(candidate) && candidate.symlink_metadata().is_err())
+        .expect("unbounded range")
+}
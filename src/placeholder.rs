@@ -0,0 +1,37 @@
+//! Cloud-only placeholders: files that OneDrive and other sync clients show
+//! in a folder while their data stays online. A backup of the folder holds
+//! the placeholder, not the data, so restoring it gives an empty stub.
+
+/// Windows file attributes of files whose data is not stored locally.
+const OFFLINE: u32 = 0x0000_1000;
+const RECALL_ON_OPEN: u32 = 0x0004_0000;
+pub(crate) const RECALL_ON_DATA_ACCESS: u32 = 0x0040_0000;
+
+/// Whether an entry with restore path `path`, `size` and, if the archive
+/// records them, Windows file `attributes` is a cloud-only placeholder.
+///
+/// Placeholders are marked offline or recall-on-access by Windows. Archives
+/// that do not keep these attributes, such as Windows Backup ZIPs, store a
+/// placeholder as an empty file, so empty files in a OneDrive folder count
+/// as placeholders too.
+pub(crate) fn is_cloud_placeholder(path: &str, size: u64, attributes: Option<u32>) -> bool {
+    attributes.is_some_and(|attributes| attributes & (OFFLINE | RECALL_ON_OPEN | RECALL_ON_DATA_ACCESS) != 0)
+        || size == 0 && in_onedrive(path)
+}
+
+/// Whether `path` lies in a OneDrive folder, `OneDrive` for personal
+/// accounts or `OneDrive - <organization>` for work and school ones.
+fn in_onedrive(path: &str) -> bool {
+    let Some((dirs, _)) = path.rsplit_once('/') else { return false };
+    dirs.split('/').any(|dir| {
+        let dir = dir.to_ascii_lowercase();
+        dir == "onedrive" || dir.starts_with("onedrive - ")
+    })
+}
+
+/// Whether an NTFS reparse tag is one of the cloud file tags,
+/// `IO_REPARSE_TAG_CLOUD` to `IO_REPARSE_TAG_CLOUD_F`.
+#[cfg(feature = "vhd")]
+pub(crate) fn is_cloud_reparse_tag(tag: u32) -> bool {
+    tag & 0xffff_0fff == 0x9000_001a
+}
@@ -0,0 +1,130 @@
+//! Where browsers keep bookmarks, history and saved passwords inside a
+//! Windows user profile, for restoring just those files.
+
+use crate::filter::{Filter, escape};
+
+/// A browser whose profile [`Browser::filter`] picks out of a backup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Browser {
+    Firefox,
+    Chrome,
+    Edge,
+}
+
+/// Files of a Firefox profile folder worth restoring: bookmarks and
+/// history, favicons, saved passwords and their key, form history,
+/// cookies, certificates and the last session.
+const FIREFOX_FILES: &[&str] = &[
+    "places.sqlite*",
+    "favicons.sqlite*",
+    "bookmarkbackups/**",
+    "logins.json",
+    "logins-backup.json",
+    "key4.db",
+    "key3.db",
+    "formhistory.sqlite*",
+    "cookies.sqlite*",
+    "cert9.db",
+    "sessionstore.jsonlz4",
+    "sessionstore-backups/**",
+];
+
+/// Files of a Chromium profile folder (`Default`, `Profile 1`, ...):
+/// bookmarks, history, saved passwords, autofill data, cookies and
+/// settings.
+const CHROMIUM_FILES: &[&str] =
+    &["Bookmarks", "Bookmarks.bak", "History", "Favicons", "Login Data", "Web Data", "Cookies", "Network/Cookies", "Preferences"];
+
+impl Browser {
+    /// Name shown to the user.
+    pub fn name(self) -> &'static str {
+        match self {
+            Browser::Firefox => "Firefox",
+            Browser::Chrome => "Google Chrome",
+            Browser::Edge => "Microsoft Edge",
+        }
+    }
+
+    /// The folders holding the browser's profiles, below `Users/<name>` and,
+    /// on Windows XP, `Documents and Settings/<name>`.
+    fn profile_roots(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Browser::Firefox => {
+                &[("Users", "AppData/Roaming/Mozilla/Firefox"), ("Documents and Settings", "Application Data/Mozilla/Firefox")]
+            }
+            Browser::Chrome => &[
+                ("Users", "AppData/Local/Google/Chrome/User Data"),
+                ("Documents and Settings", "Local Settings/Application Data/Google/Chrome/User Data"),
+            ],
+            Browser::Edge => &[("Users", "AppData/Local/Microsoft/Edge/User Data")],
+        }
+    }
+
+    /// A filter restoring the profile files of the browser for `user`, or for
+    /// every user if `None`.
+    pub fn filter(self, user: Option<&str>) -> Filter {
+        let user = user.map_or_else(|| "*".to_string(), escape);
+        let mut filter = Filter::new();
+        for (profiles_dir, root) in self.profile_roots() {
+            let root = format!("**/{}/{}/{}", profiles_dir, user, root);
+            let (index, files) = match self {
+                // `profiles.ini` names the profile folders.
+                Browser::Firefox => ("profiles.ini", FIREFOX_FILES),
+                // `Local State` holds the key the saved passwords are encrypted with.
+                Browser::Chrome | Browser::Edge => ("Local State", CHROMIUM_FILES),
+            };
+            filter = filter.include(format!("{}/{}", root, index));
+            let profiles = if self == Browser::Firefox { "Profiles/*" } else { "*" };
+            for file in files {
+                filter = filter.include(format!("{}/{}/{}", root, profiles, file));
+            }
+        }
+        filter
+    }
+
+    /// How to bring the restored files into the browser on the new machine.
+    pub fn instructions(self) -> &'static str {
+        match self {
+            Browser::Firefox => {
+                "\
+The restored Firefox profiles are in
+  Users/<name>/AppData/Roaming/Mozilla/Firefox/Profiles/<profile>/
+(Documents and Settings/<name>/Application Data/... for Windows XP backups).
+profiles.ini next to the Profiles folder tells which profile was the default.
+
+To move one into Firefox on the new machine:
+ 1. Start Firefox once, open about:profiles and click \"Open Folder\" next to
+    the root directory of the profile in use. Then quit Firefox.
+ 2. Copy the restored files of the old profile into that folder, replacing
+    the ones there: places.sqlite (bookmarks and history), favicons.sqlite,
+    logins.json and key4.db (saved passwords, they only work together),
+    formhistory.sqlite, cookies.sqlite, cert9.db and the session files.
+ 3. Start Firefox again.
+
+Saved passwords protected by a Primary Password need that password.
+"
+            }
+            Browser::Chrome | Browser::Edge => {
+                "\
+The restored profiles are in
+  Users/<name>/AppData/Local/<Google/Chrome or Microsoft/Edge>/User Data/<profile>/
+where <profile> is Default, Profile 1, ... for each browser profile.
+
+To move one into the browser on the new machine:
+ 1. Quit the browser completely (also from the notification area).
+ 2. Open %LOCALAPPDATA%\\Google\\Chrome\\User Data\\Default (or
+    %LOCALAPPDATA%\\Microsoft\\Edge\\User Data\\Default) and copy the restored
+    Bookmarks, History, Favicons and Web Data files of the old profile into
+    it, replacing the ones there.
+ 3. Start the browser again.
+
+Saved passwords (Login Data) and cookies are encrypted with a key bound to the
+old Windows account (DPAPI, through Local State) and cannot be read on another
+machine or account. If the browser was signed in to sync, signing in again
+restores them instead; otherwise export them on the old system if it still
+boots.
+"
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::ArchiveStats;
+use crate::error::{ArchiveError, EntryError};
+
+/// Receives progress events from a [`crate::Restorer`].
+///
+/// All methods default to doing nothing, so implementations only override the
+/// events they care about. Events may arrive from several threads at once.
+pub trait ProgressSink: Send + Sync {
+    fn archive_started(&self, _archive: &Path) {}
+    fn entry_extracted(&self, _archive: &Path, _entry: &str, _target: &Path) {}
+    fn bytes_written(&self, _bytes: u64) {}
+    fn error(&self, _error: &EntryError) {}
+    fn archive_finished(&self, _archive: &Path, _stats: &ArchiveStats) {}
+    /// The archive could not be opened at all.
+    fn archive_failed(&self, _archive: &Path, _error: &ArchiveError) {}
+}
+
+/// A sink that ignores every event.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {}
+
+/// Forwards the number of bytes written through it to a [`ProgressSink`].
+pub(crate) struct ProgressWriter<'a, W> {
+    pub(crate) inner: W,
+    pub(crate) sink: &'a dyn ProgressSink,
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.sink.bytes_written(n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
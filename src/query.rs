@@ -0,0 +1,521 @@
+//! Queries on the files of a backup, for `restore query` and
+//! [`crate::Filter::query`]: conditions on their path, name, extension,
+//! size and modification time, joined with `AND`, `OR`, `NOT` and
+//! parentheses, such as `ext = 'jpg' AND size > 5M AND mtime > '2019-01-01'`.
+//!
+//! The fields are `path`, the restore path; `name`, the file name; `ext`,
+//! its extension without the dot; `size`, in bytes or with a `K`, `M`, `G`
+//! or `T` suffix; and `mtime`. Text is compared case-insensitively with `=`
+//! and `!=`, or matched against a glob with `~`, see [`crate::Filter`].
+//! Sizes and times take `=`, `!=`, `<`, `<=`, `>` and `>=`. A time is as
+//! precise as it is written, `2019`, `2019-06`, `2019-06-30` or
+//! `2019-06-30 14:05`, and a file is compared at that precision:
+//! `mtime = '2019'` is any time in 2019, `mtime > '2019'` from 2020 on.
+//! Values are quoted with `'` or `"` unless they are a single word.
+
+use std::fmt;
+
+use crate::backup_set::{BackupSet, EntryInfo};
+use crate::catalog::BackupTime;
+use crate::error::ArchiveError;
+use crate::filter::glob_match;
+use crate::size::parse_size;
+
+/// A parsed query, see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    expr: Expr,
+}
+
+/// A query that does not parse: what is wrong, and where.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message} at character {}", .position + 1)]
+pub struct QueryError {
+    /// Character offset of the problem in the query.
+    pub position: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Text(TextField, TextOp, String),
+    Size(Compare, u64),
+    Time(Compare, Vec<u16>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextField {
+    Path,
+    Name,
+    Ext,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextOp {
+    Equal,
+    NotEqual,
+    Like,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compare {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Compare {
+    fn holds<T: Ord>(self, left: T, right: T) -> bool {
+        match self {
+            Compare::Equal => left == right,
+            Compare::NotEqual => left != right,
+            Compare::Less => left < right,
+            Compare::LessOrEqual => left <= right,
+            Compare::Greater => left > right,
+            Compare::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query, QueryError> {
+        let tokens = tokenize(query)?;
+        let mut parser = Parser { tokens: &tokens, next: 0, end: query.chars().count(), depth: 0 };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(Query { expr }),
+            Some(token) => Err(error(token.position, format!("expected AND or OR, got {}", token.text))),
+        }
+    }
+
+    /// Whether the file at restore path `path` matches.
+    pub fn matches(&self, path: &str, size: u64, modified: Option<BackupTime>) -> bool {
+        self.expr.matches(path, size, modified)
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+impl Expr {
+    fn matches(&self, path: &str, size: u64, modified: Option<BackupTime>) -> bool {
+        match self {
+            Expr::And(left, right) => left.matches(path, size, modified) && right.matches(path, size, modified),
+            Expr::Or(left, right) => left.matches(path, size, modified) || right.matches(path, size, modified),
+            Expr::Not(expr) => !expr.matches(path, size, modified),
+            Expr::Text(field, op, value) => {
+                let name = path.rsplit('/').next().unwrap_or(path);
+                let text = match field {
+                    TextField::Path => path,
+                    TextField::Name => name,
+                    TextField::Ext => name.rsplit_once('.').map_or("", |(_, ext)| ext),
+                };
+                match op {
+                    TextOp::Equal => text.to_lowercase() == value.to_lowercase(),
+                    TextOp::NotEqual => text.to_lowercase() != value.to_lowercase(),
+                    TextOp::Like => glob_match(value.as_bytes(), text.as_bytes()),
+                }
+            }
+            Expr::Size(compare, value) => compare.holds(size, *value),
+            // Files without a time match no condition on it.
+            Expr::Time(compare, value) => modified.is_some_and(|t| {
+                let parts = [t.year, u16::from(t.month), u16::from(t.day), u16::from(t.hour), u16::from(t.minute), u16::from(t.second)];
+                compare.holds(&parts[..value.len()], value)
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let compare = |compare: &Compare| match compare {
+            Compare::Equal => "=",
+            Compare::NotEqual => "!=",
+            Compare::Less => "<",
+            Compare::LessOrEqual => "<=",
+            Compare::Greater => ">",
+            Compare::GreaterOrEqual => ">=",
+        };
+        match self {
+            Expr::And(left, right) => write!(f, "({} AND {})", left, right),
+            Expr::Or(left, right) => write!(f, "({} OR {})", left, right),
+            Expr::Not(expr) => write!(f, "NOT {}", expr),
+            Expr::Text(field, op, value) => {
+                let field = match field {
+                    TextField::Path => "path",
+                    TextField::Name => "name",
+                    TextField::Ext => "ext",
+                };
+                let op = match op {
+                    TextOp::Equal => "=",
+                    TextOp::NotEqual => "!=",
+                    TextOp::Like => "~",
+                };
+                write!(f, "{} {} '{}'", field, op, value)
+            }
+            Expr::Size(op, value) => write!(f, "size {} {}", compare(op), value),
+            Expr::Time(op, value) => {
+                let separators = ["", "-", "-", " ", ":", ":"];
+                let time: String = value.iter().zip(separators).map(|(part, sep)| format!("{}{:02}", sep, part)).collect();
+                write!(f, "mtime {} '{}'", compare(op), time)
+            }
+        }
+    }
+}
+
+/// The files of a backup matching a query, as found by [`run_query`].
+#[derive(Debug, Clone, Default)]
+pub struct QueryMatches {
+    /// In the order of the archives; a file backed up by several runs is
+    /// there once for each.
+    pub files: Vec<EntryInfo>,
+    pub errors: Vec<ArchiveError>,
+}
+
+/// Finds the files of `set` matching `query`.
+pub fn run_query(set: &BackupSet, query: &Query) -> QueryMatches {
+    let mut matches = QueryMatches::default();
+    for entry in set.entries() {
+        match entry {
+            Ok(entry) if !entry.is_dir && query.matches(entry.path(), entry.size, entry.modified.map(BackupTime::from)) => {
+                matches.files.push(entry)
+            }
+            Ok(_) => {}
+            Err(e) => matches.errors.push(e),
+        }
+    }
+    matches
+}
+
+fn error(position: usize, message: impl Into<String>) -> QueryError {
+    QueryError { position, message: message.into() }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// A field name, keyword or unquoted value.
+    Word,
+    Quoted,
+    Operator,
+    Open,
+    Close,
+}
+
+#[derive(Debug)]
+struct Token {
+    kind: Kind,
+    text: String,
+    position: usize,
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, QueryError> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let (kind, text) = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' | ')' => {
+                i += 1;
+                (if c == '(' { Kind::Open } else { Kind::Close }, c.to_string())
+            }
+            '\'' | '"' => {
+                let end = chars[i + 1..].iter().position(|&d| d == c).ok_or_else(|| error(start, "unclosed quote"))?;
+                i += end + 2;
+                (Kind::Quoted, chars[start + 1..start + 1 + end].iter().collect())
+            }
+            '=' | '~' => {
+                i += 1;
+                (Kind::Operator, c.to_string())
+            }
+            '!' | '<' | '>' => {
+                i += 1;
+                if chars.get(i) == Some(&'=') {
+                    i += 1;
+                } else if c == '!' {
+                    return Err(error(start, "expected != "));
+                }
+                (Kind::Operator, chars[start..i].iter().collect())
+            }
+            _ => {
+                while i < chars.len() && !chars[i].is_whitespace() && !"()'\"=~!<>".contains(chars[i]) {
+                    i += 1;
+                }
+                (Kind::Word, chars[start..i].iter().collect())
+            }
+        };
+        tokens.push(Token { kind, text, position: start });
+    }
+    Ok(tokens)
+}
+
+/// How deep parentheses and `NOT`s may nest; the parser recurses into
+/// each, and so would matching.
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    next: usize,
+    /// Length of the query, where a missing token is reported.
+    end: usize,
+    /// Parentheses and `NOT`s open at the next token.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek().is_some_and(|token| token.kind == Kind::Word && token.text.eq_ignore_ascii_case(keyword));
+        self.next += usize::from(found);
+        found
+    }
+
+    fn take(&mut self, what: &str) -> Result<&'a Token, QueryError> {
+        let token = self.peek().ok_or_else(|| error(self.end, format!("expected {}", what)))?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    /// Runs `parse` one level deeper, for the parenthesis or `NOT` of
+    /// `token`.
+    fn nested(&mut self, token: &Token, parse: fn(&mut Self) -> Result<Expr, QueryError>) -> Result<Expr, QueryError> {
+        if self.depth == MAX_DEPTH {
+            return Err(error(token.position, format!("nested more than {} deep", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and()?;
+        while self.keyword("OR") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.not()?;
+        while self.keyword("AND") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, QueryError> {
+        if let Some(token) = self.peek()
+            && self.keyword("NOT")
+        {
+            return Ok(Expr::Not(Box::new(self.nested(token, Self::not)?)));
+        }
+        self.condition()
+    }
+
+    fn condition(&mut self) -> Result<Expr, QueryError> {
+        let token = self.take("a condition")?;
+        if token.kind == Kind::Open {
+            let expr = self.nested(token, Self::or)?;
+            let close = self.take(")")?;
+            if close.kind != Kind::Close {
+                return Err(error(close.position, format!("expected ), got {}", close.text)));
+            }
+            return Ok(expr);
+        }
+        let field = token.text.to_ascii_lowercase();
+        if token.kind != Kind::Word || !["path", "name", "ext", "size", "mtime"].contains(&field.as_str()) {
+            return Err(error(token.position, format!("expected path, name, ext, size or mtime, got {}", token.text)));
+        }
+        let op = self.take("an operator")?;
+        if op.kind != Kind::Operator {
+            return Err(error(op.position, format!("expected an operator such as = or >, got {}", op.text)));
+        }
+        let value = self.take("a value")?;
+        if !matches!(value.kind, Kind::Word | Kind::Quoted) {
+            return Err(error(value.position, format!("expected a value, got {}", value.text)));
+        }
+        let compare = match op.text.as_str() {
+            "=" => Some(Compare::Equal),
+            "!=" => Some(Compare::NotEqual),
+            "<" => Some(Compare::Less),
+            "<=" => Some(Compare::LessOrEqual),
+            ">" => Some(Compare::Greater),
+            ">=" => Some(Compare::GreaterOrEqual),
+            _ => None,
+        };
+        match field.as_str() {
+            "size" => {
+                let compare = compare.ok_or_else(|| error(op.position, "compare sizes with =, !=, <, <=, > or >="))?;
+                let size = parse_size(&value.text).ok_or_else(|| error(value.position, format!("expected a size such as 5M, got {}", value.text)))?;
+                Ok(Expr::Size(compare, size))
+            }
+            "mtime" => {
+                let compare = compare.ok_or_else(|| error(op.position, "compare times with =, !=, <, <=, > or >="))?;
+                let time = parse_time(&value.text)
+                    .ok_or_else(|| error(value.position, format!("expected a time such as 2019-06-30 or 2019-06-30 14:05, got {}", value.text)))?;
+                Ok(Expr::Time(compare, time))
+            }
+            _ => {
+                let field = match field.as_str() {
+                    "path" => TextField::Path,
+                    "name" => TextField::Name,
+                    _ => TextField::Ext,
+                };
+                let op = match op.text.as_str() {
+                    "=" => TextOp::Equal,
+                    "!=" => TextOp::NotEqual,
+                    "~" => TextOp::Like,
+                    _ => return Err(error(op.position, "compare text with =, != or ~")),
+                };
+                let mut text = value.text.clone();
+                if field == TextField::Ext {
+                    text = text.trim_start_matches('.').to_string();
+                } else if field == TextField::Path {
+                    text = text.replace('\\', "/").trim_matches('/').to_string();
+                }
+                Ok(Expr::Text(field, op, text))
+            }
+        }
+    }
+}
+
+/// A time as precise as written, from the year to the second, as its
+/// parts.
+fn parse_time(value: &str) -> Option<Vec<u16>> {
+    let (date, time) = value.trim().split_once([' ', 'T']).unwrap_or((value.trim(), ""));
+    let mut parts = Vec::new();
+    for part in date.split('-').chain(time.split(':').filter(|part| !part.is_empty())) {
+        parts.push(part.parse::<u16>().ok()?);
+    }
+    let limits = [(1, 9999), (1, 12), (1, 31), (0, 23), (0, 59), (0, 59)];
+    let valid = parts.len() <= limits.len() && (date.split('-').count() == 3 || time.is_empty());
+    (valid && parts.iter().zip(limits).all(|(part, (min, max))| (min..=max).contains(part))).then_some(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str) -> Query {
+        Query::parse(query).unwrap_or_else(|e| panic!("{:?}: {}", query, e))
+    }
+
+    fn at(year: u16, month: u8, day: u8) -> Option<BackupTime> {
+        Some(BackupTime { year, month, day, hour: 12, minute: 0, second: 0 })
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let query = parse("ext = a OR ext = b AND size > 1K");
+        assert_eq!(query.to_string(), "(ext = 'a' OR (ext = 'b' AND size > 1024))");
+        assert!(query.matches("x.a", 0, None));
+        assert!(!query.matches("x.b", 0, None));
+        assert!(query.matches("x.b", 2048, None));
+        let query = parse("(ext = a OR ext = b) AND size > 1K");
+        assert!(!query.matches("x.a", 0, None));
+    }
+
+    #[test]
+    fn not_applies_to_the_next_condition() {
+        let query = parse("NOT ext = a AND name ~ 'x*'");
+        assert_eq!(query.to_string(), "(NOT ext = 'a' AND name ~ 'x*')");
+        assert!(query.matches("dir/x.b", 0, None));
+        assert!(!query.matches("dir/x.a", 0, None));
+        assert!(!query.matches("dir/y.b", 0, None));
+        assert!(parse("not not ext = a").matches("x.a", 0, None));
+        assert!(parse("NOT (ext = a or ext = b)").matches("x.c", 0, None));
+    }
+
+    #[test]
+    fn unclosed_quotes_are_reported_where_they_open() {
+        let e = Query::parse("name = 'abc").unwrap_err();
+        assert_eq!((e.position, e.message.as_str()), (7, "unclosed quote"));
+        let e = Query::parse("path = \"x\" AND name = \"y").unwrap_err();
+        assert_eq!(e.position, 22);
+        assert_eq!(e.to_string(), "unclosed quote at character 23");
+        // The other kind of quote does not close it.
+        assert_eq!(Query::parse("name = 'a\"").unwrap_err().position, 7);
+    }
+
+    #[test]
+    fn missing_parts_are_reported_at_the_end() {
+        let e = Query::parse("size >").unwrap_err();
+        assert_eq!((e.position, e.message.as_str()), (6, "expected a value"));
+        let e = Query::parse("(ext = a").unwrap_err();
+        assert_eq!((e.position, e.message.as_str()), (8, "expected )"));
+        let e = Query::parse("ext = a ext = b").unwrap_err();
+        assert_eq!((e.position, e.message.as_str()), (8, "expected AND or OR, got ext"));
+    }
+
+    #[test]
+    fn deep_nesting_is_refused() {
+        assert!(Query::parse(&format!("{}ext = a{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH))).is_ok());
+        let e = Query::parse(&format!("{}ext = a{}", "(".repeat(100_000), ")".repeat(100_000))).unwrap_err();
+        assert_eq!((e.position, e.message.as_str()), (MAX_DEPTH, "nested more than 64 deep"));
+        let e = Query::parse(&format!("{}ext = a", "NOT ".repeat(MAX_DEPTH + 1))).unwrap_err();
+        assert_eq!(e.position, MAX_DEPTH * 4);
+    }
+
+    #[test]
+    fn times_compare_at_the_precision_written() {
+        let in_2019 = parse("mtime = '2019'");
+        assert!(in_2019.matches("a", 0, at(2019, 1, 1)));
+        assert!(in_2019.matches("a", 0, at(2019, 12, 31)));
+        assert!(!in_2019.matches("a", 0, at(2020, 1, 1)));
+        let after_2019 = parse("mtime > '2019'");
+        assert!(!after_2019.matches("a", 0, at(2019, 12, 31)));
+        assert!(after_2019.matches("a", 0, at(2020, 1, 1)));
+        let from_june = parse("mtime >= '2019-06-30 12:00'");
+        assert!(from_june.matches("a", 0, at(2019, 6, 30)));
+        assert!(!from_june.matches("a", 0, at(2019, 6, 29)));
+        assert!(!parse("mtime != '2019'").matches("a", 0, None));
+        assert_eq!(Query::parse("mtime < '2019-13'").unwrap_err().position, 8);
+    }
+
+    #[test]
+    fn sizes_take_binary_suffixes() {
+        let size = |query: &str| match parse(query).expr {
+            Expr::Size(_, size) => size,
+            expr => panic!("{:?}: {}", query, expr),
+        };
+        assert_eq!(size("size = 512"), 512);
+        assert_eq!(size("size = 5k"), 5 << 10);
+        assert_eq!(size("size = 5M"), 5 << 20);
+        assert_eq!(size("size = 1.5GB"), 3 << 29);
+        assert_eq!(size("size = 2GiB"), 2 << 30);
+        assert_eq!(size("size = '1 TB'"), 1 << 40);
+        let e = Query::parse("size > 5X").unwrap_err();
+        assert_eq!((e.position, e.message.as_str()), (7, "expected a size such as 5M, got 5X"));
+        assert_eq!(Query::parse("size ~ 5M").unwrap_err().position, 5);
+    }
+
+    #[test]
+    fn extensions_and_paths_are_normalized() {
+        let query = parse("ext = '.JPG'");
+        assert_eq!(query.to_string(), "ext = 'JPG'");
+        assert!(query.matches("Photos/a.jpg", 0, None));
+        assert!(!query.matches("Photos/jpg", 0, None));
+        let query = parse(r"path = '\Users\bob\Desktop\'");
+        assert_eq!(query.to_string(), "path = 'Users/bob/Desktop'");
+        assert!(query.matches("users/Bob/desktop", 0, None));
+        assert!(parse("path ~ 'Users/*/Desktop/*'").matches("Users/bob/Desktop/a.txt", 0, None));
+    }
+}
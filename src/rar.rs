@@ -0,0 +1,153 @@
+//! RAR archives (feature `rar`), read with the UnRAR library.
+//!
+//! UnRAR extracts files front to back, following multi-volume archives
+//! (`name.part1.rar`, `name.part2.rar`, ... or `name.rar`, `name.r00`, ...)
+//! from the first volume on, so a RAR archive is restored in archive order
+//! by one reader. Each file is decompressed into memory before it is
+//! handed to the writers.
+
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use unrar::error::{Code, UnrarError};
+
+use crate::archive::{ArchiveReader, Member};
+use crate::catalog::BackupTime;
+
+#[derive(Debug, Clone)]
+struct RarEntry {
+    name: String,
+    size: u64,
+    modified: Option<BackupTime>,
+    attributes: u32,
+    encrypted: bool,
+}
+
+/// The files of a RAR archive, over all its volumes.
+pub(crate) struct RarArchive {
+    /// The first volume.
+    path: PathBuf,
+    entries: Vec<RarEntry>,
+}
+
+impl RarArchive {
+    pub(crate) fn open(path: &Path) -> io::Result<RarArchive> {
+        let mut entries = Vec::new();
+        for header in unrar::Archive::new(path).open_for_listing().map_err(io_error)? {
+            let header = header.map_err(io_error)?;
+            if !header.is_file() {
+                continue;
+            }
+            let date = zip::DateTime::try_from_msdos((header.file_time >> 16) as u16, header.file_time as u16).ok();
+            entries.push(RarEntry {
+                name: header.filename.to_string_lossy().replace('\\', "/"),
+                size: header.unpacked_size,
+                modified: date.map(|d| BackupTime {
+                    year: d.year(),
+                    month: d.month(),
+                    day: d.day(),
+                    hour: d.hour(),
+                    minute: d.minute(),
+                    second: d.second(),
+                }),
+                attributes: header.file_attr,
+                encrypted: header.is_encrypted(),
+            });
+        }
+        Ok(RarArchive { path: path.to_path_buf(), entries })
+    }
+}
+
+fn io_error(e: UnrarError) -> io::Error {
+    match e.code {
+        Code::MissingPassword | Code::BadPassword => {
+            io::Error::new(io::ErrorKind::Unsupported, "encrypted RAR archives are not supported")
+        }
+        Code::EOpen => io::Error::new(io::ErrorKind::NotFound, e.to_string()),
+        _ => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+    }
+}
+
+impl ArchiveReader for RarArchive {
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn member(&self, j: usize) -> Member<'_> {
+        let entry = &self.entries[j];
+        Member { name: &entry.name, size: entry.size, modified: entry.modified, attributes: Some(entry.attributes) }
+    }
+
+    fn read(&self, j: usize, f: &mut dyn FnMut(&mut dyn Read)) -> io::Result<()> {
+        let mut result = Ok(());
+        self.read_in_order(&mut |k, data| {
+            if k < j {
+                return true;
+            }
+            match data {
+                Ok(data) => f(data),
+                Err(e) => result = Err(e),
+            }
+            false
+        });
+        result
+    }
+
+    fn sequential(&self) -> bool {
+        true
+    }
+
+    fn read_in_order(&self, f: &mut dyn FnMut(usize, io::Result<&mut dyn Read>) -> bool) {
+        let mut next = 0;
+        let error = 'read: {
+            let mut archive = match unrar::Archive::new(&self.path).open_for_processing() {
+                Ok(archive) => archive,
+                Err(e) => break 'read io_error(e),
+            };
+            loop {
+                let file = match archive.read_header() {
+                    Ok(Some(file)) => file,
+                    Ok(None) => return,
+                    Err(e) => break 'read io_error(e),
+                };
+                if !file.entry().is_file() {
+                    archive = match file.skip() {
+                        Ok(archive) => archive,
+                        Err(e) => break 'read io_error(e),
+                    };
+                    continue;
+                }
+                let j = next;
+                let Some(entry) = self.entries.get(j) else { return };
+                next += 1;
+                if entry.encrypted {
+                    let go = f(j, Err(io::Error::new(io::ErrorKind::Unsupported, "encrypted RAR files are not supported")));
+                    archive = match file.skip() {
+                        Ok(archive) if go => archive,
+                        Ok(_) => return,
+                        Err(e) => break 'read io_error(e),
+                    };
+                    continue;
+                }
+                match file.read() {
+                    Ok((data, rest)) => {
+                        if !f(j, Ok(&mut data.as_slice())) {
+                            return;
+                        }
+                        archive = rest;
+                    }
+                    // UnRAR cannot go on after a failed file.
+                    Err(e) => {
+                        next -= 1;
+                        break 'read io_error(e);
+                    }
+                }
+            }
+        };
+        for j in next..self.entries.len() {
+            if !f(j, Err(io::Error::new(error.kind(), error.to_string()))) {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+
+use crate::page_cache;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+enum Data {
+    File(File),
+    Mapped(Mmap),
+}
+
+impl Data {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        match self {
+            Data::File(file) => read_at(file, buf, offset),
+            Data::Mapped(map) => {
+                let start = usize::try_from(offset).unwrap_or(usize::MAX).min(map.len());
+                let n = buf.len().min(map.len() - start);
+                buf[..n].copy_from_slice(&map[start..start + n]);
+                Ok(n)
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+/// An opened archive file, read either through a buffer or a memory map.
+///
+/// Reads are positional, so clones are cheap and each keeps its own offset:
+/// several threads can read entries of the same archive at once.
+pub(crate) struct ArchiveFile {
+    data: Arc<Data>,
+    len: u64,
+    pos: u64,
+    buf: Vec<u8>,
+    /// File offset of `buf[0]`.
+    buf_start: u64,
+}
+
+impl ArchiveFile {
+    /// Opens `path`, memory-mapping it when `mmap` is set.
+    ///
+    /// Mapping is fast for random access on local disks, but the archive must
+    /// not be modified while it is mapped; on network filesystems a
+    /// truncated file can even crash the process, so buffered reads stay
+    /// the default.
+    pub(crate) fn open(path: &Path, mmap: bool) -> io::Result<Self> {
+        let file = page_cache::open_sequential(path)?;
+        let len = file.metadata()?.len();
+        let data = if mmap {
+            // SAFETY: the map is only read, and the caller opted in to the
+            // requirement that backup archives are not changed during a run.
+            let map = unsafe { Mmap::map(&file)? };
+            #[cfg(unix)]
+            let _ = map.advise(memmap2::Advice::Sequential);
+            Data::Mapped(map)
+        } else {
+            Data::File(file)
+        };
+        Ok(ArchiveFile { data: Arc::new(data), len, pos: 0, buf: Vec::new(), buf_start: 0 })
+    }
+
+    fn buffered(&self) -> Option<&[u8]> {
+        let offset = usize::try_from(self.pos.checked_sub(self.buf_start)?).ok()?;
+        self.buf.get(offset..).filter(|rest| !rest.is_empty())
+    }
+}
+
+impl Clone for ArchiveFile {
+    fn clone(&self) -> Self {
+        ArchiveFile { data: Arc::clone(&self.data), len: self.len, pos: self.pos, buf: Vec::new(), buf_start: 0 }
+    }
+}
+
+impl Read for ArchiveFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.buffered().is_none() {
+            // Large reads and mapped files skip the buffer.
+            if buf.len() >= BUFFER_SIZE || matches!(*self.data, Data::Mapped(_)) {
+                let n = self.data.read_at(buf, self.pos)?;
+                self.pos += n as u64;
+                return Ok(n);
+            }
+            self.buf.resize(BUFFER_SIZE, 0);
+            let n = self.data.read_at(&mut self.buf, self.pos)?;
+            self.buf.truncate(n);
+            self.buf_start = self.pos;
+        }
+        let Some(available) = self.buffered() else { return Ok(0) };
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for ArchiveFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = target
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        Ok(self.pos)
+    }
+}
@@ -0,0 +1,129 @@
+//! Well-known places of files that are hard to replace and small enough to
+//! restore first: saved games, SSH keys, certificates, wallets and license
+//! files.
+
+use crate::backup_set::BackupSet;
+use crate::error::ArchiveError;
+use crate::filter::Filter;
+use crate::paths::profile_owner;
+
+/// A kind of file [`find_recoverables`] looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RecoverableKind {
+    SavedGames,
+    SshKeys,
+    Certificates,
+    Wallets,
+    Licenses,
+}
+
+impl RecoverableKind {
+    pub const ALL: [RecoverableKind; 5] = [
+        RecoverableKind::SavedGames,
+        RecoverableKind::SshKeys,
+        RecoverableKind::Certificates,
+        RecoverableKind::Wallets,
+        RecoverableKind::Licenses,
+    ];
+
+    /// Name shown to the user.
+    pub fn label(self) -> &'static str {
+        match self {
+            RecoverableKind::SavedGames => "Saved games",
+            RecoverableKind::SshKeys => "SSH and PGP keys",
+            RecoverableKind::Certificates => "Certificates",
+            RecoverableKind::Wallets => "Wallets and password vaults",
+            RecoverableKind::Licenses => "License files",
+        }
+    }
+
+    /// Patterns of the files of this kind, see [`Filter`].
+    fn patterns(self) -> &'static [&'static str] {
+        match self {
+            RecoverableKind::SavedGames => &[
+                "**/Users/*/Saved Games/**",
+                "**/Users/*/Documents/My Games/**",
+                "**/Users/*/AppData/Roaming/.minecraft/saves/**",
+                "**/Steam/userdata/**",
+                "**/Documents and Settings/*/My Documents/My Games/**",
+            ],
+            RecoverableKind::SshKeys => &["**/.ssh/id_*", "**/.ssh/config", "*.ppk", "**/.gnupg/**", "**/AppData/Roaming/gnupg/**"],
+            RecoverableKind::Certificates => &[
+                "*.pfx",
+                "*.p12",
+                "**/AppData/Roaming/Microsoft/SystemCertificates/My/**",
+                "**/AppData/Roaming/Microsoft/Crypto/**",
+            ],
+            RecoverableKind::Wallets => &[
+                "wallet.dat",
+                "*.wallet",
+                "**/AppData/Roaming/Electrum/wallets/**",
+                "**/AppData/Roaming/Ethereum/keystore/**",
+                "**/AppData/Roaming/Exodus/exodus.wallet/**",
+                "*.kdbx",
+                "*.1pif",
+            ],
+            RecoverableKind::Licenses => &["*.lic", "*.license", "license.dat", "*.licx"],
+        }
+    }
+
+    fn filter(self) -> Filter {
+        self.patterns().iter().fold(Filter::new(), |filter, pattern| filter.include(*pattern))
+    }
+}
+
+/// A file of a [`RecoverableKind`] found in the backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recoverable {
+    pub kind: RecoverableKind,
+    /// Restore path, e.g. `Users/bob/.ssh/id_ed25519`.
+    pub path: String,
+    pub size: u64,
+    /// The Windows user whose profile holds the file, if it is in one.
+    pub owner: Option<String>,
+}
+
+/// What [`find_recoverables`] found.
+#[derive(Debug, Clone, Default)]
+pub struct Recoverables {
+    /// Files found, by kind and then in archive order.
+    pub found: Vec<Recoverable>,
+    pub errors: Vec<ArchiveError>,
+}
+
+impl Recoverables {
+    /// The files of `kind`.
+    pub fn of_kind(&self, kind: RecoverableKind) -> impl Iterator<Item = &Recoverable> {
+        self.found.iter().filter(move |r| r.kind == kind)
+    }
+}
+
+/// Reads the central directories of `set` and collects the files of every
+/// [`RecoverableKind`]. A file matching several kinds counts for the first.
+pub fn find_recoverables(set: &BackupSet) -> Recoverables {
+    let filters: Vec<(RecoverableKind, Filter)> = RecoverableKind::ALL.iter().map(|&kind| (kind, kind.filter())).collect();
+    let mut result = Recoverables::default();
+    for entry in set.entries() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                result.errors.push(e);
+                continue;
+            }
+        };
+        if entry.is_dir {
+            continue;
+        }
+        let path = entry.path();
+        if let Some((kind, _)) = filters.iter().find(|(_, filter)| filter.matches(path)) {
+            result.found.push(Recoverable {
+                kind: *kind,
+                path: path.to_string(),
+                size: entry.size,
+                owner: profile_owner(path).map(String::from),
+            });
+        }
+    }
+    result.found.sort_by_key(|r| r.kind);
+    result
+}
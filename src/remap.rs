@@ -0,0 +1,425 @@
+//! Restoring a user profile under another name: `Users/Old/...` of the
+//! backup becomes `Users/New/...`, and optionally the absolute paths inside
+//! the `.lnk` shortcuts of the profile follow.
+
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::backup_set::BackupSet;
+
+/// Profile folders Windows creates for itself rather than for a user.
+const SHARED_PROFILES: &[&str] = &["Public", "Default", "Default User", "All Users", "LocalService", "NetworkService"];
+
+/// Folders user profiles lie in.
+fn is_profiles_dir(name: &str) -> bool {
+    name.eq_ignore_ascii_case("users") || name.eq_ignore_ascii_case("documents and settings")
+}
+
+/// `path`, with `sep` between folders, with the profile folder of the
+/// first user of `users` it lies in renamed to the user's new name.
+fn remap_with<'p>(path: &'p str, sep: char, users: &[(String, String)]) -> Cow<'p, str> {
+    let mut start = 0;
+    let mut parent = "";
+    for component in path.split(sep) {
+        if is_profiles_dir(parent)
+            && let Some((_, new)) = users.iter().find(|(old, _)| old.eq_ignore_ascii_case(component))
+        {
+            let end = start + component.len();
+            return Cow::Owned(format!("{}{}{}", &path[..start], new, &path[end..]));
+        }
+        parent = component;
+        start += component.len() + sep.len_utf8();
+    }
+    Cow::Borrowed(path)
+}
+
+/// The restore path `path`, with `/` between folders, with the profile of a
+/// user in `users` renamed, see [`crate::RestorerBuilder::remap_user`].
+pub(crate) fn remap_profile<'p>(path: &'p str, users: &[(String, String)]) -> Cow<'p, str> {
+    remap_with(path, '/', users)
+}
+
+/// The real users with a profile in `set`.
+pub fn backup_profiles(set: &BackupSet) -> BTreeSet<String> {
+    let mut users = BTreeSet::new();
+    for entry in set.entries().filter_map(Result::ok) {
+        let path = entry.path().to_string();
+        let components: Vec<&str> = path.split('/').collect();
+        // The profile folder itself, or anything inside it.
+        let Some(k) = components.iter().position(|c| is_profiles_dir(c)) else { continue };
+        let Some(&user) = components.get(k + 1) else { continue };
+        if !user.is_empty() && (entry.is_dir || components.len() > k + 2) && !is_shared(user) {
+            users.insert(user.to_string());
+        }
+    }
+    users
+}
+
+/// The real users with a profile in `dest/Users`.
+pub fn target_profiles(dest: &Path) -> BTreeSet<String> {
+    let Ok(dirs) = fs::read_dir(dest.join("Users")) else { return BTreeSet::new() };
+    dirs.filter_map(Result::ok)
+        .filter(|dir| dir.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|dir| dir.file_name().into_string().ok())
+        .filter(|name| !is_shared(name))
+        .collect()
+}
+
+/// The old and new name of the user when `set` holds exactly one user
+/// profile and the `Users` folder of `dest` exactly one other.
+pub fn detect_user_remap(set: &BackupSet, dest: &Path) -> Option<(String, String)> {
+    let single = |users: BTreeSet<String>| (users.len() == 1).then(|| users.into_iter().next()).flatten();
+    let old = single(backup_profiles(set))?;
+    let new = single(target_profiles(dest))?;
+    (!old.eq_ignore_ascii_case(&new)).then_some((old, new))
+}
+
+fn is_shared(user: &str) -> bool {
+    SHARED_PROFILES.iter().any(|shared| shared.eq_ignore_ascii_case(user))
+}
+
+/// Link flags of a shell link, see `[MS-SHLLINK]` 2.1.1.
+const HAS_LINK_TARGET_ID_LIST: u32 = 0x1;
+const HAS_LINK_INFO: u32 = 0x2;
+const IS_UNICODE: u32 = 0x80;
+/// The `HasName` to `HasIconLocation` flags, one per string of the
+/// StringData section, in their order.
+const STRING_FLAGS: [u32; 5] = [0x4, 0x8, 0x10, 0x20, 0x40];
+const HEADER_SIZE: usize = 0x4c;
+/// The largest shortcut renamed in; shortcuts take a few KB, and
+/// [`remap_shortcut`] holds one in memory.
+pub(crate) const MAX_SHORTCUT_SIZE: u64 = 1 << 20;
+/// LinkInfo flags.
+const VOLUME_ID_AND_LOCAL_BASE_PATH: u32 = 0x1;
+/// ExtraData blocks holding a path in a fixed ANSI and a fixed UTF-16
+/// buffer: EnvironmentVariableDataBlock and IconEnvironmentDataBlock.
+const ENVIRONMENT_BLOCKS: [u32; 2] = [0xa000_0001, 0xa000_0007];
+
+/// The shell link `data` with the profile paths of `users` in it renamed,
+/// or `None` if it is not one, or refers to none of them. The target ID
+/// list names the target by shell items that cannot be renamed safely; it
+/// is left out when the path of the LinkInfo was renamed, so Windows
+/// resolves the link by that path.
+pub(crate) fn remap_shortcut(data: &[u8], users: &[(String, String)]) -> Option<Vec<u8>> {
+    let mut link = Link { data, pos: 0 };
+    let header = link.take(HEADER_SIZE)?;
+    if u32_at(header, 0)? != HEADER_SIZE as u32 {
+        return None;
+    }
+    let mut flags = u32_at(header, 0x14)?;
+    let id_list = if flags & HAS_LINK_TARGET_ID_LIST != 0 {
+        let size = u16::from_le_bytes(link.take(2)?.try_into().ok()?) as usize;
+        Some(link.take(size)?)
+    } else {
+        None
+    };
+    let link_info = if flags & HAS_LINK_INFO != 0 {
+        let size = u32_at(link.data, link.pos)? as usize;
+        let info = link.take(size)?;
+        Some(remap_link_info(info, users).map_or(Cow::Borrowed(info), Cow::Owned))
+    } else {
+        None
+    };
+    let info_remapped = matches!(link_info, Some(Cow::Owned(_)));
+    let mut changed = info_remapped;
+    let unicode = flags & IS_UNICODE != 0;
+    let mut strings = Vec::new();
+    for flag in STRING_FLAGS {
+        if flags & flag == 0 {
+            continue;
+        }
+        let count = u16::from_le_bytes(link.take(2)?.try_into().ok()?) as usize;
+        let raw = link.take(if unicode { count * 2 } else { count })?;
+        let string = decode(raw, unicode)?;
+        let remapped = remap_with(&string, '\\', users);
+        changed |= matches!(remapped, Cow::Owned(_));
+        strings.push(encode(&remapped, unicode)?);
+    }
+    // ExtraData, up to the terminal block.
+    let mut extra = Vec::new();
+    while let Some(size) = u32_at(link.data, link.pos) {
+        if size < 8 {
+            break;
+        }
+        let mut block = link.take(size as usize)?.to_vec();
+        if ENVIRONMENT_BLOCKS.contains(&u32_at(&block, 4)?) && block.len() >= 8 + 260 + 520 {
+            changed |= remap_fixed(&mut block[8..8 + 260], false, users);
+            changed |= remap_fixed(&mut block[8 + 260..8 + 780], true, users);
+        }
+        extra.extend_from_slice(&block);
+    }
+    if !changed {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(header);
+    let id_list = id_list.filter(|_| !info_remapped);
+    if id_list.is_none() {
+        flags &= !HAS_LINK_TARGET_ID_LIST;
+    }
+    out[0x14..0x18].copy_from_slice(&flags.to_le_bytes());
+    if let Some(id_list) = id_list {
+        out.extend_from_slice(&(id_list.len() as u16).to_le_bytes());
+        out.extend_from_slice(id_list);
+    }
+    if let Some(info) = link_info {
+        out.extend_from_slice(&info);
+    }
+    for string in strings {
+        let count = if unicode { string.len() / 2 } else { string.len() };
+        out.extend_from_slice(&u16::try_from(count).ok()?.to_le_bytes());
+        out.extend_from_slice(&string);
+    }
+    out.extend_from_slice(&extra);
+    // The terminal block and whatever follows it.
+    out.extend_from_slice(&link.data[link.pos..]);
+    Some(out)
+}
+
+/// A LinkInfo structure with its local base path renamed, or `None` if that
+/// refers to none of `users`. The other parts are kept as they are.
+fn remap_link_info(info: &[u8], users: &[(String, String)]) -> Option<Vec<u8>> {
+    let header_size = u32_at(info, 4)? as usize;
+    let link_flags = u32_at(info, 8)?;
+    if link_flags & VOLUME_ID_AND_LOCAL_BASE_PATH == 0 {
+        return None;
+    }
+    let offset = |at: usize| u32_at(info, at).map(|offset| offset as usize);
+    let volume_id = offset(0x0c)?;
+    let volume_id = info.get(volume_id..volume_id + u32_at(info, volume_id)? as usize)?;
+    let base_path = nul_terminated(info, offset(0x10)?, false)?;
+    let network = match offset(0x14)? {
+        0 => None,
+        at => Some(info.get(at..at + u32_at(info, at)? as usize)?),
+    };
+    let suffix = nul_terminated(info, offset(0x18)?, false)?;
+    let unicode = header_size >= 0x24;
+    let (base_path_unicode, suffix_unicode) = if unicode {
+        let suffix = match offset(0x20)? {
+            0 => None,
+            at => Some(nul_terminated(info, at, true)?),
+        };
+        (Some(nul_terminated(info, offset(0x1c)?, true)?), suffix)
+    } else {
+        (None, None)
+    };
+
+    let ansi = decode(base_path, false)?;
+    let remapped = remap_with(&ansi, '\\', users);
+    let unicode_path = match base_path_unicode {
+        Some(raw) => Some(decode(raw, true)?),
+        None => None,
+    };
+    let remapped_unicode = unicode_path.as_deref().map(|path| remap_with(path, '\\', users));
+    if matches!(remapped, Cow::Borrowed(_)) && !matches!(remapped_unicode, Some(Cow::Owned(_))) {
+        return None;
+    }
+
+    // Rebuilt in the usual order: header, VolumeID, LocalBasePath,
+    // CommonNetworkRelativeLink, CommonPathSuffix and their Unicode forms.
+    let mut out = info[..header_size].to_vec();
+    let put = |out: &mut Vec<u8>, at: usize, part: &[u8]| {
+        let offset = out.len() as u32;
+        out[at..at + 4].copy_from_slice(&offset.to_le_bytes());
+        out.extend_from_slice(part);
+    };
+    put(&mut out, 0x0c, volume_id);
+    let mut base_path = encode(&remapped, false)?;
+    base_path.push(0);
+    put(&mut out, 0x10, &base_path);
+    if let Some(network) = network {
+        put(&mut out, 0x14, network);
+    }
+    put(&mut out, 0x18, &[suffix, &[0]].concat());
+    if let Some(path) = remapped_unicode {
+        let mut path = encode(&path, true)?;
+        path.extend_from_slice(&[0, 0]);
+        put(&mut out, 0x1c, &path);
+        if let Some(suffix) = suffix_unicode {
+            put(&mut out, 0x20, &[suffix, &[0, 0]].concat());
+        }
+    }
+    let size = out.len() as u32;
+    out[0..4].copy_from_slice(&size.to_le_bytes());
+    Some(out)
+}
+
+/// Renames the profile path in the NUL-padded buffer `buf` in place, if it
+/// refers to one of `users` and the new path fits.
+fn remap_fixed(buf: &mut [u8], unicode: bool, users: &[(String, String)]) -> bool {
+    let Some(raw) = nul_terminated(buf, 0, unicode) else { return false };
+    let Some(path) = decode(raw, unicode) else { return false };
+    let Cow::Owned(remapped) = remap_with(&path, '\\', users) else { return false };
+    let Some(encoded) = encode(&remapped, unicode) else { return false };
+    let nul = if unicode { 2 } else { 1 };
+    if encoded.len() + nul > buf.len() {
+        return false;
+    }
+    buf.fill(0);
+    buf[..encoded.len()].copy_from_slice(&encoded);
+    true
+}
+
+/// Reads the sections of a shell link one after another.
+struct Link<'d> {
+    data: &'d [u8],
+    pos: usize,
+}
+
+impl<'d> Link<'d> {
+    fn take(&mut self, len: usize) -> Option<&'d [u8]> {
+        let part = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(part)
+    }
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at.checked_add(4)?)?.try_into().ok()?))
+}
+
+/// The string at `at` in `data` up to its NUL, without it.
+fn nul_terminated(data: &[u8], at: usize, unicode: bool) -> Option<&[u8]> {
+    let rest = data.get(at..)?;
+    let len = if unicode {
+        rest.chunks_exact(2).position(|c| c == [0, 0])? * 2
+    } else {
+        rest.iter().position(|&b| b == 0)?
+    };
+    Some(&rest[..len])
+}
+
+/// UTF-16LE, or the ANSI code page read as Latin-1, which keeps it intact
+/// for the ASCII paths that are renamed.
+fn decode(raw: &[u8], unicode: bool) -> Option<String> {
+    if unicode {
+        let units: Vec<u16> = raw.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+        String::from_utf16(&units).ok()
+    } else {
+        Some(raw.iter().map(|&b| b as char).collect())
+    }
+}
+
+/// The reverse of [`decode`]; `None` for characters the ANSI form cannot hold.
+fn encode(string: &str, unicode: bool) -> Option<Vec<u8>> {
+    if unicode {
+        Some(string.encode_utf16().flat_map(u16::to_le_bytes).collect())
+    } else {
+        string.chars().map(|c| u8::try_from(c).ok()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRING_DATA: u32 = 0x8 | 0x10;
+
+    fn users(old: &str, new: &str) -> Vec<(String, String)> {
+        vec![(old.to_string(), new.to_string())]
+    }
+
+    fn string(value: &str, unicode: bool, nul: bool) -> Vec<u8> {
+        let mut raw = encode(value, unicode).unwrap();
+        if nul {
+            raw.extend_from_slice(if unicode { &[0, 0] } else { &[0] });
+        }
+        raw
+    }
+
+    /// A LinkInfo with a volume ID, the local base path `base` in its ANSI
+    /// and Unicode forms, and empty suffixes.
+    fn link_info(base: &str) -> Vec<u8> {
+        let volume_id = [&0x10u32.to_le_bytes()[..], &[7; 12]].concat();
+        let parts = [volume_id, string(base, false, true), string("", false, true), string(base, true, true), string("", true, true)];
+        let mut info = vec![0; 0x24];
+        info[4..8].copy_from_slice(&0x24u32.to_le_bytes());
+        info[8..12].copy_from_slice(&VOLUME_ID_AND_LOCAL_BASE_PATH.to_le_bytes());
+        for (part, at) in parts.iter().zip([0x0c, 0x10, 0x18, 0x1c, 0x20]) {
+            let offset = info.len() as u32;
+            info[at..at + 4].copy_from_slice(&offset.to_le_bytes());
+            info.extend_from_slice(part);
+        }
+        let size = info.len() as u32;
+        info[0..4].copy_from_slice(&size.to_le_bytes());
+        info
+    }
+
+    /// A shell link with a target ID list, the LinkInfo of `base`, the
+    /// relative path and working folder of `strings` and no ExtraData.
+    fn shortcut(base: &str, strings: [&str; 2], unicode: bool) -> Vec<u8> {
+        let mut flags = HAS_LINK_TARGET_ID_LIST | HAS_LINK_INFO | STRING_DATA;
+        if unicode {
+            flags |= IS_UNICODE;
+        }
+        let mut data = vec![0; HEADER_SIZE];
+        data[0..4].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        data[0x14..0x18].copy_from_slice(&flags.to_le_bytes());
+        data.extend_from_slice(&[4, 0, 1, 2, 3, 4]);
+        data.extend_from_slice(&link_info(base));
+        for value in strings {
+            data.extend_from_slice(&(value.encode_utf16().count() as u16).to_le_bytes());
+            data.extend_from_slice(&string(value, unicode, false));
+        }
+        data.extend_from_slice(&[0; 4]);
+        data
+    }
+
+    /// The flags, the ANSI and Unicode local base paths, the StringData
+    /// and what follows it of the shell link `data`.
+    fn read(data: &[u8]) -> (u32, String, String, Vec<String>, Vec<u8>) {
+        let mut link = Link { data, pos: HEADER_SIZE };
+        let flags = u32_at(data, 0x14).unwrap();
+        if flags & HAS_LINK_TARGET_ID_LIST != 0 {
+            let size = u16::from_le_bytes(link.take(2).unwrap().try_into().unwrap());
+            link.take(size as usize).unwrap();
+        }
+        let info = link.take(u32_at(data, link.pos).unwrap() as usize).unwrap();
+        let path = |at: usize, unicode: bool| {
+            decode(nul_terminated(info, u32_at(info, at).unwrap() as usize, unicode).unwrap(), unicode).unwrap()
+        };
+        let unicode = flags & IS_UNICODE != 0;
+        let strings = (0..2)
+            .map(|_| {
+                let count = u16::from_le_bytes(link.take(2).unwrap().try_into().unwrap()) as usize;
+                decode(link.take(if unicode { count * 2 } else { count }).unwrap(), unicode).unwrap()
+            })
+            .collect();
+        (flags, path(0x10, false), path(0x1c, true), strings, data[link.pos..].to_vec())
+    }
+
+    #[test]
+    fn link_info_and_string_data_are_renamed() {
+        for unicode in [true, false] {
+            let original = shortcut(r"C:\Users\Old\a.txt", [r"..\..\Users\Old\a.txt", r"C:\Users\Old"], unicode);
+            let remapped = remap_shortcut(&original, &users("Old", "New")).unwrap();
+            let (flags, ansi, wide, strings, rest) = read(&remapped);
+            assert_eq!(flags & HAS_LINK_TARGET_ID_LIST, 0);
+            assert_eq!(ansi, r"C:\Users\New\a.txt");
+            assert_eq!(wide, r"C:\Users\New\a.txt");
+            assert_eq!(strings, [r"..\..\Users\New\a.txt", r"C:\Users\New"]);
+            assert_eq!(rest, [0; 4]);
+        }
+    }
+
+    #[test]
+    fn renaming_back_restores_the_paths() {
+        let original = shortcut(r"C:\Users\Old\Documents\b.docx", [r"Users\Old\b.docx", r"C:\Users\Old\Documents"], true);
+        let there = remap_shortcut(&original, &users("Old", "A much longer name")).unwrap();
+        let back = remap_shortcut(&there, &users("A much longer name", "Old")).unwrap();
+        let (flags, ansi, wide, strings, rest) = read(&original);
+        assert_eq!(read(&back), (flags & !HAS_LINK_TARGET_ID_LIST, ansi, wide, strings, rest));
+    }
+
+    #[test]
+    fn other_shortcuts_are_left_alone() {
+        let other = shortcut(r"C:\Users\Someone\a.txt", [r"a.txt", r"C:\Windows"], true);
+        assert_eq!(remap_shortcut(&other, &users("Old", "New")), None);
+        let truncated = shortcut(r"C:\Users\Old\a.txt", [r"a.txt", r"C:\Users\Old"], true);
+        assert_eq!(remap_shortcut(&truncated[..truncated.len() - 10], &users("Old", "New")), None);
+        assert_eq!(remap_shortcut(b"not a shortcut", &users("Old", "New")), None);
+    }
+}
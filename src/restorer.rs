@@ -0,0 +1,650 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Serializer};
+use zip::ZipArchive;
+
+use crate::apple_double::is_apple_double;
+use crate::audit::AuditLog;
+use crate::backup_set::{BackupSet, EntryInfo};
+use crate::builder::RestorerBuilder;
+use crate::cancel::CancellationToken;
+use crate::conflict::ConflictResolver;
+use crate::catalog::BackupTime;
+use crate::discovery::{find_archives, latest_backup_sets, select_archives};
+use crate::error::{ArchiveError, EntryError, Error, ErrorKind};
+use crate::manifest::ManifestWriter;
+use crate::options::{ConflictPolicy, DestLayout, ManifestFormat, Options, PlaceholderPolicy, SystemFilePolicy};
+use crate::paths::{Normalized, Unsafe, drive_letter, normalize_entry, strip_drive_letter};
+use crate::pipeline::{self, OrderStats, PipelineStats};
+use crate::progress::ProgressSink;
+use crate::system_files::is_system_file;
+use crate::reader::ArchiveFile;
+use crate::remap::remap_profile;
+use crate::selinux::{selinux_enabled, selinux_relabel};
+
+/// What a restore does with an entry as its name and the options decide,
+/// before it claims a target, see [`Restorer::admit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Admission {
+    /// Left out by the filter.
+    Filtered,
+    /// A cloud-only placeholder, left out, see [`PlaceholderPolicy`].
+    Placeholder,
+    /// AppleDouble metadata of macOS, left out.
+    AppleDouble,
+    /// A system file left out, see [`SystemFilePolicy::Skip`].
+    SystemFile,
+    /// Restored; below the folder of system files if `isolated`, see
+    /// [`SystemFilePolicy::Isolate`].
+    Restore { isolated: bool },
+}
+
+/// Folder of the destination that files failing their CRC check are moved
+/// to, at their restore path, each with a `.crc-mismatch` marker next to it.
+pub const QUARANTINE_DIR: &str = ".restore-quarantine";
+
+/// Result of extracting a single archive.
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveStats {
+    pub files: usize,
+    pub bytes: u64,
+    /// Entries rejected by the filter.
+    pub filtered: usize,
+    /// Entries not restored because the destination already existed.
+    pub skipped: usize,
+    pub overwritten: usize,
+    pub renamed: usize,
+    /// Cloud-only placeholders left out, see [`PlaceholderPolicy`].
+    pub placeholders: usize,
+    /// Their paths, with [`PlaceholderPolicy::List`].
+    pub placeholder_paths: Vec<String>,
+    /// System files left out or set apart, see [`SystemFilePolicy`].
+    pub system_files: usize,
+    /// AppleDouble files left out, see
+    /// [`crate::RestorerBuilder::keep_apple_double`].
+    pub apple_double: usize,
+    /// Entries whose names lost `.` segments, repeated separators or
+    /// trailing dots and spaces on the way.
+    pub normalized: usize,
+    /// Entries with absolute names restored below the destination, see
+    /// [`crate::AbsoluteEntries::StripRoot`].
+    pub absolute: usize,
+    /// Names of the entries not restored because they lead out of the
+    /// destination, are absolute with [`crate::AbsoluteEntries::Reject`], or
+    /// are empty.
+    pub rejected: Vec<String>,
+    /// Paths of the restored files that are still EFS-encrypted and cannot
+    /// be read without the key of the account that encrypted them.
+    pub efs_paths: Vec<String>,
+    /// Paths of the files moved to [`QUARANTINE_DIR`] because their data
+    /// failed the CRC check.
+    pub quarantined: Vec<String>,
+    /// Files of a [`DestLayout::Cas`] restore whose content was stored
+    /// already.
+    pub deduplicated: usize,
+    /// Files hard-linked to the previous snapshot, see
+    /// [`crate::RestorerBuilder::link_dest`].
+    pub linked: usize,
+    pub errors: Vec<EntryError>,
+    /// Wall time from when the run turned to the archive, including
+    /// opening it, until its last entry was done.
+    pub elapsed: Duration,
+    /// The restore was cancelled before all entries were processed.
+    pub cancelled: bool,
+}
+
+/// The counts of one archive of a run, see [`Stats::per_archive`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ArchiveSummary {
+    pub archive: PathBuf,
+    pub files: usize,
+    pub bytes: u64,
+    pub filtered: usize,
+    pub skipped: usize,
+    pub overwritten: usize,
+    pub renamed: usize,
+    pub errors: usize,
+    #[serde(rename = "elapsed_secs", serialize_with = "serialize_secs")]
+    pub elapsed: Duration,
+    /// The archive could not be opened at all.
+    pub failed: bool,
+}
+
+/// Totals for a whole extraction run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Stats {
+    pub archives: usize,
+    pub files_extracted: usize,
+    pub bytes_written: u64,
+    pub filtered: usize,
+    pub skipped: usize,
+    pub overwritten: usize,
+    pub renamed: usize,
+    pub placeholders: usize,
+    pub placeholder_paths: Vec<String>,
+    pub system_files: usize,
+    pub apple_double: usize,
+    pub normalized: usize,
+    pub absolute: usize,
+    pub rejected: Vec<String>,
+    pub efs_paths: Vec<String>,
+    pub quarantined: Vec<String>,
+    pub deduplicated: usize,
+    pub linked: usize,
+    pub errors: Vec<Error>,
+    /// The counts of each archive, in archive order.
+    pub per_archive: Vec<ArchiveSummary>,
+    /// Thread counts and queue depths of the extraction stages.
+    pub pipeline: PipelineStats,
+    pub ordering: OrderStats,
+    #[serde(rename = "elapsed_secs", serialize_with = "serialize_secs")]
+    pub elapsed: Duration,
+}
+
+pub(crate) fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+impl Stats {
+    pub fn add_archive(&mut self, path: &Path, archive: ArchiveStats) {
+        self.archives += 1;
+        self.per_archive.push(ArchiveSummary {
+            archive: path.to_path_buf(),
+            files: archive.files,
+            bytes: archive.bytes,
+            filtered: archive.filtered,
+            skipped: archive.skipped,
+            overwritten: archive.overwritten,
+            renamed: archive.renamed,
+            errors: archive.errors.len(),
+            elapsed: archive.elapsed,
+            failed: false,
+        });
+        self.files_extracted += archive.files;
+        self.bytes_written += archive.bytes;
+        self.filtered += archive.filtered;
+        self.skipped += archive.skipped;
+        self.overwritten += archive.overwritten;
+        self.renamed += archive.renamed;
+        self.placeholders += archive.placeholders;
+        self.placeholder_paths.extend(archive.placeholder_paths);
+        self.system_files += archive.system_files;
+        self.apple_double += archive.apple_double;
+        self.normalized += archive.normalized;
+        self.absolute += archive.absolute;
+        self.rejected.extend(archive.rejected);
+        self.efs_paths.extend(archive.efs_paths);
+        self.quarantined.extend(archive.quarantined);
+        self.deduplicated += archive.deduplicated;
+        self.linked += archive.linked;
+        self.errors.extend(archive.errors.into_iter().map(Error::Entry));
+    }
+
+    /// Adds the totals of another run, e.g. of the next snapshot. The
+    /// extraction order and its completion stats stay those of this run.
+    pub fn merge(&mut self, other: Stats) {
+        self.archives += other.archives;
+        self.files_extracted += other.files_extracted;
+        self.bytes_written += other.bytes_written;
+        self.filtered += other.filtered;
+        self.skipped += other.skipped;
+        self.overwritten += other.overwritten;
+        self.renamed += other.renamed;
+        self.placeholders += other.placeholders;
+        self.placeholder_paths.extend(other.placeholder_paths);
+        self.system_files += other.system_files;
+        self.apple_double += other.apple_double;
+        self.normalized += other.normalized;
+        self.absolute += other.absolute;
+        self.rejected.extend(other.rejected);
+        self.efs_paths.extend(other.efs_paths);
+        self.quarantined.extend(other.quarantined);
+        self.deduplicated += other.deduplicated;
+        self.linked += other.linked;
+        self.errors.extend(other.errors);
+        self.per_archive.extend(other.per_archive);
+        self.pipeline.merge(&other.pipeline);
+        self.elapsed += other.elapsed;
+    }
+
+    pub fn add_failed_archive(&mut self, error: ArchiveError) {
+        self.archives += 1;
+        self.per_archive.push(ArchiveSummary { archive: error.archive.clone(), errors: 1, failed: true, ..ArchiveSummary::default() });
+        self.errors.push(Error::Archive(error));
+    }
+
+    /// The `n` archives that took longest, slowest first.
+    pub fn slowest_archives(&self, n: usize) -> Vec<&ArchiveSummary> {
+        let mut archives: Vec<&ArchiveSummary> = self.per_archive.iter().collect();
+        archives.sort_by_key(|a| std::cmp::Reverse(a.elapsed));
+        archives.truncate(n);
+        archives
+    }
+
+    /// The kinds of the errors with their counts, most frequent first.
+    pub fn errors_by_kind(&self) -> Vec<(ErrorKind, usize)> {
+        let mut counts: BTreeMap<ErrorKind, usize> = BTreeMap::new();
+        for error in &self.errors {
+            *counts.entry(error.kind()).or_default() += 1;
+        }
+        let mut kinds: Vec<(ErrorKind, usize)> = counts.into_iter().collect();
+        kinds.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        kinds
+    }
+}
+
+/// Amount of work a run will do, counted before extraction starts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Totals {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// The space a run needs in one of the folders it writes to, see
+/// [`Restorer::space`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Space {
+    pub dir: PathBuf,
+    /// Uncompressed size of the files restored there.
+    pub needed: u64,
+    /// Space available on its file system, if that could be found out.
+    pub available: Option<u64>,
+}
+
+impl Space {
+    /// Whether the files fit, as far as known.
+    pub fn fits(&self) -> bool {
+        self.available.is_none_or(|available| available >= self.needed)
+    }
+}
+
+/// How a run ended, with the statistics gathered up to that point.
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Completed(Stats),
+    Cancelled(Stats),
+}
+
+impl Outcome {
+    pub fn stats(&self) -> &Stats {
+        match self {
+            Outcome::Completed(stats) | Outcome::Cancelled(stats) => stats,
+        }
+    }
+
+    pub fn into_stats(self) -> Stats {
+        match self {
+            Outcome::Completed(stats) | Outcome::Cancelled(stats) => stats,
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, Outcome::Cancelled(_))
+    }
+
+    /// The outcome of this run followed by `next`: cancelled if either was.
+    pub fn merge(self, next: Outcome) -> Outcome {
+        let cancelled = self.is_cancelled() || next.is_cancelled();
+        let mut stats = self.into_stats();
+        stats.merge(next.into_stats());
+        if cancelled { Outcome::Cancelled(stats) } else { Outcome::Completed(stats) }
+    }
+}
+
+/// Extracts every archive of a backup folder into a destination directory.
+#[derive(Clone)]
+pub struct Restorer {
+    source: PathBuf,
+    pub(crate) dest: PathBuf,
+    pub(crate) options: Options,
+    pub(crate) progress: Arc<dyn ProgressSink>,
+    pub(crate) resolver: Option<Arc<dyn ConflictResolver>>,
+    pub(crate) cancel: CancellationToken,
+}
+
+impl fmt::Debug for Restorer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Restorer")
+            .field("source", &self.source)
+            .field("dest", &self.dest)
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Restorer {
+    /// Starts configuring a restorer; this is the primary entry point.
+    pub fn builder() -> RestorerBuilder {
+        RestorerBuilder::new()
+    }
+
+    /// A restorer with default settings, same as `builder().source(..).dest(..).build()`.
+    pub fn new(source: impl Into<PathBuf>, dest: impl Into<PathBuf>) -> Self {
+        Self::builder().source(source).dest(dest).build()
+    }
+
+    pub(crate) fn from_parts(
+        source: PathBuf,
+        dest: PathBuf,
+        options: Options,
+        progress: Arc<dyn ProgressSink>,
+        resolver: Option<Arc<dyn ConflictResolver>>,
+        cancel: CancellationToken,
+    ) -> Self {
+        Restorer { source, dest, options, progress, resolver, cancel }
+    }
+
+    /// Reports progress of every extraction to `sink`.
+    pub fn with_progress(mut self, sink: Arc<dyn ProgressSink>) -> Self {
+        self.progress = sink;
+        self
+    }
+
+    /// Stops the run once `token` is cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+
+    pub fn dest(&self) -> &Path {
+        &self.dest
+    }
+
+    /// The folders every restored file is also written to, see
+    /// [`crate::RestorerBuilder::mirror`]. None with [`DestLayout::Cas`].
+    pub fn mirrors(&self) -> &[PathBuf] {
+        match self.options.dest_layout {
+            DestLayout::Tree => &self.options.mirrors,
+            DestLayout::Cas => &[],
+        }
+    }
+
+    /// The folder the entries of `archive` are restored into: the
+    /// destination, or a folder of its own below it, see
+    /// [`crate::RestorerBuilder::subdir_per_archive`].
+    pub fn archive_dest(&self, archive: &Path) -> PathBuf {
+        if !self.options.subdir_per_archive || self.options.dest_layout == DestLayout::Cas {
+            return self.dest.clone();
+        }
+        let relative = match archive.strip_prefix(&self.source) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative,
+            _ => Path::new(archive.file_name().unwrap_or(archive.as_os_str())),
+        };
+        self.dest.join(relative.with_extension(""))
+    }
+
+    /// The file mapping paths to contents of a [`DestLayout::Cas`] restore.
+    pub fn tree_file(&self) -> Option<PathBuf> {
+        (self.options.dest_layout == DestLayout::Cas).then(|| self.dest.join(DestLayout::TREE_FILE))
+    }
+
+    /// Where the SHA-256 of the restored files go, see
+    /// [`crate::RestorerBuilder::manifest`].
+    pub fn manifest(&self) -> Option<&Path> {
+        self.options.manifest.as_deref()
+    }
+
+    /// The archives this restorer would extract, in extraction order. In a
+    /// Windows 7/8 backup folder these are the latest full backup and its
+    /// incremental runs, unless [`all_runs`](RestorerBuilder::all_runs) is set.
+    /// With [`archive`](RestorerBuilder::archive), those it names, or an
+    /// error if it names none. Less the first
+    /// [`skip_archives`](RestorerBuilder::skip_archives).
+    pub fn archives(&self) -> io::Result<Vec<PathBuf>> {
+        let zips = find_archives(&self.source)?;
+        let mut zips = match &self.options.archive {
+            Some(selector) => {
+                // Numbers count the archives a restore reads; names and
+                // globs find those of older backup sets too.
+                let numbered = selector.parse::<usize>().is_ok() && !self.options.all_runs;
+                let selected = select_archives(&if numbered { latest_backup_sets(&zips) } else { zips }, selector);
+                if selected.is_empty() {
+                    return Err(io::Error::new(io::ErrorKind::NotFound, format!("no archive of {} matches \"{}\"", self.source.display(), selector)));
+                }
+                selected
+            }
+            None if self.options.all_runs => zips,
+            None => latest_backup_sets(&zips),
+        };
+        zips.drain(..self.options.skip_archives.min(zips.len()));
+        Ok(zips)
+    }
+
+    /// The archives left out by [`skip_archives`](RestorerBuilder::skip_archives).
+    pub fn skipped_archives(&self) -> usize {
+        self.options.skip_archives
+    }
+
+    /// The archives given to [`archive`](RestorerBuilder::archive), if any.
+    pub fn archive_selector(&self) -> Option<&str> {
+        self.options.archive.as_deref()
+    }
+
+    /// Counts the files the filter and the placeholder and system file
+    /// policies let through and their uncompressed size by reading the
+    /// central directories of `zips`, without extracting anything.
+    /// Unreadable archives count as empty.
+    pub fn count(&self, zips: &[PathBuf]) -> Totals {
+        let set = BackupSet::from_archives(&self.source, zips.to_vec()).with_mmap(self.options.mmap).with_cache(self.options.analysis_cache.clone());
+        let mut totals = Totals::default();
+        for entry in set.entries().filter_map(Result::ok) {
+            if self.restores(&entry) {
+                totals.files += 1;
+                totals.bytes += entry.size;
+            }
+        }
+        totals
+    }
+
+    /// The space restoring `zips` takes in each folder it writes to: the
+    /// destination, the folders of mapped drives and the mirrors, each
+    /// checked against the free space of its own file system. Files
+    /// overwritten or deduplicated on the way are counted in full.
+    pub fn space(&self, zips: &[PathBuf]) -> Vec<Space> {
+        let set = BackupSet::from_archives(&self.source, zips.to_vec()).with_mmap(self.options.mmap).with_cache(self.options.analysis_cache.clone());
+        let mut needed: BTreeMap<&Path, u64> = BTreeMap::new();
+        needed.insert(&self.dest, 0);
+        for entry in set.entries().filter_map(Result::ok) {
+            if !self.restores(&entry) {
+                continue;
+            }
+            let Ok(normalized) = normalize_entry(&entry.name, self.options.absolute_entries) else { continue };
+            let drive = drive_letter(&normalized.name);
+            let dir = drive.and_then(|drive| self.mapped_drive(drive)).unwrap_or(&self.dest);
+            *needed.entry(dir).or_default() += entry.size;
+        }
+        let total: u64 = needed.values().sum();
+        for mirror in self.mirrors() {
+            *needed.entry(mirror).or_default() += total;
+        }
+        needed
+            .into_iter()
+            .map(|(dir, needed)| {
+                // The folder may not exist yet; its file system is that of
+                // the nearest one above it that does.
+                let existing = dir.ancestors().find(|dir| dir.exists());
+                let available = existing.and_then(|dir| fs4::available_space(dir).ok());
+                Space { dir: dir.to_path_buf(), needed, available }
+            })
+            .collect()
+    }
+
+    /// Whether `entry` gets restored: a file the restore admits, see
+    /// [`admit`](Self::admit).
+    fn restores(&self, entry: &EntryInfo) -> bool {
+        let admitted = self.admit(&entry.name, entry.placeholder, entry.size, entry.modified.map(BackupTime::from));
+        !entry.is_dir && matches!(admitted, Ok((_, Admission::Restore { .. })))
+    }
+
+    /// Relabels the folders restored into for SELinux, see
+    /// [`crate::RestorerBuilder::selinux_relabel`]: each with whether that
+    /// worked. None unless asked for and SELinux is on.
+    pub fn relabel(&self) -> Vec<(PathBuf, io::Result<()>)> {
+        if !self.options.selinux_relabel || !selinux_enabled() {
+            return Vec::new();
+        }
+        let mapped = match self.options.dest_layout {
+            DestLayout::Tree => self.options.drive_map.values().collect(),
+            DestLayout::Cas => Vec::new(),
+        };
+        let mut dirs: Vec<&PathBuf> = std::iter::once(&self.dest).chain(self.mirrors()).chain(mapped).collect();
+        // A folder within another is relabeled with it.
+        dirs.sort();
+        dirs.dedup_by(|dir, outer| dir.starts_with(outer));
+        dirs.into_iter().map(|dir| (dir.clone(), selinux_relabel(dir))).collect()
+    }
+
+    /// The folder entries of `drive` are restored into instead of the
+    /// destination, see [`crate::RestorerBuilder::drive_map`].
+    pub(crate) fn mapped_drive(&self, drive: char) -> Option<&Path> {
+        match self.options.dest_layout {
+            DestLayout::Tree => self.options.drive_map.get(&drive).map(PathBuf::as_path),
+            DestLayout::Cas => None,
+        }
+    }
+
+    /// Where the entry `raw_name` of an archive restored into
+    /// `archive_dest`, see [`archive_dest`](Self::archive_dest), is restored
+    /// to: below it, or the folder its drive is mapped to.
+    pub(crate) fn restore_path(&self, archive_dest: &Path, raw_name: &str) -> PathBuf {
+        let restored = remap_profile(strip_drive_letter(raw_name), &self.options.user_map);
+        match drive_letter(raw_name).and_then(|drive| self.mapped_drive(drive)) {
+            Some(dir) => dir.join(&*restored),
+            None => self.path_under(archive_dest, raw_name),
+        }
+    }
+
+    /// The path of the entry `raw_name` below `dir`, in the folder of its
+    /// drive with [`crate::RestorerBuilder::keep_drives`].
+    pub(crate) fn path_under(&self, dir: &Path, raw_name: &str) -> PathBuf {
+        let restored = remap_profile(strip_drive_letter(raw_name), &self.options.user_map);
+        match drive_letter(raw_name).filter(|_| self.options.keep_drives) {
+            Some(drive) => dir.join(drive.to_string()).join(&*restored),
+            None => dir.join(&*restored),
+        }
+    }
+
+    /// Makes the name `raw_name` of an entry safe, see [`normalize_entry`],
+    /// and decides with the filter and the placeholder, AppleDouble and
+    /// system file policies whether the entry is restored. Restores,
+    /// simulations and the totals of a restore all ask here.
+    pub(crate) fn admit<'n>(&self, raw_name: &'n str, placeholder: bool, size: u64, modified: Option<BackupTime>) -> Result<(Normalized<'n>, Admission), Unsafe> {
+        let normalized = normalize_entry(raw_name, self.options.absolute_entries)?;
+        let clean = strip_drive_letter(&normalized.name);
+        let admission = if !self.options.filter.matches_file(clean, size, modified) {
+            Admission::Filtered
+        } else if placeholder && self.options.placeholders != PlaceholderPolicy::Extract {
+            Admission::Placeholder
+        } else if !self.options.keep_apple_double && is_apple_double(clean) {
+            Admission::AppleDouble
+        } else if self.options.system_files != SystemFilePolicy::Extract && is_system_file(clean) {
+            match self.options.system_files {
+                SystemFilePolicy::Skip => Admission::SystemFile,
+                _ => Admission::Restore { isolated: true },
+            }
+        } else {
+            Admission::Restore { isolated: false }
+        };
+        Ok((normalized, admission))
+    }
+
+    /// Where the entry `raw_name`, as normalized, of an archive restored
+    /// into `archive_dest` goes when admitted with
+    /// [`Admission::Restore`]: its restore path, or below the folder of
+    /// system files if `isolated`.
+    pub(crate) fn admitted_path(&self, archive_dest: &Path, raw_name: &str, isolated: bool) -> PathBuf {
+        if isolated {
+            self.path_under(&archive_dest.join(SystemFilePolicy::ISOLATED_DIR), raw_name)
+        } else {
+            self.restore_path(archive_dest, raw_name)
+        }
+    }
+
+    /// How the conflict of the entry at restore path `name` with a file at
+    /// its target is decided: as the [`crate::Answers`] say, or by the
+    /// conflict policy.
+    pub(crate) fn conflict_policy(&self, name: &str) -> ConflictPolicy {
+        let answer = self.options.answers.as_ref().and_then(|answers| answers.conflict(name));
+        answer.map_or(self.options.conflict_policy, ConflictPolicy::from)
+    }
+
+    /// The file of the previous snapshot the entry at restore path `name`
+    /// could be linked to instead of restored to `target`, its path in
+    /// `archive_dest` before any conflict renamed it: one the snapshot's
+    /// archives hold with the same CRC-32 and size. See
+    /// [`crate::RestorerBuilder::link_dest`].
+    pub(crate) fn previous_copy(&self, archive_dest: &Path, name: &str, target: &Path, size: u64, crc32: Option<u32>) -> Option<PathBuf> {
+        let previous = self.options.link_dest.as_ref()?;
+        let relative = target.strip_prefix(archive_dest).ok()?;
+        previous.unchanged(name, crc32?, size).then(|| previous.dir.join(relative))
+    }
+
+    /// Extracts one archive. Per-entry failures are collected in the returned
+    /// stats; an error is only returned when the archive cannot be opened.
+    pub fn extract_archive(&self, zip_path: &Path) -> Result<ArchiveStats, ArchiveError> {
+        let manifest = match self.options.manifest.as_deref().map(|path| ManifestWriter::append(path, self.options.manifest_format)).transpose() {
+            Ok(manifest) => manifest,
+            Err(e) => return Err(ArchiveError::io(zip_path, e)),
+        };
+        let audit = self.options.audit_log.as_deref().map(AuditLog::append).transpose().map_err(|e| ArchiveError::io(zip_path, e))?;
+        let tree = self.tree_file().map(|path| ManifestWriter::append(&path, ManifestFormat::Json)).transpose();
+        let tree = tree.map_err(|e| ArchiveError::io(zip_path, e))?;
+        let (mut results, _, _) = pipeline::run(self, &[zip_path.to_path_buf()], manifest.as_ref(), audit.as_ref(), tree.as_ref());
+        results.pop().flatten().unwrap_or_else(|| Ok(ArchiveStats { cancelled: true, ..ArchiveStats::default() }))
+    }
+
+    pub(crate) fn open_archive(&self, zip_path: &Path) -> Result<ZipArchive<ArchiveFile>, ArchiveError> {
+        let file = ArchiveFile::open(zip_path, self.options.mmap).map_err(|e| ArchiveError::io(zip_path, e))?;
+        ZipArchive::new(file).map_err(|e| ArchiveError::zip(zip_path, e))
+    }
+
+    /// Extracts the given archives, `threads` at a time, through the
+    /// reader/decoder/writer pipeline. Results are merged in archive order.
+    /// Archives that cannot be opened are recorded as errors and do not
+    /// abort the run.
+    pub fn extract_all(&self, zips: &[PathBuf]) -> io::Result<Outcome> {
+        fs::create_dir_all(&self.dest)?;
+        for mirror in self.mirrors() {
+            fs::create_dir_all(mirror)?;
+        }
+        let manifest = self.options.manifest.as_deref().map(|path| ManifestWriter::create(path, self.options.manifest_format)).transpose()?;
+        let audit = self.options.audit_log.as_deref().map(AuditLog::append).transpose()?;
+        let tree = self.tree_file().map(|path| ManifestWriter::create(&path, ManifestFormat::Json)).transpose()?;
+        let started = Instant::now();
+        let (results, pipeline, ordering) = pipeline::run(self, zips, manifest.as_ref(), audit.as_ref(), tree.as_ref());
+
+        let mut stats = Stats { pipeline, ordering, elapsed: started.elapsed(), ..Stats::default() };
+        let mut cancelled = false;
+        for (path, result) in zips.iter().zip(results) {
+            match result {
+                Some(Ok(archive)) => {
+                    cancelled |= archive.cancelled;
+                    stats.add_archive(path, archive);
+                }
+                Some(Err(e)) => stats.add_failed_archive(e),
+                None => cancelled = true,
+            }
+        }
+        Ok(if cancelled { Outcome::Cancelled(stats) } else { Outcome::Completed(stats) })
+    }
+
+    /// Discovers and extracts all archives below the source directory.
+    pub fn run(&self) -> io::Result<Outcome> {
+        let zips = self.archives()?;
+        self.extract_all(&zips)
+    }
+}
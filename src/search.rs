@@ -0,0 +1,177 @@
+//! Searching the files of a backup by name, for `restore search`: by the
+//! words of the search, with [`Search::regex`] by a regular expression, or
+//! with [`Search::fuzzy`] by words that are nearly right, so
+//! `thesys final v2` finds `Thesis_Final_v2.docx`.
+//!
+//! A fuzzy word matches a word of the file name best when it is the same,
+//! then when it starts or contains it, differs from it in a letter or two
+//! (one per four letters of the search word), or has its letters in order.
+//! Words of the folders count for less. A file is found when its words
+//! match those of the search well enough on average, and the best are
+//! listed first.
+
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::backup_set::{BackupSet, EntryInfo};
+use crate::error::ArchiveError;
+
+/// How well a file must match on average, from 0 to 1, to be found.
+const THRESHOLD: f64 = 0.5;
+
+/// Weight of a match in the folders, against one in the file name.
+const FOLDER_WEIGHT: f64 = 0.6;
+
+/// A search of the files of a backup.
+#[derive(Debug, Clone)]
+pub struct Search {
+    /// The words, in lowercase.
+    terms: Vec<String>,
+    fuzzy: bool,
+    regex: Option<Regex>,
+}
+
+impl Search {
+    /// A search for the files whose path holds every word of `text`,
+    /// ignoring case.
+    pub fn new(text: &str) -> Search {
+        Search { terms: text.split_whitespace().map(str::to_lowercase).collect(), fuzzy: false, regex: None }
+    }
+
+    /// A search for the files whose path matches `regex` anywhere.
+    pub fn regex(regex: Regex) -> Search {
+        Search { terms: Vec::new(), fuzzy: false, regex: Some(regex) }
+    }
+
+    /// A search for the files whose name matches the words of `text` best,
+    /// see the [module documentation](self).
+    pub fn fuzzy(text: &str) -> Search {
+        Search { terms: words(text), fuzzy: true, regex: None }
+    }
+
+    pub fn is_fuzzy(&self) -> bool {
+        self.fuzzy
+    }
+
+    /// How well the file at restore path `path` matches, from 0 to 1; `None`
+    /// if it does not.
+    pub fn score(&self, path: &str) -> Option<f64> {
+        if let Some(regex) = &self.regex {
+            return regex.is_match(path).then_some(1.0);
+        }
+        if self.terms.is_empty() {
+            return None;
+        }
+        if !self.fuzzy {
+            let path = path.to_lowercase();
+            return self.terms.iter().all(|term| path.contains(term.as_str())).then_some(1.0);
+        }
+        let (folders, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let (name, folders) = (words(name), words(folders));
+        let best = |term: &str| {
+            let in_name = name.iter().map(|word| similarity(term, word)).fold(0.0, f64::max);
+            let in_folders = folders.iter().map(|word| similarity(term, word)).fold(0.0, f64::max);
+            in_name.max(in_folders * FOLDER_WEIGHT)
+        };
+        let score = self.terms.iter().map(|term| best(term)).sum::<f64>() / self.terms.len() as f64;
+        (score >= THRESHOLD).then_some(score)
+    }
+}
+
+/// The files of a backup found by [`search`].
+#[derive(Debug, Clone, Default)]
+pub struct SearchMatches {
+    /// Best first for a fuzzy search, otherwise in the order of the
+    /// archives; a file backed up by several runs is there once, where it was
+    /// first found, and from the latest run.
+    pub files: Vec<EntryInfo>,
+    pub errors: Vec<ArchiveError>,
+}
+
+/// Finds the files of `set` matching `search`, the best `limit` of them if
+/// given.
+pub fn search(set: &BackupSet, search: &Search, limit: Option<usize>) -> SearchMatches {
+    let mut matches = SearchMatches::default();
+    let mut found: Vec<(EntryInfo, f64)> = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for entry in set.entries() {
+        let entry = match entry {
+            Ok(entry) if !entry.is_dir => entry,
+            Ok(_) => continue,
+            Err(e) => {
+                matches.errors.push(e);
+                continue;
+            }
+        };
+        let Some(score) = search.score(entry.path()) else { continue };
+        match seen.get(&entry.path().to_lowercase()) {
+            Some(&i) => found[i] = (entry, score),
+            None => {
+                seen.insert(entry.path().to_lowercase(), found.len());
+                found.push((entry, score));
+            }
+        }
+    }
+    if search.fuzzy {
+        // Stable, so equal scores stay in the order of the archives.
+        found.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    }
+    found.truncate(limit.unwrap_or(usize::MAX));
+    matches.files = found.into_iter().map(|(entry, _)| entry).collect();
+    matches
+}
+
+/// The words of `text` in lowercase: its runs of letters and digits.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).map(str::to_lowercase).collect()
+}
+
+/// How well the search word `term` matches `word`, from 0 to 1.
+fn similarity(term: &str, word: &str) -> f64 {
+    let len = term.chars().count();
+    if term == word {
+        return 1.0;
+    }
+    if len >= 2 && word.starts_with(term) {
+        return 0.9;
+    }
+    if len >= 3 && word.contains(term) {
+        return 0.8;
+    }
+    let distance = edit_distance(term, word);
+    if distance <= len / 4 {
+        return 0.8 - 0.15 * distance as f64;
+    }
+    if len >= 3 && is_subsequence(term, word) {
+        return 0.4;
+    }
+    0.0
+}
+
+/// The number of letters to insert, remove, change or swap with the next
+/// to turn `a` into `b` (the optimal string alignment distance).
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // Rows i - 2, i - 1 and i of the distances between prefixes.
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (previous[j] + 1).min(row[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut previous, row);
+    }
+    previous[b.len()]
+}
+
+/// Whether the letters of `term` are in `word`, in order.
+fn is_subsequence(term: &str, word: &str) -> bool {
+    let mut letters = word.chars();
+    term.chars().all(|c| letters.any(|letter| letter == c))
+}
@@ -0,0 +1,50 @@
+//! Reading selection files, the saved lists of files for
+//! [`crate::Filter::selection`]: what `restore query --format json` prints,
+//! a JSON array of the same rows or of paths, or one path per line, so
+//! files found once can be restored without typing the patterns again.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde_json::Value;
+
+/// The paths listed in the selection file at `path`. Rows are JSON objects
+/// with a `path`, as `restore query` prints them, or JSON strings; any other
+/// line is a path as it is. Empty lines and lines starting with `#` are
+/// skipped.
+pub fn read_selection(path: &Path) -> io::Result<Vec<String>> {
+    let text = fs::read_to_string(path)?;
+    if text.trim_start().starts_with('[') {
+        let rows: Vec<Value> = serde_json::from_str(&text).map_err(|e| invalid(path, e.line(), &e.to_string()))?;
+        return rows.iter().map(|row| row_path(row).ok_or_else(|| invalid(path, 0, "an item is neither a path nor an object with one"))).collect();
+    }
+    let mut paths = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with(['{', '"']) {
+            paths.push(line.to_string());
+            continue;
+        }
+        let row = serde_json::from_str(line).map_err(|e| invalid(path, i + 1, &e.to_string()))?;
+        paths.push(row_path(&row).ok_or_else(|| invalid(path, i + 1, "no \"path\" in the row"))?);
+    }
+    Ok(paths)
+}
+
+fn row_path(row: &Value) -> Option<String> {
+    match row {
+        Value::String(path) => Some(path.clone()),
+        Value::Object(row) => row.get("path")?.as_str().map(String::from),
+        _ => None,
+    }
+}
+
+/// An error in the selection file at `path`, on `line` if not 0.
+fn invalid(path: &Path, line: usize, message: &str) -> io::Error {
+    let at = if line > 0 { format!(" line {}", line) } else { String::new() };
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}{}: {}", path.display(), at, message))
+}
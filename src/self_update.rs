@@ -0,0 +1,170 @@
+//! Replacing the running program by the latest release on GitHub, for
+//! `restore self-update` (feature `self-update`). The program mostly runs
+//! from a rescue stick without a package manager, so it fetches the binary
+//! for its platform itself. Each release binary comes with a `.sha256` file
+//! and a `.minisig` signature; both are checked before the binary is
+//! swapped in.
+
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+
+use minisign::{PError, PublicKey, SignatureBox};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+const LATEST_RELEASE: &str = "https://api.github.com/repos/dariusgm/restore/releases/latest";
+
+/// The minisign public key release binaries are signed with, given to the
+/// build in `RESTORE_RELEASE_PUBLIC_KEY` as the base64 line of a
+/// `minisign.pub`.
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("RESTORE_RELEASE_PUBLIC_KEY");
+
+/// Binaries are a few MiB; this leaves room to grow.
+const MAX_DOWNLOAD: u64 = 256 << 20;
+
+/// The latest release on GitHub.
+#[derive(Debug, Clone)]
+pub struct Release {
+    /// Version of the release, without the `v` of its tag.
+    pub version: String,
+    /// Name of the binary for this platform, e.g. `restore-linux-x86_64`.
+    pub asset: String,
+    binary: Option<String>,
+    checksum: Option<String>,
+    signature: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitHubRelease {
+    tag_name: String,
+    assets: Vec<GitHubAsset>,
+}
+
+#[derive(Deserialize)]
+struct GitHubAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    /// Asks GitHub for the latest release.
+    pub fn latest() -> io::Result<Release> {
+        let json = get(LATEST_RELEASE)?;
+        let release: GitHubRelease = serde_json::from_slice(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let asset = asset_name();
+        let url = |name: &str| release.assets.iter().find(|a| a.name == name).map(|a| a.browser_download_url.clone());
+        Ok(Release {
+            version: release.tag_name.trim_start_matches('v').to_string(),
+            binary: url(&asset),
+            checksum: url(&format!("{}.sha256", asset)),
+            signature: url(&format!("{}.minisig", asset)),
+            asset,
+        })
+    }
+
+    /// Whether the release is newer than the running program.
+    pub fn is_newer(&self) -> bool {
+        version_parts(&self.version) > version_parts(env!("CARGO_PKG_VERSION"))
+    }
+
+    /// Downloads the binary of the release, checks its checksum and its
+    /// signature by the minisign public key file at `public_key`, or the
+    /// [`RELEASE_PUBLIC_KEY`] without one, and that the signature names the
+    /// release, and puts it in place of the running program. Returns the
+    /// path of the program.
+    pub fn install(&self, public_key: Option<&Path>) -> io::Result<PathBuf> {
+        let public_key = release_public_key(public_key)?;
+        let missing = |what: &str| io::Error::new(io::ErrorKind::NotFound, format!("release {} has no {} for {}", self.version, what, self.asset));
+        let binary = get(self.binary.as_deref().ok_or_else(|| missing("binary"))?)?;
+        let checksum = get(self.checksum.as_deref().ok_or_else(|| missing("checksum"))?)?;
+        let signature = get(self.signature.as_deref().ok_or_else(|| missing("signature"))?)?;
+
+        // `sha256sum` format: the hash, then the file name.
+        let expected = String::from_utf8_lossy(&checksum).split_whitespace().next().unwrap_or_default().to_lowercase();
+        let actual = format!("{:x}", Sha256::digest(&binary));
+        if expected != actual {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("checksum mismatch: expected {}, downloaded {}", expected, actual)));
+        }
+        let signature = SignatureBox::from_string(&String::from_utf8_lossy(&signature)).map_err(invalid)?;
+        minisign::verify(&public_key, &signature, Cursor::new(&binary), true, false, false).map_err(invalid)?;
+        // The trusted comment is signed with the binary and names its
+        // release, so an older signed binary cannot pass for this one.
+        let comment = signature.trusted_comment().map_err(invalid)?;
+        let signed = comment.strip_prefix("restore ").map(|tag| tag.trim_start_matches('v'));
+        if signed != Some(self.version.as_str()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("signature is for {:?}, not release {}", comment, self.version),
+            ));
+        }
+
+        let exe = std::env::current_exe()?;
+        replace_exe(&exe, &binary)?;
+        Ok(exe)
+    }
+}
+
+fn release_public_key(path: Option<&Path>) -> io::Result<PublicKey> {
+    match (path, RELEASE_PUBLIC_KEY) {
+        (Some(path), _) => PublicKey::from_file(path).map_err(invalid),
+        // The release workflow passes an unset key as an empty one.
+        (None, Some(key)) if !key.trim().is_empty() => PublicKey::from_base64(key.trim()).map_err(invalid),
+        (None, _) => Err(io::Error::new(io::ErrorKind::NotFound, "this build has no release key; give one with --public-key")),
+    }
+}
+
+/// Name of the release binary for this platform, as the release workflow
+/// names them.
+fn asset_name() -> String {
+    format!("restore-{}-{}{}", std::env::consts::OS, std::env::consts::ARCH, std::env::consts::EXE_SUFFIX)
+}
+
+/// The numbers of a version such as `1.2.0`, for comparing; whatever
+/// follows a `-` is left out.
+fn version_parts(version: &str) -> Vec<u64> {
+    let release = version.split('-').next().unwrap_or_default();
+    release.split('.').map(|part| part.parse().unwrap_or(0)).collect()
+}
+
+fn get(url: &str) -> io::Result<Vec<u8>> {
+    let mut response = ureq::get(url)
+        .header("User-Agent", concat!("restore/", env!("CARGO_PKG_VERSION")))
+        .call()
+        .map_err(|e| io::Error::other(format!("{}: {}", url, e)))?;
+    response.body_mut().with_config().limit(MAX_DOWNLOAD).read_to_vec().map_err(ureq::Error::into_io)
+}
+
+/// Writes `binary` next to `exe` and renames it over it, so the program is
+/// never half written. Windows does not replace a running program but
+/// lets it be renamed, so there it moves aside to `<name>.old` first, and
+/// back if the new one cannot take its place.
+fn replace_exe(exe: &Path, binary: &[u8]) -> io::Result<()> {
+    let mut new = exe.as_os_str().to_owned();
+    new.push(".new");
+    let new = PathBuf::from(new);
+    fs::write(&new, binary)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&new, fs::Permissions::from_mode(0o755))?;
+    }
+    #[cfg(windows)]
+    let old = {
+        let mut old = exe.as_os_str().to_owned();
+        old.push(".old");
+        let old = PathBuf::from(old);
+        let _ = fs::remove_file(&old);
+        fs::rename(exe, &old)?;
+        old
+    };
+    fs::rename(&new, exe).inspect_err(|_| {
+        #[cfg(windows)]
+        let _ = fs::rename(&old, exe);
+        let _ = fs::remove_file(&new);
+    })
+}
+
+fn invalid(e: PError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
@@ -0,0 +1,30 @@
+//! Relabeling restored files on SELinux systems, for
+//! [`crate::RestorerBuilder::selinux_relabel`]. Files take the label of
+//! the folder they are created in, or of the process creating them, which
+//! for a web root or a home folder restored elsewhere first, or by root, is
+//! rarely the one the policy wants there: the web server or the user then
+//! cannot read them. `restorecon` sets the labels the policy gives their
+//! paths.
+
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Whether the system runs SELinux.
+pub(crate) fn selinux_enabled() -> bool {
+    cfg!(target_os = "linux") && Path::new("/sys/fs/selinux/enforce").exists()
+}
+
+/// Sets the SELinux labels of everything in `dir` to those the policy
+/// gives their paths, by running `restorecon -R`.
+pub(crate) fn selinux_relabel(dir: &Path) -> io::Result<()> {
+    let output = Command::new("restorecon").arg("-R").arg("--").arg(dir).output().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => io::Error::new(e.kind(), "restorecon not found (policycoreutils)"),
+        _ => e,
+    })?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(io::Error::other(format!("restorecon failed ({}): {}", output.status, stderr.trim())))
+}
@@ -0,0 +1,171 @@
+//! 7-Zip archives (feature `sevenz`).
+//!
+//! Files in a 7z archive are compressed in blocks. Archives made with
+//! 7-Zip's default settings are solid: one block holds many files that can
+//! only be decompressed front to back, so they are restored in archive
+//! order by a single reader. Non-solid archives are read like ZIPs, a block
+//! per file.
+
+use std::io::{self, Read};
+use std::path::Path;
+
+use sevenz_rust2::{Archive, BlockDecoder, Error, Password};
+
+use crate::archive::{ArchiveReader, Member};
+use crate::catalog::BackupTime;
+use crate::reader::ArchiveFile;
+
+/// Seconds between 1601-01-01, where NT timestamps start, and 1970-01-01.
+const NT_TO_UNIX: i64 = 11_644_473_600;
+
+#[derive(Debug, Clone)]
+struct SevenZipEntry {
+    name: String,
+    /// Index in the archive's file list.
+    file: usize,
+    /// Block holding the data; `None` for empty files.
+    block: Option<usize>,
+    size: u64,
+    modified: Option<BackupTime>,
+    attributes: Option<u32>,
+}
+
+/// The files of a 7z archive in the order of their data.
+pub(crate) struct SevenZipArchive {
+    file: ArchiveFile,
+    archive: Archive,
+    entries: Vec<SevenZipEntry>,
+    /// Entry index of every file of the archive, `None` for directories.
+    positions: Vec<Option<usize>>,
+}
+
+impl SevenZipArchive {
+    pub(crate) fn open(path: &Path, mmap: bool) -> io::Result<SevenZipArchive> {
+        let mut file = ArchiveFile::open(path, mmap)?;
+        let archive = Archive::read(&mut file, &Password::empty()).map_err(io_error)?;
+        let map = &archive.stream_map;
+        // Files with data block by block, then the empty ones, as decoded.
+        let in_blocks = (0..archive.files.len()).filter_map(|file| map.file_block_index[file].map(|block| (file, Some(block))));
+        let empty = (0..archive.files.len()).filter(|&file| map.file_block_index[file].is_none()).map(|file| (file, None));
+        let mut entries = Vec::new();
+        let mut positions = vec![None; archive.files.len()];
+        for (file, block) in in_blocks.chain(empty) {
+            let f = &archive.files[file];
+            let name = f.name.replace('\\', "/").trim_start_matches('/').to_string();
+            if f.is_directory || f.is_anti_item || name.is_empty() {
+                continue;
+            }
+            let modified = f.has_last_modified_date.then(|| u64::from(f.last_modified_date)).and_then(|t| {
+                BackupTime::from_unix((t / 10_000_000) as i64 - NT_TO_UNIX)
+            });
+            // With 0x8000 set, the upper 16 bits hold a Unix mode.
+            let attributes = f
+                .has_windows_attributes
+                .then_some(f.windows_attributes)
+                .map(|a| if a & 0x8000 != 0 { a & 0xffff } else { a });
+            positions[file] = Some(entries.len());
+            entries.push(SevenZipEntry { name, file, block, size: f.size, modified, attributes });
+        }
+        Ok(SevenZipArchive { file, archive, entries, positions })
+    }
+}
+
+/// Turns the errors of the 7z decoder into I/O errors with readable messages.
+fn io_error(e: Error) -> io::Error {
+    match e {
+        Error::Io(e, _) | Error::FileOpen(e, _) => e,
+        Error::BadSignature(_) => io::Error::new(io::ErrorKind::InvalidData, "not a 7z archive"),
+        Error::PasswordRequired | Error::MaybeBadPassword(_) => {
+            io::Error::new(io::ErrorKind::Unsupported, "encrypted 7z archives are not supported")
+        }
+        Error::UnsupportedCompressionMethod(method) => {
+            io::Error::new(io::ErrorKind::Unsupported, format!("7z compression method {} is not supported", method))
+        }
+        e @ (Error::Unsupported(_) | Error::ExternalUnsupported | Error::UnsupportedVersion { .. }) => {
+            io::Error::new(io::ErrorKind::Unsupported, e.to_string())
+        }
+        e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+    }
+}
+
+impl ArchiveReader for SevenZipArchive {
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn member(&self, j: usize) -> Member<'_> {
+        let entry = &self.entries[j];
+        Member { name: &entry.name, size: entry.size, modified: entry.modified, attributes: entry.attributes }
+    }
+
+    fn read(&self, j: usize, f: &mut dyn FnMut(&mut dyn Read)) -> io::Result<()> {
+        let entry = &self.entries[j];
+        let Some(block) = entry.block else {
+            f(&mut io::empty());
+            return Ok(());
+        };
+        let mut source = self.file.clone();
+        let password = Password::empty();
+        let mut file = self.archive.stream_map.block_first_file_index[block];
+        let mut found = false;
+        BlockDecoder::new(1, block, &self.archive, &password, &mut source)
+            .for_each_entries(&mut |_, data| {
+                if file == entry.file {
+                    found = true;
+                    f(data);
+                    return Ok(false);
+                }
+                file += 1;
+                io::copy(data, &mut io::sink())?;
+                Ok(true)
+            })
+            .map_err(io_error)?;
+        if !found {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "file not found in its 7z block"));
+        }
+        Ok(())
+    }
+
+    fn sequential(&self) -> bool {
+        self.archive.is_solid
+    }
+
+    fn read_in_order(&self, f: &mut dyn FnMut(usize, io::Result<&mut dyn Read>) -> bool) {
+        let mut source = self.file.clone();
+        let password = Password::empty();
+        // The first entry not handed to `f` yet.
+        let mut next = 0;
+        for block in 0..self.archive.blocks.len() {
+            let mut file = self.archive.stream_map.block_first_file_index[block];
+            let mut go = true;
+            let result = BlockDecoder::new(1, block, &self.archive, &password, &mut source).for_each_entries(&mut |_, data| {
+                let position = self.positions[file];
+                file += 1;
+                if let Some(j) = position {
+                    next = j + 1;
+                    go = f(j, Ok(data));
+                    io::copy(data, &mut io::sink())?;
+                }
+                Ok(go)
+            });
+            if let Err(e) = result {
+                // The rest of the block cannot be decoded.
+                let e = io_error(e);
+                while self.entries.get(next).is_some_and(|entry| entry.block == Some(block)) {
+                    if !f(next, Err(io::Error::new(e.kind(), e.to_string()))) {
+                        return;
+                    }
+                    next += 1;
+                }
+            }
+            if !go {
+                return;
+            }
+        }
+        for j in next..self.entries.len() {
+            if !f(j, Ok(&mut io::empty())) {
+                return;
+            }
+        }
+    }
+}
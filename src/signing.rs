@@ -0,0 +1,58 @@
+//! Minisign signatures of the manifest and the JSON summary of a restore,
+//! as an attestation of what it produced and when. They are Ed25519
+//! signatures in the format of `minisign`, which checks them with
+//! `minisign -V -p <public key> -m <file>`.
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use minisign::{PError, PublicKey, SecretKey, SignatureBox};
+
+/// A minisign secret key to sign files with.
+pub struct Signer {
+    key: SecretKey,
+}
+
+impl Signer {
+    /// Reads the secret key at `path`, as written by `minisign -G`, and
+    /// decrypts it with `password`. `None` asks for the password on the
+    /// terminal; keys made with `minisign -W` take an empty one.
+    pub fn load(path: &Path, password: Option<String>) -> io::Result<Signer> {
+        let key = SecretKey::from_file(path, password).map_err(invalid)?;
+        Ok(Signer { key })
+    }
+
+    /// Signs the file at `path` into [`signature_path`], with a
+    /// `trusted_comment` the signature covers as well. Returns the path of
+    /// the signature.
+    pub fn sign(&self, path: &Path, trusted_comment: &str) -> io::Result<PathBuf> {
+        let untrusted_comment = concat!("signature from restore ", env!("CARGO_PKG_VERSION"));
+        let signature = minisign::sign(None, &self.key, File::open(path)?, Some(trusted_comment), Some(untrusted_comment))
+            .map_err(invalid)?;
+        let signature_path = signature_path(path);
+        std::fs::write(&signature_path, signature.into_string())?;
+        Ok(signature_path)
+    }
+}
+
+/// Where the signature of the file at `path` goes: next to it, with
+/// `.minisig` added to its name.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".minisig");
+    PathBuf::from(name)
+}
+
+/// Checks the [`signature_path`] of the file at `path` with the minisign
+/// public key at `public_key`. Returns the trusted comment.
+pub fn verify_signature(path: &Path, public_key: &Path) -> io::Result<String> {
+    let key = PublicKey::from_file(public_key).map_err(invalid)?;
+    let signature = SignatureBox::from_file(signature_path(path)).map_err(invalid)?;
+    minisign::verify(&key, &signature, File::open(path)?, true, false, false).map_err(invalid)?;
+    signature.trusted_comment().map_err(invalid)
+}
+
+fn invalid(e: PError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
@@ -0,0 +1,233 @@
+//! Dry runs of a restore, for `restore simulate`: every entry goes through
+//! the same name checks, path resolution, filter, placeholder and system
+//! file policies and conflict policy as in a restore, decided by the same
+//! [`Restorer::admit`], against the files at the destination and those
+//! restored before it in the run, but nothing is written. What would be renamed, skipped or asked about is listed before
+//! a restore of hours does it.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::backup_set::BackupSet;
+use crate::catalog::BackupTime;
+use crate::conflict::same_contents;
+use crate::error::ArchiveError;
+use crate::options::{ConflictPolicy, DestLayout};
+use crate::paths::strip_drive_letter;
+use crate::pipeline::free_name;
+use crate::restorer::{Admission, Restorer};
+
+/// What a restore would do with a file whose restore path is taken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlannedAction {
+    Overwrite,
+    Skip,
+    /// Restore it next to the other file, at this path.
+    Rename(PathBuf),
+    /// Ask, with [`ConflictPolicy::Ask`].
+    Ask,
+}
+
+/// A file of the backup whose restore path is taken, by a file at the
+/// destination or by a file restored before it in the run, such as the
+/// same file of an earlier backup run.
+#[derive(Debug, Clone)]
+pub struct PlannedConflict {
+    pub archive: PathBuf,
+    /// Restore path of the entry, e.g. `Users/bob/Documents/a.txt`.
+    pub path: String,
+    pub target: PathBuf,
+    /// Whether the file at the target is at the destination already,
+    /// rather than restored before in the run.
+    pub existing: bool,
+    pub action: PlannedAction,
+}
+
+/// Two files of the backup whose targets differ only in case. File systems
+/// that ignore case, as those of Windows and macOS do by default, take the
+/// second for the first, which the conflict policy then decides.
+#[derive(Debug, Clone)]
+pub struct CaseCollision {
+    pub first: PathBuf,
+    pub second: PathBuf,
+}
+
+/// What [`Restorer::simulate`] found.
+#[derive(Debug, Clone, Default)]
+pub struct Simulation {
+    /// Files that would be restored, and their uncompressed size. A file
+    /// of the backup another one replaces counts no more.
+    pub files: usize,
+    pub bytes: u64,
+    /// Files that would be hard-linked to the previous snapshot instead,
+    /// see [`crate::RestorerBuilder::link_dest`].
+    pub linked: usize,
+    pub filtered: usize,
+    pub placeholders: usize,
+    /// System files left out or set apart, see [`SystemFilePolicy`].
+    pub system_files: usize,
+    pub apple_double: usize,
+    pub conflicts: Vec<PlannedConflict>,
+    pub case_collisions: Vec<CaseCollision>,
+    /// Entries whose names would be cleaned, and absolute ones restored
+    /// below the destination, see [`crate::ArchiveStats::normalized`].
+    pub normalized: usize,
+    pub absolute: usize,
+    /// Names of the entries left out because they lead out of the
+    /// destination, are absolute with [`crate::AbsoluteEntries::Reject`],
+    /// or are empty.
+    pub rejected: Vec<String>,
+    pub errors: Vec<ArchiveError>,
+}
+
+/// What the restore would leave at a target.
+#[derive(Debug, Clone, Copy)]
+enum Planned {
+    /// A file of this size, written.
+    Written(u64),
+    /// A link to the previous snapshot.
+    Linked,
+}
+
+impl Simulation {
+    fn add(&mut self, planned: Planned) {
+        match planned {
+            Planned::Written(size) => {
+                self.files += 1;
+                self.bytes += size;
+            }
+            Planned::Linked => self.linked += 1,
+        }
+    }
+
+    fn remove(&mut self, planned: Planned) {
+        match planned {
+            Planned::Written(size) => {
+                self.files -= 1;
+                self.bytes -= size;
+            }
+            Planned::Linked => self.linked -= 1,
+        }
+    }
+
+    /// The conflicts with files at the destination.
+    pub fn existing(&self) -> impl Iterator<Item = &PlannedConflict> {
+        self.conflicts.iter().filter(|conflict| conflict.existing)
+    }
+
+    /// The conflicts between files of the backup.
+    pub fn within_backup(&self) -> impl Iterator<Item = &PlannedConflict> {
+        self.conflicts.iter().filter(|conflict| !conflict.existing)
+    }
+}
+
+impl Restorer {
+    /// Works out what restoring `zips` would do with each file, reading
+    /// only the central directories and the metadata of the destination.
+    /// The answers of the [`crate::Answers`] apply; conflicts left to ask
+    /// are listed as [`PlannedAction::Ask`], except that files at the
+    /// destination with the contents of the entry are skipped, as a
+    /// restore does.
+    pub fn simulate(&self, zips: &[PathBuf]) -> Simulation {
+        let set = BackupSet::from_archives(self.source(), zips.to_vec()).with_mmap(self.options.mmap).with_cache(self.options.analysis_cache.clone());
+        let mut simulation = Simulation::default();
+        let mut planned: HashMap<PathBuf, Planned> = HashMap::new();
+        let mut cases: HashMap<String, PathBuf> = HashMap::new();
+        for zip in zips {
+            let dest = self.archive_dest(zip);
+            for entry in set.archive_entries(zip) {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        simulation.errors.push(e);
+                        continue;
+                    }
+                };
+                if entry.is_dir {
+                    continue;
+                }
+                let (normalized, admission) = match self.admit(&entry.name, entry.placeholder, entry.size, entry.modified.map(BackupTime::from)) {
+                    Ok(admitted) => admitted,
+                    Err(_) => {
+                        simulation.rejected.push(entry.name.clone());
+                        continue;
+                    }
+                };
+                simulation.absolute += usize::from(normalized.absolute);
+                simulation.normalized += usize::from(normalized.cleaned);
+                let raw_name = &*normalized.name;
+                let clean = strip_drive_letter(raw_name);
+                let isolated = match admission {
+                    Admission::Restore { isolated } => isolated,
+                    Admission::Filtered => {
+                        simulation.filtered += 1;
+                        continue;
+                    }
+                    Admission::Placeholder => {
+                        simulation.placeholders += 1;
+                        continue;
+                    }
+                    Admission::AppleDouble => {
+                        simulation.apple_double += 1;
+                        continue;
+                    }
+                    Admission::SystemFile => {
+                        simulation.system_files += 1;
+                        continue;
+                    }
+                };
+                simulation.system_files += usize::from(isolated);
+                let target = self.admitted_path(&dest, raw_name, isolated);
+                let folded = target.to_string_lossy().to_lowercase();
+                match cases.get(&folded) {
+                    Some(first) if *first != target => {
+                        simulation.case_collisions.push(CaseCollision { first: first.clone(), second: target.clone() });
+                    }
+                    Some(_) => {}
+                    None => {
+                        cases.insert(folded, target.clone());
+                    }
+                }
+
+                // A restore also checks the CRC-32 of the previous copy
+                // before linking to it, which would read it whole.
+                let previous = self.previous_copy(&dest, clean, &target, entry.size, Some(entry.crc32));
+                let linked = previous.is_some_and(|previous| previous.symlink_metadata().is_ok_and(|meta| meta.is_file() && meta.len() == entry.size));
+                let restored = if linked { Planned::Linked } else { Planned::Written(entry.size) };
+                let earlier = planned.get(&target).copied();
+                // The content-addressed layout has no files at these paths.
+                let tree = self.options.dest_layout == DestLayout::Tree;
+                let existing = tree && earlier.is_none() && target.symlink_metadata().is_ok();
+                if !existing && earlier.is_none() {
+                    simulation.add(restored);
+                    planned.insert(target, restored);
+                    continue;
+                }
+                let action = match self.conflict_policy(clean) {
+                    ConflictPolicy::Overwrite => PlannedAction::Overwrite,
+                    ConflictPolicy::Skip => PlannedAction::Skip,
+                    ConflictPolicy::Ask if existing && same_contents(&target, entry.size, entry.crc32) => PlannedAction::Skip,
+                    ConflictPolicy::Ask => PlannedAction::Ask,
+                    ConflictPolicy::Rename => PlannedAction::Rename(free_name(&target, |candidate| planned.contains_key(candidate))),
+                };
+                match &action {
+                    PlannedAction::Skip => {}
+                    // The file restored before in the run is replaced.
+                    PlannedAction::Overwrite | PlannedAction::Ask => {
+                        if let Some(earlier) = earlier {
+                            simulation.remove(earlier);
+                        }
+                        simulation.add(restored);
+                        planned.insert(target.clone(), restored);
+                    }
+                    PlannedAction::Rename(renamed) => {
+                        simulation.add(restored);
+                        planned.insert(renamed.clone(), restored);
+                    }
+                }
+                simulation.conflicts.push(PlannedConflict { archive: zip.clone(), path: clean.to_string(), target, existing, action });
+            }
+        }
+        simulation
+    }
+}
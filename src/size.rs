@@ -0,0 +1,33 @@
+//! Sizes in bytes as people read and write them, in KB, MB, GB and TB of
+//! 1024 of the unit below, for the options and queries that take a size and
+//! everything that shows one.
+
+const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+/// `bytes` in the largest unit it reaches, such as `512 B` or `1.50 GB`.
+pub fn format_size(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.2} {}", value, UNITS[unit]) }
+}
+
+/// A size in bytes, or with a `K`, `M`, `G` or `T` suffix, as
+/// [`format_size`] shows them: `512`, `512M`, `2G`, `1.5 GB` or `2GiB`.
+/// `None` for anything else, and sizes of 16 EiB or more.
+pub fn parse_size(value: &str) -> Option<u64> {
+    let upper = value.trim().to_ascii_uppercase();
+    let number = upper.trim_end_matches(['B', 'I']);
+    let (number, shift) = match number.char_indices().last() {
+        Some((i, 'K')) => (&number[..i], 10),
+        Some((i, 'M')) => (&number[..i], 20),
+        Some((i, 'G')) => (&number[..i], 30),
+        Some((i, 'T')) => (&number[..i], 40),
+        _ => (number, 0),
+    };
+    let bytes = number.trim().parse::<f64>().ok()? * (1u64 << shift) as f64;
+    (0.0..u64::MAX as f64).contains(&bytes).then_some(bytes as u64)
+}
@@ -0,0 +1,173 @@
+//! The folders of a backup with the uncompressed size of their files: where
+//! the bulk of it lives, as `du` shows it, for choosing what to filter out
+//! of a restore, the folders holding the most files themselves, such as
+//! caches and photo dumps, and a listing of the files of all archives
+//! merged, for finding one's way around it.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::analyze::ExtensionStats;
+use crate::backup_set::BackupSet;
+use crate::catalog::BackupTime;
+use crate::error::ArchiveError;
+use crate::filter::Filter;
+
+/// A folder of a [`SizeTree`] with everything below it.
+#[derive(Debug, Clone, Default)]
+pub struct FolderSize {
+    pub files: u64,
+    pub bytes: u64,
+    /// The folders in it, by name, down to the depth of the tree.
+    pub folders: BTreeMap<String, FolderSize>,
+    /// The files directly in it and their sizes, by name, in a
+    /// [`list_tree`] down to its depth.
+    pub names: BTreeMap<String, u64>,
+}
+
+impl FolderSize {
+    /// The folders in it, the largest first.
+    pub fn largest(&self) -> Vec<(&str, &FolderSize)> {
+        let mut folders: Vec<_> = self.folders.iter().map(|(name, folder)| (name.as_str(), folder)).collect();
+        folders.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(b.0)));
+        folders
+    }
+}
+
+/// What [`size_tree`] found.
+#[derive(Debug, Clone, Default)]
+pub struct SizeTree {
+    /// The restore root, holding every file.
+    pub root: FolderSize,
+    pub errors: Vec<ArchiveError>,
+}
+
+/// Reads the central directories of `set` and adds up the files by folder,
+/// `depth` folders deep; those below count for their folder at that depth.
+pub fn size_tree(set: &BackupSet, depth: usize) -> SizeTree {
+    let mut tree = SizeTree::default();
+    for entry in set.entries() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                tree.errors.push(e);
+                continue;
+            }
+        };
+        if entry.is_dir {
+            continue;
+        }
+        tree.add(entry.path(), entry.size, depth, false);
+    }
+    tree
+}
+
+/// Reads the central directories of `set` and lists the files below folder
+/// `under`, a restore path such as `Users/bob`, that `filter` matches,
+/// `depth` levels deep. A file in several archives, such as one changed
+/// between incremental runs, counts once, with its last size.
+pub fn list_tree(set: &BackupSet, under: &str, depth: usize, filter: &Filter) -> SizeTree {
+    let under = under.trim_matches('/');
+    let mut tree = SizeTree::default();
+    let mut files = HashMap::new();
+    for entry in set.entries() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                tree.errors.push(e);
+                continue;
+            }
+        };
+        if entry.is_dir || !filter.matches_file(entry.path(), entry.size, entry.modified.map(BackupTime::from)) {
+            continue;
+        }
+        let Some(relative) = below(entry.path(), under) else { continue };
+        files.insert(relative.to_string(), entry.size);
+    }
+    for (path, size) in files {
+        tree.add(&path, size, depth, true);
+    }
+    tree
+}
+
+/// What [`hotspots`] found.
+#[derive(Debug, Clone, Default)]
+pub struct Hotspots {
+    /// The files directly in each folder, by restore path.
+    pub folders: HashMap<String, ExtensionStats>,
+    pub errors: Vec<ArchiveError>,
+}
+
+impl Hotspots {
+    /// The `n` folders with the most bytes in their files.
+    pub fn by_bytes(&self, n: usize) -> Vec<(&str, ExtensionStats)> {
+        self.top(n, |stats| stats.bytes)
+    }
+
+    /// The `n` folders with the most files.
+    pub fn by_files(&self, n: usize) -> Vec<(&str, ExtensionStats)> {
+        self.top(n, |stats| stats.files)
+    }
+
+    fn top(&self, n: usize, key: impl Fn(&ExtensionStats) -> u64) -> Vec<(&str, ExtensionStats)> {
+        let mut folders: Vec<_> = self.folders.iter().map(|(folder, stats)| (folder.as_str(), *stats)).collect();
+        folders.sort_by(|a, b| key(&b.1).cmp(&key(&a.1)).then_with(|| a.0.cmp(b.0)));
+        folders.truncate(n);
+        folders
+    }
+}
+
+/// Reads the central directories of `set` and adds up the files directly
+/// in each folder, not those in folders below it, so the folders that hold
+/// the most stand out rather than those they are in.
+pub fn hotspots(set: &BackupSet) -> Hotspots {
+    let mut hotspots = Hotspots::default();
+    for entry in set.entries() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                hotspots.errors.push(e);
+                continue;
+            }
+        };
+        if entry.is_dir {
+            continue;
+        }
+        let folder = entry.path().rsplit_once('/').map_or("", |(dir, _)| dir);
+        let stats = hotspots.folders.entry(folder.to_string()).or_default();
+        stats.files += 1;
+        stats.bytes += entry.size;
+    }
+    hotspots
+}
+
+impl SizeTree {
+    /// Counts the file at `path`, of `size` bytes, for each folder it is
+    /// in down to `depth`, and lists it if `named` and it is at most
+    /// `depth` levels deep.
+    fn add(&mut self, path: &str, size: u64, depth: usize, named: bool) {
+        let (dirs, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let dirs: Vec<&str> = dirs.split('/').filter(|dir| !dir.is_empty()).collect();
+        let mut folder = &mut self.root;
+        folder.files += 1;
+        folder.bytes += size;
+        for dir in dirs.iter().take(depth) {
+            folder = folder.folders.entry(dir.to_string()).or_default();
+            folder.files += 1;
+            folder.bytes += size;
+        }
+        if named && dirs.len() < depth {
+            folder.names.insert(name.to_string(), size);
+        }
+    }
+}
+
+/// `path` relative to folder `under`, compared as Windows does, ignoring
+/// case; `None` if it is not below it.
+fn below<'a>(path: &'a str, under: &str) -> Option<&'a str> {
+    if under.is_empty() {
+        return Some(path);
+    }
+    let head = path.get(..under.len())?;
+    let rest = path[under.len()..].strip_prefix('/')?;
+    head.eq_ignore_ascii_case(under).then_some(rest)
+}
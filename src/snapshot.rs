@@ -0,0 +1,96 @@
+//! Restoring each backup set of a Windows 7/8 backup folder into a folder
+//! of its own, rsnapshot-style: files unchanged since the previous set are
+//! hard links to its copy, so every generation can be browsed for little
+//! more space than the latest one.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::backup_set::BackupSet;
+use crate::catalog::BackupTime;
+use crate::discovery::run_location;
+
+/// One backup set, a full backup and the incremental runs after it, as
+/// found by [`snapshots`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// The machine folder the set is in, if any.
+    pub machine: Option<String>,
+    /// The `Backup Set <time>` folder.
+    pub dir: PathBuf,
+    pub time: BackupTime,
+    /// Its archives, in extraction order.
+    pub archives: Vec<PathBuf>,
+}
+
+impl Snapshot {
+    /// Where the set goes below `dest`: `<machine>/<time>`, with the time
+    /// as in the set's folder name, `YYYY-MM-DD HHMMSS`.
+    pub fn dest(&self, dest: &Path) -> PathBuf {
+        let t = self.time;
+        let name = format!("{:04}-{:02}-{:02} {:02}{:02}{:02}", t.year, t.month, t.day, t.hour, t.minute, t.second);
+        match &self.machine {
+            Some(machine) => dest.join(machine).join(name),
+            None => dest.join(name),
+        }
+    }
+}
+
+/// Groups `zips` into their backup sets, oldest first within each machine.
+/// Archives outside the `Backup Set` folder hierarchy belong to no set and
+/// are left out.
+pub fn snapshots(zips: &[PathBuf]) -> Vec<Snapshot> {
+    let mut snapshots: Vec<Snapshot> = Vec::new();
+    for zip in zips {
+        let Some(location) = run_location(zip) else { continue };
+        let Some(time) = BackupTime::of_folder(&location.run.set) else { continue };
+        match snapshots.iter_mut().find(|s| s.machine == location.machine && s.time == time) {
+            Some(snapshot) => snapshot.archives.push(zip.clone()),
+            None => {
+                let dir = set_dir(zip, &location.run.set);
+                snapshots.push(Snapshot { machine: location.machine, dir, time, archives: vec![zip.clone()] });
+            }
+        }
+    }
+    snapshots.sort_by(|a, b| a.machine.cmp(&b.machine).then(a.time.cmp(&b.time)));
+    snapshots
+}
+
+/// The `set` folder among the ancestors of `zip`.
+fn set_dir(zip: &Path, set: &str) -> PathBuf {
+    zip.ancestors().find(|dir| dir.file_name().is_some_and(|name| name == set)).unwrap_or(zip).to_path_buf()
+}
+
+/// A previous snapshot to hard-link unchanged files from, see
+/// [`crate::RestorerBuilder::link_dest`].
+#[derive(Debug, Clone)]
+pub struct LinkDest {
+    pub(crate) dir: PathBuf,
+    /// CRC-32 and size of the files it holds, by restore path.
+    files: Arc<HashMap<String, (u32, u64)>>,
+}
+
+impl LinkDest {
+    /// The snapshot restored into `dir` from `archives`. Their central
+    /// directories tell what it holds: a later entry for a path replaces an
+    /// earlier one, as on restore.
+    pub fn new(dir: impl Into<PathBuf>, archives: &[PathBuf]) -> LinkDest {
+        let set = BackupSet::from_archives(Path::new(""), archives.to_vec());
+        let mut files = HashMap::new();
+        for entry in set.entries().filter_map(Result::ok) {
+            // Only ZIP entries record a CRC-32.
+            if !entry.is_dir && entry.crc32 != 0 {
+                files.insert(entry.path().to_string(), (entry.crc32, entry.size));
+            }
+        }
+        LinkDest { dir: dir.into(), files: Arc::new(files) }
+    }
+
+    /// Whether the entry at restore path `path` has the content the
+    /// snapshot's archives hold for it. The file restored from them may
+    /// still differ, so the caller checks it too.
+    pub(crate) fn unchanged(&self, path: &str, crc32: u32, size: u64) -> bool {
+        self.files.get(path) == Some(&(crc32, size))
+    }
+}
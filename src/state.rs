@@ -0,0 +1,102 @@
+//! The state file a restore keeps in its destination folder, so its
+//! progress can be followed from another process, such as `restore status`
+//! for a restore running in the background.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// Name of the state file in the destination folder.
+pub const STATE_FILE: &str = ".restore-state.json";
+
+/// What a restore is doing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    #[default]
+    Analyzing,
+    /// Waiting for the time given with `--start-at`.
+    Waiting,
+    Counting,
+    Restoring,
+    Finished,
+}
+
+/// The progress of a restore, as kept in its [`STATE_FILE`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunState {
+    /// Process id of the restore.
+    pub pid: u32,
+    pub source: PathBuf,
+    pub dest: PathBuf,
+    pub phase: Phase,
+    /// When the restore started and when the file was last written, in
+    /// seconds since the Unix epoch. A running restore writes it every
+    /// second, so an old `updated` means the process is gone.
+    pub started: u64,
+    pub updated: u64,
+    /// When a restore that was told to wait starts, in the same unit.
+    pub start_at: Option<u64>,
+    pub archives_done: usize,
+    pub archives_total: usize,
+    pub files_done: usize,
+    pub files_total: usize,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    pub errors: usize,
+    /// The archive last started.
+    pub current_archive: Option<PathBuf>,
+    /// Where the output of a restore running in the background goes.
+    pub log: Option<PathBuf>,
+    /// The exit status, once finished.
+    pub exit_code: Option<i32>,
+}
+
+impl RunState {
+    /// A state for a restore of `source` into `dest` by this process,
+    /// starting now.
+    pub fn new(source: &Path, dest: &Path) -> RunState {
+        let now = now();
+        RunState {
+            pid: std::process::id(),
+            source: source.to_path_buf(),
+            dest: dest.to_path_buf(),
+            started: now,
+            updated: now,
+            ..RunState::default()
+        }
+    }
+
+    /// The path of the state file of a restore into `dest`.
+    pub fn path(dest: &Path) -> PathBuf {
+        dest.join(STATE_FILE)
+    }
+
+    pub fn read(path: &Path) -> io::Result<RunState> {
+        let json = fs::read(path)?;
+        serde_json::from_slice(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Sets `updated` and writes the state to `path`. The file is replaced
+    /// as a whole, so readers never see a partly written one.
+    pub fn write(&mut self, path: &Path) -> io::Result<()> {
+        self.updated = now();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        fs::write(&partial, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&partial, path)
+    }
+
+    /// Seconds since the state was last written.
+    pub fn age(&self) -> u64 {
+        now().saturating_sub(self.updated)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
@@ -0,0 +1,22 @@
+//! Files of the Windows installation that a drive backup picks up along
+//! with the user's data: registry hives, the page and hibernation files,
+//! and the contents of `Windows/System32`. They are tied to the machine
+//! they came from and of no use when restoring onto another one.
+
+/// Whether `path`, a restore path such as `Windows/System32/config/SAM`, is
+/// a system file.
+pub(crate) fn is_system_file(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    let components: Vec<&str> = path.split('/').collect();
+    let name = components.last().copied().unwrap_or_default();
+    // The hives of the user profiles, with their transaction logs
+    // (`NTUSER.DAT.LOG1`, `NTUSER.DAT{...}.TM.blf`, ...); the machine's
+    // hives are in `System32/config`.
+    if name.starts_with("ntuser.dat") || name.starts_with("usrclass.dat") {
+        return true;
+    }
+    if matches!(name, "pagefile.sys" | "hiberfil.sys" | "swapfile.sys") {
+        return true;
+    }
+    components.windows(3).any(|w| w[0] == "windows" && w[1] == "system32")
+}
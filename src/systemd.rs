@@ -0,0 +1,135 @@
+//! The systemd notification protocol (`sd_notify`), for running a restore
+//! as a service: readiness, status lines and watchdog pings are datagrams
+//! sent to the socket systemd names in `NOTIFY_SOCKET`.
+
+use std::io;
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// A connection to the service manager's notification socket.
+///
+/// Notifications are best effort, as with `sd_notify(3)`: failures to send
+/// them are ignored.
+#[derive(Debug)]
+pub struct Notifier {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+    /// The watchdog timeout, if systemd expects pings from this process.
+    watchdog: Option<Duration>,
+}
+
+impl Notifier {
+    /// Connects to the socket in `NOTIFY_SOCKET`. `None` if the process was
+    /// not started by systemd with `Type=notify`, and on systems without
+    /// Unix sockets.
+    #[cfg(unix)]
+    pub fn from_env() -> io::Result<Option<Notifier>> {
+        use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+        let Some(path) = std::env::var_os("NOTIFY_SOCKET") else { return Ok(None) };
+        let address = match path.as_encoded_bytes().strip_prefix(b"@") {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                SocketAddr::from_abstract_name(name)?
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => return Err(io::Error::new(io::ErrorKind::Unsupported, "abstract notification sockets need Linux")),
+            None => SocketAddr::from_pathname(&path)?,
+        };
+        let socket = UnixDatagram::unbound()?;
+        socket.connect_addr(&address)?;
+        Ok(Some(Notifier { socket, watchdog: watchdog_from_env() }))
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_env() -> io::Result<Option<Notifier>> {
+        Ok(None)
+    }
+
+    /// The interval at which [`Notifier::ping`] should be called, half the
+    /// watchdog timeout; `None` without a watchdog.
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog.map(|timeout| timeout / 2)
+    }
+
+    /// Tells systemd the service has started, with a status line.
+    pub fn ready(&self, status: &str) {
+        self.send(&format!("READY=1\nSTATUS={}", one_line(status)));
+    }
+
+    /// Replaces the status line `systemctl status` shows.
+    pub fn status(&self, status: &str) {
+        self.send(&format!("STATUS={}", one_line(status)));
+    }
+
+    /// Resets the watchdog timer, if there is one.
+    pub fn ping(&self) {
+        if self.watchdog.is_some() {
+            self.send("WATCHDOG=1");
+        }
+    }
+
+    /// Tells systemd the service is shutting down, with a last status line.
+    pub fn stopping(&self, status: &str) {
+        self.send(&format!("STOPPING=1\nSTATUS={}", one_line(status)));
+    }
+
+    /// Pings the watchdog from a background thread until the returned guard
+    /// is dropped, for phases that report no progress of their own.
+    pub fn keep_alive(self: &Arc<Self>) -> KeepAlive {
+        let Some(interval) = self.watchdog_interval() else { return KeepAlive { stop: None, thread: None } };
+        let (stop, stopped) = mpsc::channel::<()>();
+        let notifier = Arc::clone(self);
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                notifier.ping();
+            }
+        });
+        KeepAlive { stop: Some(stop), thread: Some(thread) }
+    }
+
+    fn send(&self, state: &str) {
+        #[cfg(unix)]
+        let _ = self.socket.send(state.as_bytes());
+        #[cfg(not(unix))]
+        let _ = state;
+    }
+}
+
+/// Stops the pings of [`Notifier::keep_alive`] when dropped.
+#[derive(Debug)]
+pub struct KeepAlive {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The watchdog timeout from `WATCHDOG_USEC`, unless `WATCHDOG_PID` names
+/// another process.
+#[cfg(unix)]
+fn watchdog_from_env() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse() != Ok(std::process::id())
+    {
+        return None;
+    }
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// Status lines end at the first newline, which would start another
+/// assignment.
+fn one_line(status: &str) -> &str {
+    status.lines().next().unwrap_or_default()
+}
@@ -0,0 +1,195 @@
+//! Tarballs made by other backup tools, plain or compressed with gzip,
+//! bzip2 or zstd.
+//!
+//! Opening a tarball reads it once to list its files. Uncompressed
+//! tarballs are then read like ZIPs, at the offset of each file; compressed
+//! ones can only be decompressed front to back, so their files are restored
+//! in archive order by a single reader.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
+
+use tar::EntryType;
+
+use crate::archive::{ArchiveReader, Member};
+use crate::catalog::BackupTime;
+use crate::reader::ArchiveFile;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Zstd,
+}
+
+impl Compression {
+    /// The compression of a file starting with `head`, going by its magic
+    /// bytes.
+    fn of(head: &[u8]) -> Compression {
+        if head.starts_with(b"\x1f\x8b") {
+            Compression::Gzip
+        } else if head.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if head.starts_with(b"\x28\xb5\x2f\xfd") {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// The tar stream of `file`.
+    fn reader(self, file: ArchiveFile) -> io::Result<Box<dyn Read + Send>> {
+        Ok(match self {
+            Compression::None => Box::new(file),
+            Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(file)),
+            Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(file)),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(file)?),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TarEntry {
+    name: String,
+    /// Offset of the file data in the tar stream.
+    offset: u64,
+    size: u64,
+    modified: Option<BackupTime>,
+    unsupported: Option<&'static str>,
+}
+
+/// A compressed tar stream and how far it has been read.
+struct Cursor {
+    reader: Box<dyn Read + Send>,
+    pos: u64,
+}
+
+/// The regular files of a tarball. A file added several times (`tar -r`)
+/// is restored in its last version, as `tar -x` would.
+pub(crate) struct TarArchive {
+    file: ArchiveFile,
+    compression: Compression,
+    entries: Vec<TarEntry>,
+    cursor: Mutex<Option<Cursor>>,
+}
+
+impl TarArchive {
+    pub(crate) fn open(path: &Path, mmap: bool) -> io::Result<TarArchive> {
+        let mut file = ArchiveFile::open(path, mmap)?;
+        let mut head = Vec::new();
+        (&mut file).take(4).read_to_end(&mut head)?;
+        file.seek(SeekFrom::Start(0))?;
+        let compression = Compression::of(&head);
+        let mut archive = tar::Archive::new(compression.reader(file.clone())?);
+        let mut entries: Vec<TarEntry> = Vec::new();
+        let mut by_name: HashMap<String, usize> = HashMap::new();
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let header = entry.header();
+            let name = clean_name(&entry.path_bytes());
+            let kind = header.entry_type();
+            let (offset, size, unsupported) = match kind {
+                EntryType::Regular | EntryType::Continuous => (entry.raw_file_position(), entry.size(), None),
+                EntryType::GNUSparse => (entry.raw_file_position(), entry.size(), Some("sparse files in tarballs are not supported")),
+                // A hard link restores the data of the file it links to.
+                EntryType::Link => {
+                    let target = entry.link_name_bytes().map(|t| clean_name(&t));
+                    match target.and_then(|t| by_name.get(&t)).map(|&i| &entries[i]) {
+                        Some(target) => (target.offset, target.size, target.unsupported),
+                        None => (0, 0, Some("hard link to a file not in the tarball")),
+                    }
+                }
+                _ => continue,
+            };
+            if name.is_empty() {
+                continue;
+            }
+            let modified = header.mtime().ok().and_then(|t| BackupTime::from_unix(t as i64));
+            let entry = TarEntry { name: name.clone(), offset, size, modified, unsupported };
+            match by_name.get(&name) {
+                Some(&i) => entries[i] = entry,
+                None => {
+                    by_name.insert(name, entries.len());
+                    entries.push(entry);
+                }
+            }
+        }
+        Ok(TarArchive { file, compression, entries, cursor: Mutex::new(None) })
+    }
+}
+
+/// Whether the file at `path`, starting with `head`, is a tarball: its
+/// first header, decompressed if need be, has the `ustar` magic. Tarballs
+/// of the original V7 format have none and are only known by their name.
+pub(crate) fn is_tarball(path: &Path, head: &[u8]) -> bool {
+    let header = match Compression::of(head) {
+        Compression::None => head.to_vec(),
+        compression => {
+            let mut header = Vec::new();
+            let read = ArchiveFile::open(path, false)
+                .and_then(|file| compression.reader(file))
+                .and_then(|reader| reader.take(512).read_to_end(&mut header));
+            if read.is_err() {
+                return false;
+            }
+            header
+        }
+    };
+    header.get(257..262) == Some(b"ustar")
+}
+
+/// `./home/bob/file` into `home/bob/file`.
+fn clean_name(raw: &[u8]) -> String {
+    let name = String::from_utf8_lossy(raw).replace('\\', "/");
+    name.split('/').filter(|c| !c.is_empty() && *c != ".").collect::<Vec<_>>().join("/")
+}
+
+impl ArchiveReader for TarArchive {
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn member(&self, j: usize) -> Member<'_> {
+        let entry = &self.entries[j];
+        Member { name: &entry.name, size: entry.size, modified: entry.modified, attributes: None }
+    }
+
+    fn read(&self, j: usize, f: &mut dyn FnMut(&mut dyn Read)) -> io::Result<()> {
+        let entry = &self.entries[j];
+        if let Some(reason) = entry.unsupported {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, reason));
+        }
+        if self.compression == Compression::None {
+            let mut file = self.file.clone();
+            file.seek(SeekFrom::Start(entry.offset))?;
+            f(&mut file.take(entry.size));
+            return Ok(());
+        }
+        let mut cursor = self.cursor.lock().unwrap();
+        if cursor.as_ref().is_none_or(|c| c.pos > entry.offset) {
+            *cursor = Some(Cursor { reader: self.compression.reader(self.file.clone())?, pos: 0 });
+        }
+        let c = cursor.as_mut().expect("cursor was just set");
+        let skip = entry.offset - c.pos;
+        if io::copy(&mut (&mut c.reader).take(skip), &mut io::sink())? < skip {
+            *cursor = None;
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "tarball ends before the file's data"));
+        }
+        let mut data = (&mut c.reader).take(entry.size);
+        f(&mut data);
+        if data.limit() == 0 {
+            c.pos = entry.offset + entry.size;
+        } else {
+            // Not read to the end, e.g. after an error: start over next time.
+            *cursor = None;
+        }
+        Ok(())
+    }
+
+    fn sequential(&self) -> bool {
+        self.compression != Compression::None
+    }
+}
@@ -0,0 +1,168 @@
+//! Writing small files through io_uring on Linux, with the `uring` feature.
+//! The creates, writes and closes of a batch of files go to the kernel in
+//! three submissions rather than as three system calls per file, which is
+//! where restores of millions of tiny files spend their time.
+
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::mem::ManuallyDrop;
+use std::os::fd::FromRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::time::Duration;
+
+use io_uring::{IoUring, opcode, squeue, types};
+
+/// Files up to this size are written in batches.
+pub(crate) const SMALL_FILE: u64 = 64 * 1024;
+/// Files per batch.
+pub(crate) const BATCH: usize = 64;
+/// How long a batch that is not full waits for more files.
+pub(crate) const BATCH_WAIT: Duration = Duration::from_millis(2);
+
+/// The ring of one writer thread.
+pub(crate) struct Ring {
+    ring: IoUring,
+    /// Set once a submission failed; its entries may still be queued, so
+    /// the ring is not used again.
+    broken: bool,
+}
+
+impl Ring {
+    /// `None` where the kernel lacks io_uring or does not allow it, as the
+    /// seccomp profiles of many containers do.
+    pub(crate) fn new() -> Option<Ring> {
+        IoUring::new(BATCH as u32).ok().map(|ring| Ring { ring, broken: false })
+    }
+
+    /// Whether the ring failed and is of no further use.
+    pub(crate) fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Creates each file of `files`, or truncates it, and writes its
+    /// content, at most [`BATCH`] files at once; returns how each went.
+    /// `None` if the ring failed before creating any, so they are all left
+    /// to be written otherwise.
+    pub(crate) fn write_all(&mut self, files: &[(&Path, &[u8])]) -> Option<Vec<io::Result<()>>> {
+        assert!(files.len() <= BATCH && !self.broken);
+        let mut results: Vec<io::Result<()>> = Vec::with_capacity(files.len());
+        let mut paths = Vec::with_capacity(files.len());
+        for (path, _) in files {
+            match CString::new(path.as_os_str().as_bytes()) {
+                Ok(path) => {
+                    results.push(Ok(()));
+                    paths.push(Some(path));
+                }
+                Err(e) => {
+                    results.push(Err(io::Error::new(io::ErrorKind::InvalidInput, e)));
+                    paths.push(None);
+                }
+            }
+        }
+
+        let mut fds: Vec<Option<i32>> = vec![None; files.len()];
+        let opens = paths.iter().enumerate().filter_map(|(i, path)| {
+            let path = path.as_ref()?;
+            let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC | libc::O_CLOEXEC;
+            Some(opcode::OpenAt::new(types::Fd(libc::AT_FDCWD), path.as_ptr()).flags(flags).mode(0o666).build().user_data(i as u64))
+        });
+        let opened = self.run(opens.collect(), |i, result| match result {
+            Ok(fd) => fds[i] = Some(fd),
+            Err(e) => results[i] = Err(e),
+        });
+        if opened.is_err() {
+            // Opens still in flight may read their paths.
+            if self.broken {
+                std::mem::forget(paths);
+            }
+            return None;
+        }
+
+        let mut written = vec![0; files.len()];
+        let writes = fds.iter().enumerate().filter_map(|(i, fd)| {
+            let data = files[i].1;
+            Some(opcode::Write::new(types::Fd((*fd)?), data.as_ptr(), data.len() as u32).build().user_data(i as u64))
+        });
+        let wrote = self.run(writes.collect(), |i, result| match result {
+            Ok(n) => written[i] = n as usize,
+            Err(e) => results[i] = Err(e),
+        });
+        for (i, fd) in fds.iter().enumerate() {
+            let (Some(fd), Ok(())) = (*fd, &results[i]) else { continue };
+            let rest = &files[i].1[written[i]..];
+            if wrote.is_err() || !rest.is_empty() {
+                // Short writes are rare for files this small; they, and
+                // all of a batch the ring failed on, finish here. The file
+                // stays open for the close below.
+                // SAFETY: `fd` was opened above and is closed only below.
+                let file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+                let offset = if wrote.is_err() { 0 } else { written[i] };
+                results[i] = file.write_all_at(&files[i].1[offset..], offset as u64);
+            }
+        }
+
+        let closes = fds.iter().enumerate().filter_map(|(i, fd)| Some(opcode::Close::new(types::Fd((*fd)?)).build().user_data(i as u64)));
+        let closed = self.run(closes.collect(), |i, result| {
+            if let (Err(e), Ok(())) = (result, &results[i]) {
+                results[i] = Err(e);
+            }
+        });
+        if closed.is_err() {
+            for fd in fds.into_iter().flatten() {
+                // SAFETY: the ring closed none of them.
+                drop(unsafe { File::from_raw_fd(fd) });
+            }
+        }
+        Some(results)
+    }
+
+    /// Submits `entries` and waits for all of them, handing the result of
+    /// each to `done` with its index. Fails, with none of them done, if the
+    /// ring does not take them, and with some still in flight, breaking
+    /// the ring, if it cannot wait for them but for a signal.
+    fn run(&mut self, entries: Vec<squeue::Entry>, mut done: impl FnMut(usize, io::Result<i32>)) -> io::Result<()> {
+        if self.broken {
+            return Err(io::Error::other("io_uring failed before"));
+        }
+        let mut pending = entries.len();
+        for entry in &entries {
+            // SAFETY: the paths and buffers the entries point to outlive
+            // this call, which waits for their completions.
+            if unsafe { self.ring.submission().push(entry) }.is_err() {
+                self.broken = true;
+                return Err(io::Error::other("io_uring submission queue full"));
+            }
+        }
+        let wait = |ring: &IoUring, want| loop {
+            match ring.submit_and_wait(want) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => return result,
+            }
+        };
+        if let Err(e) = wait(&self.ring, pending) {
+            self.broken = true;
+            return Err(e);
+        }
+        loop {
+            for completion in self.ring.completion() {
+                let result = completion.result();
+                let result = if result < 0 { Err(io::Error::from_raw_os_error(-result)) } else { Ok(result) };
+                done(completion.user_data() as usize, result);
+                pending -= 1;
+            }
+            if pending == 0 {
+                return Ok(());
+            }
+            // The entries are in flight, and their buffers must stay until
+            // the kernel is done with them. A ring that cannot wait for
+            // them is broken; its owner drops it, which cancels them.
+            if let Err(e) = wait(&self.ring, pending) {
+                self.broken = true;
+                return Err(e);
+            }
+        }
+    }
+}
@@ -0,0 +1,514 @@
+//! Windows system image backups: VHD and VHDX disk images holding NTFS
+//! volumes (feature `vhd`).
+//!
+//! Images are read-only. Fixed and dynamic VHD and VHDX disks are
+//! supported; differencing disks, which need their parent image, are not.
+//! Of the NTFS partitions on the disk the largest one is read, which is the
+//! system volume rather than the small boot partition next to it.
+
+use std::collections::HashSet;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+use ntfs::structured_values::{NtfsFileAttributeFlags, NtfsFileNamespace, NtfsStandardInformation};
+use ntfs::{Ntfs, NtfsAttributeType, NtfsFile};
+
+use crate::archive::{ArchiveReader, Member};
+use crate::catalog::BackupTime;
+use crate::placeholder::{RECALL_ON_DATA_ACCESS, is_cloud_reparse_tag};
+use crate::reader::ArchiveFile;
+
+const SECTOR: u64 = 512;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_exact_at(file: &mut ArchiveFile, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
+
+fn be_u32(b: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(b[at..at + 4].try_into().unwrap())
+}
+
+fn be_u64(b: &[u8], at: usize) -> u64 {
+    u64::from_be_bytes(b[at..at + 8].try_into().unwrap())
+}
+
+fn le_u32(b: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(b[at..at + 4].try_into().unwrap())
+}
+
+fn le_u64(b: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(b[at..at + 8].try_into().unwrap())
+}
+
+/// GUID in its on-disk (mixed-endian) byte order.
+fn guid(text: &str) -> [u8; 16] {
+    let hex: Vec<u8> = text
+        .split('-')
+        .flat_map(|part| (0..part.len()).step_by(2).map(move |i| u8::from_str_radix(&part[i..i + 2], 16).unwrap()))
+        .collect();
+    let mut g = [0u8; 16];
+    g[..4].copy_from_slice(&[hex[3], hex[2], hex[1], hex[0]]);
+    g[4..6].copy_from_slice(&[hex[5], hex[4]]);
+    g[6..8].copy_from_slice(&[hex[7], hex[6]]);
+    g[8..].copy_from_slice(&hex[8..16]);
+    g
+}
+
+/// Where the virtual disk's blocks live in the image file.
+enum Layout {
+    /// The disk is stored as-is, starting at the beginning of the file.
+    Fixed,
+    /// Blocks of `block_size` bytes; `None` for blocks never written.
+    Blocks { block_size: u64, offsets: Vec<Option<u64>> },
+}
+
+/// The virtual disk inside a VHD or VHDX file.
+#[derive(Clone)]
+struct Disk {
+    file: ArchiveFile,
+    layout: Arc<Layout>,
+    size: u64,
+    pos: u64,
+}
+
+impl Disk {
+    fn open(mut file: ArchiveFile) -> io::Result<Disk> {
+        let mut signature = [0u8; 8];
+        read_exact_at(&mut file, 0, &mut signature)?;
+        let (layout, size) = if &signature == b"vhdxfile" { vhdx_layout(&mut file)? } else { vhd_layout(&mut file)? };
+        Ok(Disk { file, layout: Arc::new(layout), size, pos: 0 })
+    }
+}
+
+fn vhd_layout(file: &mut ArchiveFile) -> io::Result<(Layout, u64)> {
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut footer = [0u8; 512];
+    read_exact_at(file, len.checked_sub(512).ok_or_else(|| invalid("file too small for a VHD"))?, &mut footer)?;
+    if &footer[..8] != b"conectix" {
+        // Dynamic disks may end in a 511-byte footer written by old tools;
+        // the copy at the start of the file is always complete.
+        read_exact_at(file, 0, &mut footer)?;
+        if &footer[..8] != b"conectix" {
+            return Err(invalid("no VHD footer found"));
+        }
+    }
+    let size = be_u64(&footer, 48);
+    match be_u32(&footer, 60) {
+        2 => Ok((Layout::Fixed, size)),
+        3 => {
+            let mut header = [0u8; 1024];
+            read_exact_at(file, be_u64(&footer, 16), &mut header)?;
+            if &header[..8] != b"cxsparse" {
+                return Err(invalid("invalid VHD dynamic disk header"));
+            }
+            let table = be_u64(&header, 16);
+            let entries = be_u32(&header, 28) as usize;
+            let block_size = u64::from(be_u32(&header, 32));
+            let bitmap = (block_size / SECTOR).div_ceil(8).next_multiple_of(SECTOR);
+            let mut bat = vec![0u8; entries * 4];
+            read_exact_at(file, table, &mut bat)?;
+            let offsets = bat
+                .chunks_exact(4)
+                .map(|e| u32::from_be_bytes(e.try_into().unwrap()))
+                .map(|sector| (sector != u32::MAX).then(|| u64::from(sector) * SECTOR + bitmap))
+                .collect();
+            Ok((Layout::Blocks { block_size, offsets }, size))
+        }
+        4 => Err(io::Error::new(io::ErrorKind::Unsupported, "differencing VHD disks are not supported")),
+        _ => Err(invalid("unknown VHD disk type")),
+    }
+}
+
+/// The `N` bytes at `at` of `b`, or `InvalidData` if `b` ends before.
+fn field<const N: usize>(b: &[u8], at: usize) -> io::Result<[u8; N]> {
+    b.get(at..at + N).and_then(|bytes| bytes.try_into().ok()).ok_or_else(|| invalid("truncated VHDX structure"))
+}
+
+fn vhdx_layout(file: &mut ArchiveFile) -> io::Result<(Layout, u64)> {
+    const BAT: &str = "2DC27766-F623-4200-9D64-115E9BFD4A08";
+    const METADATA: &str = "8B7CA206-4790-4B9A-B8FE-575F050F886E";
+    const FILE_PARAMETERS: &str = "CAA16737-FA36-4D43-B3B6-33F0AA44E76B";
+    const DISK_SIZE: &str = "2FA54224-CD1B-4876-B211-5DBED83BF4B8";
+    const SECTOR_SIZE: &str = "8141BF1D-A96F-4709-BA47-F233A8FAAB5F";
+    /// The metadata region holds at most 2047 entries of 32 bytes and
+    /// their items, within 1 MiB.
+    const MAX_METADATA: u32 = 1 << 20;
+
+    let len = file.seek(SeekFrom::End(0))?;
+    // Two copies of the region table; both are written identically.
+    let mut table = vec![0u8; 64 * 1024];
+    read_exact_at(file, 192 * 1024, &mut table)?;
+    if &table[..4] != b"regi" {
+        read_exact_at(file, 256 * 1024, &mut table)?;
+        if &table[..4] != b"regi" {
+            return Err(invalid("no VHDX region table found"));
+        }
+    }
+    let count = u32::from_le_bytes(field(&table, 8)?) as usize;
+    let region = |id: &str| -> io::Result<Option<(u64, u32)>> {
+        let id = guid(id);
+        for i in 0..count.min(2047) {
+            let entry: [u8; 32] = field(&table, 16 + i * 32)?;
+            if entry[..16] == id {
+                return Ok(Some((u64::from_le_bytes(field(&entry, 16)?), u32::from_le_bytes(field(&entry, 24)?))));
+            }
+        }
+        Ok(None)
+    };
+    let (bat_offset, bat_len) = region(BAT)?.ok_or_else(|| invalid("VHDX has no block allocation table"))?;
+    let (meta_offset, meta_len) = region(METADATA)?.ok_or_else(|| invalid("VHDX has no metadata region"))?;
+    // Both are read whole, so they must lie within the file.
+    let within = |offset: u64, region_len: u32| offset.checked_add(u64::from(region_len)).is_some_and(|end| end <= len);
+    if meta_len > MAX_METADATA || !within(meta_offset, meta_len) || !within(bat_offset, bat_len) {
+        return Err(invalid("VHDX region outside the file"));
+    }
+
+    let mut meta = vec![0u8; meta_len as usize];
+    read_exact_at(file, meta_offset, &mut meta)?;
+    if meta.get(..8) != Some(b"metadata") {
+        return Err(invalid("invalid VHDX metadata table"));
+    }
+    let items = usize::from(u16::from_le_bytes(field(&meta, 10)?));
+    let item = |id: &str, what: &str| -> io::Result<&[u8]> {
+        let id = guid(id);
+        for i in 0..items.min(2047) {
+            let entry: [u8; 32] = field(&meta, 32 + i * 32)?;
+            if entry[..16] == id {
+                return meta.get(u32::from_le_bytes(field(&entry, 16)?) as usize..).ok_or_else(|| invalid("VHDX metadata item outside its region"));
+            }
+        }
+        Err(invalid(&format!("VHDX lacks the {}", what)))
+    };
+    let parameters = item(FILE_PARAMETERS, "file parameters")?;
+    if u32::from_le_bytes(field(parameters, 4)?) & 2 != 0 {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "differencing VHDX disks are not supported"));
+    }
+    let block_size = u64::from(u32::from_le_bytes(field(parameters, 0)?));
+    let size = u64::from_le_bytes(field(item(DISK_SIZE, "disk size")?, 0)?);
+    let sector_size = u64::from(u32::from_le_bytes(field(item(SECTOR_SIZE, "sector size")?, 0)?));
+    // The format allows blocks of 1 to 256 MiB, powers of two, and
+    // sectors of 512 or 4096 bytes.
+    if !block_size.is_power_of_two() || !(1 << 20..=256 << 20).contains(&block_size) || !matches!(sector_size, 512 | 4096) {
+        return Err(invalid("invalid VHDX block or sector size"));
+    }
+
+    // Every `chunk_ratio` payload blocks the table holds one entry for a
+    // sector bitmap block, which only differencing disks use. At least 16
+    // with the sizes above.
+    let chunk_ratio = (1u64 << 23) * sector_size / block_size;
+    let mut bat = vec![0u8; bat_len as usize];
+    read_exact_at(file, bat_offset, &mut bat)?;
+    // Blocks past the end of the table were never written; they read as
+    // zeros without an entry.
+    let offsets = (0..size.div_ceil(block_size))
+        .map_while(|b| {
+            let i = usize::try_from(b + b / chunk_ratio).ok()?;
+            let entry = u64::from_le_bytes(bat.get(i * 8..i * 8 + 8)?.try_into().ok()?);
+            // PAYLOAD_BLOCK_FULLY_PRESENT and _PARTIALLY_PRESENT.
+            Some(matches!(entry & 7, 6 | 7).then_some((entry >> 20) << 20))
+        })
+        .collect();
+    Ok((Layout::Blocks { block_size, offsets }, size))
+}
+
+impl Read for Disk {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.size.saturating_sub(self.pos);
+        if remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let n = match &*self.layout {
+            Layout::Fixed => {
+                let len = buf.len().min(remaining as usize);
+                self.file.seek(SeekFrom::Start(self.pos))?;
+                self.file.read(&mut buf[..len])?
+            }
+            Layout::Blocks { block_size, offsets } => {
+                let within = self.pos % block_size;
+                let len = buf.len().min((block_size - within).min(remaining) as usize);
+                match offsets.get((self.pos / block_size) as usize).copied().flatten() {
+                    Some(offset) => {
+                        self.file.seek(SeekFrom::Start(offset + within))?;
+                        self.file.read(&mut buf[..len])?
+                    }
+                    None => {
+                        buf[..len].fill(0);
+                        len
+                    }
+                }
+            }
+        };
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Disk {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.size.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        Ok(self.pos)
+    }
+}
+
+/// A partition of the virtual disk.
+#[derive(Clone)]
+struct Volume {
+    disk: Disk,
+    start: u64,
+    len: u64,
+}
+
+impl Read for Volume {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let pos = self.disk.pos.saturating_sub(self.start);
+        let len = buf.len().min(self.len.saturating_sub(pos) as usize);
+        self.disk.read(&mut buf[..len])
+    }
+}
+
+impl Seek for Volume {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let current = self.disk.pos.saturating_sub(self.start);
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => current.checked_add_signed(delta),
+        };
+        let target = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        self.disk.seek(SeekFrom::Start(self.start + target))?;
+        Ok(target)
+    }
+}
+
+/// Start and length in bytes of the partitions listed in the MBR or GPT.
+fn partitions(disk: &mut Disk) -> io::Result<Vec<(u64, u64)>> {
+    let mut mbr = [0u8; 512];
+    disk.seek(SeekFrom::Start(0))?;
+    disk.read_exact(&mut mbr)?;
+    if mbr[510..] != [0x55, 0xAA] || &mbr[3..11] == b"NTFS    " {
+        // A bare volume without partition table.
+        return Ok(vec![(0, disk.size)]);
+    }
+    let entries: Vec<&[u8]> = (0..4).map(|i| &mbr[446 + i * 16..462 + i * 16]).collect();
+    if entries.iter().any(|e| e[4] == 0xEE) {
+        let mut header = [0u8; 512];
+        disk.seek(SeekFrom::Start(SECTOR))?;
+        disk.read_exact(&mut header)?;
+        if &header[..8] != b"EFI PART" {
+            return Err(invalid("invalid GPT header"));
+        }
+        let table = le_u64(&header, 72) * SECTOR;
+        let count = le_u32(&header, 80) as usize;
+        let entry_size = le_u32(&header, 84) as usize;
+        if entry_size < 56 {
+            return Err(invalid("invalid GPT entry size"));
+        }
+        let mut raw = vec![0u8; count.min(1024) * entry_size];
+        disk.seek(SeekFrom::Start(table))?;
+        disk.read_exact(&mut raw)?;
+        return Ok(raw
+            .chunks_exact(entry_size)
+            .filter(|e| e[..16] != [0; 16])
+            .map(|e| (le_u64(e, 32) * SECTOR, (le_u64(e, 40) + 1).saturating_sub(le_u64(e, 32)) * SECTOR))
+            .collect());
+    }
+    Ok(entries
+        .iter()
+        .filter(|e| e[4] != 0)
+        .map(|e| (u64::from(le_u32(e, 8)) * SECTOR, u64::from(le_u32(e, 12)) * SECTOR))
+        .collect())
+}
+
+/// One file of the image's NTFS volume.
+#[derive(Debug, Clone)]
+pub(crate) struct ImageEntry {
+    /// Path from the volume root with `/` separators.
+    pub(crate) name: String,
+    pub(crate) record: u64,
+    pub(crate) size: u64,
+    /// Last modification as NT timestamp.
+    pub(crate) modified: u64,
+    /// Windows file attributes.
+    pub(crate) attributes: u32,
+    /// Why the file record could not be read, if it is damaged.
+    pub(crate) damage: Option<String>,
+}
+
+/// The largest NTFS volume of a disk image and the files on it, sorted by
+/// name. NTFS metadata files (`$MFT`, ...) are left out. Damaged file
+/// records do not stop the listing: they stay entries that fail to restore.
+pub(crate) struct DiskImage {
+    ntfs: Ntfs,
+    volume: Volume,
+    pub(crate) entries: Vec<ImageEntry>,
+}
+
+impl DiskImage {
+    pub(crate) fn open(path: &Path, mmap: bool) -> io::Result<DiskImage> {
+        let mut disk = Disk::open(ArchiveFile::open(path, mmap)?)?;
+        let mut volumes = Vec::new();
+        for (start, len) in partitions(&mut disk)? {
+            let mut boot = [0u8; 11];
+            disk.seek(SeekFrom::Start(start))?;
+            if disk.read_exact(&mut boot).is_ok() && &boot[3..] == b"NTFS    " {
+                volumes.push(Volume { disk: disk.clone(), start, len });
+            }
+        }
+        let mut volume = volumes.into_iter().max_by_key(|v| v.len).ok_or_else(|| invalid("no NTFS partition in disk image"))?;
+        let mut ntfs = Ntfs::new(&mut volume).map_err(io::Error::from)?;
+        ntfs.read_upcase_table(&mut volume).map_err(io::Error::from)?;
+        let entries = walk(&ntfs, &mut volume).map_err(io::Error::from)?;
+        Ok(DiskImage { ntfs, volume, entries })
+    }
+}
+
+impl ArchiveReader for DiskImage {
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn member(&self, j: usize) -> Member<'_> {
+        let entry = &self.entries[j];
+        // NT timestamps count 100 ns intervals since 1601.
+        let modified = BackupTime::from_unix((entry.modified / 10_000_000) as i64 - 11_644_473_600);
+        Member { name: &entry.name, size: entry.size, modified, attributes: Some(entry.attributes) }
+    }
+
+    fn read(&self, j: usize, f: &mut dyn FnMut(&mut dyn Read)) -> io::Result<()> {
+        let entry = &self.entries[j];
+        if let Some(damage) = &entry.damage {
+            return Err(invalid(damage));
+        }
+        let mut volume = self.volume.clone();
+        let file = self.ntfs.file(&mut volume, entry.record).map_err(io::Error::from)?;
+        let item = match file.data(&mut volume, "") {
+            Some(item) => item.map_err(io::Error::from)?,
+            None => {
+                f(&mut io::empty());
+                return Ok(());
+            }
+        };
+        let value = item.to_attribute().and_then(|attribute| attribute.value(&mut volume)).map_err(io::Error::from)?;
+        f(&mut value.attach(&mut volume));
+        Ok(())
+    }
+}
+
+fn data_size(file: &NtfsFile<'_>, volume: &mut Volume) -> ntfs::Result<u64> {
+    match file.data(volume, "") {
+        Some(item) => Ok(item?.to_attribute()?.value_length()),
+        None => Ok(0),
+    }
+}
+
+/// The Windows attributes of `file`. NTFS keeps the recall flags of cloud
+/// files out of the standard information; their reparse tag stands in.
+fn attributes(file: &NtfsFile<'_>, info: &NtfsStandardInformation, volume: &mut Volume) -> ntfs::Result<u32> {
+    let mut attributes = info.file_attributes().bits();
+    if info.file_attributes().contains(NtfsFileAttributeFlags::REPARSE_POINT) {
+        let mut iter = file.attributes();
+        while let Some(item) = iter.next(volume) {
+            let item = item?;
+            let attribute = item.to_attribute()?;
+            if attribute.ty()? == NtfsAttributeType::ReparsePoint {
+                let mut tag = [0u8; 4];
+                attribute.value(volume)?.attach(volume).read_exact(&mut tag)?;
+                if is_cloud_reparse_tag(u32::from_le_bytes(tag)) {
+                    attributes |= RECALL_ON_DATA_ACCESS;
+                }
+                break;
+            }
+        }
+    }
+    Ok(attributes)
+}
+
+fn walk(ntfs: &Ntfs, volume: &mut Volume) -> ntfs::Result<Vec<ImageEntry>> {
+    let mut entries = Vec::new();
+    let mut visited = HashSet::new();
+    let mut dirs = vec![(ntfs.root_directory(volume)?.file_record_number(), String::new())];
+    while let Some((record, prefix)) = dirs.pop() {
+        if !visited.insert(record) {
+            continue;
+        }
+        if let Err(e) = list_dir(ntfs, volume, record, &prefix, &mut dirs, &mut entries) {
+            if prefix.is_empty() {
+                return Err(e);
+            }
+            // What is left of a damaged directory cannot be listed; restoring
+            // it reports the damage.
+            let name = prefix.trim_end_matches('/').to_string();
+            entries.push(ImageEntry { name, record, size: 0, modified: 0, attributes: 0, damage: Some(e.to_string()) });
+        }
+    }
+    // Hard links show up once per name; every name is restored.
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+fn list_dir(
+    ntfs: &Ntfs,
+    volume: &mut Volume,
+    record: u64,
+    prefix: &str,
+    dirs: &mut Vec<(u64, String)>,
+    entries: &mut Vec<ImageEntry>,
+) -> ntfs::Result<()> {
+    let dir = ntfs.file(volume, record)?;
+    let index = dir.directory_index(volume)?;
+    let mut iter = index.entries();
+    while let Some(entry) = iter.next(volume) {
+        let entry = entry?;
+        let Some(key) = entry.key() else { continue };
+        let file_name = key?;
+        // Short 8.3 aliases are listed next to the long names.
+        if file_name.namespace() == NtfsFileNamespace::Dos {
+            continue;
+        }
+        let name = file_name.name().to_string_lossy();
+        if name == "." || (prefix.is_empty() && name.starts_with('$')) {
+            continue;
+        }
+        let path = format!("{}{}", prefix, name);
+        let record = entry.file_reference().file_record_number();
+        if file_name.is_directory() {
+            dirs.push((record, format!("{}/", path)));
+            continue;
+        }
+        let file = entry.to_file(ntfs, volume).and_then(|file| {
+            let info = file.info()?;
+            Ok((data_size(&file, volume)?, attributes(&file, &info, volume)?, info))
+        });
+        entries.push(match file {
+            Ok((size, attributes, info)) => ImageEntry {
+                name: path,
+                record,
+                size,
+                modified: info.modification_time().nt_timestamp(),
+                attributes,
+                damage: None,
+            },
+            // Fall back to the copy of the metadata kept in the directory index.
+            Err(e) => ImageEntry {
+                name: path,
+                record,
+                size: file_name.data_size(),
+                modified: file_name.modification_time().nt_timestamp(),
+                attributes: file_name.file_attributes().bits(),
+                damage: Some(e.to_string()),
+            },
+        });
+    }
+    Ok(())
+}